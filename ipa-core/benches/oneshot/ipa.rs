@@ -1,14 +1,18 @@
 use std::{
     env,
-    num::{NonZeroU32, NonZeroUsize},
-    time::Instant,
+    num::{NonZeroU32, NonZeroU64, NonZeroUsize},
+    time::{Duration, Instant},
 };
 
 use clap::Parser;
 use ipa_core::{
     error::Error,
     ff::Fp32BitPrime,
-    helpers::{query::IpaQueryConfig, GatewayConfig},
+    helpers::{
+        in_memory_config::{no_emulation, wan, DynNetworkEmulator, LinkConditions},
+        query::IpaQueryConfig,
+        GatewayConfig,
+    },
     protocol::{step::ProtocolStep::IpaPrf, Gate},
     test_fixture::{
         ipa::{ipa_in_the_clear, test_oprf_ipa, CappingOrder, IpaSecurityModel},
@@ -64,6 +68,16 @@ struct Args {
     /// Desired security model for IPA protocol
     #[arg(short = 'm', long, value_enum, default_value_t=IpaSecurityModel::Malicious)]
     security_model: IpaSecurityModel,
+    /// One-way latency between helpers, in milliseconds. Setting any of the network
+    /// options makes helpers talk over emulated WAN links.
+    #[arg(long, default_value = "0")]
+    latency_ms: u64,
+    /// Upper bound for the random delay added to latency, in milliseconds.
+    #[arg(long, default_value = "0")]
+    jitter_ms: u64,
+    /// Bandwidth of each link between helpers, in megabits per second.
+    #[arg(long)]
+    bandwidth_mbps: Option<NonZeroU64>,
     /// Needed for benches.
     #[arg(long, hide = true)]
     bench: bool,
@@ -81,6 +95,25 @@ impl Args {
         NonZeroU32::new(self.attribution_window)
     }
 
+    fn helper_link(&self) -> LinkConditions {
+        LinkConditions {
+            latency: Duration::from_millis(self.latency_ms),
+            jitter: Duration::from_millis(self.jitter_ms),
+            bandwidth: self
+                .bandwidth_mbps
+                .map(|mbps| mbps.checked_mul(NonZeroU64::new(125_000).unwrap()).unwrap()),
+        }
+    }
+
+    fn network_emulator(&self) -> DynNetworkEmulator {
+        let link = self.helper_link();
+        if link.is_instant() {
+            no_emulation()
+        } else {
+            wan(link)
+        }
+    }
+
     fn config(&self) -> IpaQueryConfig {
         IpaQueryConfig {
             per_user_credit_cap: self.per_user_cap,
@@ -104,6 +137,7 @@ async fn run(args: Args) -> Result<(), Error> {
             ..Default::default()
         },
         initial_gate: Some(Gate::default().narrow(&IpaPrf)),
+        network_emulator: args.network_emulator(),
         timeout: None,
         ..TestWorldConfig::default()
     };
@@ -148,10 +182,11 @@ async fn run(args: Args) -> Result<(), Error> {
     )
     .await;
     tracing::info!(
-        "{m:?} IPA for {q} records took {t:?}",
+        "{m:?} IPA for {q} records took {t:?} over {link:?}",
         m = args.security_model,
         q = args.query_size,
-        t = _protocol_time.elapsed()
+        t = _protocol_time.elapsed(),
+        link = args.helper_link(),
     );
     Ok(())
 }
@@ -16,6 +16,7 @@ track_steps!(
             step,
         },
         context::step,
+        group_by::step,
         hybrid::step,
         ipa_prf::{
             boolean_ops::step,
@@ -1,4 +1,4 @@
-use std::sync::Weak;
+use std::{sync::Weak, time::Duration};
 
 use async_trait::async_trait;
 
@@ -6,22 +6,29 @@ use crate::{
     cli::LoggingHandle,
     executor::IpaRuntime,
     helpers::{
-        query::{CompareStatusRequest, PrepareQuery, QueryConfig, QueryInput},
+        query::{
+            CompareStatusRequest, KillQuery, PrepareQuery, QueryConfig, QueryInput,
+            WaitQueryRequest,
+        },
         routing::{Addr, RouteId},
         ApiError, BodyStream, HandlerBox, HandlerRef, HelperIdentity, HelperResponse,
-        MpcTransportImpl, RequestHandler, ShardTransportImpl, Transport, TransportIdentity,
+        MpcTransportImpl, QueryProgress, RequestHandler, ShardTransportImpl, Transport,
+        TransportIdentity,
     },
     hpke::{KeyRegistry, PrivateKeyOnly},
     protocol::QueryId,
     query::{NewQueryError, QueryProcessor, QueryStatus},
     sharding::ShardIndex,
     sync::Arc,
+    telemetry::stats::QueryMetrics,
     utils::NonZeroU32PowerOfTwo,
 };
 
 #[derive(Default)]
 pub struct AppConfig {
     active_work: Option<NonZeroU32PowerOfTwo>,
+    adaptive_active_work: bool,
+    aborted_query_retention: Option<Duration>,
     key_registry: Option<KeyRegistry<PrivateKeyOnly>>,
     runtime: IpaRuntime,
 }
@@ -33,6 +40,19 @@ impl AppConfig {
         self
     }
 
+    #[must_use]
+    pub fn with_adaptive_active_work(mut self, adaptive_active_work: bool) -> Self {
+        self.adaptive_active_work = adaptive_active_work;
+        self
+    }
+
+    /// Sets how long queries that failed or were killed remain visible through the status API.
+    #[must_use]
+    pub fn with_aborted_query_retention(mut self, retention: Duration) -> Self {
+        self.aborted_query_retention = Some(retention);
+        self
+    }
+
     #[must_use]
     pub fn with_key_registry(mut self, key_registry: KeyRegistry<PrivateKeyOnly>) -> Self {
         self.key_registry = Some(key_registry);
@@ -69,11 +89,26 @@ struct Inner {
     logging_handle: LoggingHandle,
 }
 
+impl Inner {
+    /// Leader shards are asked to complete or kill the query by the report collector, other
+    /// shards learn about it from the leader. Either way, once the query is over, records it
+    /// exchanged with both peer helpers and peer shards are no longer needed.
+    fn clear_streams(&self, query_id: QueryId) {
+        self.mpc_transport.clear_streams(query_id);
+        self.shard_transport.clear_streams(query_id);
+    }
+}
+
 impl Setup {
     #[must_use]
     pub fn new(config: AppConfig) -> (Self, HandlerRef<HelperIdentity>, HandlerRef<ShardIndex>) {
         let key_registry = config.key_registry.unwrap_or_else(KeyRegistry::empty);
-        let query_processor = QueryProcessor::new(key_registry, config.active_work, config.runtime);
+        let mut query_processor =
+            QueryProcessor::new(key_registry, config.active_work, config.runtime)
+                .with_adaptive_active_work(config.adaptive_active_work);
+        if let Some(retention) = config.aborted_query_retention {
+            query_processor = query_processor.with_aborted_query_retention(retention);
+        }
         let mpc_handler = HandlerBox::empty();
         let shard_handler = HandlerBox::empty();
         let this = Self {
@@ -174,6 +209,35 @@ impl HelperApp {
             .await?)
     }
 
+    /// Retrieves the status of a query along with its progress.
+    ///
+    /// ## Errors
+    /// Propagates errors from the helper.
+    pub async fn query_status_with_progress(
+        &self,
+        query_id: QueryId,
+    ) -> Result<(QueryStatus, Option<QueryProgress>), ApiError> {
+        let shard_transport = self.inner.shard_transport.clone_ref();
+        Ok(self
+            .inner
+            .query_processor
+            .query_status_with_progress(shard_transport, query_id)
+            .await?)
+    }
+
+    /// Retrieves metrics collected while running a query.
+    ///
+    /// ## Errors
+    /// If query does not exist or has not started running yet.
+    pub fn query_metrics(&self, query_id: QueryId) -> Result<QueryMetrics, ApiError> {
+        let partition = self.inner.query_processor.metric_partition(query_id)?;
+        Ok(self
+            .inner
+            .logging_handle
+            .metrics_handle
+            .partition_metrics(partition))
+    }
+
     /// Waits for a query to complete and returns the result.
     ///
     /// ## Errors
@@ -211,7 +275,7 @@ impl RequestHandler<ShardIndex> for Inner {
                 let req = req.into::<CompareStatusRequest>()?;
                 HelperResponse::from(qp.shard_status(&self.shard_transport, &req)?)
             }
-            RouteId::CompleteQuery => {
+            RouteId::CompleteQuery | RouteId::KillQuery => {
                 // The processing flow for this API is exactly the same, regardless
                 // whether it was received from a peer shard or from report collector.
                 // Authentication is handled on the layer above, so we erase the identity
@@ -277,25 +341,48 @@ impl RequestHandler<HelperIdentity> for Inner {
             RouteId::QueryStatus => {
                 let query_id = ext_query_id(&req)?;
                 let shard_transport = Transport::clone_ref(&self.shard_transport);
-                let query_status = qp.query_status(shard_transport, query_id).await?;
-                HelperResponse::from(query_status)
-            }
-            RouteId::CompleteQuery => {
-                let query_id = ext_query_id(&req)?;
                 HelperResponse::from(
-                    qp.complete(query_id, self.shard_transport.clone_ref())
+                    qp.query_status_with_progress(shard_transport, query_id)
                         .await?,
                 )
             }
-            RouteId::KillQuery => {
+            RouteId::QueryWait => {
+                let req = req.into::<WaitQueryRequest>()?;
+                let shard_transport = Transport::clone_ref(&self.shard_transport);
+                HelperResponse::from(qp.wait_for_completion(shard_transport, req).await?)
+            }
+            RouteId::CompleteQuery => {
                 let query_id = ext_query_id(&req)?;
-                HelperResponse::from(qp.kill(query_id)?)
+                let result = qp
+                    .complete(query_id, self.shard_transport.clone_ref())
+                    .await?;
+                self.clear_streams(query_id);
+                HelperResponse::from(result)
+            }
+            RouteId::KillQuery => {
+                let req = req.into::<KillQuery>()?;
+                let query_id = req.query_id;
+                let result = qp
+                    .kill_query(
+                        self.mpc_transport.clone_ref(),
+                        self.shard_transport.clone_ref(),
+                        req,
+                    )
+                    .await?;
+                self.clear_streams(query_id);
+                HelperResponse::from(result)
             }
             RouteId::Metrics => {
                 let logging_handler = &self.logging_handle;
                 let metrics_handle = &logging_handler.metrics_handle;
                 HelperResponse::from(metrics_handle.scrape_metrics())
             }
+            RouteId::QueryMetrics => {
+                let query_id = ext_query_id(&req)?;
+                let partition = qp.metric_partition(query_id)?;
+                let metrics_handle = &self.logging_handle.metrics_handle;
+                HelperResponse::from(metrics_handle.partition_metrics(partition))
+            }
         })
     }
 }
@@ -3,7 +3,10 @@ use std::fmt::Debug;
 use clap::{Parser, Subcommand};
 use ipa_core::{
     cli::{
-        crypto::{DecryptArgs, EncryptArgs, HybridDecryptArgs, HybridEncryptArgs},
+        crypto::{
+            DecryptArgs, EncryptArgs, HybridDecryptArgs, HybridEncryptArgs, HybridSplitArgs,
+            HybridValidateArgs,
+        },
         Verbosity,
     },
     error::BoxError,
@@ -27,6 +30,10 @@ enum CryptoUtilCommand {
     HybridEncrypt(HybridEncryptArgs),
     Decrypt(DecryptArgs),
     HybridDecrypt(HybridDecryptArgs),
+    /// Check encrypted hybrid reports for problems before submitting them
+    Validate(HybridValidateArgs),
+    /// Split encrypted hybrid reports into per-shard files
+    Split(HybridSplitArgs),
 }
 
 #[tokio::main]
@@ -40,6 +47,8 @@ async fn main() -> Result<(), BoxError> {
         CryptoUtilCommand::HybridDecrypt(hybrid_decrypt_args) => {
             hybrid_decrypt_args.decrypt_and_reconstruct().await?
         }
+        CryptoUtilCommand::Validate(validate_args) => validate_args.validate()?,
+        CryptoUtilCommand::Split(split_args) => split_args.split()?,
     }
     Ok(())
 }
@@ -1,10 +1,11 @@
 use std::{
     fs,
-    io::BufReader,
     net::TcpListener,
     os::fd::{FromRawFd, RawFd},
     path::{Path, PathBuf},
     process,
+    sync::Arc,
+    time::Duration,
 };
 
 use clap::{self, Parser, Subcommand};
@@ -13,16 +14,16 @@ use hyper::http::uri::Scheme;
 use ipa_core::{
     cli::{
         client_config_setup, keygen, sharded_client_config_setup, sharded_server_from_toml_str,
-        test_setup, ConfGenArgs, KeygenArgs, LoggingHandle, ShardedConfGenArgs, TestSetupArgs,
-        Verbosity,
+        test_network, test_setup, ConfGenArgs, KeygenArgs, LoggingHandle, ShardedConfGenArgs,
+        TestNetworkArgs, TestSetupArgs, Verbosity,
     },
-    config::{hpke_registry, HpkeServerConfig, ServerConfig, TlsConfig},
+    config::{hpke_registry, HpkeServerConfig, NetworkConfig, ServerConfig, TlsConfig},
     error::BoxError,
     executor::IpaRuntime,
     helpers::HelperIdentity,
     net::{
-        ClientIdentity, ConnectionFlavor, IpaHttpClient, MpcHttpTransport, Shard,
-        ShardHttpTransport,
+        CertificateReloader, ClientIdentity, ConnectionFlavor, IpaHttpClient, MpcHttpTransport,
+        Shard, ShardHttpTransport,
     },
     sharding::ShardIndex,
     AppConfig, AppSetup, NonZeroU32PowerOfTwo,
@@ -103,6 +104,12 @@ struct ServerArgs {
     #[arg(long, visible_alias("key"), requires = "tls_cert")]
     tls_key: Option<PathBuf>,
 
+    /// How often, in seconds, to check TLS certificate, key and network configuration files
+    /// for changes. Changed certificates are used for new connections without restarting
+    /// the helper.
+    #[arg(long, default_value = "60")]
+    tls_reload_interval: u64,
+
     /// Public key for encrypting match keys
     #[arg(long, requires = "mk_private_key")]
     mk_public_key: Option<PathBuf>,
@@ -114,6 +121,16 @@ struct ServerArgs {
     /// Override the amount of active work processed in parallel
     #[arg(long)]
     active_work: Option<NonZeroU32PowerOfTwo>,
+
+    /// Measure backpressure while queries run and let helpers reduce the amount of active
+    /// work if the network can't keep up
+    #[arg(long)]
+    adaptive_active_work: bool,
+
+    /// How long, in seconds, queries that failed or were killed remain visible through the
+    /// status API, so operators can find out why they ended
+    #[arg(long, default_value = "3600")]
+    aborted_query_retention: u64,
 }
 
 #[derive(Debug, Subcommand)]
@@ -122,40 +139,51 @@ enum HelperCommand {
     Confgen(ConfGenArgs),
     Keygen(KeygenArgs),
     TestSetup(TestSetupArgs),
-}
-
-fn read_file(path: &Path) -> Result<BufReader<fs::File>, BoxError> {
-    Ok(fs::OpenOptions::new()
-        .read(true)
-        .open(path)
-        .map(BufReader::new)
-        .map_err(|e| format!("failed to open file {}: {e:?}", path.display()))?)
+    TestNetwork(TestNetworkArgs),
 }
 
 /// Helper function that creates the client identity; either with certificates if they are provided
 /// or just with headers otherwise. This works both for sharded and helper configs.
-fn create_client_identity<F: ConnectionFlavor>(
+///
+/// When certificates are provided, they are held by a [`CertificateReloader`] that picks up
+/// changes to them and to the peer certificates listed in `network_file`.
+async fn create_client_identity<F, P>(
     id: F::Identity,
     tls_cert: Option<PathBuf>,
     tls_key: Option<PathBuf>,
-) -> Result<(ClientIdentity<F>, Option<TlsConfig>), BoxError> {
+    network: NetworkConfig<F>,
+    network_file: &Path,
+    parse: P,
+) -> Result<(ClientIdentity<F>, Option<TlsConfig>), BoxError>
+where
+    F: ConnectionFlavor,
+    P: Fn(&str) -> Result<NetworkConfig<F>, BoxError> + Send + Sync + 'static,
+{
     match (tls_cert, tls_key) {
         (Some(cert_file), Some(key_file)) => {
-            let mut key = read_file(&key_file)?;
-            let mut certs = read_file(&cert_file)?;
-            Ok((
-                ClientIdentity::<F>::from_pkcs8(&mut certs, &mut key)?,
-                Some(TlsConfig::File {
-                    certificate_file: cert_file,
-                    private_key_file: key_file,
-                }),
-            ))
+            let tls = TlsConfig::File {
+                certificate_file: cert_file,
+                private_key_file: key_file,
+            };
+            let reloader = CertificateReloader::new(tls.clone(), network)
+                .await?
+                .with_network_file(network_file.to_path_buf(), parse);
+            Ok((ClientIdentity::Reloadable(Arc::new(reloader)), Some(tls)))
         }
         (None, None) => Ok((ClientIdentity::Header(id), None)),
         _ => Err("should have been rejected by clap".into()),
     }
 }
 
+fn certificate_reloader<F: ConnectionFlavor>(
+    identity: &ClientIdentity<F>,
+) -> Option<Arc<CertificateReloader<F>>> {
+    match identity {
+        ClientIdentity::Reloadable(certificates) => Some(Arc::clone(certificates)),
+        _ => None,
+    }
+}
+
 /// Creates a [`TcpListener`] from an optional raw file descriptor. Safety notes:
 ///  1. The `--server-socket-fd` option is only intended for use in tests, not in production.
 ///  2. This must be the only call to from_raw_fd for this file descriptor, to ensure it has
@@ -188,10 +216,56 @@ async fn server(args: ServerArgs, logging_handle: LoggingHandle) -> Result<(), B
         "Inconsistent configuration: TLS certs and disable_http"
     );
 
-    let (identity, server_tls) =
-        create_client_identity(my_identity, args.tls_cert.clone(), args.tls_key.clone())?;
-    let (shard_identity, shard_server_tls) =
-        create_client_identity(shard_index, args.tls_cert, args.tls_key)?;
+    let scheme = if args.disable_https {
+        Scheme::HTTP
+    } else {
+        Scheme::HTTPS
+    };
+
+    let network_config_path = args.network.as_deref().unwrap();
+    let network_config_string = &fs::read_to_string(network_config_path)?;
+    let parse_network = {
+        let scheme = scheme.clone();
+        move |network_config_string: &str| {
+            sharded_server_from_toml_str(
+                network_config_string,
+                my_identity,
+                shard_index,
+                shard_count,
+                args.shard_port,
+            )
+            .map(|(mpc_network, shard_network)| {
+                (
+                    mpc_network.override_scheme(&scheme),
+                    shard_network.override_scheme(&scheme),
+                )
+            })
+            .map_err(BoxError::from)
+        }
+    };
+    let (mpc_network, shard_network) = parse_network(network_config_string)?;
+
+    let (identity, server_tls) = create_client_identity(
+        my_identity,
+        args.tls_cert.clone(),
+        args.tls_key.clone(),
+        mpc_network.clone(),
+        network_config_path,
+        {
+            let parse_network = parse_network.clone();
+            move |s| parse_network(s).map(|(mpc_network, _)| mpc_network)
+        },
+    )
+    .await?;
+    let (shard_identity, shard_server_tls) = create_client_identity(
+        shard_index,
+        args.tls_cert,
+        args.tls_key,
+        shard_network.clone(),
+        network_config_path,
+        move |s| parse_network(s).map(|(_, shard_network)| shard_network),
+    )
+    .await?;
 
     let mk_encryption = args.mk_private_key.map(|sk_path| HpkeServerConfig::File {
         private_key_file: sk_path,
@@ -201,6 +275,8 @@ async fn server(args: ServerArgs, logging_handle: LoggingHandle) -> Result<(), B
     let app_config = AppConfig::default()
         .with_key_registry(hpke_registry(mk_encryption.as_ref()).await?)
         .with_active_work(args.active_work)
+        .with_adaptive_active_work(args.adaptive_active_work)
+        .with_aborted_query_retention(Duration::from_secs(args.aborted_query_retention))
         .with_runtime(IpaRuntime::from_tokio_runtime(&query_runtime));
 
     let (setup, handler, shard_handler) = AppSetup::new(app_config);
@@ -219,25 +295,22 @@ async fn server(args: ServerArgs, logging_handle: LoggingHandle) -> Result<(), B
         hpke_config: mk_encryption,
     };
 
-    let scheme = if args.disable_https {
-        Scheme::HTTP
-    } else {
-        Scheme::HTTPS
-    };
-
-    let network_config_path = args.network.as_deref().unwrap();
-    let network_config_string = &fs::read_to_string(network_config_path)?;
-    let (mut mpc_network, mut shard_network) = sharded_server_from_toml_str(
-        network_config_string,
-        my_identity,
-        shard_index,
-        shard_count,
-        args.shard_port,
-    )?;
-    mpc_network = mpc_network.override_scheme(&scheme);
-    shard_network = shard_network.override_scheme(&scheme);
-
     let http_runtime = new_http_runtime(&logging_handle);
+    let reload_interval = Duration::from_secs(args.tls_reload_interval);
+    let certificates = certificate_reloader(&identity);
+    let shard_certificates = certificate_reloader(&shard_identity);
+    if let Some(certificates) = &certificates {
+        certificates.watch(
+            &IpaRuntime::from_tokio_runtime(&http_runtime),
+            reload_interval,
+        );
+    }
+    if let Some(certificates) = &shard_certificates {
+        certificates.watch(
+            &IpaRuntime::from_tokio_runtime(&http_runtime),
+            reload_interval,
+        );
+    }
     let clients = IpaHttpClient::from_conf(
         &IpaRuntime::from_tokio_runtime(&http_runtime),
         &mpc_network,
@@ -251,6 +324,10 @@ async fn server(args: ServerArgs, logging_handle: LoggingHandle) -> Result<(), B
         &clients,
         Some(handler),
     );
+    let server = match certificates {
+        Some(certificates) => server.with_certificate_reloader(certificates),
+        None => server,
+    };
 
     let shard_clients = IpaHttpClient::<Shard>::shards_from_conf(
         &IpaRuntime::from_tokio_runtime(&http_runtime),
@@ -266,6 +343,10 @@ async fn server(args: ServerArgs, logging_handle: LoggingHandle) -> Result<(), B
         shard_clients,
         Some(shard_handler),
     );
+    let shard_server = match shard_certificates {
+        Some(certificates) => shard_server.with_certificate_reloader(certificates),
+        None => shard_server,
+    };
 
     let _app = setup.connect(transport.clone(), shard_transport.clone(), logging_handle);
 
@@ -375,6 +456,7 @@ pub async fn main() {
         None => server(args.server, handle).await,
         Some(HelperCommand::Keygen(args)) => keygen(&args),
         Some(HelperCommand::TestSetup(args)) => test_setup(&args),
+        Some(HelperCommand::TestNetwork(args)) => test_network(&args).await,
         Some(HelperCommand::Confgen(args)) => client_config_setup(args),
         Some(HelperCommand::ShardedConfgen(args)) => sharded_client_config_setup(args),
     };
@@ -4,10 +4,11 @@ use std::{
     fmt::Debug,
     fs::{File, OpenOptions},
     io,
-    io::{stdout, BufRead, BufReader, Write},
+    io::{stdout, BufRead, BufReader, BufWriter, Write},
     iter::zip,
     ops::Deref,
     path::{Path, PathBuf},
+    slice,
 };
 
 use clap::{Parser, Subcommand};
@@ -15,23 +16,28 @@ use hyper::{http::uri::Scheme, Uri};
 use ipa_core::{
     cli::{
         playbook::{
-            make_clients, make_sharded_clients, playbook_oprf_ipa, run_hybrid_query_and_validate,
-            run_query_and_validate, validate, validate_dp, HybridQueryResult, InputSource,
-            RoundRobinSubmission, StreamingSubmission,
+            make_clients, make_sharded_clients, playbook_oprf_ipa, run_batch,
+            run_conversion_lift_query, run_hybrid_query_and_validate, run_query_and_validate,
+            run_reach_frequency_query, shard_input_streams, validate, validate_dp, BatchInputs,
+            BatchManifest, BatchQuery, BatchQueryKind, InputSource, RoundRobinSubmission,
+            StreamingSubmission,
         },
-        CsvSerializer, IpaQueryResult, Verbosity,
+        write_query_result, BreakdownHistograms, BreakdownNames, CsvSerializer, OutputFormat,
+        Verbosity,
     },
     config::{KeyRegistries, NetworkConfig},
+    error::BoxError,
     ff::{boolean_array::BA32, FieldType},
     helpers::{
         query::{
-            DpMechanism, HybridQueryParams, IpaQueryConfig, QueryConfig, QueryInput, QuerySize,
-            QueryType,
+            DpMechanism, HybridQueryParams, IpaQueryConfig, KillQuery, QueryConfig, QueryInput,
+            QuerySize, QueryType,
         },
         BodyStream,
     },
     net::{Helper, IpaHttpClient},
     protocol::QueryId,
+    query::estimate::{Calibration, CostModel},
     report::{EncryptedOprfReportStreams, DEFAULT_KEY_ID},
     test_fixture::{
         ipa::{ipa_in_the_clear, CappingOrder, IpaSecurityModel, TestRawDataRecord},
@@ -40,6 +46,7 @@ use ipa_core::{
 };
 use rand::{distributions::Alphanumeric, rngs::StdRng, thread_rng, Rng};
 use rand_core::SeedableRng;
+use serde::Serialize;
 
 #[derive(Debug, Parser)]
 #[clap(name = "rc", about = "Report Collector CLI")]
@@ -67,13 +74,31 @@ struct Args {
     #[arg(long, value_name = "OUTPUT_FILE")]
     output_file: Option<PathBuf>,
 
+    /// Format of the query results. The csv and jsonl formats have one row per breakdown key
+    /// and metric, with the noisy value and the standard deviation of the DP noise.
+    #[arg(long, value_enum, default_value_t = OutputFormat::Json)]
+    output_format: OutputFormat,
+
+    /// CSV file with `breakdown_key,name` rows. The names are added to csv and jsonl results.
+    #[arg(long, value_name = "BREAKDOWN_NAMES_FILE", value_parser = parse_breakdown_names)]
+    breakdown_names: Option<BreakdownNames>,
+
     #[arg(long, default_value_t = 1)]
     shard_count: usize,
 
+    /// Save the metrics collected by every helper while running the query to this file.
+    /// Metrics of small runs saved this way are used to calibrate the `estimate` command.
+    #[arg(long, value_name = "CALIBRATION_FILE")]
+    save_calibration: Option<PathBuf>,
+
     #[command(subcommand)]
     action: ReportCollectorCommand,
 }
 
+fn parse_breakdown_names(path: &str) -> Result<BreakdownNames, BoxError> {
+    BreakdownNames::from_file(Path::new(path))
+}
+
 #[derive(Debug, Parser)]
 pub struct CommandInput {
     #[arg(
@@ -155,17 +180,117 @@ enum ReportCollectorCommand {
         )]
         url_file_list: Option<PathBuf>,
 
+        #[arg(
+            long,
+            value_name = "DIR",
+            help = "Read length delimited inputs for every helper and shard from the provided directory, as written by `crypto_util split`",
+            conflicts_with_all = ["enc_input_file1", "enc_input_file2", "enc_input_file3", "url_file_list"]
+        )]
+        enc_input_dir: Option<PathBuf>,
+
         #[clap(flatten)]
         hybrid_query_config: HybridQueryParams,
 
+        /// Run the query in the semi-honest security model instead of the malicious one.
+        /// Intended for experiments and cost comparisons only.
+        #[clap(long)]
+        semi_honest: bool,
+
         /// Number of records to aggregate
         #[clap(long, short = 'n')]
         count: u32,
 
-        // If set, use the specified fixed polling interval when running a query.
-        // Otherwise, use exponential backoff.
-        #[clap(long)]
-        set_fixed_polling_ms: Option<u64>,
+        /// How long helpers may hold a status request while the query is still running,
+        /// which is also how often progress is refreshed. Defaults to 5 seconds.
+        #[clap(long, alias = "set-fixed-polling-ms")]
+        status_refresh_ms: Option<u64>,
+    },
+    /// Compute DP-noised conversion totals per breakdown key, separately for the test and
+    /// control groups of a lift experiment, from encrypted reports in the hybrid format
+    MaliciousConversionLift {
+        #[clap(flatten)]
+        encrypted_inputs: Option<EncryptedInputs>,
+
+        #[arg(
+            long,
+            help = "Read the list of URLs that contain the input from the provided file",
+            conflicts_with_all = ["enc_input_file1", "enc_input_file2", "enc_input_file3"]
+        )]
+        url_file_list: Option<PathBuf>,
+
+        #[arg(
+            long,
+            value_name = "DIR",
+            help = "Read length delimited inputs for every helper and shard from the provided directory, as written by `crypto_util split`",
+            conflicts_with_all = ["enc_input_file1", "enc_input_file2", "enc_input_file3", "url_file_list"]
+        )]
+        enc_input_dir: Option<PathBuf>,
+
+        #[clap(flatten)]
+        hybrid_query_config: HybridQueryParams,
+
+        /// Number of records to aggregate
+        #[clap(long, short = 'n')]
+        count: u32,
+
+        /// How long helpers may hold a status request while the query is still running,
+        /// which is also how often progress is refreshed. Defaults to 5 seconds.
+        #[clap(long, alias = "set-fixed-polling-ms")]
+        status_refresh_ms: Option<u64>,
+    },
+    /// Compute a DP-noised frequency histogram per breakdown key, from encrypted
+    /// impression reports in the hybrid format
+    MaliciousReachFrequency {
+        #[clap(flatten)]
+        encrypted_inputs: Option<EncryptedInputs>,
+
+        #[arg(
+            long,
+            help = "Read the list of URLs that contain the input from the provided file",
+            conflicts_with_all = ["enc_input_file1", "enc_input_file2", "enc_input_file3"]
+        )]
+        url_file_list: Option<PathBuf>,
+
+        #[arg(
+            long,
+            value_name = "DIR",
+            help = "Read length delimited inputs for every helper and shard from the provided directory, as written by `crypto_util split`",
+            conflicts_with_all = ["enc_input_file1", "enc_input_file2", "enc_input_file3", "url_file_list"]
+        )]
+        enc_input_dir: Option<PathBuf>,
+
+        #[clap(flatten)]
+        hybrid_query_config: HybridQueryParams,
+
+        /// Number of records to aggregate
+        #[clap(long, short = 'n')]
+        count: u32,
+
+        /// How long helpers may hold a status request while the query is still running,
+        /// which is also how often progress is refreshed. Defaults to 5 seconds.
+        #[clap(long, alias = "set-fixed-polling-ms")]
+        status_refresh_ms: Option<u64>,
+    },
+    /// Run all queries listed in the manifest one after another, retrying the ones that fail,
+    /// and write a summary with the status and duration of every query. See `BatchManifest`
+    /// for the manifest format.
+    Batch {
+        /// TOML or JSON file that lists the queries to run
+        #[arg(long, value_name = "MANIFEST_FILE")]
+        manifest: PathBuf,
+    },
+    /// Predict network volume and resources each helper needs to run a query of the given
+    /// size, without running MPC. The prediction is calibrated with metrics saved by
+    /// `--save-calibration` from smaller runs of the same query.
+    Estimate {
+        /// Calibration files saved by `--save-calibration`. Runs of at least two different
+        /// sizes are required to account for costs that do not depend on the number of rows.
+        #[arg(long = "calibration", value_name = "CALIBRATION_FILE", required = true)]
+        calibrations: Vec<PathBuf>,
+
+        /// Number of records in the query to estimate
+        #[clap(long, short = 'n')]
+        count: u32,
     },
 }
 
@@ -198,6 +323,15 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let args = Args::parse();
     let _handle = args.logging.setup_logging();
 
+    // estimates are computed locally and do not need to talk to helpers
+    if let ReportCollectorCommand::Estimate {
+        ref calibrations,
+        count,
+    } = args.action
+    {
+        return estimate(&args, calibrations, count);
+    }
+
     let scheme = if args.disable_https {
         Scheme::HTTP
     } else {
@@ -278,17 +412,91 @@ async fn main() -> Result<(), Box<dyn Error>> {
         ReportCollectorCommand::MaliciousHybrid {
             ref encrypted_inputs,
             ref url_file_list,
+            ref enc_input_dir,
             hybrid_query_config,
+            semi_honest,
             count,
-            set_fixed_polling_ms,
+            status_refresh_ms,
         } => {
+            let security_model = if semi_honest {
+                IpaSecurityModel::SemiHonest
+            } else {
+                IpaSecurityModel::Malicious
+            };
             hybrid(
+                &args,
+                security_model,
+                hybrid_query_config,
+                clients,
+                |query_id| {
+                    if let Some(ref url_file_list) = url_file_list {
+                        inputs_from_url_file(url_file_list, query_id, args.shard_count)
+                    } else if let Some(ref enc_input_dir) = enc_input_dir {
+                        inputs_from_shard_dir(enc_input_dir, query_id, args.shard_count)
+                    } else if let Some(ref encrypted_inputs) = encrypted_inputs {
+                        Ok(inputs_from_encrypted_inputs(
+                            encrypted_inputs,
+                            query_id,
+                            args.shard_count,
+                        ))
+                    } else {
+                        panic!("Either --url-file-list, --enc-input-dir or --enc-input-file1, --enc-input-file2, and --enc-input-file3 must be provided");
+                    }
+                },
+                count.try_into().expect("u32 should fit into usize"),
+                status_refresh_ms,
+            )
+            .await?
+        }
+        ReportCollectorCommand::MaliciousReachFrequency {
+            ref encrypted_inputs,
+            ref url_file_list,
+            ref enc_input_dir,
+            hybrid_query_config,
+            count,
+            status_refresh_ms,
+        } => {
+            reach_frequency(
+                &args,
+                hybrid_query_config,
+                clients,
+                |query_id| {
+                    if let Some(ref url_file_list) = url_file_list {
+                        inputs_from_url_file(url_file_list, query_id, args.shard_count)
+                    } else if let Some(ref enc_input_dir) = enc_input_dir {
+                        inputs_from_shard_dir(enc_input_dir, query_id, args.shard_count)
+                    } else if let Some(ref encrypted_inputs) = encrypted_inputs {
+                        Ok(inputs_from_encrypted_inputs(
+                            encrypted_inputs,
+                            query_id,
+                            args.shard_count,
+                        ))
+                    } else {
+                        panic!("Either --url-file-list, --enc-input-dir or --enc-input-file1, --enc-input-file2, and --enc-input-file3 must be provided");
+                    }
+                },
+                count.try_into().expect("u32 should fit into usize"),
+                status_refresh_ms,
+            )
+            .await?
+        }
+        ReportCollectorCommand::MaliciousConversionLift {
+            ref encrypted_inputs,
+            ref url_file_list,
+            ref enc_input_dir,
+            hybrid_query_config,
+            count,
+            status_refresh_ms,
+        } => {
+            conversion_lift(
                 &args,
                 hybrid_query_config,
                 clients,
                 |query_id| {
                     if let Some(ref url_file_list) = url_file_list {
                         inputs_from_url_file(url_file_list, query_id, args.shard_count)
+                    } else if let Some(ref enc_input_dir) = enc_input_dir {
+                        inputs_from_shard_dir(enc_input_dir, query_id, args.shard_count)
                     } else if let Some(ref encrypted_inputs) = encrypted_inputs {
                         Ok(inputs_from_encrypted_inputs(
                             encrypted_inputs,
@@ -296,14 +504,16 @@ async fn main() -> Result<(), Box<dyn Error>> {
                             args.shard_count,
                         ))
                     } else {
-                        panic!("Either --url-file-list or --enc-input-file1, --enc-input-file2, and --enc-input-file3 must be provided");
+                        panic!("Either --url-file-list, --enc-input-dir or --enc-input-file1, --enc-input-file2, and --enc-input-file3 must be provided");
                     }
                 },
                 count.try_into().expect("u32 should fit into usize"),
-                set_fixed_polling_ms,
+                status_refresh_ms,
             )
             .await?
         }
+        ReportCollectorCommand::Batch { ref manifest } => batch(&args, manifest, clients).await?,
+        ReportCollectorCommand::Estimate { .. } => unreachable!("estimate is handled above"),
     };
 
     Ok(())
@@ -358,6 +568,23 @@ fn inputs_from_url_file(
         .collect())
 }
 
+fn inputs_from_shard_dir(
+    dir: &Path,
+    query_id: QueryId,
+    shard_count: usize,
+) -> Result<Vec<[QueryInput; 3]>, Box<dyn Error>> {
+    Ok(shard_input_streams(dir, shard_count)
+        .map_err(|e| e as Box<dyn Error>)?
+        .into_iter()
+        .map(|streams| {
+            streams.map(|s| QueryInput::Inline {
+                input_stream: BodyStream::from_bytes_stream(s),
+                query_id,
+            })
+        })
+        .collect())
+}
+
 fn inputs_from_encrypted_inputs(
     encrypted_inputs: &EncryptedInputs,
     query_id: QueryId,
@@ -456,11 +683,9 @@ fn get_query_type(security_model: IpaSecurityModel, ipa_query_config: IpaQueryCo
     }
 }
 
-fn write_ipa_output_file(
-    path: &PathBuf,
-    query_result: &IpaQueryResult,
-) -> Result<(), Box<dyn Error>> {
-    // it will be sad to lose the results if file already exists.
+/// Creates the output file. If it already exists, a new file with a random suffix is created
+/// instead, because it will be sad to lose the results.
+fn create_output_file(path: &PathBuf) -> Result<File, Box<dyn Error>> {
     let path = if Path::is_file(path) {
         let mut new_file_name = thread_rng()
             .sample_iter(&Alphanumeric)
@@ -485,64 +710,269 @@ fn write_ipa_output_file(
     } else {
         Cow::Borrowed(path)
     };
-    let mut file = File::options()
+
+    Ok(File::options()
         .write(true)
         .create_new(true)
         .open(path.deref())
-        .map_err(|e| format!("Failed to create output file {}: {e}", path.display()))?;
+        .map_err(|e| format!("Failed to create output file {}: {e}", path.display()))?)
+}
 
+fn write_hybrid_output_file<T: Serialize>(
+    path: &PathBuf,
+    query_result: &T,
+) -> Result<(), Box<dyn Error>> {
+    let mut file = create_output_file(path)?;
     write!(file, "{}", serde_json::to_string_pretty(query_result)?)?;
     Ok(())
 }
 
-fn write_hybrid_output_file(
-    path: &PathBuf,
-    query_result: &HybridQueryResult,
+/// Writes the query result to the output file, or to stdout if there isn't one, in the
+/// requested output format.
+fn write_query_output<T: Serialize + BreakdownHistograms>(
+    args: &Args,
+    query_result: &T,
 ) -> Result<(), Box<dyn Error>> {
-    // it will be sad to lose the results if file already exists.
-    let path = if Path::is_file(path) {
-        let mut new_file_name = thread_rng()
-            .sample_iter(&Alphanumeric)
-            .take(5)
-            .map(char::from)
-            .collect::<String>();
-        let file_name = path.file_stem().ok_or("not a file")?;
+    let mut writer: Box<dyn Write> = if let Some(ref path) = args.output_file {
+        Box::new(BufWriter::new(create_output_file(path)?))
+    } else {
+        Box::new(stdout().lock())
+    };
+    write_query_result(
+        query_result,
+        args.output_format,
+        args.breakdown_names.as_ref(),
+        &mut writer,
+    )
+    .map_err(|e| e as Box<dyn Error>)?;
+    writer.flush()?;
+    Ok(())
+}
 
-        new_file_name.insert(0, '-');
-        new_file_name.insert_str(0, &file_name.to_string_lossy());
-        tracing::warn!(
-            "{} file exists, renaming to {:?}",
-            path.display(),
-            new_file_name
+/// Saves the metrics collected by every shard of every helper while running the query, if
+/// requested with `--save-calibration`. This runs after the query results are written, and
+/// failing to save the calibration only gets logged, so it never costs the results.
+async fn save_calibration(
+    args: &Args,
+    query_config: QueryConfig,
+    query_id: QueryId,
+    helper_clients: &[[IpaHttpClient<Helper>; 3]],
+) {
+    let Some(ref path) = args.save_calibration else {
+        return;
+    };
+
+    if let Err(e) = try_save_calibration(path, query_config, query_id, helper_clients).await {
+        tracing::error!(
+            "Failed to save calibration metrics to {}: {e}",
+            path.display()
         );
+    }
+}
 
-        // it will not be 100% accurate until file_prefix API is stabilized
-        Cow::Owned(
-            path.with_file_name(&new_file_name)
-                .with_extension(path.extension().unwrap_or("".as_ref())),
-        )
-    } else {
-        Cow::Borrowed(path)
+async fn try_save_calibration(
+    path: &Path,
+    query_config: QueryConfig,
+    query_id: QueryId,
+    helper_clients: &[[IpaHttpClient<Helper>; 3]],
+) -> Result<(), Box<dyn Error>> {
+    let mut shards = Vec::with_capacity(helper_clients.len());
+    for [h1, h2, h3] in helper_clients {
+        shards.push([
+            h1.query_metrics(query_id).await?,
+            h2.query_metrics(query_id).await?,
+            h3.query_metrics(query_id).await?,
+        ]);
+    }
+    let calibration = Calibration::new(query_config, &shards);
+
+    let file = File::create(path)
+        .map_err(|e| format!("Failed to create calibration file {}: {e}", path.display()))?;
+    serde_json::to_writer_pretty(file, &calibration)?;
+    tracing::info!("Saved calibration metrics to {}", path.display());
+
+    Ok(())
+}
+
+fn estimate(args: &Args, calibrations: &[PathBuf], count: u32) -> Result<(), Box<dyn Error>> {
+    let calibrations = calibrations
+        .iter()
+        .map(|path| {
+            let file = File::open(path)
+                .map_err(|e| format!("Failed to open calibration file {}: {e}", path.display()))?;
+            Ok(serde_json::from_reader(BufReader::new(file))?)
+        })
+        .collect::<Result<Vec<Calibration>, Box<dyn Error>>>()?;
+
+    let model = CostModel::fit(&calibrations)?;
+    let query_config = QueryConfig {
+        size: QuerySize::try_from(count)?,
+        ..*model.config()
     };
-    let mut file = File::options()
-        .write(true)
-        .create_new(true)
-        .open(path.deref())
-        .map_err(|e| format!("Failed to create output file {}: {e}", path.display()))?;
+    let estimate = model.estimate(&query_config)?;
 
-    write!(file, "{}", serde_json::to_string_pretty(query_result)?)?;
+    print!("{estimate}");
+    if let Some(ref path) = args.output_file {
+        write_hybrid_output_file(path, &estimate)?;
+    }
+
+    Ok(())
+}
+
+async fn batch(
+    args: &Args,
+    manifest: &Path,
+    helper_clients: Vec<[IpaHttpClient<Helper>; 3]>,
+) -> Result<(), Box<dyn Error>> {
+    let manifest = BatchManifest::from_file(manifest)?;
+    let summary = run_batch(&manifest, |query| {
+        run_batch_query(helper_clients.clone(), args.shard_count, query.clone())
+    })
+    .await;
+
+    if let Some(ref path) = args.output_file {
+        write_hybrid_output_file(path, &summary)?;
+    } else {
+        println!("{}", serde_json::to_string_pretty(&summary)?);
+    }
+
+    if summary.failed > 0 {
+        return Err(format!(
+            "{} out of {} queries failed",
+            summary.failed,
+            summary.queries.len()
+        )
+        .into());
+    }
     Ok(())
 }
 
+/// Makes one attempt to run a query from the batch manifest. The playbook panics if the query
+/// fails, so it runs in a separate task to keep the rest of the batch going. Failed queries are
+/// killed to release the resources helpers hold for them.
+async fn run_batch_query(
+    helper_clients: Vec<[IpaHttpClient<Helper>; 3]>,
+    shard_count: usize,
+    query: BatchQuery,
+) -> Result<(), BoxError> {
+    let query_config = query.query_config()?;
+    let leader = helper_clients[0][0].clone();
+
+    let query_id = leader.create_query(query_config).await?;
+
+    let result = match tokio::spawn(execute_batch_query(
+        helper_clients,
+        shard_count,
+        query,
+        query_id,
+    ))
+    .await
+    {
+        Ok(result) => result,
+        Err(e) if e.is_panic() => {
+            let panic = e.into_panic();
+            let reason = panic
+                .downcast_ref::<&str>()
+                .map(ToString::to_string)
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            Err(format!("query {query_id} panicked: {reason}").into())
+        }
+        Err(e) => Err(e.into()),
+    };
+
+    if result.is_err() {
+        let kill = KillQuery {
+            query_id,
+            forwarded: false,
+        };
+        if let Err(e) = leader.kill_query(kill).await {
+            tracing::warn!("Failed to kill query {query_id}: {e}");
+        }
+    }
+
+    result
+}
+
+async fn execute_batch_query(
+    helper_clients: Vec<[IpaHttpClient<Helper>; 3]>,
+    shard_count: usize,
+    query: BatchQuery,
+    query_id: QueryId,
+) -> Result<(), BoxError> {
+    let inputs = match query.inputs {
+        BatchInputs::UrlFileList(ref path) => {
+            inputs_from_url_file(path, query_id, shard_count).map_err(|e| e.to_string())?
+        }
+        BatchInputs::EncryptedDir(ref dir) => {
+            inputs_from_shard_dir(dir, query_id, shard_count).map_err(|e| e.to_string())?
+        }
+        BatchInputs::Encrypted([ref h1, ref h2, ref h3]) => inputs_from_encrypted_inputs(
+            &EncryptedInputs {
+                enc_input_file1: h1.clone(),
+                enc_input_file2: h2.clone(),
+                enc_input_file3: h3.clone(),
+            },
+            query_id,
+            shard_count,
+        ),
+    };
+    let count = usize::try_from(query.count)?;
+
+    // the value for histogram values (BA32) must be kept in sync with the server-side
+    // implementation, otherwise a runtime reconstruct error will be generated.
+    let written = match query.kind {
+        BatchQueryKind::MaliciousHybrid | BatchQueryKind::SemiHonestHybrid => {
+            let actual = run_hybrid_query_and_validate::<BA32>(
+                inputs,
+                count,
+                helper_clients,
+                query.params,
+                None,
+            )
+            .await;
+            write_hybrid_output_file(&query.output, &actual)
+        }
+        BatchQueryKind::MaliciousReachFrequency => {
+            let actual = run_reach_frequency_query::<BA32>(
+                inputs,
+                count,
+                helper_clients,
+                query.params,
+                None,
+            )
+            .await;
+            write_hybrid_output_file(&query.output, &actual)
+        }
+        BatchQueryKind::MaliciousConversionLift => {
+            let actual = run_conversion_lift_query::<BA32>(
+                inputs,
+                count,
+                helper_clients,
+                query.params,
+                None,
+            )
+            .await;
+            write_hybrid_output_file(&query.output, &actual)
+        }
+    };
+
+    Ok(written.map_err(|e| e.to_string())?)
+}
+
 async fn hybrid<F: FnOnce(QueryId) -> Result<Vec<[QueryInput; 3]>, Box<dyn Error>>>(
     args: &Args,
+    security_model: IpaSecurityModel,
     hybrid_query_config: HybridQueryParams,
     helper_clients: Vec<[IpaHttpClient<Helper>; 3]>,
     make_inputs_fn: F,
     count: usize,
-    set_fixed_polling_ms: Option<u64>,
+    status_refresh_ms: Option<u64>,
 ) -> Result<(), Box<dyn Error>> {
-    let query_type = QueryType::MaliciousHybrid(hybrid_query_config);
+    let query_type = match security_model {
+        IpaSecurityModel::SemiHonest => QueryType::SemiHonestHybrid(hybrid_query_config),
+        IpaSecurityModel::Malicious => QueryType::MaliciousHybrid(hybrid_query_config),
+    };
 
     let query_config = QueryConfig {
         size: QuerySize::try_from(count).unwrap(),
@@ -561,20 +991,98 @@ async fn hybrid<F: FnOnce(QueryId) -> Result<Vec<[QueryInput; 3]>, Box<dyn Error
     // the value for histogram values (BA32) must be kept in sync with the server-side
     // implementation, otherwise a runtime reconstruct error will be generated.
     // see ipa-core/src/query/executor.rs
+    let metric_clients = helper_clients.clone();
     let actual = run_hybrid_query_and_validate::<BA32>(
         submissions,
         count,
         helper_clients,
         hybrid_query_config,
-        set_fixed_polling_ms,
+        status_refresh_ms,
     )
     .await;
+    write_query_output(args, &actual)?;
+    save_calibration(args, query_config, query_id, &metric_clients).await;
+
+    Ok(())
+}
+
+async fn reach_frequency<F: FnOnce(QueryId) -> Result<Vec<[QueryInput; 3]>, Box<dyn Error>>>(
+    args: &Args,
+    hybrid_query_config: HybridQueryParams,
+    helper_clients: Vec<[IpaHttpClient<Helper>; 3]>,
+    make_inputs_fn: F,
+    count: usize,
+    status_refresh_ms: Option<u64>,
+) -> Result<(), Box<dyn Error>> {
+    let query_config = QueryConfig {
+        size: QuerySize::try_from(count).unwrap(),
+        field_type: FieldType::Fp32BitPrime,
+        query_type: QueryType::MaliciousReachFrequency(hybrid_query_config),
+    };
+
+    let query_id = helper_clients[0][0]
+        .create_query(query_config)
+        .await
+        .expect("Unable to create query!");
+
+    tracing::info!("Starting reach and frequency query");
+    let submissions = make_inputs_fn(query_id)?;
+
+    // the value for histogram values (BA32) must be kept in sync with the server-side
+    // implementation, otherwise a runtime reconstruct error will be generated.
+    // see ipa-core/src/query/runner/reach_frequency.rs
+    let metric_clients = helper_clients.clone();
+    let actual = run_reach_frequency_query::<BA32>(
+        submissions,
+        count,
+        helper_clients,
+        hybrid_query_config,
+        status_refresh_ms,
+    )
+    .await;
+    write_query_output(args, &actual)?;
+    save_calibration(args, query_config, query_id, &metric_clients).await;
+
+    Ok(())
+}
+
+async fn conversion_lift<F: FnOnce(QueryId) -> Result<Vec<[QueryInput; 3]>, Box<dyn Error>>>(
+    args: &Args,
+    hybrid_query_config: HybridQueryParams,
+    helper_clients: Vec<[IpaHttpClient<Helper>; 3]>,
+    make_inputs_fn: F,
+    count: usize,
+    status_refresh_ms: Option<u64>,
+) -> Result<(), Box<dyn Error>> {
+    let query_config = QueryConfig {
+        size: QuerySize::try_from(count).unwrap(),
+        field_type: FieldType::Fp32BitPrime,
+        query_type: QueryType::MaliciousConversionLift(hybrid_query_config),
+    };
+
+    let query_id = helper_clients[0][0]
+        .create_query(query_config)
+        .await
+        .expect("Unable to create query!");
+
+    tracing::info!("Starting conversion lift query");
+    let submissions = make_inputs_fn(query_id)?;
+
+    // the value for histogram values (BA32) must be kept in sync with the server-side
+    // implementation, otherwise a runtime reconstruct error will be generated.
+    // see ipa-core/src/query/runner/conversion_lift.rs
+    let metric_clients = helper_clients.clone();
+    let actual = run_conversion_lift_query::<BA32>(
+        submissions,
+        count,
+        helper_clients,
+        hybrid_query_config,
+        status_refresh_ms,
+    )
+    .await;
+    write_query_output(args, &actual)?;
+    save_calibration(args, query_config, query_id, &metric_clients).await;
 
-    if let Some(ref path) = args.output_file {
-        write_hybrid_output_file(path, &actual)?;
-    } else {
-        println!("{}", serde_json::to_string_pretty(&actual)?);
-    }
     Ok(())
 }
 
@@ -618,12 +1126,15 @@ async fn ipa(
         ipa_query_config,
     )
     .await;
+    write_query_output(args, &actual)?;
+    save_calibration(
+        args,
+        query_config,
+        query_id,
+        slice::from_ref(helper_clients),
+    )
+    .await;
 
-    if let Some(ref path) = args.output_file {
-        write_ipa_output_file(path, &actual)?;
-    } else {
-        println!("{}", serde_json::to_string_pretty(&actual)?);
-    }
     Ok(())
 }
 
@@ -681,10 +1192,16 @@ async fn ipa_test(
         Some((DEFAULT_KEY_ID, key_registries.each_ref())),
     )
     .await;
-
-    if let Some(ref path) = args.output_file {
-        write_ipa_output_file(path, &actual)?;
+    if args.output_file.is_some() {
+        write_query_output(args, &actual)?;
     }
+    save_calibration(
+        args,
+        query_config,
+        query_id,
+        slice::from_ref(helper_clients),
+    )
+    .await;
 
     tracing::info!("{m:?}", m = ipa_query_config);
 
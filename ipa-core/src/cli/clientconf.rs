@@ -66,7 +66,7 @@ pub fn setup(args: ConfGenArgs) -> Result<(), BoxError> {
                 host: host.to_string(),
                 port,
                 shard_port,
-                tls_cert_file: args.keys_dir.helper_tls_cert(id),
+                tls_cert_file: Some(args.keys_dir.helper_tls_cert(id)),
                 mk_public_key_file: args.keys_dir.helper_mk_public_key(id),
             }
         })
@@ -167,7 +167,7 @@ fn create_sharded_conf_from_files(
                 host: host_name,
                 port,
                 shard_port,
-                tls_cert_file,
+                tls_cert_file: Some(tls_cert_file),
                 mk_public_key_file,
             }
         })
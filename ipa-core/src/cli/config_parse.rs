@@ -39,7 +39,8 @@ pub struct HelperClientConf {
     pub(crate) host: String,
     pub(crate) port: u16,
     pub(crate) shard_port: u16,
-    pub(crate) tls_cert_file: PathBuf,
+    /// Helpers without a TLS certificate are reached over plain HTTP.
+    pub(crate) tls_cert_file: Option<PathBuf>,
     pub(crate) mk_public_key_file: PathBuf,
 }
 
@@ -130,12 +131,14 @@ pub fn gen_client_config<I: IntoIterator<Item = HelperClientConf>>(
 ) -> Result<(), BoxError> {
     let mut peers = Vec::<Value>::new();
     for client_conf in clients_conf {
-        let certificate = fs::read_to_string(&client_conf.tls_cert_file).map_err(|e| {
-            format!(
-                "Failed to open {}: {e}",
-                client_conf.tls_cert_file.display()
-            )
-        })?;
+        let certificate = client_conf
+            .tls_cert_file
+            .as_ref()
+            .map(|path| {
+                fs::read_to_string(path)
+                    .map_err(|e| format!("Failed to open {}: {e}", path.display()))
+            })
+            .transpose()?;
         let mk_public_key = fs::read_to_string(&client_conf.mk_public_key_file).map_err(|e| {
             format!(
                 "Failed to open {}: {e}",
@@ -145,11 +148,12 @@ pub fn gen_client_config<I: IntoIterator<Item = HelperClientConf>>(
 
         // Constructing toml directly because it avoids linking
         // a PEM library to serialize the certificate.
+        let scheme = if certificate.is_some() { "" } else { "http://" };
         let mut peer = Map::new();
         peer.insert(
             String::from("url"),
             Value::String(format!(
-                "{host}:{port}",
+                "{scheme}{host}:{port}",
                 host = client_conf.host,
                 port = client_conf.port
             )),
@@ -157,12 +161,14 @@ pub fn gen_client_config<I: IntoIterator<Item = HelperClientConf>>(
         peer.insert(
             String::from("shard_url"),
             Value::String(format!(
-                "{host}:{port}",
+                "{scheme}{host}:{port}",
                 host = client_conf.host,
                 port = client_conf.shard_port
             )),
         );
-        peer.insert(String::from("certificate"), Value::String(certificate));
+        if let Some(certificate) = certificate {
+            peer.insert(String::from("certificate"), Value::String(certificate));
+        }
         peer.insert(
             String::from("hpke"),
             Value::Table(encode_hpke(mk_public_key)),
@@ -223,14 +229,18 @@ fn assert_network_config(config_toml: &Map<String, Value>, config_str: &str) {
 ///
 /// [`PeerConfig`]: PeerConfig
 fn assert_peer_config(expected: &Value, actual: &ShardedPeerConfigToml) {
-    assert_eq!(
-        expected.get("url").unwrap().as_str(),
-        Some(actual.config.url.to_string()).as_deref()
-    );
-    assert_eq!(
-        expected.get("shard_url").unwrap().as_str(),
-        Some(actual.shard_url.as_ref().unwrap().to_string()).as_deref()
-    );
+    // URIs with a scheme are displayed with a trailing slash, compare them parsed.
+    let uri = |name: &str| -> Uri {
+        expected
+            .get(name)
+            .unwrap()
+            .as_str()
+            .unwrap()
+            .parse()
+            .unwrap()
+    };
+    assert_eq!(uri("url"), actual.config.url);
+    assert_eq!(&uri("shard_url"), actual.shard_url.as_ref().unwrap());
 
     assert_hpke_config(
         expected.get("hpke").expect("hpke section must be present"),
@@ -104,68 +104,107 @@ impl HybridDecryptArgs {
         for (dec_report1, (dec_report2, dec_report3)) in
             decrypted_reports1.zip(decrypted_reports2.zip(decrypted_reports3))
         {
-            match (dec_report1, dec_report2, dec_report3) {
-                (
-                    HybridReport::Impression(impression_report1),
-                    HybridReport::Impression(impression_report2),
-                    HybridReport::Impression(impression_report3),
-                ) => {
-                    let match_key = [
-                        impression_report1.match_key,
-                        impression_report2.match_key,
-                        impression_report3.match_key,
-                    ]
-                    .reconstruct()
-                    .as_u128();
-
-                    let breakdown_key = [
-                        impression_report1.breakdown_key,
-                        impression_report2.breakdown_key,
-                        impression_report3.breakdown_key,
-                    ]
-                    .reconstruct()
-                    .as_u128();
-                    let key_id = impression_report1.info.key_id;
-
-                    writeln!(writer, "i,{match_key},{breakdown_key},{key_id}")?;
-                }
-                (
-                    HybridReport::Conversion(conversion_report1),
-                    HybridReport::Conversion(conversion_report2),
-                    HybridReport::Conversion(conversion_report3),
-                ) => {
-                    let match_key = [
-                        conversion_report1.match_key,
-                        conversion_report2.match_key,
-                        conversion_report3.match_key,
-                    ]
-                    .reconstruct()
-                    .as_u128();
-
-                    let value = [
-                        conversion_report1.value,
-                        conversion_report2.value,
-                        conversion_report3.value,
-                    ]
-                    .reconstruct()
-                    .as_u128();
-                    let key_id = conversion_report1.info.key_id;
-                    let conversion_site_domain = conversion_report1.info.conversion_site_domain;
-                    let timestamp = conversion_report1.info.timestamp;
-                    let epsilon = conversion_report1.info.epsilon;
-                    let sensitivity = conversion_report1.info.sensitivity;
-                    writeln!(writer, "c,{match_key},{value},{key_id},{conversion_site_domain},{timestamp},{epsilon},{sensitivity}")?;
-                }
-                _ => {
-                    panic!("Reports are not all the same type");
-                }
-            }
+            write_reconstructed_report(&mut writer, [dec_report1, dec_report2, dec_report3])?;
         }
 
         Ok(())
     }
 }
 
+/// Reconstructs one report from the shares of all three helpers and writes it to `writer` in
+/// the same format that the input generator uses.
+///
+/// # Panics
+/// If the reports are not all of the same type.
+fn write_reconstructed_report<W: Write>(
+    writer: &mut W,
+    reports: [HybridReport<BA8, BA3>; 3],
+) -> Result<(), BoxError> {
+    match reports {
+        [HybridReport::Impression(impression_report1), HybridReport::Impression(impression_report2), HybridReport::Impression(impression_report3)] =>
+        {
+            let match_key = [
+                impression_report1.match_key,
+                impression_report2.match_key,
+                impression_report3.match_key,
+            ]
+            .reconstruct()
+            .as_u128();
+
+            let breakdown_key = [
+                impression_report1.breakdown_key,
+                impression_report2.breakdown_key,
+                impression_report3.breakdown_key,
+            ]
+            .reconstruct()
+            .as_u128();
+            let key_id = impression_report1.info.key_id;
+
+            writeln!(writer, "i,{match_key},{breakdown_key},{key_id}")?;
+        }
+        [HybridReport::Conversion(conversion_report1), HybridReport::Conversion(conversion_report2), HybridReport::Conversion(conversion_report3)] =>
+        {
+            let match_key = [
+                conversion_report1.match_key,
+                conversion_report2.match_key,
+                conversion_report3.match_key,
+            ]
+            .reconstruct()
+            .as_u128();
+
+            let value = [
+                conversion_report1.value,
+                conversion_report2.value,
+                conversion_report3.value,
+            ]
+            .reconstruct()
+            .as_u128();
+            let key_id = conversion_report1.info.key_id;
+            let conversion_site_domain = conversion_report1.info.conversion_site_domain;
+            let timestamp = conversion_report1.info.timestamp;
+            let epsilon = conversion_report1.info.epsilon;
+            let sensitivity = conversion_report1.info.sensitivity;
+            writeln!(writer, "c,{match_key},{value},{key_id},{conversion_site_domain},{timestamp},{epsilon},{sensitivity}")?;
+        }
+        [HybridReport::LiftImpression(lift_impression_report1), HybridReport::LiftImpression(lift_impression_report2), HybridReport::LiftImpression(lift_impression_report3)] =>
+        {
+            let match_key = [
+                lift_impression_report1.match_key,
+                lift_impression_report2.match_key,
+                lift_impression_report3.match_key,
+            ]
+            .reconstruct()
+            .as_u128();
+
+            let breakdown_key = [
+                lift_impression_report1.breakdown_key,
+                lift_impression_report2.breakdown_key,
+                lift_impression_report3.breakdown_key,
+            ]
+            .reconstruct()
+            .as_u128();
+            let test_group = [
+                lift_impression_report1.test_group,
+                lift_impression_report2.test_group,
+                lift_impression_report3.test_group,
+            ]
+            .reconstruct()
+            .as_u128();
+            let key_id = lift_impression_report1.info.key_id;
+
+            writeln!(
+                writer,
+                "l,{match_key},{breakdown_key},{key_id},{test_group}"
+            )?;
+        }
+        _ => {
+            panic!("Reports are not all the same type");
+        }
+    }
+
+    Ok(())
+}
+
 struct DecryptedHybridReports {
     reader: BufReader<File>,
     key_registry: KeyRegistry<PrivateKeyOnly>,
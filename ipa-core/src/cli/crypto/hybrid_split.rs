@@ -0,0 +1,335 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, Read, Write},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+};
+
+use clap::{Parser, ValueEnum};
+
+use crate::{
+    cli::{crypto::hybrid_validate::next_record, playbook::shard_input_file_name},
+    error::BoxError,
+};
+
+#[derive(Debug, Parser)]
+#[clap(
+    name = "hybrid_split",
+    about = "Split encrypted hybrid reports into per-shard files"
+)]
+#[command(about)]
+pub struct HybridSplitArgs {
+    /// Path to helper1 encrypted file
+    #[arg(long)]
+    input_file1: PathBuf,
+
+    /// Path to helper2 encrypted file
+    #[arg(long)]
+    input_file2: PathBuf,
+
+    /// Path to helper3 encrypted file
+    #[arg(long)]
+    input_file3: PathBuf,
+
+    /// The destination dir for per-shard files. In that dir, it will create
+    /// `helper{1,2,3}_shard_{NNN}.bin` files with length delimited binary reports,
+    /// that can be passed to the report collector with `--enc-input-dir`.
+    #[arg(long, value_name = "DIR")]
+    output_dir: PathBuf,
+
+    /// Number of shards to split the input into
+    #[arg(long)]
+    shards: NonZeroUsize,
+
+    /// How records are assigned to shards
+    #[arg(long, value_enum, default_value_t = SplitStrategy::RoundRobin)]
+    strategy: SplitStrategy,
+
+    /// a flag to read length delimited binary instead of newline delimited hex
+    #[arg(long)]
+    length_delimited: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SplitStrategy {
+    /// Record `i` goes to shard `i % shards`.
+    RoundRobin,
+    /// Each shard gets a contiguous range of records of roughly the same size in bytes.
+    BySize,
+}
+
+impl HybridSplitArgs {
+    #[must_use]
+    pub fn new(
+        input_files: [&Path; 3],
+        output_dir: &Path,
+        shards: NonZeroUsize,
+        strategy: SplitStrategy,
+        length_delimited: bool,
+    ) -> Self {
+        let [input_file1, input_file2, input_file3] = input_files.map(Path::to_path_buf);
+        Self {
+            input_file1,
+            input_file2,
+            input_file3,
+            output_dir: output_dir.to_path_buf(),
+            shards,
+            strategy,
+            length_delimited,
+        }
+    }
+
+    /// Splits the input files of all helpers into per-shard files. Every shard gets the
+    /// same records for all three helpers, so the shares stay aligned.
+    ///
+    /// Only the framing of the records is checked, use `crypto_util validate` to check
+    /// their contents.
+    ///
+    /// # Errors
+    /// If the input files cannot be read or don't contain the same number of records,
+    /// or the output files cannot be written.
+    pub fn split(&self) -> Result<(), BoxError> {
+        let shards = self.shards.get();
+        let input_files = [&self.input_file1, &self.input_file2, &self.input_file3];
+        let total_size = std::fs::metadata(&self.input_file1)?.len();
+        let mut readers = input_files
+            .map(|path| {
+                File::open(path)
+                    .map(|file| CountingReader::new(BufReader::new(file)))
+                    .map_err(|e| format!("unable to open file {}. {e}", path.display()))
+            })
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut writers = (1..=3)
+            .map(|helper| {
+                (0..shards)
+                    .map(|shard| {
+                        let path = self.output_dir.join(shard_input_file_name(helper, shard));
+                        OpenOptions::new()
+                            .write(true)
+                            .create_new(true)
+                            .open(&path)
+                            .map(BufWriter::new)
+                            .map_err(|e| format!("unable to write to {}. {e}", path.display()))
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut shard_sizes = vec![0_usize; shards];
+        for index in 0.. {
+            // Decided by the position in the first file, so that all helpers agree.
+            let shard = match self.strategy {
+                SplitStrategy::RoundRobin => index % shards,
+                SplitStrategy::BySize => {
+                    let shard = u128::from(readers[0].count) * u128::try_from(shards)?
+                        / u128::from(total_size.max(1));
+                    usize::try_from(shard)?.min(shards - 1)
+                }
+            };
+
+            let mut records = Vec::with_capacity(3);
+            for (helper, reader) in readers.iter_mut().enumerate() {
+                let record = next_record(reader, self.length_delimited)
+                    .map_err(|e| format!("{}: {e}", input_files[helper].display()))?
+                    .map(|record| {
+                        record.map_err(|e| {
+                            format!("{}: record {index}: {e}", input_files[helper].display())
+                        })
+                    })
+                    .transpose()?;
+                records.push(record);
+            }
+
+            if records.iter().all(Option::is_none) {
+                break;
+            }
+            let Some(records) = records.into_iter().collect::<Option<Vec<_>>>() else {
+                return Err(format!(
+                    "input files do not contain the same number of records, \
+                     some of them end after {index} records"
+                )
+                .into());
+            };
+            for (record, writer) in records.into_iter().zip(&mut writers) {
+                writer[shard].write_all(&u16::try_from(record.len())?.to_le_bytes())?;
+                writer[shard].write_all(&record)?;
+            }
+            shard_sizes[shard] += 1;
+        }
+
+        for writer in writers.iter_mut().flatten() {
+            writer.flush()?;
+        }
+        for (shard, size) in shard_sizes.iter().enumerate() {
+            tracing::info!("shard {shard}: {size} records");
+        }
+
+        Ok(())
+    }
+}
+
+/// Keeps track of how many bytes were read from the inner reader.
+struct CountingReader<R> {
+    inner: R,
+    count: u64,
+}
+
+impl<R> CountingReader<R> {
+    fn new(inner: R) -> Self {
+        Self { inner, count: 0 }
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.count += read as u64;
+        Ok(read)
+    }
+}
+
+impl<R: BufRead> BufRead for CountingReader<R> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.count += amt as u64;
+        self.inner.consume(amt);
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use std::{fs, num::NonZeroUsize, path::Path};
+
+    use tempfile::tempdir;
+
+    use crate::cli::{
+        crypto::{
+            hybrid_encrypt::HybridEncryptArgs,
+            hybrid_sample_data,
+            hybrid_split::{HybridSplitArgs, SplitStrategy},
+            hybrid_validate::HybridValidateArgs,
+        },
+        playbook::shard_input_file_name,
+    };
+
+    const RECORDS: usize = 20;
+
+    /// Returns the number of length delimited records in the file.
+    fn record_count(path: &Path) -> usize {
+        let data = fs::read(path).unwrap();
+        let mut pos = 0;
+        let mut count = 0;
+        while pos < data.len() {
+            pos += 2 + usize::from(u16::from_le_bytes([data[pos], data[pos + 1]]));
+            count += 1;
+        }
+        assert_eq!(pos, data.len());
+        count
+    }
+
+    fn split(length_delimited: bool, strategy: SplitStrategy) -> Vec<usize> {
+        let input_file =
+            hybrid_sample_data::write_csv(hybrid_sample_data::test_hybrid_data().take(RECORDS))
+                .unwrap();
+        let enc_dir = tempdir().unwrap();
+        let network_file = hybrid_sample_data::test_keys().network_config();
+        HybridEncryptArgs::new(
+            input_file.path(),
+            enc_dir.path(),
+            network_file.path(),
+            length_delimited,
+        )
+        .encrypt()
+        .unwrap();
+        let inputs = [1, 2, 3].map(|helper| enc_dir.path().join(format!("helper{helper}.enc")));
+
+        let output_dir = tempdir().unwrap();
+        HybridSplitArgs::new(
+            inputs.each_ref().map(|p| p.as_path()),
+            output_dir.path(),
+            NonZeroUsize::new(3).unwrap(),
+            strategy,
+            length_delimited,
+        )
+        .split()
+        .unwrap();
+
+        let mut shard_sizes = Vec::new();
+        for shard in 0..3 {
+            let [h1, h2, h3] = [1, 2, 3]
+                .map(|helper| output_dir.path().join(shard_input_file_name(helper, shard)));
+            let size = record_count(&h1);
+            assert_eq!(size, record_count(&h2));
+            assert_eq!(size, record_count(&h3));
+            HybridValidateArgs::new(&h1, &h2, &h3, network_file.path(), true, None)
+                .validate()
+                .unwrap();
+            shard_sizes.push(size);
+        }
+
+        shard_sizes
+    }
+
+    #[test]
+    fn round_robin() {
+        for length_delimited in [false, true] {
+            assert_eq!(
+                vec![7, 7, 6],
+                split(length_delimited, SplitStrategy::RoundRobin)
+            );
+        }
+    }
+
+    #[test]
+    fn by_size() {
+        for length_delimited in [false, true] {
+            let shard_sizes = split(length_delimited, SplitStrategy::BySize);
+            assert_eq!(RECORDS, shard_sizes.iter().sum::<usize>());
+            assert!(shard_sizes.iter().all(|&size| size > 0), "{shard_sizes:?}");
+        }
+    }
+
+    #[test]
+    fn misaligned_inputs() {
+        let input_file =
+            hybrid_sample_data::write_csv(hybrid_sample_data::test_hybrid_data().take(RECORDS))
+                .unwrap();
+        let enc_dir = tempdir().unwrap();
+        let network_file = hybrid_sample_data::test_keys().network_config();
+        HybridEncryptArgs::new(
+            input_file.path(),
+            enc_dir.path(),
+            network_file.path(),
+            false,
+        )
+        .encrypt()
+        .unwrap();
+        let inputs = [1, 2, 3].map(|helper| enc_dir.path().join(format!("helper{helper}.enc")));
+        let data = fs::read_to_string(&inputs[1]).unwrap();
+        fs::write(
+            &inputs[1],
+            data.lines().skip(1).collect::<Vec<_>>().join("\n"),
+        )
+        .unwrap();
+
+        let output_dir = tempdir().unwrap();
+        let err = HybridSplitArgs::new(
+            inputs.each_ref().map(|p| p.as_path()),
+            output_dir.path(),
+            NonZeroUsize::new(2).unwrap(),
+            SplitStrategy::RoundRobin,
+            false,
+        )
+        .split()
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("do not contain the same number of records"),
+            "{err}"
+        );
+    }
+}
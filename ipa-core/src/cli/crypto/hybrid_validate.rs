@@ -0,0 +1,598 @@
+use std::{
+    fs::{read_to_string, File},
+    io::{self, BufRead, BufReader, ErrorKind, Read},
+    path::{Path, PathBuf},
+};
+
+use bytes::Bytes;
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cli::{
+        config_parse::HelperNetworkConfigParseExt,
+        playbook::{BreakdownKey, TriggerValue},
+    },
+    config::{KeyRegistries, NetworkConfig},
+    error::BoxError,
+    hpke::{KeyRegistry, PublicKeyOnly, PublicKeyRegistry},
+    report::hybrid::{EncryptedHybridReport, HybridEventType, UniqueTagValidator},
+};
+
+/// Maximum number of record errors listed for each file in the validation report.
+/// Errors past this limit are still counted.
+const MAX_LISTED_ERRORS: usize = 100;
+
+/// Maximum size of a single encrypted report. Reports are submitted to helpers with a
+/// 16 bit length prefix.
+const MAX_REPORT_SIZE: usize = 1 << 16;
+
+#[derive(Debug, Parser)]
+#[clap(
+    name = "hybrid_validate",
+    about = "Validate encrypted hybrid reports before submitting them to helpers"
+)]
+#[command(about)]
+pub struct HybridValidateArgs {
+    /// Path to helper1 encrypted file
+    #[arg(long)]
+    input_file1: PathBuf,
+
+    /// Path to helper2 encrypted file
+    #[arg(long)]
+    input_file2: PathBuf,
+
+    /// Path to helper3 encrypted file
+    #[arg(long)]
+    input_file3: PathBuf,
+
+    /// Path to helper network configuration file. Public keys listed there
+    /// are used to check key identifiers of encrypted reports.
+    #[arg(long)]
+    network: PathBuf,
+
+    /// a flag to read length delimited binary instead of newline delimited hex
+    #[arg(long)]
+    length_delimited: bool,
+
+    /// The destination file for the validation report. If not set, the report
+    /// is printed to stdout.
+    #[arg(long, value_name = "FILE")]
+    output_file: Option<PathBuf>,
+}
+
+/// Result of validating encrypted input files for all three helpers.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ValidationReport {
+    /// Validation results for each helper file, in helper order.
+    pub files: Vec<FileValidation>,
+    /// Set if helper files do not contain the same number of records.
+    pub record_count_mismatch: bool,
+    /// Number of records whose event type is not the same in all helper files.
+    pub event_type_mismatches: usize,
+    /// Index of the first record whose event type is not the same in all helper files.
+    pub first_event_type_mismatch: Option<usize>,
+}
+
+impl ValidationReport {
+    #[must_use]
+    pub fn is_valid(&self) -> bool {
+        !self.record_count_mismatch
+            && self.event_type_mismatches == 0
+            && self.files.iter().all(FileValidation::is_valid)
+    }
+}
+
+/// Validation result for a single helper file.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileValidation {
+    pub path: PathBuf,
+    /// Total number of records read, including invalid ones.
+    pub records: usize,
+    /// Number of impressions, including lift impressions.
+    pub impressions: usize,
+    pub conversions: usize,
+    /// Set if the file could not be split into records, e.g. because it was
+    /// truncated in the middle of a length-delimited record. Records past that point
+    /// are not validated.
+    pub framing_error: Option<String>,
+    /// Number of records that could not be parsed as encrypted hybrid reports.
+    pub malformed_records: usize,
+    /// Number of records encrypted with a key that is not known to the helper.
+    pub unknown_key_ids: usize,
+    /// Number of records whose unique tag has been seen before in this file.
+    pub duplicate_tags: usize,
+    /// Errors for individual records, up to [`MAX_LISTED_ERRORS`].
+    pub errors: Vec<RecordError>,
+}
+
+impl FileValidation {
+    #[must_use]
+    pub fn is_valid(&self) -> bool {
+        self.framing_error.is_none()
+            && self.malformed_records == 0
+            && self.unknown_key_ids == 0
+            && self.duplicate_tags == 0
+    }
+
+    fn record_error(&mut self, record: usize, error: String) {
+        if self.errors.len() < MAX_LISTED_ERRORS {
+            self.errors.push(RecordError { record, error });
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecordError {
+    /// Zero-based index of the record in the file.
+    pub record: usize,
+    pub error: String,
+}
+
+impl HybridValidateArgs {
+    #[must_use]
+    pub fn new(
+        input_file1: &Path,
+        input_file2: &Path,
+        input_file3: &Path,
+        network: &Path,
+        length_delimited: bool,
+        output_file: Option<&Path>,
+    ) -> Self {
+        Self {
+            input_file1: input_file1.to_path_buf(),
+            input_file2: input_file2.to_path_buf(),
+            input_file3: input_file3.to_path_buf(),
+            network: network.to_path_buf(),
+            length_delimited,
+            output_file: output_file.map(Path::to_path_buf),
+        }
+    }
+
+    /// Validates the encrypted input files and writes the validation report.
+    ///
+    /// # Errors
+    /// If any of the files cannot be read, the network file is invalid, or any
+    /// problems were found in the encrypted inputs.
+    pub fn validate(&self) -> Result<(), BoxError> {
+        let report = self.check()?;
+
+        let json = serde_json::to_string_pretty(&report)?;
+        if let Some(output_file) = &self.output_file {
+            std::fs::write(output_file, json)?;
+        } else {
+            println!("{json}");
+        }
+
+        if report.is_valid() {
+            Ok(())
+        } else {
+            Err("encrypted input files failed validation".into())
+        }
+    }
+
+    fn check(&self) -> Result<ValidationReport, BoxError> {
+        let network =
+            NetworkConfig::from_toml_str_sharded(&read_to_string(&self.network).map_err(|e| {
+                format!(
+                    "Failed to open network file: {}. {e}",
+                    self.network.display()
+                )
+            })?)?;
+        let key_registries = KeyRegistries::default()
+            .init_from(&network[0])
+            .ok_or("network file does not contain public keys for all helpers")?;
+
+        let mut files = Vec::with_capacity(3);
+        let mut event_types = Vec::with_capacity(3);
+        for (path, key_registry) in [&self.input_file1, &self.input_file2, &self.input_file3]
+            .into_iter()
+            .zip(&key_registries)
+        {
+            let file = File::open(path)
+                .map_err(|e| format!("unable to open file {}. {e}", path.display()))?;
+            let (validation, types) = validate_file(
+                path,
+                BufReader::new(file),
+                self.length_delimited,
+                key_registry,
+            )?;
+            files.push(validation);
+            event_types.push(types);
+        }
+
+        let record_count_mismatch = files.windows(2).any(|w| w[0].records != w[1].records);
+        let mut event_type_mismatches = 0;
+        let mut first_event_type_mismatch = None;
+        for (i, ((t1, t2), t3)) in event_types[0]
+            .iter()
+            .zip(&event_types[1])
+            .zip(&event_types[2])
+            .enumerate()
+        {
+            // Malformed records are already reported for the file they belong to.
+            if let (Some(t1), Some(t2), Some(t3)) = (t1, t2, t3) {
+                if t1 != t2 || t2 != t3 {
+                    event_type_mismatches += 1;
+                    first_event_type_mismatch.get_or_insert(i);
+                }
+            }
+        }
+
+        Ok(ValidationReport {
+            files,
+            record_count_mismatch,
+            event_type_mismatches,
+            first_event_type_mismatch,
+        })
+    }
+}
+
+/// Validates all records in one helper's file. In addition to the validation result,
+/// returns the event type of every record (or `None` for malformed ones) so that
+/// the files can be compared against each other.
+fn validate_file<R: BufRead>(
+    path: &Path,
+    mut reader: R,
+    length_delimited: bool,
+    key_registry: &KeyRegistry<PublicKeyOnly>,
+) -> Result<(FileValidation, Vec<Option<HybridEventType>>), io::Error> {
+    let mut validation = FileValidation {
+        path: path.to_path_buf(),
+        records: 0,
+        impressions: 0,
+        conversions: 0,
+        framing_error: None,
+        malformed_records: 0,
+        unknown_key_ids: 0,
+        duplicate_tags: 0,
+        errors: Vec::new(),
+    };
+    let mut event_types = Vec::new();
+    let mut unique_tags = UniqueTagValidator::new(0);
+
+    loop {
+        let record = match next_record(&mut reader, length_delimited) {
+            Ok(Some(record)) => record,
+            Ok(None) => break,
+            Err(RecordReadError::Io(e)) => return Err(e),
+            Err(RecordReadError::Framing(e)) => {
+                validation.framing_error = Some(e);
+                break;
+            }
+        };
+        let index = validation.records;
+        validation.records += 1;
+
+        let report = record
+            .and_then(|bytes| {
+                EncryptedHybridReport::<BreakdownKey, TriggerValue>::from_bytes(bytes)
+                    .map_err(|e| e.to_string())
+            })
+            .and_then(|report| {
+                report.validate_info().map_err(|e| e.to_string())?;
+                Ok(report)
+            });
+        let report = match report {
+            Ok(report) => report,
+            Err(e) => {
+                validation.malformed_records += 1;
+                validation.record_error(index, e);
+                event_types.push(None);
+                continue;
+            }
+        };
+
+        let event_type = match report {
+            EncryptedHybridReport::Impression(_) => {
+                validation.impressions += 1;
+                HybridEventType::Impression
+            }
+            EncryptedHybridReport::Conversion(_) => {
+                validation.conversions += 1;
+                HybridEventType::Conversion
+            }
+            EncryptedHybridReport::LiftImpression(_) => {
+                validation.impressions += 1;
+                HybridEventType::LiftImpression
+            }
+        };
+        event_types.push(Some(event_type));
+
+        if key_registry.public_key(report.key_id()).is_none() {
+            validation.unknown_key_ids += 1;
+            validation.record_error(index, format!("unknown key id: {}", report.key_id()));
+        }
+        if unique_tags.check_duplicate(&report).is_err() {
+            validation.duplicate_tags += 1;
+            validation.record_error(index, "duplicate unique tag".to_string());
+        }
+    }
+
+    Ok((validation, event_types))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(super) enum RecordReadError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// The input cannot be split into records past this point.
+    #[error("{0}")]
+    Framing(String),
+}
+
+/// Reads the next record from the input. The outer result fails if no further records
+/// can be read, the inner one if only this record could not be decoded.
+pub(super) fn next_record<R: BufRead>(
+    reader: &mut R,
+    length_delimited: bool,
+) -> Result<Option<Result<Bytes, String>>, RecordReadError> {
+    if length_delimited {
+        let mut len = [0_u8; 2];
+        match reader.read(&mut len[..1]).map_err(RecordReadError::Io)? {
+            0 => return Ok(None),
+            _ => read_exact(reader, &mut len[1..], "record length")?,
+        }
+        let mut record = vec![0_u8; usize::from(u16::from_le_bytes(len))];
+        read_exact(reader, &mut record, "record")?;
+
+        Ok(Some(Ok(Bytes::from(record))))
+    } else {
+        let mut line = Vec::new();
+        if reader
+            .read_until(b'\n', &mut line)
+            .map_err(RecordReadError::Io)?
+            == 0
+        {
+            return Ok(None);
+        }
+        if line.last() == Some(&b'\n') {
+            line.pop();
+        }
+
+        Ok(Some(
+            hex::decode(&line)
+                .map_err(|e| format!("invalid hex encoding: {e}"))
+                .and_then(|v| {
+                    if v.len() < MAX_REPORT_SIZE {
+                        Ok(Bytes::from(v))
+                    } else {
+                        Err(format!("report size {} is too big", v.len()))
+                    }
+                }),
+        ))
+    }
+}
+
+fn read_exact<R: Read>(reader: &mut R, buf: &mut [u8], what: &str) -> Result<(), RecordReadError> {
+    reader.read_exact(buf).map_err(|e| {
+        if e.kind() == ErrorKind::UnexpectedEof {
+            RecordReadError::Framing(format!(
+                "file ends in the middle of a {what}, expected {} more bytes",
+                buf.len()
+            ))
+        } else {
+            RecordReadError::Io(e)
+        }
+    })
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use std::{
+        fs,
+        io::Write,
+        path::{Path, PathBuf},
+    };
+
+    use rand::thread_rng;
+    use tempfile::{tempdir, NamedTempFile, TempDir};
+
+    use crate::{
+        cli::{
+            crypto::{
+                hybrid_encrypt::HybridEncryptArgs, hybrid_sample_data,
+                hybrid_validate::HybridValidateArgs,
+            },
+            playbook::{BreakdownKey, TriggerValue},
+        },
+        hpke::{KeyPair, KeyRegistry},
+        report::hybrid::HybridReport,
+        secret_sharing::IntoShares,
+        test_fixture::hybrid::TestHybridRecord,
+    };
+
+    const RECORDS: usize = 10;
+
+    struct EncryptedFiles {
+        dir: TempDir,
+        network: NamedTempFile,
+    }
+
+    impl EncryptedFiles {
+        fn new(length_delimited: bool) -> Self {
+            let input_file =
+                hybrid_sample_data::write_csv(hybrid_sample_data::test_hybrid_data().take(RECORDS))
+                    .unwrap();
+            let dir = tempdir().unwrap();
+            let network = hybrid_sample_data::test_keys().network_config();
+            HybridEncryptArgs::new(
+                input_file.path(),
+                dir.path(),
+                network.path(),
+                length_delimited,
+            )
+            .encrypt()
+            .unwrap();
+
+            Self { dir, network }
+        }
+
+        fn file(&self, helper: usize) -> PathBuf {
+            self.dir.path().join(format!("helper{helper}.enc"))
+        }
+
+        fn args(&self, length_delimited: bool) -> HybridValidateArgs {
+            HybridValidateArgs::new(
+                &self.file(1),
+                &self.file(2),
+                &self.file(3),
+                self.network.path(),
+                length_delimited,
+                None,
+            )
+        }
+    }
+
+    fn append_line(path: &Path, line: &[u8]) {
+        let mut file = fs::OpenOptions::new().append(true).open(path).unwrap();
+        file.write_all(line).unwrap();
+        file.write_all(b"\n").unwrap();
+    }
+
+    fn encrypted_hex(report: &HybridReport<BreakdownKey, TriggerValue>, key_id: u8) -> Vec<u8> {
+        let key_registry =
+            KeyRegistry::<KeyPair>::random(usize::from(key_id) + 1, &mut thread_rng());
+        let bytes = report
+            .encrypt(key_id, &key_registry, &mut thread_rng())
+            .unwrap();
+        hex::encode(bytes).into_bytes()
+    }
+
+    #[test]
+    fn valid_files() {
+        for length_delimited in [false, true] {
+            let files = EncryptedFiles::new(length_delimited);
+            let output = files.dir.path().join("report.json");
+            let mut args = files.args(length_delimited);
+            args.output_file = Some(output.clone());
+            args.validate().unwrap();
+
+            let report: super::ValidationReport =
+                serde_json::from_str(&fs::read_to_string(output).unwrap()).unwrap();
+            assert!(report.is_valid(), "{report:?}");
+            for file in &report.files {
+                assert_eq!(RECORDS, file.records);
+                assert_eq!(RECORDS, file.impressions + file.conversions);
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_length_delimited_file() {
+        let files = EncryptedFiles::new(true);
+        let data = fs::read(files.file(2)).unwrap();
+        fs::write(files.file(2), &data[..data.len() - 3]).unwrap();
+
+        let report = files.args(true).check().unwrap();
+        assert!(!report.is_valid());
+        assert!(report.files[0].is_valid());
+        assert!(report.files[1].framing_error.is_some());
+        assert_eq!(RECORDS - 1, report.files[1].records);
+        assert!(report.record_count_mismatch);
+    }
+
+    #[test]
+    fn record_count_mismatch() {
+        let files = EncryptedFiles::new(false);
+        let data = fs::read_to_string(files.file(3)).unwrap();
+        let truncated = data
+            .lines()
+            .take(RECORDS - 1)
+            .collect::<Vec<_>>()
+            .join("\n");
+        fs::write(files.file(3), truncated).unwrap();
+
+        let report = files.args(false).check().unwrap();
+        assert!(report.files.iter().all(super::FileValidation::is_valid));
+        assert_eq!(RECORDS - 1, report.files[2].records);
+        assert!(report.record_count_mismatch);
+        assert!(!report.is_valid());
+        assert!(files.args(false).validate().is_err());
+    }
+
+    #[test]
+    fn duplicate_tags() {
+        let files = EncryptedFiles::new(false);
+        let data = fs::read_to_string(files.file(1)).unwrap();
+        append_line(&files.file(1), data.lines().next().unwrap().as_bytes());
+
+        let report = files.args(false).check().unwrap();
+        assert_eq!(1, report.files[0].duplicate_tags);
+        assert_eq!(RECORDS, report.files[0].errors[0].record);
+        assert!(!report.is_valid());
+    }
+
+    #[test]
+    fn malformed_records() {
+        let files = EncryptedFiles::new(false);
+        append_line(&files.file(1), b"not hex");
+        append_line(&files.file(2), b"03abcdef");
+
+        let [_, mut non_ascii, _] = TestHybridRecord::TestConversion {
+            match_key: 12345,
+            value: 2,
+            key_id: 0,
+            conversion_site_domain: "meta.com".to_string(),
+            timestamp: 100,
+            epsilon: 0.0,
+            sensitivity: 0.0,
+        }
+        .share();
+        let HybridReport::Conversion(ref mut conversion) = non_ascii else {
+            unreachable!()
+        };
+        conversion.info.conversion_site_domain = "mëta.com".to_string();
+        append_line(&files.file(3), &encrypted_hex(&non_ascii, 0));
+
+        let report = files.args(false).check().unwrap();
+        for (file, expected) in
+            report
+                .files
+                .iter()
+                .zip(["invalid hex encoding", "unknown event type", "non-ascii"])
+        {
+            assert_eq!(1, file.malformed_records);
+            assert_eq!(RECORDS, file.errors[0].record);
+            assert!(
+                file.errors[0].error.contains(expected),
+                "{}",
+                file.errors[0].error
+            );
+        }
+        assert!(!report.record_count_mismatch);
+        assert_eq!(0, report.event_type_mismatches);
+    }
+
+    #[test]
+    fn unknown_key_id_and_event_type_mismatch() {
+        let files = EncryptedFiles::new(false);
+        let [impression, _, _] = TestHybridRecord::TestImpression {
+            match_key: 12345,
+            breakdown_key: 2,
+            key_id: 1,
+        }
+        .share();
+        let [_, conversion, _] = TestHybridRecord::TestConversion {
+            match_key: 12345,
+            value: 2,
+            key_id: 1,
+            conversion_site_domain: "meta.com".to_string(),
+            timestamp: 100,
+            epsilon: 0.0,
+            sensitivity: 0.0,
+        }
+        .share();
+        append_line(&files.file(1), &encrypted_hex(&impression, 1));
+        append_line(&files.file(2), &encrypted_hex(&conversion, 1));
+        append_line(&files.file(3), &encrypted_hex(&conversion, 1));
+
+        let report = files.args(false).check().unwrap();
+        for file in &report.files {
+            assert_eq!(1, file.unknown_key_ids);
+            assert!(file.errors[0].error.contains("unknown key id: 1"));
+        }
+        assert_eq!(1, report.event_type_mismatches);
+        assert_eq!(Some(RECORDS), report.first_event_type_mismatch);
+    }
+}
@@ -2,11 +2,15 @@ mod decrypt;
 mod encrypt;
 mod hybrid_decrypt;
 mod hybrid_encrypt;
+mod hybrid_split;
+mod hybrid_validate;
 
 pub use decrypt::DecryptArgs;
 pub use encrypt::EncryptArgs;
 pub use hybrid_decrypt::HybridDecryptArgs;
 pub use hybrid_encrypt::HybridEncryptArgs;
+pub use hybrid_split::{HybridSplitArgs, SplitStrategy};
+pub use hybrid_validate::{FileValidation, HybridValidateArgs, RecordError, ValidationReport};
 
 #[cfg(test)]
 mod sample_data {
@@ -43,6 +43,18 @@ impl Serializer for crate::test_fixture::hybrid::TestHybridRecord {
             } => {
                 write!(buf, "c,{match_key},{value},{key_id},{conversion_site_domain},{timestamp},{epsilon},{sensitivity}")?;
             }
+            crate::test_fixture::hybrid::TestHybridRecord::TestLiftImpression {
+                match_key,
+                breakdown_key,
+                key_id,
+                test_group,
+            } => {
+                write!(
+                    buf,
+                    "l,{match_key},{breakdown_key},{key_id},{}",
+                    u8::from(*test_group)
+                )?;
+            }
         }
 
         Ok(())
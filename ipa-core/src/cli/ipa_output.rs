@@ -1,8 +1,14 @@
-use std::time::Duration;
+use std::{borrow::Cow, time::Duration};
 
 use serde::{Deserialize, Serialize};
 
-use crate::helpers::query::{IpaQueryConfig, QuerySize};
+use crate::{
+    cli::query_output::BreakdownHistograms,
+    error::BoxError,
+    helpers::query::{DpMechanism, IpaQueryConfig, QuerySize},
+    protocol::dp::histogram_noise_std,
+    query::{credit_cap_ss_bits, IPA_BREAKDOWNS},
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct QueryResult {
@@ -15,3 +21,28 @@ pub struct QueryResult {
     pub latency: Duration,
     pub breakdowns: Vec<u32>,
 }
+
+impl BreakdownHistograms for QueryResult {
+    fn histograms(&self) -> Vec<(Cow<'static, str>, Cow<'_, [u32]>)> {
+        vec![("value".into(), self.breakdowns.as_slice().into())]
+    }
+
+    fn noise_std(&self) -> Result<f64, BoxError> {
+        let dp_params = match self.config.with_dp {
+            0 => DpMechanism::NoDp,
+            _ => DpMechanism::DiscreteLaplace {
+                epsilon: self.config.epsilon,
+            },
+        };
+        // Noise is calibrated to `2^SS_BITS` for the `SS_BITS` the runner picks for the credit cap.
+        let ss_bits = credit_cap_ss_bits(self.config.per_user_credit_cap)
+            .ok_or("unsupported per-user credit cap")?;
+        let per_user_credit_cap = 1 << ss_bits;
+
+        Ok(histogram_noise_std(
+            dp_params,
+            per_user_credit_cap,
+            u32::try_from(IPA_BREAKDOWNS)?,
+        )?)
+    }
+}
@@ -99,7 +99,10 @@ pub fn keygen_tls<R: Rng + CryptoRng>(args: &KeygenArgs, rng: &mut R) -> Result<
 }
 
 /// Generates public and private key used for encrypting and decrypting match keys.
-fn keygen_matchkey<R: Rng + CryptoRng>(args: &KeygenArgs, mut rng: &mut R) -> Result<(), BoxError> {
+pub(crate) fn keygen_matchkey<R: Rng + CryptoRng>(
+    args: &KeygenArgs,
+    mut rng: &mut R,
+) -> Result<(), BoxError> {
     let keypair = KeyPair::gen(&mut rng);
 
     if args.mk_public_key.is_some() && args.mk_private_key.is_some() {
@@ -1,11 +1,14 @@
 use std::{io, thread, thread::JoinHandle};
 
 use ipa_metrics::{
-    MetricChannelType, MetricsCollectorController, MetricsCurrentThreadContext, MetricsProducer,
+    MetricChannelType, MetricPartition, MetricsCollectorController, MetricsCurrentThreadContext,
+    MetricsProducer,
 };
 use ipa_metrics_prometheus::PrometheusMetricsExporter;
 use tokio::runtime::Builder;
 
+use crate::telemetry::stats::{Metrics, QueryMetrics};
+
 /// Holds a reference to metrics controller and producer
 pub struct CollectorHandle {
     thread_handle: JoinHandle<()>,
@@ -32,6 +35,28 @@ pub fn install_collector() -> io::Result<CollectorHandle> {
     })
 }
 
+/// Same as [`install_collector`], but metrics are handed over to the collector synchronously
+/// and the current thread reports to this collector, unless it is already connected to
+/// another one. In-memory helpers run on the test thread, so metrics they emit become
+/// visible as soon as the test thread flushes them.
+///
+/// ## Errors
+/// If it fails to start a new thread
+#[cfg(any(test, feature = "test-fixture"))]
+pub fn install_test_collector() -> io::Result<CollectorHandle> {
+    let (producer, controller, handle) =
+        ipa_metrics::install_new_thread(MetricChannelType::Rendezvous)?;
+    if !MetricsCurrentThreadContext::is_connected() {
+        producer.install();
+    }
+
+    Ok(CollectorHandle {
+        thread_handle: handle,
+        controller,
+        producer,
+    })
+}
+
 impl Drop for CollectorHandle {
     fn drop(&mut self) {
         if !thread::panicking() && !self.thread_handle.is_finished() {
@@ -61,10 +86,30 @@ impl CollectorHandle {
     /// If metrics is not initialized
     #[must_use]
     pub fn scrape_metrics(&self) -> Vec<u8> {
-        let mut store = self.controller.snapshot().expect("Metrics must be set up");
+        // queries record metrics inside their own partitions, but scraper needs
+        // to see all of them.
+        let mut store = self
+            .controller
+            .snapshot()
+            .expect("Metrics must be set up")
+            .flatten();
         let mut buff = Vec::new();
         store.export(&mut buff);
 
         buff
     }
+
+    /// Returns metrics recorded inside the given partition. If nothing has been
+    /// recorded there yet, the returned snapshot is empty.
+    ///
+    /// # Panics
+    /// If metrics is not initialized
+    #[must_use]
+    pub fn partition_metrics(&self, partition: MetricPartition) -> QueryMetrics {
+        let store = self.controller.snapshot().expect("Metrics must be set up");
+        Metrics::try_from_partition(&store, partition)
+            .as_ref()
+            .map(QueryMetrics::from)
+            .unwrap_or_default()
+    }
 }
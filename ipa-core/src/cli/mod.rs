@@ -12,6 +12,9 @@ mod metric_collector;
 mod paths;
 #[cfg(all(feature = "test-fixture", feature = "web-app", feature = "cli"))]
 pub mod playbook;
+mod query_output;
+#[cfg(feature = "web-app")]
+mod test_network;
 #[cfg(feature = "web-app")]
 mod test_setup;
 mod verbosity;
@@ -26,8 +29,15 @@ pub use csv::Serializer as CsvSerializer;
 pub use ipa_output::QueryResult as IpaQueryResult;
 #[cfg(feature = "web-app")]
 pub use keygen::{keygen, KeygenArgs};
+#[cfg(any(test, feature = "test-fixture"))]
+pub use metric_collector::install_test_collector;
 pub use metric_collector::{install_collector, CollectorHandle};
 pub use paths::PathExt as CliPaths;
+pub use query_output::{
+    write_query_result, BreakdownHistograms, BreakdownNames, BreakdownRow, OutputFormat,
+};
+#[cfg(feature = "web-app")]
+pub use test_network::{test_network, TestNetwork, TestNetworkArgs};
 #[cfg(feature = "web-app")]
 pub use test_setup::{test_setup, TestSetupArgs};
 pub use verbosity::{LoggingHandle, Verbosity};
@@ -0,0 +1,567 @@
+use std::{
+    collections::HashSet,
+    fs,
+    future::Future,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use futures_util::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::time::sleep;
+
+use crate::{
+    error::BoxError,
+    ff::FieldType,
+    helpers::query::{HybridQueryParams, QueryConfig, QuerySize, QueryType},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ManifestError {
+    #[error("Failed to read manifest {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to parse manifest: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("Failed to parse manifest: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Manifest does not contain any queries")]
+    Empty,
+    #[error("Query name {0:?} is used more than once")]
+    DuplicateName(String),
+    #[error("Output file {0:?} is used by more than one query")]
+    DuplicateOutput(PathBuf),
+    #[error(
+        "Concurrency {0} is not supported: helpers run one query at a time until query ids are unique (TODO(615))"
+    )]
+    UnsupportedConcurrency(NonZeroUsize),
+}
+
+/// A list of queries the report collector runs one after another, or several at a time.
+/// Until query ids are unique (TODO(615)), helpers can only run one query at a time, so
+/// manifests that ask for more than one query in flight are rejected.
+///
+/// Manifests are written in TOML, or in JSON if the file has a `.json` extension. Relative
+/// paths inside the manifest are resolved against the directory it is located in.
+///
+/// ```toml
+/// concurrency = 1
+/// retries = 1
+///
+/// [[queries]]
+/// name = "advertiser-1"
+/// count = 100000
+/// output = "results/advertiser-1.json"
+/// inputs = { url_file_list = "advertiser-1.urls" }
+/// params = { max_breakdown_key = 32, with_dp = 1, epsilon = 3.0 }
+///
+/// [[queries]]
+/// name = "advertiser-2-reach"
+/// kind = "malicious-reach-frequency"
+/// count = 50000
+/// output = "results/advertiser-2-reach.json"
+/// inputs = { encrypted = ["h1.enc", "h2.enc", "h3.enc"] }
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BatchManifest {
+    /// How many queries may be in flight at the same time. Must be 1 until query ids are
+    /// unique (TODO(615)).
+    #[serde(default = "BatchManifest::default_concurrency")]
+    pub concurrency: NonZeroUsize,
+    /// How many more times a failed query is submitted before giving up on it.
+    #[serde(default)]
+    pub retries: u32,
+    /// How long to wait before submitting a failed query again.
+    #[serde(default = "BatchManifest::default_retry_delay_ms")]
+    pub retry_delay_ms: u64,
+    pub queries: Vec<BatchQuery>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BatchQuery {
+    /// Identifies the query in logs and in the summary.
+    pub name: String,
+    #[serde(default)]
+    pub kind: BatchQueryKind,
+    /// Number of records to aggregate
+    pub count: u32,
+    #[serde(default)]
+    pub params: HybridQueryParams,
+    pub inputs: BatchInputs,
+    /// The destination file for query results.
+    pub output: PathBuf,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BatchQueryKind {
+    #[default]
+    MaliciousHybrid,
+    SemiHonestHybrid,
+    MaliciousReachFrequency,
+    MaliciousConversionLift,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchInputs {
+    /// A file with the list of URLs helpers download their inputs from, one per shard of
+    /// every helper.
+    UrlFileList(PathBuf),
+    /// Encrypted reports for H1, H2 and H3 that are streamed to helpers directly.
+    Encrypted([PathBuf; 3]),
+    /// A directory with length delimited encrypted reports for every helper and shard,
+    /// as written by `crypto_util split`.
+    EncryptedDir(PathBuf),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchQueryStatus {
+    Succeeded,
+    Failed,
+}
+
+/// Outcome of every query in the manifest, listed in the same order.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchSummary {
+    #[serde(
+        serialize_with = "crate::serde::duration::to_secs",
+        deserialize_with = "crate::serde::duration::from_secs"
+    )]
+    pub elapsed: Duration,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub queries: Vec<BatchQueryOutcome>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchQueryOutcome {
+    pub name: String,
+    pub status: BatchQueryStatus,
+    pub attempts: u32,
+    /// Time spent on the query, including all attempts, delays between them and waiting for
+    /// helpers to finish other queries.
+    #[serde(
+        serialize_with = "crate::serde::duration::to_secs",
+        deserialize_with = "crate::serde::duration::from_secs"
+    )]
+    pub elapsed: Duration,
+    /// How long the last attempt took.
+    #[serde(
+        serialize_with = "crate::serde::duration::to_secs",
+        deserialize_with = "crate::serde::duration::from_secs"
+    )]
+    pub last_attempt: Duration,
+    pub output: PathBuf,
+    /// The reason the last attempt failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl BatchManifest {
+    fn default_concurrency() -> NonZeroUsize {
+        NonZeroUsize::MIN
+    }
+
+    fn default_retry_delay_ms() -> u64 {
+        5_000
+    }
+
+    /// Reads the manifest from the file and makes all paths inside it absolute.
+    ///
+    /// ## Errors
+    /// If the file can't be read or parsed, or the manifest is not valid.
+    pub fn from_file(path: &Path) -> Result<Self, ManifestError> {
+        let input = fs::read_to_string(path).map_err(|source| ManifestError::Io {
+            path: path.to_owned(),
+            source,
+        })?;
+        let mut manifest = if path.extension().is_some_and(|ext| ext == "json") {
+            serde_json::from_str::<Self>(&input)?
+        } else {
+            toml::from_str::<Self>(&input)?
+        };
+        manifest.validate()?;
+
+        if let Some(base) = path.parent() {
+            manifest.resolve_paths(base);
+        }
+
+        Ok(manifest)
+    }
+
+    fn validate(&self) -> Result<(), ManifestError> {
+        if self.queries.is_empty() {
+            return Err(ManifestError::Empty);
+        }
+        // TODO(615): allow running queries concurrently once query ids are unique.
+        if self.concurrency > NonZeroUsize::MIN {
+            return Err(ManifestError::UnsupportedConcurrency(self.concurrency));
+        }
+
+        let mut names = HashSet::new();
+        let mut outputs = HashSet::new();
+        for query in &self.queries {
+            if !names.insert(&query.name) {
+                return Err(ManifestError::DuplicateName(query.name.clone()));
+            }
+            if !outputs.insert(&query.output) {
+                return Err(ManifestError::DuplicateOutput(query.output.clone()));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn resolve_paths(&mut self, base: &Path) {
+        for query in &mut self.queries {
+            query.output = base.join(&query.output);
+            match query.inputs {
+                BatchInputs::UrlFileList(ref mut path)
+                | BatchInputs::EncryptedDir(ref mut path) => {
+                    *path = base.join(&path);
+                }
+                BatchInputs::Encrypted(ref mut paths) => {
+                    for path in paths {
+                        *path = base.join(&path);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl BatchQuery {
+    /// ## Errors
+    /// If the number of records does not make a valid query size.
+    pub fn query_config(&self) -> Result<QueryConfig, BoxError> {
+        let query_type = match self.kind {
+            BatchQueryKind::MaliciousHybrid => QueryType::MaliciousHybrid(self.params),
+            BatchQueryKind::SemiHonestHybrid => QueryType::SemiHonestHybrid(self.params),
+            BatchQueryKind::MaliciousReachFrequency => {
+                QueryType::MaliciousReachFrequency(self.params)
+            }
+            BatchQueryKind::MaliciousConversionLift => {
+                QueryType::MaliciousConversionLift(self.params)
+            }
+        };
+
+        Ok(QueryConfig {
+            size: QuerySize::try_from(self.count)?,
+            field_type: FieldType::Fp32BitPrime,
+            query_type,
+        })
+    }
+}
+
+/// Runs all queries from the manifest using `run_fn`, keeping at most `concurrency` of them
+/// in flight. A query that fails is submitted again, up to `retries` more times. Failures
+/// do not stop the batch, they are recorded in the returned summary instead.
+pub async fn run_batch<F, Fut>(manifest: &BatchManifest, run_fn: F) -> BatchSummary
+where
+    F: Fn(&BatchQuery) -> Fut,
+    Fut: Future<Output = Result<(), BoxError>>,
+{
+    let start = Instant::now();
+    let queries = stream::iter(&manifest.queries)
+        .map(|query| run_with_retries(manifest, query, &run_fn))
+        .buffered(manifest.concurrency.get())
+        .collect::<Vec<_>>()
+        .await;
+
+    let succeeded = queries
+        .iter()
+        .filter(|outcome| outcome.status == BatchQueryStatus::Succeeded)
+        .count();
+
+    BatchSummary {
+        elapsed: start.elapsed(),
+        succeeded,
+        failed: queries.len() - succeeded,
+        queries,
+    }
+}
+
+async fn run_with_retries<F, Fut>(
+    manifest: &BatchManifest,
+    query: &BatchQuery,
+    run_fn: &F,
+) -> BatchQueryOutcome
+where
+    F: Fn(&BatchQuery) -> Fut,
+    Fut: Future<Output = Result<(), BoxError>>,
+{
+    let start = Instant::now();
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        tracing::info!("Starting query {} (attempt {attempts})", query.name);
+        let attempt_start = Instant::now();
+        let result = run_fn(query).await;
+        let last_attempt = attempt_start.elapsed();
+
+        let error = match result {
+            Ok(()) => None,
+            Err(e) if attempts <= manifest.retries => {
+                tracing::warn!("Query {} failed, submitting it again: {e}", query.name);
+                sleep(Duration::from_millis(manifest.retry_delay_ms)).await;
+                continue;
+            }
+            Err(e) => {
+                tracing::error!("Query {} failed: {e}", query.name);
+                Some(e.to_string())
+            }
+        };
+
+        return BatchQueryOutcome {
+            name: query.name.clone(),
+            status: if error.is_none() {
+                BatchQueryStatus::Succeeded
+            } else {
+                BatchQueryStatus::Failed
+            },
+            attempts,
+            elapsed: start.elapsed(),
+            last_attempt,
+            output: query.output.clone(),
+            error,
+        };
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use std::{
+        collections::HashMap,
+        fs,
+        path::{Path, PathBuf},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Mutex,
+        },
+        time::Duration,
+    };
+
+    use tempfile::TempDir;
+
+    use super::{
+        run_batch, BatchInputs, BatchManifest, BatchQueryKind, BatchQueryStatus, ManifestError,
+    };
+    use crate::helpers::query::{HybridQueryParams, QueryType};
+
+    const MANIFEST: &str = r#"
+concurrency = 1
+retries = 1
+retry_delay_ms = 0
+
+[[queries]]
+name = "first"
+count = 10
+output = "first.json"
+inputs = { url_file_list = "first.urls" }
+params = { max_breakdown_key = 32, with_dp = 0, epsilon = 3.0 }
+
+[[queries]]
+name = "second"
+kind = "malicious-reach-frequency"
+count = 20
+output = "/tmp/second.json"
+inputs = { encrypted = ["h1.enc", "h2.enc", "/tmp/h3.enc"] }
+"#;
+
+    fn write_manifest(name: &str, contents: &str) -> (TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(name);
+        fs::write(&path, contents).unwrap();
+        (dir, path)
+    }
+
+    fn parse(contents: &str) -> Result<BatchManifest, ManifestError> {
+        let (_dir, path) = write_manifest("manifest.toml", contents);
+        BatchManifest::from_file(&path)
+    }
+
+    #[test]
+    fn parse_toml() {
+        let (dir, path) = write_manifest("manifest.toml", MANIFEST);
+        let manifest = BatchManifest::from_file(&path).unwrap();
+
+        assert_eq!(1, manifest.concurrency.get());
+        assert_eq!(1, manifest.retries);
+        let [first, second] = manifest.queries.as_slice() else {
+            panic!("expected two queries");
+        };
+
+        assert_eq!(BatchQueryKind::MaliciousHybrid, first.kind);
+        assert_eq!(dir.path().join("first.json"), first.output);
+        assert_eq!(
+            BatchInputs::UrlFileList(dir.path().join("first.urls")),
+            first.inputs
+        );
+        assert_eq!(
+            QueryType::MaliciousHybrid(HybridQueryParams {
+                max_breakdown_key: 32,
+                with_dp: 0,
+                epsilon: 3.0,
+                plaintext_match_keys: false,
+            }),
+            first.query_config().unwrap().query_type
+        );
+
+        assert_eq!(BatchQueryKind::MaliciousReachFrequency, second.kind);
+        assert_eq!(Path::new("/tmp/second.json"), second.output);
+        assert_eq!(
+            BatchInputs::Encrypted([
+                dir.path().join("h1.enc"),
+                dir.path().join("h2.enc"),
+                PathBuf::from("/tmp/h3.enc"),
+            ]),
+            second.inputs
+        );
+        assert_eq!(
+            QueryType::MaliciousReachFrequency(HybridQueryParams::default()),
+            second.query_config().unwrap().query_type
+        );
+    }
+
+    #[test]
+    fn parse_json() {
+        let (dir, path) = write_manifest(
+            "manifest.json",
+            r#"{
+                "queries": [{
+                    "name": "lift",
+                    "kind": "malicious-conversion-lift",
+                    "count": 5,
+                    "output": "lift.json",
+                    "inputs": { "encrypted_dir": "shards" }
+                }]
+            }"#,
+        );
+        let manifest = BatchManifest::from_file(&path).unwrap();
+
+        assert_eq!(1, manifest.concurrency.get());
+        assert_eq!(0, manifest.retries);
+        assert_eq!(
+            BatchQueryKind::MaliciousConversionLift,
+            manifest.queries[0].kind
+        );
+        assert_eq!(dir.path().join("lift.json"), manifest.queries[0].output);
+        assert_eq!(
+            BatchInputs::EncryptedDir(dir.path().join("shards")),
+            manifest.queries[0].inputs
+        );
+    }
+
+    #[test]
+    fn reject_invalid_manifests() {
+        assert!(matches!(parse("queries = []"), Err(ManifestError::Empty)));
+        assert!(matches!(
+            parse(&MANIFEST.replace("second", "first")),
+            Err(ManifestError::DuplicateName(name)) if name == "first"
+        ));
+        assert!(matches!(
+            parse(&MANIFEST.replace("/tmp/second.json", "first.json")),
+            Err(ManifestError::DuplicateOutput(_))
+        ));
+        assert!(matches!(
+            parse(&MANIFEST.replace("concurrency = 1", "concurrency = 0")),
+            Err(ManifestError::Toml(_))
+        ));
+        assert!(matches!(
+            parse(&MANIFEST.replace("concurrency = 1", "concurrency = 2")),
+            Err(ManifestError::UnsupportedConcurrency(n)) if n.get() == 2
+        ));
+        assert!(matches!(
+            parse(&MANIFEST.replace("retries = 1", "retries = -1")),
+            Err(ManifestError::Toml(_))
+        ));
+        assert!(matches!(
+            parse(&format!("{MANIFEST}\nunknown = 1")),
+            Err(ManifestError::Toml(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn retries_failed_queries() {
+        let (_dir, path) = write_manifest("manifest.toml", MANIFEST);
+        let manifest = BatchManifest::from_file(&path).unwrap();
+        let attempts = Mutex::new(HashMap::<String, u32>::new());
+
+        let summary = run_batch(&manifest, |query| {
+            let attempt = {
+                let mut attempts = attempts.lock().unwrap();
+                let attempt = attempts.entry(query.name.clone()).or_default();
+                *attempt += 1;
+                *attempt
+            };
+            let name = query.name.clone();
+            async move {
+                match (name.as_str(), attempt) {
+                    ("first", 1) => Err("transient".into()),
+                    ("first", _) => Ok(()),
+                    (_, n) => Err(format!("attempt {n} failed").into()),
+                }
+            }
+        })
+        .await;
+
+        assert_eq!(1, summary.succeeded);
+        assert_eq!(1, summary.failed);
+
+        let [first, second] = summary.queries.as_slice() else {
+            panic!("expected two outcomes");
+        };
+        assert_eq!("first", first.name);
+        assert_eq!(BatchQueryStatus::Succeeded, first.status);
+        assert_eq!(2, first.attempts);
+        assert_eq!(None, first.error);
+
+        assert_eq!("second", second.name);
+        assert_eq!(BatchQueryStatus::Failed, second.status);
+        assert_eq!(2, second.attempts);
+        assert_eq!(Some("attempt 2 failed"), second.error.as_deref());
+    }
+
+    #[tokio::test]
+    async fn limits_concurrency() {
+        let (_dir, path) = write_manifest("manifest.toml", MANIFEST);
+        let mut manifest = BatchManifest::from_file(&path).unwrap();
+        let template = manifest.queries[0].clone();
+        manifest.queries = (0..6)
+            .map(|i| {
+                let mut query = template.clone();
+                query.name = format!("query-{i}");
+                query
+            })
+            .collect();
+
+        let running = AtomicUsize::new(0);
+        let max_running = AtomicUsize::new(0);
+        let summary = run_batch(&manifest, |_| async {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            max_running.fetch_max(now, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            running.fetch_sub(1, Ordering::SeqCst);
+            Ok(())
+        })
+        .await;
+
+        assert_eq!(6, summary.succeeded);
+        assert_eq!(1, max_running.load(Ordering::SeqCst));
+        assert_eq!(
+            (0..6).map(|i| format!("query-{i}")).collect::<Vec<_>>(),
+            summary
+                .queries
+                .iter()
+                .map(|outcome| outcome.name.clone())
+                .collect::<Vec<_>>()
+        );
+    }
+}
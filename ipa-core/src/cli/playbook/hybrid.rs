@@ -1,33 +1,81 @@
 #![cfg(all(feature = "web-app", feature = "cli"))]
 use std::{
-    cmp::min,
+    borrow::Cow,
     iter::zip,
     time::{Duration, Instant},
 };
 
 use futures_util::future::try_join_all;
 use serde::{Deserialize, Serialize};
-use tokio::time::sleep;
 
 use crate::{
+    cli::{playbook::wait_for_completion, query_output::BreakdownHistograms},
+    error::BoxError,
     ff::{Serializable, U128Conversions},
     helpers::query::{HybridQueryParams, QueryInput, QuerySize},
     net::{Helper, IpaHttpClient},
-    query::QueryStatus,
+    protocol::{
+        dp::histogram_noise_std,
+        hybrid::{reach_frequency::FREQUENCY_BUCKETS, LiftHistogram},
+    },
+    query::{hybrid_dp_params, HYBRID_BREAKDOWNS, HYBRID_SS_BITS, REACH_FREQUENCY_SS_BITS},
     secret_sharing::{replicated::semi_honest::AdditiveShare, SharedValue},
     test_fixture::Reconstruct,
 };
 
 /// # Panics
 /// if results are invalid
-#[allow(clippy::disallowed_methods)] // allow try_join_all
 pub async fn run_hybrid_query_and_validate<HV>(
     inputs: Vec<[QueryInput; 3]>,
     query_size: usize,
     clients: Vec<[IpaHttpClient<Helper>; 3]>,
     query_config: HybridQueryParams,
-    set_fixed_polling_ms: Option<u64>,
+    status_refresh_ms: Option<u64>,
 ) -> HybridQueryResult
+where
+    HV: SharedValue + U128Conversions,
+    AdditiveShare<HV>: Serializable,
+{
+    let (results, lat) = run_hybrid_query::<HV>(inputs, clients, status_refresh_ms).await;
+
+    tracing::info!("Running IPA for {query_size:?} records took {t:?}", t = lat);
+    let mut breakdowns = vec![0; usize::try_from(query_config.max_breakdown_key).unwrap()];
+    for (breakdown_key, trigger_value) in results.into_iter().enumerate() {
+        // TODO: make the data type used consistent with `ipa_in_the_clear`
+        // I think using u32 is wrong, we should move to u128
+        if query_config.with_dp == 0 {
+            // otherwise if DP is added trigger_values will not be zero due to noise
+            assert!(
+                breakdown_key < query_config.max_breakdown_key.try_into().unwrap()
+                    || trigger_value == HV::ZERO,
+                "trigger values were attributed to buckets more than max breakdown key"
+            );
+        }
+
+        if breakdown_key < query_config.max_breakdown_key.try_into().unwrap() {
+            breakdowns[breakdown_key] += u32::try_from(trigger_value.as_u128()).unwrap();
+        }
+    }
+
+    HybridQueryResult {
+        input_size: QuerySize::try_from(query_size).unwrap(),
+        config: query_config,
+        latency: lat,
+        breakdowns,
+    }
+}
+
+/// Submits the inputs of a query that takes hybrid reports, waits for it to complete and
+/// returns the reconstructed results along with the time it took.
+///
+/// # Panics
+/// if the query fails or results can't be reconstructed
+#[allow(clippy::disallowed_methods)] // allow try_join_all
+async fn run_hybrid_query<HV>(
+    inputs: Vec<[QueryInput; 3]>,
+    clients: Vec<[IpaHttpClient<Helper>; 3]>,
+    status_refresh_ms: Option<u64>,
+) -> (Vec<HV>, Duration)
 where
     HV: SharedValue + U128Conversions,
     AdditiveShare<HV>: Serializable,
@@ -54,32 +102,12 @@ where
 
     let leader_clients = &clients[0];
 
-    let (exponential_backoff, mut delay) = match set_fixed_polling_ms {
-        Some(specified_delay) => (false, Duration::from_millis(specified_delay)),
-        None => (true, Duration::from_millis(125)),
-    };
-
-    loop {
-        if try_join_all(
-            leader_clients
-                .each_ref()
-                .map(|client| client.query_status(query_id)),
-        )
-        .await
-        .unwrap()
-        .into_iter()
-        .all(|status| status == QueryStatus::Completed)
-        {
-            break;
-        }
-
-        sleep(delay).await;
-        if exponential_backoff {
-            delay = min(Duration::from_secs(5), delay * 2);
-        }
-        // TODO: Add a timeout of some sort. Possibly, add some sort of progress indicator to
-        // the status API so we can check whether the query is making progress.
-    }
+    wait_for_completion(
+        leader_clients,
+        query_id,
+        status_refresh_ms.map(Duration::from_millis),
+    )
+    .await;
 
     // wait until helpers have processed the query and get the results from them
     let results: [_; 3] = try_join_all(
@@ -100,37 +128,111 @@ where
         })
         .reconstruct();
 
-    let lat = mpc_time.elapsed();
+    (results, mpc_time.elapsed())
+}
 
-    tracing::info!("Running IPA for {query_size:?} records took {t:?}", t = lat);
-    let mut breakdowns = vec![0; usize::try_from(query_config.max_breakdown_key).unwrap()];
-    for (breakdown_key, trigger_value) in results.into_iter().enumerate() {
-        // TODO: make the data type used consistent with `ipa_in_the_clear`
-        // I think using u32 is wrong, we should move to u128
-        if query_config.with_dp == 0 {
-            // otherwise if DP is added trigger_values will not be zero due to noise
-            assert!(
-                breakdown_key < query_config.max_breakdown_key.try_into().unwrap()
-                    || trigger_value == HV::ZERO,
-                "trigger values were attributed to buckets more than max breakdown key"
-            );
-        }
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HybridQueryResult {
+    pub input_size: QuerySize,
+    pub config: HybridQueryParams,
+    #[serde(
+        serialize_with = "crate::serde::duration::to_secs",
+        deserialize_with = "crate::serde::duration::from_secs"
+    )]
+    pub latency: Duration,
+    pub breakdowns: Vec<u32>,
+}
 
-        if breakdown_key < query_config.max_breakdown_key.try_into().unwrap() {
-            breakdowns[breakdown_key] += u32::try_from(trigger_value.as_u128()).unwrap();
-        }
+/// # Panics
+/// if the query fails or results can't be reconstructed
+pub async fn run_reach_frequency_query<HV>(
+    inputs: Vec<[QueryInput; 3]>,
+    query_size: usize,
+    clients: Vec<[IpaHttpClient<Helper>; 3]>,
+    query_config: HybridQueryParams,
+    status_refresh_ms: Option<u64>,
+) -> ReachFrequencyQueryResult
+where
+    HV: SharedValue + U128Conversions,
+    AdditiveShare<HV>: Serializable,
+{
+    let (results, lat) = run_hybrid_query::<HV>(inputs, clients, status_refresh_ms).await;
+
+    tracing::info!(
+        "Running reach and frequency for {query_size:?} records took {t:?}",
+        t = lat
+    );
+    let frequencies = results
+        .chunks(FREQUENCY_BUCKETS)
+        .take(usize::try_from(query_config.max_breakdown_key).unwrap())
+        .map(|buckets| {
+            buckets
+                .iter()
+                .map(|users| u32::try_from(users.as_u128()).unwrap())
+                .collect()
+        })
+        .collect();
+
+    ReachFrequencyQueryResult {
+        input_size: QuerySize::try_from(query_size).unwrap(),
+        config: query_config,
+        latency: lat,
+        frequencies,
     }
+}
 
-    HybridQueryResult {
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReachFrequencyQueryResult {
+    pub input_size: QuerySize,
+    pub config: HybridQueryParams,
+    #[serde(
+        serialize_with = "crate::serde::duration::to_secs",
+        deserialize_with = "crate::serde::duration::from_secs"
+    )]
+    pub latency: Duration,
+    /// The number of users per frequency bucket, for every breakdown key. The last bucket
+    /// holds the users with that many impressions or more.
+    pub frequencies: Vec<Vec<u32>>,
+}
+
+/// # Panics
+/// if the query fails or results can't be reconstructed
+pub async fn run_conversion_lift_query<HV>(
+    inputs: Vec<[QueryInput; 3]>,
+    query_size: usize,
+    clients: Vec<[IpaHttpClient<Helper>; 3]>,
+    query_config: HybridQueryParams,
+    status_refresh_ms: Option<u64>,
+) -> ConversionLiftQueryResult
+where
+    HV: SharedValue + U128Conversions,
+    AdditiveShare<HV>: Serializable,
+{
+    let (results, lat) = run_hybrid_query::<HV>(inputs, clients, status_refresh_ms).await;
+
+    tracing::info!(
+        "Running conversion lift for {query_size:?} records took {t:?}",
+        t = lat
+    );
+    let max_breakdown_key = usize::try_from(query_config.max_breakdown_key).unwrap();
+    let LiftHistogram { control, test } = LiftHistogram::from(
+        results
+            .iter()
+            .map(|total| u32::try_from(total.as_u128()).unwrap())
+            .collect::<Vec<_>>(),
+    );
+
+    ConversionLiftQueryResult {
         input_size: QuerySize::try_from(query_size).unwrap(),
         config: query_config,
         latency: lat,
-        breakdowns,
+        control: control.into_iter().take(max_breakdown_key).collect(),
+        test: test.into_iter().take(max_breakdown_key).collect(),
     }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct HybridQueryResult {
+pub struct ConversionLiftQueryResult {
     pub input_size: QuerySize,
     pub config: HybridQueryParams,
     #[serde(
@@ -138,5 +240,73 @@ pub struct HybridQueryResult {
         deserialize_with = "crate::serde::duration::from_secs"
     )]
     pub latency: Duration,
-    pub breakdowns: Vec<u32>,
+    /// Conversion totals per breakdown key for impressions in the control group.
+    pub control: Vec<u32>,
+    /// Conversion totals per breakdown key for impressions in the test group.
+    pub test: Vec<u32>,
+}
+
+/// Standard deviation of the noise added to each of the `columns` histograms a hybrid query
+/// outputs. The DP budget is split evenly across the columns and noise is calibrated to
+/// `2^ss_bits`, the same way the query runners do it.
+fn hybrid_noise_std(
+    config: &HybridQueryParams,
+    ss_bits: usize,
+    columns: usize,
+) -> Result<f64, BoxError> {
+    let dp_params = hybrid_dp_params(config).split(u32::try_from(columns)?);
+
+    Ok(histogram_noise_std(
+        dp_params,
+        1 << ss_bits,
+        u32::try_from(HYBRID_BREAKDOWNS)?,
+    )?)
+}
+
+impl BreakdownHistograms for HybridQueryResult {
+    fn histograms(&self) -> Vec<(Cow<'static, str>, Cow<'_, [u32]>)> {
+        vec![("value".into(), self.breakdowns.as_slice().into())]
+    }
+
+    fn noise_std(&self) -> Result<f64, BoxError> {
+        hybrid_noise_std(&self.config, HYBRID_SS_BITS, 1)
+    }
+}
+
+impl BreakdownHistograms for ReachFrequencyQueryResult {
+    /// Every frequency bucket is a separate metric, `frequency_1` is the number of users
+    /// with a single impression for the breakdown key.
+    fn histograms(&self) -> Vec<(Cow<'static, str>, Cow<'_, [u32]>)> {
+        // Frequencies are stored per breakdown key, rows are written per bucket.
+        let buckets = self.frequencies.first().map_or(0, Vec::len);
+        (0..buckets)
+            .map(|bucket| {
+                (
+                    format!("frequency_{}", bucket + 1).into(),
+                    self.frequencies
+                        .iter()
+                        .map(|buckets| buckets[bucket])
+                        .collect::<Vec<_>>()
+                        .into(),
+                )
+            })
+            .collect()
+    }
+
+    fn noise_std(&self) -> Result<f64, BoxError> {
+        hybrid_noise_std(&self.config, REACH_FREQUENCY_SS_BITS, FREQUENCY_BUCKETS)
+    }
+}
+
+impl BreakdownHistograms for ConversionLiftQueryResult {
+    fn histograms(&self) -> Vec<(Cow<'static, str>, Cow<'_, [u32]>)> {
+        vec![
+            ("control".into(), self.control.as_slice().into()),
+            ("test".into(), self.test.as_slice().into()),
+        ]
+    }
+
+    fn noise_std(&self) -> Result<f64, BoxError> {
+        hybrid_noise_std(&self.config, HYBRID_SS_BITS, 1)
+    }
 }
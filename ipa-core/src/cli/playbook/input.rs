@@ -124,10 +124,41 @@ impl InputItem for TestHybridRecord {
                     panic!("{s} is not a valid {}", type_name::<Self>())
                 }
             }
+            'l' => {
+                if let [_, match_key, number, key_id, test_group] =
+                    s.splitn(5, ',').collect::<Vec<_>>()[..]
+                {
+                    let match_key: u64 = match_key
+                        .parse()
+                        .unwrap_or_else(|e| panic!("Expected a u64, got {match_key}: {e}"));
+
+                    let number: u32 = number
+                        .parse()
+                        .unwrap_or_else(|e| panic!("Expected a u32, got {number}: {e}"));
+
+                    let key_id: u8 = key_id
+                        .parse()
+                        .unwrap_or_else(|e| panic!("Expected a u8, got {key_id}: {e}"));
+
+                    let test_group = match test_group {
+                        "0" => false,
+                        "1" => true,
+                        _ => panic!("Expected 0 or 1, got {test_group}"),
+                    };
+                    TestHybridRecord::TestLiftImpression {
+                        match_key,
+                        breakdown_key: number,
+                        key_id,
+                        test_group,
+                    }
+                } else {
+                    panic!("{s} is not a valid {}", type_name::<Self>())
+                }
+            }
             _ => panic!(
                 "{}",
                 format!(
-                    "Invalid input. Rows should start with 'i' or 'c'. Did not expect {event_type}"
+                    "Invalid input. Rows should start with 'i', 'c' or 'l'. Did not expect {event_type}"
                 )
             ),
         }
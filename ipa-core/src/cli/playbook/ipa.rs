@@ -1,18 +1,14 @@
 #![cfg(all(feature = "web-app", feature = "cli"))]
-use std::{
-    cmp::min,
-    iter::zip,
-    time::{Duration, Instant},
-};
+use std::{iter::zip, time::Instant};
 
 use futures_util::future::try_join_all;
 use generic_array::GenericArray;
 use rand::rngs::StdRng;
 use rand_core::SeedableRng;
-use tokio::time::sleep;
 use typenum::Unsigned;
 
 use crate::{
+    cli::playbook::wait_for_completion,
     cli::{
         playbook::{BreakdownKey, Timestamp, TriggerValue},
         IpaQueryResult,
@@ -25,7 +21,6 @@ use crate::{
     hpke::PublicKeyRegistry,
     net::{Helper, IpaHttpClient},
     protocol::{ipa_prf::OPRFIPAInputRow, QueryId},
-    query::QueryStatus,
     report::{KeyIdentifier, OprfReport},
     secret_sharing::{replicated::semi_honest::AdditiveShare, IntoShares, SharedValue},
     test_fixture::{ipa::TestRawDataRecord, Reconstruct},
@@ -124,22 +119,7 @@ where
     .await
     .unwrap();
 
-    let mut delay = Duration::from_millis(125);
-    loop {
-        if try_join_all(clients.iter().map(|client| client.query_status(query_id)))
-            .await
-            .unwrap()
-            .into_iter()
-            .all(|status| status == QueryStatus::Completed)
-        {
-            break;
-        }
-
-        sleep(delay).await;
-        delay = min(Duration::from_secs(5), delay * 2);
-        // TODO: Add a timeout of some sort. Possibly, add some sort of progress indicator to
-        // the status API so we can check whether the query is making progress.
-    }
+    wait_for_completion(clients, query_id, None).await;
 
     // wait until helpers have processed the query and get the results from them
     let results: [_; 3] = try_join_all(clients.iter().map(|client| client.query_results(query_id)))
@@ -1,4 +1,5 @@
 mod add;
+mod batch;
 mod generator;
 mod hybrid;
 mod input;
@@ -13,6 +14,7 @@ use std::{fs, path::Path, time::Duration};
 
 pub use add::secure_add;
 use comfy_table::{Cell, Color, Table};
+use futures_util::future::try_join_all;
 use hyper::http::uri::Scheme;
 pub use input::InputSource;
 pub use multiply::secure_mul;
@@ -20,18 +22,28 @@ pub use sharded_shuffle::secure_shuffle;
 use tokio::time::sleep;
 
 pub use self::{
-    hybrid::{run_hybrid_query_and_validate, HybridQueryResult},
+    batch::{
+        run_batch, BatchInputs, BatchManifest, BatchQuery, BatchQueryKind, BatchQueryOutcome,
+        BatchQueryStatus, BatchSummary, ManifestError,
+    },
+    hybrid::{
+        run_conversion_lift_query, run_hybrid_query_and_validate, run_reach_frequency_query,
+        ConversionLiftQueryResult, HybridQueryResult, ReachFrequencyQueryResult,
+    },
     ipa::{playbook_oprf_ipa, run_query_and_validate},
-    streaming::{RoundRobinSubmission, StreamingSubmission},
+    streaming::{
+        shard_input_file_name, shard_input_streams, RoundRobinSubmission, StreamingSubmission,
+    },
 };
 use crate::{
     cli::config_parse::HelperNetworkConfigParseExt,
     config::{ClientConfig, NetworkConfig, PeerConfig},
     executor::IpaRuntime,
     ff::boolean_array::{BA20, BA3, BA8},
-    helpers::query::DpMechanism,
+    helpers::{min_progress, query::DpMechanism, QueryProgress},
     net::{ClientIdentity, Helper, IpaHttpClient},
-    protocol::{dp::NoiseParams, ipa_prf::oprf_padding::insecure::OPRFPaddingDp},
+    protocol::{dp::NoiseParams, ipa_prf::oprf_padding::insecure::OPRFPaddingDp, QueryId},
+    query::QueryStatus,
 };
 
 pub type BreakdownKey = BA8;
@@ -252,6 +264,89 @@ pub async fn make_sharded_clients(
     (clients, network)
 }
 
+/// Returns `true` once the query is completed on every helper.
+///
+/// ## Panics
+/// If any helper reports that the query failed or was killed, there is no point in waiting
+/// for the results.
+fn all_completed<I: IntoIterator<Item = QueryStatus>>(statuses: I) -> bool {
+    let mut completed = true;
+    for (i, status) in statuses.into_iter().enumerate() {
+        match status {
+            QueryStatus::Completed => {}
+            QueryStatus::Failed { reason } => panic!("query failed on helper {}: {reason}", i + 1),
+            QueryStatus::Killed => panic!("query was killed on helper {}", i + 1),
+            _ => completed = false,
+        }
+    }
+
+    completed
+}
+
+/// Renders the progress of the helper that is furthest behind as a single line on stderr,
+/// overwriting the previous one. Nothing is printed until the query runs on every helper.
+fn print_progress<I: IntoIterator<Item = Option<QueryProgress>>>(progress: I) {
+    const WIDTH: usize = 30;
+
+    let Some(progress) = progress.into_iter().reduce(min_progress).flatten() else {
+        return;
+    };
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    let done = (progress.completion() * WIDTH as f64) as usize;
+    let elapsed = progress
+        .phases
+        .last()
+        .map_or(Duration::ZERO, |phase| phase.elapsed);
+    eprint!(
+        "\r[{}{}] {:>3}% {} ({}/{} records, {:.1?})\x1b[K",
+        "#".repeat(done),
+        "-".repeat(WIDTH - done),
+        done * 100 / WIDTH,
+        progress.step,
+        progress.records_processed,
+        progress.records_expected,
+        elapsed,
+    );
+}
+
+/// Default time helpers may hold a status request in [`wait_for_completion`].
+const STATUS_REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Waits until the query completes on every helper, printing its progress on the way.
+/// Helpers respond as soon as the query ends, or once `refresh_interval` elapses so
+/// progress can be updated.
+///
+/// ## Panics
+/// If any helper cannot be reached or reports that the query failed or was killed.
+#[allow(clippy::disallowed_methods)] // allow try_join_all
+async fn wait_for_completion(
+    clients: &[IpaHttpClient<Helper>; 3],
+    query_id: QueryId,
+    refresh_interval: Option<Duration>,
+) {
+    let timeout = refresh_interval.unwrap_or(STATUS_REFRESH_INTERVAL);
+    loop {
+        let (statuses, progress): (Vec<_>, Vec<_>) = try_join_all(
+            clients
+                .each_ref()
+                .map(|client| client.wait_query(query_id, timeout)),
+        )
+        .await
+        .unwrap()
+        .into_iter()
+        .unzip();
+        print_progress(progress);
+        if all_completed(statuses) {
+            eprintln!();
+            break;
+        }
+    }
+}
+
 async fn wait_for_servers(mut wait: usize, clients: &[[IpaHttpClient<Helper>; 3]]) {
     while wait > 0 && !clients_ready(clients).await {
         tracing::debug!("waiting for servers to come up");
@@ -1,18 +1,14 @@
-use std::{
-    cmp::{max, min},
-    ops::Add,
-    time::Duration,
-};
+use std::{cmp::max, ops::Add};
 
 use futures_util::future::try_join_all;
 use generic_array::ArrayLength;
 
 use crate::{
+    cli::playbook::wait_for_completion,
     ff::{boolean_array::BooleanArray, Serializable},
     helpers::{query::QueryInput, BodyStream},
     net::{Helper, IpaHttpClient},
     protocol::QueryId,
-    query::QueryStatus,
     secret_sharing::{replicated::semi_honest::AdditiveShare, IntoShares},
     test_fixture::Reconstruct,
 };
@@ -57,24 +53,7 @@ where
     .unwrap();
     let leader_clients = &clients[0];
 
-    let mut delay = Duration::from_millis(125);
-    loop {
-        if try_join_all(
-            leader_clients
-                .iter()
-                .map(|client| client.query_status(query_id)),
-        )
-        .await
-        .unwrap()
-        .into_iter()
-        .all(|status| status == QueryStatus::Completed)
-        {
-            break;
-        }
-
-        tokio::time::sleep(delay).await;
-        delay = min(Duration::from_secs(5), delay * 2);
-    }
+    wait_for_completion(leader_clients, query_id, None).await;
 
     let results: [_; 3] = try_join_all(
         leader_clients
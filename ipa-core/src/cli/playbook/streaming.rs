@@ -1,5 +1,7 @@
 use std::{
-    io::BufRead,
+    fs::{self, File},
+    io::{BufRead, BufReader},
+    path::Path,
     pin::Pin,
     task::{Context, Poll, Waker},
 };
@@ -146,6 +148,81 @@ impl<R: BufRead> State<R> {
     }
 }
 
+/// Name of the file in a shard input directory that holds length-delimited reports
+/// for `helper` (1-based) and `shard`. These files are written by `crypto_util split`.
+#[must_use]
+pub fn shard_input_file_name(helper: usize, shard: usize) -> String {
+    format!("helper{helper}_shard_{shard:03}.bin")
+}
+
+/// Opens per-shard input files in `dir` for all three helpers. Files already contain
+/// length-delimited reports, so unlike [`RoundRobinSubmission`], they are streamed to
+/// helpers as-is.
+///
+/// ## Errors
+/// If `dir` does not contain exactly `shard_count` input files for every helper or any
+/// of them can't be opened.
+pub fn shard_input_streams(
+    dir: &Path,
+    shard_count: usize,
+) -> Result<Vec<[impl BytesStream; 3]>, BoxError> {
+    for helper in 1..=3 {
+        let prefix = format!("helper{helper}_shard_");
+        let found = fs::read_dir(dir)
+            .map_err(|e| format!("unable to read directory {}. {e}", dir.display()))?
+            .filter_map(Result::ok)
+            .filter(|entry| {
+                let path = entry.path();
+                path.extension().is_some_and(|ext| ext == "bin")
+                    && path
+                        .file_name()
+                        .and_then(|name| name.to_str())
+                        .is_some_and(|name| name.starts_with(&prefix))
+            })
+            .count();
+        if found != shard_count {
+            return Err(format!(
+                "{} contains {found} input files for helper {helper}, expected one per shard ({shard_count})",
+                dir.display()
+            )
+            .into());
+        }
+    }
+
+    let open = |helper: usize, shard: usize| -> Result<_, BoxError> {
+        let path = dir.join(shard_input_file_name(helper, shard));
+        let file = File::open(&path)
+            .map_err(|e| format!("unable to open file {}. {e}", path.display()))?;
+        Ok(ChunkedStream(BufReader::with_capacity(CHUNK_SIZE, file)))
+    };
+
+    (0..shard_count)
+        .map(|shard| Ok([open(1, shard)?, open(2, shard)?, open(3, shard)?]))
+        .collect()
+}
+
+const CHUNK_SIZE: usize = 1 << 20;
+
+/// Streams the provided input in chunks, without decoding it.
+struct ChunkedStream<R>(R);
+
+impl<R: BufRead + Unpin> Stream for ChunkedStream<R> {
+    type Item = Result<Bytes, BoxError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let read_from = &mut self.0;
+        match read_from.fill_buf() {
+            Ok([]) => Poll::Ready(None),
+            Ok(buf) => {
+                let bytes = Bytes::copy_from_slice(buf);
+                read_from.consume(bytes.len());
+                Poll::Ready(Some(Ok(bytes)))
+            }
+            Err(e) => Poll::Ready(Some(Err(e.into()))),
+        }
+    }
+}
+
 #[cfg(all(test, unit_test))]
 mod tests {
     use std::{
@@ -159,7 +236,9 @@ mod tests {
     use tempfile::TempDir;
 
     use crate::{
-        cli::playbook::streaming::{RoundRobinSubmission, StreamingSubmission},
+        cli::playbook::streaming::{
+            shard_input_file_name, shard_input_streams, RoundRobinSubmission, StreamingSubmission,
+        },
         helpers::BytesStream,
         test_executor::run,
     };
@@ -261,6 +340,43 @@ mod tests {
         });
     }
 
+    #[test]
+    fn shard_input_dir() {
+        run(|| async {
+            let tmp_dir = TempDir::with_prefix("ipa-unit-test").unwrap();
+            for helper in 1..=3 {
+                for shard in 0..2 {
+                    let content = format!("h{helper}s{shard}");
+                    std::fs::write(
+                        tmp_dir.path().join(shard_input_file_name(helper, shard)),
+                        content,
+                    )
+                    .unwrap();
+                }
+            }
+
+            let streams = shard_input_streams(tmp_dir.path(), 2).unwrap();
+            assert_eq!(2, streams.len());
+            for (shard, shard_streams) in streams.into_iter().enumerate() {
+                for (helper, stream) in shard_streams.into_iter().enumerate() {
+                    assert_eq!(
+                        format!("h{}s{shard}", helper + 1).into_bytes(),
+                        stream.to_vec().await
+                    );
+                }
+            }
+
+            let err = shard_input_streams(tmp_dir.path(), 3).err().unwrap();
+            assert!(
+                err.to_string().contains("expected one per shard (3)"),
+                "{err}"
+            );
+
+            std::fs::remove_file(tmp_dir.path().join(shard_input_file_name(2, 1))).unwrap();
+            assert!(shard_input_streams(tmp_dir.path(), 2).is_err());
+        });
+    }
+
     async fn verify_one<I: AsRef<str> + Clone>(input: Vec<I>, count: usize) {
         assert!(count > 0);
         let data = encoded(input.iter().map(|v| v.as_ref().as_bytes())).join("\n");
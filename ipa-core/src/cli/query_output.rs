@@ -0,0 +1,264 @@
+use std::{borrow::Cow, collections::HashMap, fs, io, io::Write, path::Path};
+
+use serde::Serialize;
+
+use crate::{cli::csv::Serializer, error::BoxError};
+
+/// How query results are written by the report collector.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum OutputFormat {
+    /// The whole result, including the query configuration, as a single JSON document.
+    #[default]
+    Json,
+    /// One row per breakdown key and metric, with a header.
+    Csv,
+    /// One JSON object per breakdown key and metric, on its own line.
+    #[cfg_attr(feature = "clap", value(name = "jsonl"))]
+    JsonLines,
+}
+
+/// Query results that consist of histograms over breakdown keys.
+pub trait BreakdownHistograms {
+    /// Returns the histograms of the result, along with the name of the metric each of them
+    /// holds.
+    fn histograms(&self) -> Vec<(Cow<'static, str>, Cow<'_, [u32]>)>;
+
+    /// Standard deviation of the DP noise added to every value of the histograms.
+    ///
+    /// ## Errors
+    /// If the DP parameters of the query are invalid.
+    fn noise_std(&self) -> Result<f64, BoxError>;
+}
+
+/// A single value of a query result, as written by the row based output formats.
+#[derive(Debug, Serialize)]
+pub struct BreakdownRow<'a> {
+    pub breakdown_key: u32,
+    pub breakdown_name: Option<&'a str>,
+    pub metric: &'a str,
+    pub noisy_value: i64,
+    pub noise_std: f64,
+}
+
+impl BreakdownRow<'_> {
+    const CSV_HEADER: &'static str = "breakdown_key,breakdown_name,metric,noisy_value,noise_std";
+}
+
+impl Serializer for BreakdownRow<'_> {
+    fn to_csv<W: Write>(&self, buf: &mut W) -> io::Result<()> {
+        write!(buf, "{},", self.breakdown_key)?;
+        if let Some(name) = self.breakdown_name {
+            write_csv_field(buf, name)?;
+        }
+        write!(buf, ",")?;
+        write_csv_field(buf, self.metric)?;
+        write!(buf, ",{},{}", self.noisy_value, self.noise_std)?;
+
+        Ok(())
+    }
+}
+
+fn write_csv_field<W: Write>(buf: &mut W, field: &str) -> io::Result<()> {
+    if field.contains([',', '"', '\n', '\r']) {
+        write!(buf, "\"{}\"", field.replace('"', "\"\""))
+    } else {
+        write!(buf, "{field}")
+    }
+}
+
+/// Human-readable names of breakdown keys. They are loaded from a CSV file with
+/// `breakdown_key,name` rows, the first row may be a header.
+#[derive(Debug, Clone, Default)]
+pub struct BreakdownNames(HashMap<u32, String>);
+
+impl BreakdownNames {
+    /// Loads breakdown names from the given file.
+    ///
+    /// ## Errors
+    /// If the file can't be read or contains invalid or duplicate breakdown keys.
+    pub fn from_file(path: &Path) -> Result<Self, BoxError> {
+        let input = fs::read_to_string(path)
+            .map_err(|e| format!("unable to read breakdown names {}. {e}", path.display()))?;
+        Self::parse(&input).map_err(|e| format!("{}: {e}", path.display()).into())
+    }
+
+    fn parse(input: &str) -> Result<Self, BoxError> {
+        let mut names = HashMap::new();
+        for (i, line) in input.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some((key, name)) = line.split_once(',') else {
+                return Err(format!("line {}: expected breakdown_key,name", i + 1).into());
+            };
+            let key = match key.trim().parse::<u32>() {
+                Ok(key) => key,
+                Err(_) if i == 0 => continue,
+                Err(e) => {
+                    return Err(format!("line {}: invalid breakdown key {key}. {e}", i + 1).into())
+                }
+            };
+            let name = name.trim();
+            let name = match name.strip_prefix('"').and_then(|n| n.strip_suffix('"')) {
+                Some(quoted) => quoted.replace("\"\"", "\""),
+                None => name.to_string(),
+            };
+            if names.insert(key, name).is_some() {
+                return Err(format!("line {}: duplicate breakdown key {key}", i + 1).into());
+            }
+        }
+
+        Ok(Self(names))
+    }
+
+    #[must_use]
+    pub fn get(&self, breakdown_key: u32) -> Option<&str> {
+        self.0.get(&breakdown_key).map(String::as_str)
+    }
+}
+
+/// Writes the query result in the requested format. Breakdown names are only used by the
+/// row based formats, JSON output is the serialized result as is.
+///
+/// ## Errors
+/// If writing fails or the noise of the result can't be determined.
+pub fn write_query_result<T, W>(
+    result: &T,
+    format: OutputFormat,
+    names: Option<&BreakdownNames>,
+    writer: &mut W,
+) -> Result<(), BoxError>
+where
+    T: Serialize + BreakdownHistograms,
+    W: Write,
+{
+    if format == OutputFormat::Json {
+        serde_json::to_writer_pretty(&mut *writer, result)?;
+        writeln!(writer)?;
+        return Ok(());
+    }
+
+    let noise_std = result.noise_std()?;
+    if format == OutputFormat::Csv {
+        writeln!(writer, "{}", BreakdownRow::CSV_HEADER)?;
+    }
+    for (metric, values) in result.histograms() {
+        for (breakdown_key, &value) in (0..).zip(values.iter()) {
+            let row = BreakdownRow {
+                breakdown_key,
+                breakdown_name: names.and_then(|names| names.get(breakdown_key)),
+                metric: &metric,
+                noisy_value: signed_value(value, noise_std),
+                noise_std,
+            };
+            match format {
+                OutputFormat::Csv => row.to_csv(writer)?,
+                OutputFormat::JsonLines => serde_json::to_writer(&mut *writer, &row)?,
+                OutputFormat::Json => unreachable!(),
+            }
+            writeln!(writer)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Histogram values are 32 bit, so negative noise added to small values wraps around to
+/// values close to 2^32. Those are turned back into negative values, if there is noise.
+fn signed_value(value: u32, noise_std: f64) -> i64 {
+    let value = i64::from(value);
+    if noise_std > 0.0 && value > i64::from(i32::MAX) {
+        value - (1 << 32)
+    } else {
+        value
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use std::borrow::Cow;
+
+    use serde::Serialize;
+
+    use crate::{
+        cli::query_output::{
+            write_query_result, BreakdownHistograms, BreakdownNames, OutputFormat,
+        },
+        error::BoxError,
+    };
+
+    #[derive(Serialize)]
+    struct TestResult {
+        control: Vec<u32>,
+        test: Vec<u32>,
+    }
+
+    impl BreakdownHistograms for TestResult {
+        fn histograms(&self) -> Vec<(Cow<'static, str>, Cow<'_, [u32]>)> {
+            vec![
+                ("control".into(), self.control.as_slice().into()),
+                ("test".into(), self.test.as_slice().into()),
+            ]
+        }
+
+        fn noise_std(&self) -> Result<f64, BoxError> {
+            Ok(1.5)
+        }
+    }
+
+    fn write(format: OutputFormat, names: Option<&BreakdownNames>) -> String {
+        let result = TestResult {
+            control: vec![10, u32::MAX],
+            test: vec![7, 3],
+        };
+        let mut buf = Vec::new();
+        write_query_result(&result, format, names, &mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn csv() {
+        let names = BreakdownNames::parse("key,name\n0,\"Campaign, \"\"A\"\"\"\n").unwrap();
+        assert_eq!(
+            "breakdown_key,breakdown_name,metric,noisy_value,noise_std\n\
+             0,\"Campaign, \"\"A\"\"\",control,10,1.5\n\
+             1,,control,-1,1.5\n\
+             0,\"Campaign, \"\"A\"\"\",test,7,1.5\n\
+             1,,test,3,1.5\n",
+            write(OutputFormat::Csv, Some(&names))
+        );
+    }
+
+    #[test]
+    fn json_lines() {
+        let names = BreakdownNames::parse("0,first\n1,second").unwrap();
+        let output = write(OutputFormat::JsonLines, Some(&names));
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(4, lines.len());
+        assert_eq!(
+            r#"{"breakdown_key":1,"breakdown_name":"second","metric":"control","noisy_value":-1,"noise_std":1.5}"#,
+            lines[1]
+        );
+    }
+
+    #[test]
+    fn json() {
+        let output = write(OutputFormat::Json, None);
+        let value: serde_json::Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(serde_json::json!([7, 3]), value["test"]);
+    }
+
+    #[test]
+    fn invalid_names() {
+        for (input, error) in [
+            ("0,a\nx,b", "line 2: invalid breakdown key"),
+            ("0,a\n0,b", "line 2: duplicate breakdown key 0"),
+            ("0", "line 1: expected breakdown_key,name"),
+        ] {
+            let err = BreakdownNames::parse(input).unwrap_err().to_string();
+            assert!(err.contains(error), "{err}");
+        }
+    }
+}
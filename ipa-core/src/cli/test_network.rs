@@ -0,0 +1,359 @@
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    net::TcpListener,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    process::{Child, Command, ExitStatus, Stdio},
+    time::{Duration, Instant},
+};
+
+use clap::Args;
+use hyper::http::uri::Scheme;
+use tokio::time::sleep;
+
+use super::clientconf::shard_conf_folder;
+use crate::{
+    cli::{config_parse::HelperNetworkConfigParseExt, paths::PathExt, test_setup, TestSetupArgs},
+    config::NetworkConfig,
+    error::BoxError,
+    executor::IpaRuntime,
+    net::{ClientIdentity, Helper, IpaHttpClient},
+};
+
+#[derive(Debug, Args)]
+#[clap(
+    name = "test_network",
+    about = "Launch a local test network of three helpers",
+    next_help_heading = "Test Network Options"
+)]
+pub struct TestNetworkArgs {
+    /// Directory for the generated configuration, keys, certificates and helper logs.
+    /// It must be empty or not exist.
+    #[arg(short, long, default_value = "test_network")]
+    output_dir: PathBuf,
+
+    /// Number of shards each helper runs
+    #[arg(long, default_value = "1")]
+    shards: NonZeroUsize,
+
+    /// Use insecure HTTP
+    #[arg(short = 'k', long)]
+    disable_https: bool,
+
+    /// Configure helper clients to use HTTP1 instead of default HTTP version (HTTP2 at the moment).
+    #[arg(long, default_value_t = false)]
+    use_http1: bool,
+
+    /// How long, in seconds, to wait for all helpers to become ready
+    #[arg(long, default_value = "30")]
+    wait: u64,
+
+    /// Helper binary to launch. Defaults to the running executable.
+    #[arg(long)]
+    helper_bin: Option<PathBuf>,
+
+    /// Command to run once all helpers are ready. The network is torn down when it exits.
+    /// Without it, helpers keep running until one of them exits or the launcher receives
+    /// SIGINT or SIGTERM.
+    #[arg(last = true, value_name = "COMMAND")]
+    command: Vec<OsString>,
+}
+
+impl TestNetworkArgs {
+    #[must_use]
+    pub fn new(output_dir: &Path, shards: NonZeroUsize, disable_https: bool) -> Self {
+        Self {
+            output_dir: output_dir.to_path_buf(),
+            shards,
+            disable_https,
+            use_http1: false,
+            wait: 30,
+            helper_bin: None,
+            command: Vec::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn with_helper_bin(mut self, helper_bin: &Path) -> Self {
+        self.helper_bin = Some(helper_bin.to_path_buf());
+        self
+    }
+
+    #[must_use]
+    pub fn with_command<I: IntoIterator<Item = S>, S: Into<OsString>>(
+        mut self,
+        command: I,
+    ) -> Self {
+        self.command = command.into_iter().map(Into::into).collect();
+        self
+    }
+}
+
+struct HelperProcess {
+    identity: usize,
+    shard: usize,
+    log_file: PathBuf,
+    child: Child,
+}
+
+/// A network of `3 * shards` helper processes running on this machine. All of them are
+/// killed when it is dropped.
+pub struct TestNetwork {
+    output_dir: PathBuf,
+    helpers: Vec<HelperProcess>,
+}
+
+impl TestNetwork {
+    /// Generates configuration for a fresh network in `args.output_dir`, launches all helpers
+    /// on free ports and waits until every one of them responds to `echo`.
+    ///
+    /// # Errors
+    /// If the configuration can't be generated, a helper fails to start or not all of them
+    /// are ready after `args.wait` seconds.
+    pub async fn start(args: &TestNetworkArgs) -> Result<Self, BoxError> {
+        let shards = args.shards.get();
+        let mut ports = free_ports(2 * 3 * shards)?;
+        let shard_ports = ports.split_off(3 * shards);
+        test_setup(&TestSetupArgs::new(
+            &args.output_dir,
+            args.disable_https,
+            args.use_http1,
+            ports.clone(),
+            shard_ports.clone(),
+        ))?;
+
+        let helper_bin = match &args.helper_bin {
+            Some(path) => path.clone(),
+            None => std::env::current_exe()?,
+        };
+        let mut network = Self {
+            output_dir: args.output_dir.clone(),
+            helpers: Vec::with_capacity(3 * shards),
+        };
+        for shard in 0..shards {
+            // `test_setup` only creates per-shard directories for sharded networks.
+            let config_dir = if shards > 1 {
+                args.output_dir.join(shard_conf_folder(shard))
+            } else {
+                args.output_dir.clone()
+            };
+            for id in 1..=3_u8 {
+                let identity = usize::from(id);
+                let index = 3 * shard + identity - 1;
+                let log_file = args
+                    .output_dir
+                    .join(format!("helper{identity}_shard{shard}.log"));
+                let log = File::create(&log_file)?;
+
+                let mut command = Command::new(&helper_bin);
+                command
+                    .args(["--identity", &identity.to_string()])
+                    .args(["--shard-index", &shard.to_string()])
+                    .args(["--shard-count", &shards.to_string()])
+                    .args(["--port", &ports[index].to_string()])
+                    .args(["--shard-port", &shard_ports[index].to_string()])
+                    .arg("--network")
+                    .arg(network.network_file())
+                    .arg("--mk-public-key")
+                    .arg(config_dir.helper_mk_public_key(id))
+                    .arg("--mk-private-key")
+                    .arg(config_dir.helper_mk_private_key(id));
+                if args.disable_https {
+                    command.arg("--disable-https");
+                } else {
+                    command
+                        .arg("--tls-cert")
+                        .arg(config_dir.helper_tls_cert(id))
+                        .arg("--tls-key")
+                        .arg(config_dir.helper_tls_key(id));
+                }
+                let child = command
+                    .stdin(Stdio::null())
+                    .stdout(log.try_clone()?)
+                    .stderr(log)
+                    .spawn()
+                    .map_err(|e| format!("unable to launch {}. {e}", helper_bin.display()))?;
+                network.helpers.push(HelperProcess {
+                    identity,
+                    shard,
+                    log_file,
+                    child,
+                });
+            }
+        }
+
+        let scheme = if args.disable_https {
+            Scheme::HTTP
+        } else {
+            Scheme::HTTPS
+        };
+        network
+            .wait_ready(&scheme, Duration::from_secs(args.wait))
+            .await?;
+        tracing::info!(
+            "{} helpers are ready, network configuration is in {}",
+            network.helpers.len(),
+            network.network_file().display()
+        );
+
+        Ok(network)
+    }
+
+    /// Path to the `network.toml` that clients can use to talk to this network.
+    #[must_use]
+    pub fn network_file(&self) -> PathBuf {
+        self.output_dir.join("network.toml")
+    }
+
+    /// Waits until any of the helpers exits. Helpers are not supposed to exit on their own,
+    /// so this always returns an error.
+    ///
+    /// # Errors
+    /// Once a helper exits.
+    pub async fn wait(&mut self) -> Result<(), BoxError> {
+        loop {
+            self.check_running()?;
+            sleep(Duration::from_secs(1)).await;
+        }
+    }
+
+    async fn wait_ready(&mut self, scheme: &Scheme, wait: Duration) -> Result<(), BoxError> {
+        let deadline = Instant::now() + wait;
+        let networks = NetworkConfig::<Helper>::from_toml_str_sharded(&fs::read_to_string(
+            self.network_file(),
+        )?)?;
+        for (shard, network) in networks.into_iter().enumerate() {
+            let network = network.override_scheme(scheme);
+            let clients =
+                IpaHttpClient::from_conf(&IpaRuntime::current(), &network, &ClientIdentity::None);
+            for (i, client) in clients.iter().enumerate() {
+                while let Err(e) = client.echo("").await {
+                    self.check_running()?;
+                    if Instant::now() >= deadline {
+                        return Err(format!(
+                            "helper {} of shard {shard} is not ready after {}s: {e}",
+                            i + 1,
+                            wait.as_secs()
+                        )
+                        .into());
+                    }
+                    sleep(Duration::from_millis(250)).await;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_running(&mut self) -> Result<(), BoxError> {
+        for helper in &mut self.helpers {
+            if let Some(status) = helper.child.try_wait()? {
+                return Err(format!(
+                    "helper {} of shard {} exited with {status}, see {} for details",
+                    helper.identity,
+                    helper.shard,
+                    helper.log_file.display()
+                )
+                .into());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for TestNetwork {
+    fn drop(&mut self) {
+        for helper in &mut self.helpers {
+            // The helper may have exited already, nothing else to do about it.
+            let _ = helper.child.kill();
+            let _ = helper.child.wait();
+        }
+    }
+}
+
+/// Launches a local test network. If a command is given, runs it once the network is ready
+/// and tears the network down after it exits. Otherwise, keeps the network running until one
+/// of the helpers exits. Either way, SIGINT or SIGTERM stops the command and the network.
+///
+/// # Errors
+/// If the network can't be started, the command fails or a helper exits.
+pub async fn test_network(args: &TestNetworkArgs) -> Result<(), BoxError> {
+    let mut network = TestNetwork::start(args).await?;
+
+    let Some((program, program_args)) = args.command.split_first() else {
+        let result = tokio::select! {
+            result = network.wait() => result,
+            result = shutdown_signal() => {
+                tracing::info!("Shutting down the test network");
+                result
+            }
+        };
+        drop(network);
+        return result;
+    };
+    let mut child = Command::new(program).args(program_args).spawn()?;
+    let exited = tokio::select! {
+        status = wait_for_exit(&mut child) => Ok(status),
+        result = shutdown_signal() => Err(result),
+    };
+    let status = match exited {
+        Ok(status) => status?,
+        Err(result) => {
+            tracing::info!("Shutting down the test network");
+            // The command may have exited already, nothing else to do about it.
+            let _ = child.kill();
+            let _ = child.wait();
+            drop(network);
+            return result;
+        }
+    };
+    drop(network);
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("{} exited with {status}", program.to_string_lossy()).into())
+    }
+}
+
+/// Waits until the child process exits.
+async fn wait_for_exit(child: &mut Child) -> Result<ExitStatus, BoxError> {
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+}
+
+/// Resolves once the process receives SIGINT or, on Unix, SIGTERM.
+async fn shutdown_signal() -> Result<(), BoxError> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result?,
+            _ = terminate.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await?;
+
+    Ok(())
+}
+
+/// Returns `count` distinct ports that are free at the moment. Listeners are held until all
+/// ports are picked, so the same port is not returned twice.
+fn free_ports(count: usize) -> Result<Vec<u16>, BoxError> {
+    let listeners = (0..count)
+        .map(|_| TcpListener::bind("127.0.0.1:0"))
+        .collect::<Result<Vec<_>, _>>()?;
+    listeners
+        .iter()
+        .map(|listener| Ok(listener.local_addr()?.port()))
+        .collect()
+}
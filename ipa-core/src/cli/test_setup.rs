@@ -5,12 +5,14 @@ use std::{
 };
 
 use clap::Args;
+use rand::thread_rng;
 
 use super::clientconf::shard_conf_folder;
 use crate::{
     cli::{
         config_parse::{gen_client_config, HelperClientConf},
         keygen,
+        keygen::keygen_matchkey,
         paths::PathExt,
         KeygenArgs,
     },
@@ -28,7 +30,8 @@ pub struct TestSetupArgs {
     #[arg(short, long, default_value = "test_data")]
     output_dir: PathBuf,
 
-    /// Ignored. The same configuration can be used for HTTP and HTTPS.
+    /// Use insecure HTTP. TLS certificates are not generated and `network.toml` points
+    /// clients at HTTP URLs.
     #[arg(long)]
     disable_https: bool,
 
@@ -48,6 +51,23 @@ pub struct TestSetupArgs {
 }
 
 impl TestSetupArgs {
+    #[must_use]
+    pub fn new(
+        output_dir: &Path,
+        disable_https: bool,
+        use_http1: bool,
+        ports: Vec<u16>,
+        shard_ports: Vec<u16>,
+    ) -> Self {
+        Self {
+            output_dir: output_dir.to_path_buf(),
+            disable_https,
+            use_http1,
+            ports,
+            shard_ports,
+        }
+    }
+
     /// Returns number of shards requested for setup.
     fn shard_count(&self) -> usize {
         self.ports.len() / 3
@@ -112,7 +132,7 @@ fn sharded_keygen(args: &TestSetupArgs) -> Result<(), BoxError> {
     .flat_map(|(shard_id, (mpc_ports, shard_ports))| {
         let shard_dir = args.output_dir.join(shard_conf_folder(shard_id));
         DirBuilder::new().create(&shard_dir)?;
-        make_client_configs(mpc_ports, shard_ports, &shard_dir)
+        make_client_configs(mpc_ports, shard_ports, &shard_dir, args.disable_https)
     })
     .flatten()
     .collect::<Vec<_>>();
@@ -142,7 +162,12 @@ fn sharded_keygen(args: &TestSetupArgs) -> Result<(), BoxError> {
 /// This generates directories and files needed to run a non-sharded MPC.
 /// The directory structure is flattened and does not include per-shard configuration.
 fn non_sharded_keygen(args: &TestSetupArgs) -> Result<(), BoxError> {
-    let client_configs = make_client_configs(&args.ports, &args.shard_ports, &args.output_dir)?;
+    let client_configs = make_client_configs(
+        &args.ports,
+        &args.shard_ports,
+        &args.output_dir,
+        args.disable_https,
+    )?;
 
     let mut conf_file = File::create(args.output_dir.join("network.toml"))?;
     gen_client_config(client_configs, args.use_http1, &mut conf_file)
@@ -152,6 +177,7 @@ fn make_client_configs(
     mpc_ports: &[u16],
     shard_ports: &[u16],
     config_dir: &Path,
+    disable_https: bool,
 ) -> Result<Vec<HelperClientConf>, BoxError> {
     assert_eq!(shard_ports.len(), mpc_ports.len());
     assert_eq!(3, shard_ports.len());
@@ -171,13 +197,19 @@ fn make_client_configs(
                 mk_private_key: Some(config_dir.helper_mk_private_key(id)),
             };
 
-            keygen(&keygen_args)?;
+            let tls_cert_file = if disable_https {
+                keygen_matchkey(&keygen_args, &mut thread_rng())?;
+                None
+            } else {
+                keygen(&keygen_args)?;
+                Some(keygen_args.tls_cert)
+            };
 
             Ok(HelperClientConf {
                 host: localhost.to_string(),
                 port: mpc_port,
                 shard_port,
-                tls_cert_file: keygen_args.tls_cert,
+                tls_cert_file,
                 mk_public_key_file: keygen_args.mk_public_key.unwrap(),
             })
         })
@@ -188,10 +220,11 @@ fn make_client_configs(
 mod tests {
     use std::fs;
 
+    use hyper::http::uri::Scheme;
     use tempfile::TempDir;
 
     use crate::{
-        cli::{sharded_server_from_toml_str, test_setup, TestSetupArgs},
+        cli::{paths::PathExt, sharded_server_from_toml_str, test_setup, TestSetupArgs},
         helpers::HelperIdentity,
         sharding::ShardIndex,
     };
@@ -220,6 +253,39 @@ mod tests {
         .unwrap();
     }
 
+    #[test]
+    fn disable_https() {
+        let temp_dir = TempDir::new().unwrap();
+        let outdir = temp_dir.path().to_path_buf();
+        let args = TestSetupArgs::new(
+            &outdir,
+            true,
+            false,
+            vec![3000, 3001, 3002],
+            vec![6000, 6001, 6002],
+        );
+        test_setup(&args).unwrap();
+
+        assert!(!outdir.helper_tls_cert(1).exists());
+        assert!(!outdir.helper_tls_key(1).exists());
+        assert!(outdir.helper_mk_public_key(1).exists());
+
+        let network_config_string = &fs::read_to_string(outdir.join("network.toml")).unwrap();
+        assert!(!network_config_string.contains("certificate"));
+        let (network, _) = sharded_server_from_toml_str(
+            network_config_string,
+            HelperIdentity::ONE,
+            ShardIndex::FIRST,
+            ShardIndex::from(1),
+            None,
+        )
+        .unwrap();
+        assert!(network
+            .peers()
+            .iter()
+            .all(|peer| peer.url.scheme() == Some(&Scheme::HTTP) && peer.certificate.is_none()));
+    }
+
     #[test]
     #[should_panic(expected = "Please provide a list of ports")]
     fn test_empty_ports() {
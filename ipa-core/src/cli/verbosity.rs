@@ -5,14 +5,16 @@ use std::{
 };
 
 use clap::Parser;
+use ipa_metrics_tracing::MetricsPartitioningLayer;
 use tracing::{info, metadata::LevelFilter, Level};
 use tracing_subscriber::{
-    fmt, fmt::format::FmtSpan, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter,
+    fmt, fmt::format::FmtSpan, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer,
 };
 
 use crate::{
     cli::{install_collector, metric_collector::CollectorHandle},
     error::set_global_panic_hook,
+    telemetry::trace::{OtlpFileExporter, TRACE_TARGET},
 };
 
 #[derive(Debug, Parser)]
@@ -27,6 +29,15 @@ pub struct Verbosity {
 
     #[arg(long, help = "Specify the output file for logs")]
     log_file: Option<PathBuf>,
+
+    /// Export tracing spans to this file in OTLP JSON format. Trace context is propagated
+    /// between helpers, so files from all helpers can be combined into a single trace.
+    #[arg(long, global = true)]
+    trace_file: Option<PathBuf>,
+
+    /// Service name reported for exported spans. Defaults to the name of the executable.
+    #[arg(long, global = true, requires = "trace_file")]
+    trace_service_name: Option<String>,
 }
 
 pub struct LoggingHandle {
@@ -46,11 +57,27 @@ impl Verbosity {
         let stderr_writer = fmt::layer()
             .with_span_events(FmtSpan::NEW | FmtSpan::CLOSE)
             .with_ansi(std::io::stderr().is_terminal())
-            .with_writer(stderr);
+            .with_writer(stderr)
+            .with_filter(filter_layer);
+
+        let trace_exporter = self.trace_file.as_ref().map(|path| {
+            let trace_file = OpenOptions::new()
+                .append(true)
+                .create(true)
+                .open(path)
+                .unwrap_or_else(|e| panic!("failed to open trace file {}: {e}", path.display()));
+            OtlpFileExporter::new(trace_file, self.trace_service_name())
+                .with_filter(LevelFilter::INFO)
+        });
+
+        // Every query runs inside its own metric partition that is set by a span.
+        // It must see query spans even if logging is turned off.
+        let metrics_partitioning = MetricsPartitioningLayer.with_filter(LevelFilter::INFO);
 
         let registry = tracing_subscriber::registry()
-            .with(filter_layer)
-            .with(stderr_writer);
+            .with(stderr_writer)
+            .with(trace_exporter)
+            .with(metrics_partitioning);
 
         if let Some(path) = &self.log_file {
             let log_file = OpenOptions::new()
@@ -61,7 +88,8 @@ impl Verbosity {
             let file_writer = fmt::layer()
                 .with_span_events(FmtSpan::NEW | FmtSpan::CLOSE)
                 .with_ansi(false)
-                .with_writer(log_file);
+                .with_writer(log_file)
+                .with_filter(self.log_filter());
 
             // that's the only stderr message that should appear to give a hint where
             // the logs are written to
@@ -94,5 +122,16 @@ impl Verbosity {
                 .into(),
             )
             .from_env_lossy()
+            // spans that only make sense for distributed tracing are not written to logs
+            .add_directive(format!("{TRACE_TARGET}=off").parse().unwrap())
+    }
+
+    fn trace_service_name(&self) -> String {
+        self.trace_service_name.clone().unwrap_or_else(|| {
+            std::env::current_exe()
+                .ok()
+                .and_then(|p| p.file_stem().map(|s| s.to_string_lossy().into_owned()))
+                .unwrap_or_else(|| env!("CARGO_PKG_NAME").to_string())
+        })
     }
 }
@@ -88,11 +88,14 @@ impl<F: ConnectionFlavor> NetworkConfig<F> {
     /// We currently require an exact match with the peer cert (i.e. we don't support verifying
     /// the certificate against a truststore and identifying the peer by the certificate
     /// subject). This could be changed if the need arises.
+    ///
+    /// While a peer rotates its certificate, both the current and the previous certificate
+    /// identify that peer. See [`PeerConfig::previous_certificate`].
     #[must_use]
     pub fn identify_cert(&self, cert: Option<&CertificateDer>) -> Option<F::Identity> {
         let cert = cert?;
         for (id, p) in zip(self.identities.iter(), self.peers.iter()) {
-            if p.certificate.as_ref() == Some(cert) {
+            if p.certificates().any(|c| c == cert) {
                 return Some(*id);
             }
         }
@@ -171,6 +174,15 @@ pub struct PeerConfig {
     #[serde(default, deserialize_with = "certificate_from_pem")]
     pub certificate: Option<OwnedCertificate>,
 
+    /// Peer's TLS certificate before the most recent rotation.
+    ///
+    /// Helpers pick up certificate changes without a restart, but they can't all do it at
+    /// the same time. Until the peer switches to `certificate`, connections authenticated
+    /// with this certificate are accepted as well. It should be removed from `network.toml`
+    /// when the rotation is complete.
+    #[serde(default, deserialize_with = "certificate_from_pem")]
+    pub previous_certificate: Option<OwnedCertificate>,
+
     /// Match key encryption configuration.
     #[serde(default, rename = "hpke")]
     pub hpke_config: Option<HpkeClientConfig>,
@@ -181,9 +193,18 @@ impl PeerConfig {
         Self {
             url,
             certificate,
+            previous_certificate: None,
             hpke_config: None,
         }
     }
+
+    /// Certificates this peer may use to authenticate: the current one and, during
+    /// rotation, the previous one.
+    pub fn certificates(&self) -> impl Iterator<Item = &OwnedCertificate> {
+        self.certificate
+            .iter()
+            .chain(self.previous_certificate.iter())
+    }
 }
 
 /// Match key encryption client configuration. To encrypt match keys towards a helper node, clients
@@ -106,6 +106,8 @@ pub enum Error {
     ShuffleValidationFailed(String),
     #[error("Duplicate bytes found after {0} checks")]
     DuplicateBytes(usize),
+    #[error("Group key {key} is out of range (expected less than {buckets})")]
+    GroupKeyOutOfRange { key: u128, buckets: usize },
 }
 
 impl Default for Error {
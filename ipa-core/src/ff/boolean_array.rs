@@ -8,7 +8,7 @@ use bitvec::{
     slice::Iter,
 };
 use generic_array::GenericArray;
-use typenum::{Unsigned, U12, U14, U18, U2, U32, U8};
+use typenum::{Unsigned, U12, U14, U18, U2, U22, U32, U8};
 
 use crate::{
     error::LengthError,
@@ -967,6 +967,9 @@ store_impl!(U14, 112);
 //impl store for U18
 store_impl!(U18, 144);
 
+//impl store for U22
+store_impl!(U22, 176);
+
 //impl store for U32
 store_impl!(U32, 256);
 
@@ -992,6 +995,7 @@ boolean_array_impl_small!(boolean_array_64, BA64, 64, infallible);
 boolean_array_impl_small!(boolean_array_96, BA96, 96, infallible);
 boolean_array_impl_small!(boolean_array_112, BA112, 112, infallible);
 boolean_array_impl_large!(boolean_array_144, BA144, 144, infallible, U18, U2);
+boolean_array_impl_large!(boolean_array_176, BA176, 176, infallible, U22, U2);
 boolean_array_impl_large!(boolean_array_256, BA256, 256, infallible, U32, U2);
 
 impl Vectorizable<256> for BA64 {
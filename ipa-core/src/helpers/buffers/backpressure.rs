@@ -0,0 +1,65 @@
+use crate::sync::atomic::{
+    AtomicUsize,
+    Ordering::{Acquire, Relaxed},
+};
+
+/// Measures how often send and receive buffers could not make progress.
+///
+/// [`OrderingSender`] stalls when its buffer is full, meaning that network can't drain
+/// it fast enough. [`UnorderedReceiver`] stalls when the next record hasn't arrived yet,
+/// meaning that data is not in flight when it is needed. The ratio between those two
+/// is used to pick the amount of active work.
+///
+/// The same instance is shared across many buffers, so counters are updated with relaxed
+/// ordering. Exact values are not important, only their proportions.
+///
+/// [`OrderingSender`]: super::OrderingSender
+/// [`UnorderedReceiver`]: super::UnorderedReceiver
+#[derive(Debug, Default)]
+pub struct Backpressure {
+    writes: AtomicUsize,
+    write_stalls: AtomicUsize,
+    reads: AtomicUsize,
+    read_stalls: AtomicUsize,
+}
+
+/// Point-in-time view of [`Backpressure`] counters.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BackpressureSnapshot {
+    /// Number of records written into send buffers.
+    pub writes: usize,
+    /// Number of times a write was attempted, but the send buffer was full.
+    pub write_stalls: usize,
+    /// Number of records read from receive buffers.
+    pub reads: usize,
+    /// Number of times a read was attempted, but there was no data to read.
+    pub read_stalls: usize,
+}
+
+impl Backpressure {
+    pub fn record_write(&self, stalled: bool) {
+        Self::record(&self.writes, &self.write_stalls, stalled);
+    }
+
+    pub fn record_read(&self, stalled: bool) {
+        Self::record(&self.reads, &self.read_stalls, stalled);
+    }
+
+    #[must_use]
+    pub fn snapshot(&self) -> BackpressureSnapshot {
+        BackpressureSnapshot {
+            writes: self.writes.load(Acquire),
+            write_stalls: self.write_stalls.load(Acquire),
+            reads: self.reads.load(Acquire),
+            read_stalls: self.read_stalls.load(Acquire),
+        }
+    }
+
+    fn record(ops: &AtomicUsize, stalls: &AtomicUsize, stalled: bool) {
+        if stalled {
+            stalls.fetch_add(1, Relaxed);
+        } else {
+            ops.fetch_add(1, Relaxed);
+        }
+    }
+}
@@ -1,8 +1,10 @@
+mod backpressure;
 mod ordering_sender;
 mod unordered_receiver;
 
 mod circular;
 
+pub use backpressure::{Backpressure, BackpressureSnapshot};
 pub use ordering_sender::OrderingSender;
 pub use unordered_receiver::{
     DeserializeError, EndOfStreamError, Error as UnorderedReceiverError, UnorderedReceiver,
@@ -12,13 +12,16 @@ use std::{
 use futures::{task::Waker, Future, Stream};
 
 use crate::{
-    helpers::{buffers::circular::CircularBuf, Message},
+    helpers::{
+        buffers::{circular::CircularBuf, Backpressure},
+        Message,
+    },
     sync::{
         atomic::{
             AtomicUsize,
             Ordering::{AcqRel, Acquire},
         },
-        Mutex, MutexGuard,
+        Arc, Mutex, MutexGuard,
     },
 };
 
@@ -273,6 +276,7 @@ pub struct OrderingSender {
     next: AtomicUsize,
     state: Mutex<State>,
     waiting: Waiting,
+    backpressure: Option<Arc<Backpressure>>,
 }
 
 impl OrderingSender {
@@ -293,6 +297,16 @@ impl OrderingSender {
                 read_threshold.get(),
             )),
             waiting: Waiting::default(),
+            backpressure: None,
+        }
+    }
+
+    /// Report every write and every time this sender's buffer is full to `backpressure`.
+    #[must_use]
+    pub fn with_backpressure(self, backpressure: Arc<Backpressure>) -> Self {
+        Self {
+            backpressure: Some(backpressure),
+            ..self
         }
     }
 
@@ -326,6 +340,21 @@ impl OrderingSender {
         Close { i, sender: self }
     }
 
+    /// Closes the sender right away, without waiting for the messages that have not been sent
+    /// yet. The stream yields whatever is left in the buffer and ends, so the receiving side
+    /// finds out that no more data is coming. It is a no-op if this sender is closed already.
+    ///
+    /// No messages can be sent after this call.
+    ///
+    /// ## Panics
+    /// If the underlying mutex is poisoned or locked by the same thread.
+    pub fn abort(&self) {
+        let mut state = self.state.lock().unwrap();
+        if !state.is_closed() {
+            state.close();
+        }
+    }
+
     /// Returns `true` if this sender is closed for writes.
     ///
     /// ## Panics
@@ -457,7 +486,11 @@ impl<'a, M: Message, B: Borrow<M> + 'a> Future for Send<'a, M, B> {
 
         let res = this.sender.next_op(this.i, cx, |b| {
             assert!(!b.is_closed(), "writing on a closed stream");
-            b.write(this.m.borrow(), cx)
+            let res = b.write(this.m.borrow(), cx);
+            if let Some(backpressure) = &this.sender.backpressure {
+                backpressure.record_write(res.is_pending());
+            }
+            res
         });
         // A successful write: wake the next in line.
         // But not while holding the lock on state.
@@ -12,7 +12,7 @@ use typenum::Unsigned;
 
 use crate::{
     error::BoxError,
-    helpers::Message,
+    helpers::{buffers::Backpressure, Message},
     protocol::RecordId,
     sync::{Arc, Mutex},
 };
@@ -169,6 +169,8 @@ where
     overflow_wakers: Vec<(Waker, usize)>,
     #[cfg(not(feature = "stall-detection"))]
     overflow_wakers: Vec<Waker>,
+    /// If set, every read and every time there is no data to read are reported here.
+    backpressure: Option<Arc<Backpressure>>,
     _marker: PhantomData<C>,
 }
 
@@ -237,6 +239,7 @@ where
     fn poll_next<M: Message>(&mut self, cx: &mut Context<'_>) -> Poll<Result<M, Error>> {
         self.max_polled_idx = std::cmp::max(self.max_polled_idx, Some(self.next));
         if let Some(m) = self.spare.read() {
+            self.record_read(false);
             self.wake_next();
             return Poll::Ready(m.map_err(|e| DeserializeError::new::<M>(self.next, e).into()));
         }
@@ -244,12 +247,14 @@ where
         loop {
             match self.stream.as_mut().poll_next(cx) {
                 Poll::Pending => {
+                    self.record_read(true);
                     return Poll::Pending;
                 }
                 Poll::Ready(Some(b)) => {
                     let b = b.as_ref();
                     tracing::trace!(len = b.len(), "next chunk");
                     if let Some(m) = self.spare.extend(b) {
+                        self.record_read(false);
                         self.wake_next();
                         return Poll::Ready(
                             m.map_err(|e| DeserializeError::new::<M>(self.next, e).into()),
@@ -263,6 +268,12 @@ where
         }
     }
 
+    fn record_read(&self, stalled: bool) {
+        if let Some(backpressure) = &self.backpressure {
+            backpressure.record_read(stalled);
+        }
+    }
+
     #[cfg(feature = "stall-detection")]
     fn waiting(&self) -> impl Iterator<Item = usize> + '_ {
         let start = self.next % self.wakers.len();
@@ -320,11 +331,22 @@ where
                 spare: Spare::default(),
                 wakers,
                 overflow_wakers: Vec::new(),
+                backpressure: None,
                 _marker: PhantomData,
             })),
         }
     }
 
+    /// Report every read and every time there is no data available to read to `backpressure`.
+    ///
+    /// ## Panics
+    /// If the internal mutex is poisoned.
+    #[must_use]
+    pub fn with_backpressure(self, backpressure: Arc<Backpressure>) -> Self {
+        self.inner.lock().unwrap().backpressure = Some(backpressure);
+        self
+    }
+
     /// Receive from the stream at index `i`.
     ///
     /// # Panics
@@ -0,0 +1,107 @@
+//! Policy for picking the amount of active work based on the observed backpressure.
+//!
+//! If send buffers are full often, network can't keep up with the amount of data produced,
+//! so a large window only costs memory. If send buffers are rarely full, but receivers keep
+//! waiting for data to arrive, more records need to be in flight to hide network latency.
+
+use std::cmp::{max, min};
+
+use crate::{helpers::buffers::BackpressureSnapshot, utils::NonZeroU32PowerOfTwo};
+
+/// Number of records that must go through the buffers before backpressure measurements
+/// can be trusted.
+const MIN_SAMPLES: usize = 1024;
+
+/// Active work is never reduced below this value. `UnorderedReceiver` requires capacity
+/// to be greater than 1.
+pub(crate) const MIN_ACTIVE_WORK: usize = 2;
+
+/// Proposes the next value for active work given the current one and the backpressure
+/// observed so far. The result is always a power of two within `[2, max_active_work]`.
+pub(super) fn propose_active_work(
+    current: NonZeroU32PowerOfTwo,
+    max_active_work: NonZeroU32PowerOfTwo,
+    stats: &BackpressureSnapshot,
+) -> NonZeroU32PowerOfTwo {
+    if stats.writes < MIN_SAMPLES || stats.reads < MIN_SAMPLES {
+        return current;
+    }
+
+    let current = current.get();
+    let proposal = if stats.write_stalls * 10 > stats.writes {
+        // buffers are full more than 10% of the time
+        max(MIN_ACTIVE_WORK, current / 2)
+    } else if stats.read_stalls * 4 > stats.reads {
+        // receivers starve more than 25% of the time
+        min(max_active_work.get(), current * 2)
+    } else {
+        current
+    };
+
+    NonZeroU32PowerOfTwo::try_from(proposal).unwrap()
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use super::propose_active_work;
+    use crate::{helpers::buffers::BackpressureSnapshot, utils::NonZeroU32PowerOfTwo};
+
+    fn active(v: usize) -> NonZeroU32PowerOfTwo {
+        NonZeroU32PowerOfTwo::try_from(v).unwrap()
+    }
+
+    fn stats(write_stalls: usize, read_stalls: usize) -> BackpressureSnapshot {
+        BackpressureSnapshot {
+            writes: 10_000,
+            write_stalls,
+            reads: 10_000,
+            read_stalls,
+        }
+    }
+
+    #[test]
+    fn not_enough_samples() {
+        let stats = BackpressureSnapshot {
+            writes: 10,
+            write_stalls: 10,
+            reads: 10,
+            read_stalls: 0,
+        };
+        assert_eq!(
+            active(64),
+            propose_active_work(active(64), active(1024), &stats)
+        );
+    }
+
+    #[test]
+    fn shrinks_when_send_buffers_are_full() {
+        assert_eq!(
+            active(32),
+            propose_active_work(active(64), active(1024), &stats(5_000, 5_000))
+        );
+        assert_eq!(
+            active(2),
+            propose_active_work(active(2), active(1024), &stats(5_000, 0))
+        );
+    }
+
+    #[test]
+    fn grows_when_receivers_starve() {
+        assert_eq!(
+            active(128),
+            propose_active_work(active(64), active(1024), &stats(0, 5_000))
+        );
+        assert_eq!(
+            active(1024),
+            propose_active_work(active(1024), active(1024), &stats(0, 5_000))
+        );
+    }
+
+    #[test]
+    fn keeps_when_balanced() {
+        assert_eq!(
+            active(64),
+            propose_active_work(active(64), active(1024), &stats(100, 100))
+        );
+    }
+}
@@ -1,3 +1,5 @@
+mod adaptive;
+mod progress;
 mod receive;
 mod send;
 #[cfg(feature = "stall-detection")]
@@ -9,6 +11,8 @@ use std::{
     num::NonZeroUsize,
 };
 
+pub(crate) use adaptive::MIN_ACTIVE_WORK;
+pub use progress::{min_progress, PhaseProgress, Progress, QueryProgress};
 pub(super) use receive::{MpcReceivingEnd, ShardReceivingEnd};
 pub(super) use send::SendingEnd;
 #[cfg(feature = "stall-detection")]
@@ -17,7 +21,7 @@ pub use transport::RoleResolvingTransport;
 
 use crate::{
     helpers::{
-        buffers::UnorderedReceiver,
+        buffers::{Backpressure, UnorderedReceiver},
         gateway::{
             receive::{GatewayReceivers, ShardReceiveStream, UR},
             send::GatewaySenders,
@@ -61,6 +65,10 @@ pub struct Gateway {
     inner: crate::sync::Arc<State>,
     #[cfg(not(feature = "stall-detection"))]
     inner: State,
+    /// Shared by all MPC send and receive buffers, if adaptive active work is enabled.
+    backpressure: Option<Arc<Backpressure>>,
+    /// Shared by all send channels, reported by the query status API.
+    progress: Arc<Progress>,
 }
 
 #[derive(Default)]
@@ -71,6 +79,16 @@ pub struct State {
     shard_receivers: GatewayReceivers<ShardIndex, ShardReceiveStream>,
 }
 
+impl State {
+    fn new(backpressure: Option<&Arc<Backpressure>>, progress: &Arc<Progress>) -> Self {
+        Self {
+            mpc_senders: GatewaySenders::new(backpressure.map(Arc::clone), Arc::clone(progress)),
+            shard_senders: GatewaySenders::new(None, Arc::clone(progress)),
+            ..Self::default()
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct GatewayConfig {
     /// The number of items that can be active at the one time.
@@ -104,6 +122,21 @@ pub struct GatewayConfig {
     /// send/receive requests
     #[cfg(feature = "stall-detection")]
     pub progress_check_interval: std::time::Duration,
+
+    /// If set, gateway measures backpressure inside MPC send and receive buffers, so
+    /// protocols can adjust the amount of active work for subsequent steps.
+    /// See [`Gateway::propose_active_work`].
+    pub adaptive_active_work: bool,
+}
+
+impl Drop for Gateway {
+    /// Gateway is dropped once the query is over, either because it finished or because its
+    /// task was aborted by the kill API. In the latter case, some channels may still be open,
+    /// leaving peers blocked on receiving records. Closing them wakes the peers up.
+    fn drop(&mut self) {
+        self.inner.mpc_senders.abort();
+        self.inner.shard_senders.abort();
+    }
 }
 
 impl ShardConfiguration for Gateway {
@@ -137,6 +170,10 @@ impl Gateway {
         shard_transport: ShardTransportImpl,
     ) -> Self {
         tracing::debug!("active_work = {}", config.active);
+        let backpressure = config
+            .adaptive_active_work
+            .then(|| Arc::new(Backpressure::default()));
+        let progress = Arc::new(Progress::default());
         #[allow(clippy::useless_conversion)] // not useless in stall-detection build
         Self {
             query_id,
@@ -148,7 +185,9 @@ impl Gateway {
                 },
                 shard: shard_transport,
             },
-            inner: State::default().into(),
+            inner: State::new(backpressure.as_ref(), &progress).into(),
+            backpressure,
+            progress,
         }
     }
 
@@ -162,6 +201,33 @@ impl Gateway {
         &self.config
     }
 
+    /// Returns the progress tracker of this gateway. It is updated while the query runs.
+    #[must_use]
+    pub fn progress(&self) -> &Arc<Progress> {
+        &self.progress
+    }
+
+    /// Proposes the amount of active work for the next steps, based on the backpressure
+    /// observed so far. If adaptive active work is not enabled, there is nothing to propose
+    /// and `None` is returned.
+    ///
+    /// Helpers must agree on the value before using it, because the flush threshold
+    /// of send buffers must not exceed the window of the receiving helper. For the same
+    /// reason, the proposal never exceeds the active work set in [`GatewayConfig`], which
+    /// is what receivers use.
+    #[must_use]
+    pub fn propose_active_work(
+        &self,
+        current: NonZeroU32PowerOfTwo,
+    ) -> Option<NonZeroU32PowerOfTwo> {
+        let backpressure = self.backpressure.as_ref()?;
+        let snapshot = backpressure.snapshot();
+        let proposal = adaptive::propose_active_work(current, self.config.active, &snapshot);
+        tracing::info!("observed {snapshot:?}, proposing active work {current} -> {proposal}");
+
+        Some(proposal)
+    }
+
     /// Returns a sender suitable for sending data between MPC helpers. The data must be approved
     /// for sending by implementing [`MpcMessage`] trait.
     ///
@@ -232,13 +298,17 @@ impl Gateway {
         receive::MpcReceivingEnd::new(
             channel_id.clone(),
             self.inner.mpc_receivers.get_or_create(channel_id, || {
-                UnorderedReceiver::new(
+                let rx = UnorderedReceiver::new(
                     Box::pin(LogErrors::new(self.transports.mpc.receive(
                         channel_id.peer,
                         (self.query_id, channel_id.gate.clone()),
                     ))),
                     self.config.active_work(),
-                )
+                );
+                match &self.backpressure {
+                    Some(backpressure) => rx.with_backpressure(Arc::clone(backpressure)),
+                    None => rx,
+                }
             }),
         )
     }
@@ -286,6 +356,7 @@ impl Default for GatewayConfig {
             } else {
                 30
             }),
+            adaptive_active_work: false,
         }
     }
 }
@@ -357,18 +428,19 @@ mod tests {
         helpers::{
             gateway::QueryConfig,
             query::{QuerySize, QueryType},
-            ChannelId, Direction, GatewayConfig, MpcMessage, MpcReceivingEnd, Role, SendingEnd,
-            TotalRecords,
+            ChannelId, Direction, Error, Gateway, GatewayConfig, HelperIdentity,
+            InMemoryMpcNetwork, InMemoryShardNetwork, MpcMessage, MpcReceivingEnd, Role,
+            RoleAssignment, SendingEnd, TotalRecords, Transport,
         },
         protocol::{
             context::{Context, ShardedContext},
-            Gate, RecordId,
+            Gate, QueryId, RecordId,
         },
         secret_sharing::{
             replicated::semi_honest::AdditiveShare, SharedValue, SharedValueArray, StdArray,
         },
         seq_join::seq_join,
-        sharding::ShardConfiguration,
+        sharding::{ShardConfiguration, ShardIndex},
         test_executor::run,
         test_fixture::{Reconstruct, Runner, TestWorld, TestWorldConfig, WithShards},
         utils::NonZeroU32PowerOfTwo,
@@ -459,6 +531,45 @@ mod tests {
         let _world = unsafe { Box::from_raw(world_ptr) };
     }
 
+    /// When a query is killed, its gateway is dropped before all records are sent. Peers
+    /// waiting for them must not be blocked forever.
+    #[tokio::test]
+    async fn drop_closes_send_channels() {
+        let mpc_network = InMemoryMpcNetwork::default();
+        let shard_network = InMemoryShardNetwork::with_shards(1);
+        let [h1, h2, _] = mpc_network.transports().map(|transport| {
+            let shard_transport = shard_network.transport(transport.identity(), ShardIndex::FIRST);
+            Gateway::new(
+                QueryId,
+                GatewayConfig::default(),
+                RoleAssignment::new(HelperIdentity::make_three()),
+                transport,
+                shard_transport,
+            )
+        });
+        let gate = Gate::from("drop-test");
+
+        h1.get_mpc_sender::<Fp31>(
+            &ChannelId::new(Role::H2, gate.clone()),
+            TotalRecords::specified(2).unwrap(),
+            h1.config().active_work_as_power_of_two(),
+        )
+        .send(RecordId::FIRST, Fp31::truncate_from(1_u128))
+        .await
+        .unwrap();
+        drop(h1);
+
+        let recv_channel = h2.get_mpc_receiver::<Fp31>(&ChannelId::new(Role::H1, gate));
+        assert_eq!(
+            Fp31::truncate_from(1_u128),
+            recv_channel.receive(RecordId::FIRST).await.unwrap()
+        );
+        assert!(matches!(
+            recv_channel.receive(RecordId::from(1)).await,
+            Err(Error::EndOfStream { .. })
+        ));
+    }
+
     /// this test requires quite a few threads to simulate send contention and will panic if
     /// there is more than one sender channel created per step.
     #[tokio::test(flavor = "multi_thread", worker_threads = 20)]
@@ -0,0 +1,300 @@
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    helpers::TotalRecords,
+    protocol::Gate,
+    sync::{
+        atomic::{AtomicUsize, Ordering::Relaxed},
+        Arc, Mutex,
+    },
+};
+
+/// Tracks how far query execution got on this helper. Every send channel opened by the gateway
+/// is attributed to the top-level protocol step of its gate, i.e. the first two components
+/// of the gate path below the root gate, like `hybrid/eval_prf`. The step of the most recently opened channel
+/// is the one currently executing.
+///
+/// Counters are shared with send channels and updated with relaxed ordering on every record
+/// sent, the values are only used for reporting.
+#[derive(Debug, Default)]
+pub struct Progress {
+    phases: Mutex<Phases>,
+}
+
+#[derive(Debug, Default)]
+struct Phases {
+    /// Top-level steps, in the order they started.
+    all: Vec<Phase>,
+    /// Index of the step currently executing.
+    current: usize,
+}
+
+#[derive(Debug)]
+struct Phase {
+    step: String,
+    started_at: Instant,
+    records: Arc<RecordCounter>,
+}
+
+/// Records sent and expected to be sent by channels opened within one top-level step.
+#[derive(Debug, Default)]
+pub(super) struct RecordCounter {
+    processed: AtomicUsize,
+    expected: AtomicUsize,
+}
+
+/// Point-in-time view of [`Progress`], reported by the query status API.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueryProgress {
+    /// Top-level protocol step currently executing.
+    pub step: String,
+    /// Number of records sent so far in the current step.
+    pub records_processed: usize,
+    /// Number of records expected to be sent in the current step, according to
+    /// [`TotalRecords`] of the channels opened so far.
+    pub records_expected: usize,
+    /// Every top-level step that started so far, in order.
+    pub phases: Vec<PhaseProgress>,
+}
+
+/// Time spent in one top-level protocol step.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PhaseProgress {
+    pub step: String,
+    /// Time from the start of this step until the next one started, or until now for the
+    /// step currently executing.
+    pub elapsed: Duration,
+}
+
+impl RecordCounter {
+    pub fn record_sent(&self) {
+        self.processed.fetch_add(1, Relaxed);
+    }
+}
+
+impl Progress {
+    /// Attributes a new send channel for `gate` to its top-level step and returns the counter
+    /// it must update when records are sent. Channels with indeterminate number of records
+    /// are not tracked, because there is nothing to compare the records sent against.
+    pub(super) fn channel_opened(
+        &self,
+        gate: &Gate,
+        total_records: TotalRecords,
+    ) -> Option<Arc<RecordCounter>> {
+        let TotalRecords::Specified(count) = total_records else {
+            return None;
+        };
+        let step = top_level_step(gate);
+        let mut phases = self.phases.lock().unwrap();
+        let index = if let Some(index) = phases.all.iter().position(|phase| phase.step == step) {
+            index
+        } else {
+            phases.all.push(Phase {
+                step: step.to_string(),
+                started_at: Instant::now(),
+                records: Arc::default(),
+            });
+            phases.all.len() - 1
+        };
+        phases.current = index;
+        let records = &phases.all[index].records;
+        records.expected.fetch_add(count.get(), Relaxed);
+
+        Some(Arc::clone(records))
+    }
+
+    /// Returns the progress made so far or [`None`] if no channels have been opened yet.
+    ///
+    /// ## Panics
+    /// If the mutex is poisoned.
+    #[must_use]
+    pub fn snapshot(&self) -> Option<QueryProgress> {
+        let phases = self.phases.lock().unwrap();
+        let current = phases.all.get(phases.current)?;
+        let now = Instant::now();
+        let ended_at = phases
+            .all
+            .iter()
+            .skip(1)
+            .map(|phase| phase.started_at)
+            .chain([now]);
+
+        Some(QueryProgress {
+            step: current.step.clone(),
+            records_processed: current.records.processed.load(Relaxed),
+            records_expected: current.records.expected.load(Relaxed),
+            phases: phases
+                .all
+                .iter()
+                .zip(ended_at)
+                .map(|(phase, ended_at)| PhaseProgress {
+                    step: phase.step.clone(),
+                    elapsed: ended_at.saturating_duration_since(phase.started_at),
+                })
+                .collect(),
+        })
+    }
+}
+
+impl QueryProgress {
+    /// Fraction of the current step completed, between 0 and 1.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn completion(&self) -> f64 {
+        if self.records_expected == 0 {
+            0.0
+        } else {
+            (self.records_processed as f64 / self.records_expected as f64).min(1.0)
+        }
+    }
+}
+
+/// Merges progress reported by different shards of the same helper, similar to
+/// [`min_status`]: the shard that is behind describes the entire helper. A shard is behind
+/// if it started fewer steps or has completed a smaller fraction of the current one.
+/// Time spent in every step is the longest time any shard spent in it.
+///
+/// [`min_status`]: crate::query::min_status
+#[must_use]
+pub fn min_progress(a: Option<QueryProgress>, b: Option<QueryProgress>) -> Option<QueryProgress> {
+    let (a, b) = (a?, b?);
+    let (mut behind, ahead) =
+        if (a.phases.len(), a.completion()) <= (b.phases.len(), b.completion()) {
+            (a, b)
+        } else {
+            (b, a)
+        };
+    for (phase, other) in behind.phases.iter_mut().zip(ahead.phases) {
+        phase.elapsed = phase.elapsed.max(other.elapsed);
+    }
+
+    Some(behind)
+}
+
+fn top_level_step(gate: &Gate) -> &str {
+    let path = gate.as_ref();
+    let path = path
+        .strip_prefix(Gate::default().as_ref())
+        .unwrap_or(path)
+        .trim_start_matches('/');
+    match path.match_indices('/').nth(1) {
+        Some((i, _)) => &path[..i],
+        None => path,
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use std::time::Duration;
+
+    use super::{min_progress, top_level_step, PhaseProgress, Progress, QueryProgress};
+    use ipa_step::StepNarrow;
+
+    use crate::{
+        helpers::TotalRecords,
+        protocol::{
+            hybrid::step::{AggregateReportsStep, HybridStep},
+            step::ProtocolStep,
+            Gate,
+        },
+    };
+
+    #[test]
+    fn top_level() {
+        let hybrid = Gate::default().narrow(&ProtocolStep::Hybrid);
+        assert_eq!("", top_level_step(&Gate::default()));
+        assert_eq!("hybrid", top_level_step(&hybrid));
+        assert_eq!(
+            "hybrid/group_by_sum",
+            top_level_step(&hybrid.narrow(&HybridStep::GroupBySum))
+        );
+        assert_eq!(
+            "hybrid/group_by_sum",
+            top_level_step(
+                &hybrid
+                    .narrow(&HybridStep::GroupBySum)
+                    .narrow(&AggregateReportsStep::AddBK)
+            )
+        );
+    }
+
+    #[test]
+    fn tracks_current_step() {
+        let progress = Progress::default();
+        assert_eq!(None, progress.snapshot());
+
+        let gate = |s: &str| Gate::from(s);
+        let first = progress
+            .channel_opened(
+                &gate("/hybrid/group_by_sum/add_bk"),
+                TotalRecords::specified(4).unwrap(),
+            )
+            .unwrap();
+        let second = progress
+            .channel_opened(
+                &gate("/hybrid/group_by_sum/add_v"),
+                TotalRecords::specified(6).unwrap(),
+            )
+            .unwrap();
+        assert!(progress
+            .channel_opened(&gate("/hybrid/reshard_by_tag"), TotalRecords::Indeterminate)
+            .is_none());
+        first.record_sent();
+        second.record_sent();
+
+        let snapshot = progress.snapshot().unwrap();
+        assert_eq!("hybrid/group_by_sum", snapshot.step);
+        assert_eq!(2, snapshot.records_processed);
+        assert_eq!(10, snapshot.records_expected);
+
+        progress
+            .channel_opened(
+                &gate("/hybrid/finalize"),
+                TotalRecords::specified(3).unwrap(),
+            )
+            .unwrap();
+        let snapshot = progress.snapshot().unwrap();
+        assert_eq!("hybrid/finalize", snapshot.step);
+        assert_eq!(0, snapshot.records_processed);
+        assert_eq!(3, snapshot.records_expected);
+        assert_eq!(
+            vec!["hybrid/group_by_sum", "hybrid/finalize"],
+            snapshot
+                .phases
+                .iter()
+                .map(|phase| phase.step.as_str())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    fn progress(processed: usize, phases: &[u64]) -> QueryProgress {
+        QueryProgress {
+            step: format!("/{}", phases.len()),
+            records_processed: processed,
+            records_expected: 10,
+            phases: phases
+                .iter()
+                .enumerate()
+                .map(|(i, &secs)| PhaseProgress {
+                    step: format!("/{}", i + 1),
+                    elapsed: Duration::from_secs(secs),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn merge_picks_shard_behind() {
+        assert_eq!(None, min_progress(Some(progress(1, &[1])), None));
+        assert_eq!(
+            Some(progress(9, &[3, 2])),
+            min_progress(Some(progress(9, &[1, 2])), Some(progress(1, &[3, 1, 1]))),
+        );
+        assert_eq!(
+            Some(progress(5, &[2, 4])),
+            min_progress(Some(progress(7, &[2, 1])), Some(progress(5, &[1, 4]))),
+        );
+    }
+}
@@ -7,7 +7,9 @@ use std::{
 use bytes::Bytes;
 use dashmap::{mapref::entry::Entry, DashMap};
 use futures::Stream;
+use ipa_metrics::counter;
 use pin_project::pin_project;
+use typenum::Unsigned;
 
 use crate::{
     error::BoxError,
@@ -20,6 +22,10 @@ use crate::{
     },
     protocol::RecordId,
     sync::{Arc, Mutex},
+    telemetry::{
+        labels::STEP,
+        metrics::{BYTES_RECEIVED, RECORDS_RECEIVED},
+    },
 };
 
 /// Receiving end of the MPC gateway channel.
@@ -81,7 +87,8 @@ impl<M: MpcMessage> MpcReceivingEnd<M> {
     /// and sent to this helper.
     #[tracing::instrument(level = "trace", "receive", skip_all, fields(i = %record_id, from = ?self.channel_id.peer, gate = ?self.channel_id.gate.as_ref()))]
     pub async fn receive(&self, record_id: RecordId) -> Result<M, Error<Role>> {
-        self.unordered_rx
+        let r = self
+            .unordered_rx
             .recv::<M, _>(record_id)
             .await
             .map_err(|e| match e {
@@ -93,7 +100,13 @@ impl<M: MpcMessage> MpcReceivingEnd<M> {
                     channel_id: self.channel_id.clone(),
                     inner,
                 },
-            })
+            });
+        if r.is_ok() {
+            counter!(RECORDS_RECEIVED, 1, STEP => &self.channel_id.gate);
+            counter!(BYTES_RECEIVED, M::Size::U64, STEP => &self.channel_id.gate);
+        }
+
+        r
     }
 }
 
@@ -12,18 +12,22 @@ use futures::Stream;
 use ipa_metrics::counter;
 #[cfg(all(test, feature = "shuttle"))]
 use shuttle::future as tokio;
+use tracing::Instrument;
 use typenum::Unsigned;
 
 use crate::{
     helpers::{
-        buffers::OrderingSender, routing::RouteId, ChannelId, Error, GatewayConfig, Message,
-        TotalRecords, Transport, TransportIdentity,
+        buffers::{Backpressure, OrderingSender},
+        gateway::progress::{Progress, RecordCounter},
+        routing::RouteId,
+        ChannelId, Error, GatewayConfig, Message, TotalRecords, Transport, TransportIdentity,
     },
     protocol::{QueryId, RecordId},
     sync::Arc,
     telemetry::{
         labels::{ROLE, STEP},
         metrics::{BYTES_SENT, RECORDS_SENT},
+        trace::TRACE_TARGET,
     },
     utils::non_zero_prev_power_of_two,
 };
@@ -39,12 +43,17 @@ pub struct SendingEnd<I: TransportIdentity, M> {
 /// Sending channels, indexed by identity and gate.
 pub(super) struct GatewaySenders<I> {
     pub(super) inner: DashMap<ChannelId<I>, Arc<GatewaySender<I>>>,
+    /// If set, all senders report backpressure here.
+    backpressure: Option<Arc<Backpressure>>,
+    /// All senders report records sent here.
+    progress: Arc<Progress>,
 }
 
 pub(super) struct GatewaySender<I> {
     channel_id: ChannelId<I>,
     ordering_tx: OrderingSender,
     total_records: TotalRecords,
+    records: Option<Arc<RecordCounter>>,
 }
 
 struct GatewaySendStream<I> {
@@ -70,18 +79,22 @@ struct SendChannelConfig {
 
 impl<I: TransportIdentity> Default for GatewaySenders<I> {
     fn default() -> Self {
-        Self {
-            inner: DashMap::default(),
-        }
+        Self::new(None, Arc::default())
     }
 }
 
 impl<I: TransportIdentity> GatewaySender<I> {
-    fn new(channel_id: ChannelId<I>, tx: OrderingSender, total_records: TotalRecords) -> Self {
+    fn new(
+        channel_id: ChannelId<I>,
+        tx: OrderingSender,
+        total_records: TotalRecords,
+        records: Option<Arc<RecordCounter>>,
+    ) -> Self {
         Self {
             channel_id,
             ordering_tx: tx,
             total_records,
+            records,
         }
     }
 
@@ -108,6 +121,9 @@ impl<I: TransportIdentity> GatewaySender<I> {
         // TODO: test channel close
         let i = usize::from(record_id);
         self.ordering_tx.send(i, msg).await;
+        if let Some(records) = &self.records {
+            records.record_sent();
+        }
         if self.total_records.is_last(record_id) {
             self.ordering_tx.close(i + 1).await;
         }
@@ -132,6 +148,10 @@ impl<I: TransportIdentity> GatewaySender<I> {
     pub async fn close(&self, at: RecordId) {
         self.ordering_tx.close(at.into()).await;
     }
+
+    fn abort(&self) {
+        self.ordering_tx.abort();
+    }
 }
 
 impl<I: TransportIdentity, M: Message> SendingEnd<I, M> {
@@ -186,6 +206,14 @@ impl<I: TransportIdentity, M: Message> SendingEnd<I, M> {
 }
 
 impl<I: TransportIdentity> GatewaySenders<I> {
+    pub fn new(backpressure: Option<Arc<Backpressure>>, progress: Arc<Progress>) -> Self {
+        Self {
+            inner: DashMap::default(),
+            backpressure,
+            progress,
+        }
+    }
+
     /// Returns a communication channel for the given [`ChannelId`]. If it does not exist, it will
     /// be created using the provided [`Transport`] implementation.
     pub fn get<M: Message, T: Transport<Identity = I>>(
@@ -207,7 +235,7 @@ impl<I: TransportIdentity> GatewaySenders<I> {
             Entry::Vacant(entry) => {
                 let config = SendChannelConfig::new::<M>(config, total_records);
                 tracing::trace!("send configuration for {channel_id:?}: {config:?}");
-                let sender = Self::new_sender(&config, channel_id.clone());
+                let sender = self.new_sender(&config, channel_id.clone());
                 entry.insert(Arc::clone(&sender));
 
                 tokio::spawn({
@@ -216,6 +244,8 @@ impl<I: TransportIdentity> GatewaySenders<I> {
                     let stream = GatewaySendStream {
                         inner: Arc::clone(&sender),
                     };
+                    let span =
+                        tracing::info_span!(target: TRACE_TARGET, "send", to = ?peer, gate = ?gate);
                     async move {
                         // TODO(651): In the HTTP case we probably need more robust error handling here.
                         transport
@@ -223,6 +253,7 @@ impl<I: TransportIdentity> GatewaySenders<I> {
                             .await
                             .expect("{channel_id:?} receiving end should be accepted by transport");
                     }
+                    .instrument(span)
                 });
 
                 sender
@@ -230,11 +261,32 @@ impl<I: TransportIdentity> GatewaySenders<I> {
         }
     }
 
-    fn new_sender(config: &SendChannelConfig, channel_id: ChannelId<I>) -> Arc<GatewaySender<I>> {
+    /// Closes every channel, including the ones that did not send all of their records. Peers
+    /// waiting to receive records that will never be sent see the end of stream and fail.
+    pub fn abort(&self) {
+        for sender in &self.inner {
+            sender.abort();
+        }
+    }
+
+    fn new_sender(
+        &self,
+        config: &SendChannelConfig,
+        channel_id: ChannelId<I>,
+    ) -> Arc<GatewaySender<I>> {
+        let tx = OrderingSender::new(config.total_capacity, config.record_size, config.read_size);
+        let tx = match &self.backpressure {
+            Some(backpressure) => tx.with_backpressure(Arc::clone(backpressure)),
+            None => tx,
+        };
+        let records = self
+            .progress
+            .channel_opened(&channel_id.gate, config.total_records);
         Arc::new(GatewaySender::new(
             channel_id,
-            OrderingSender::new(config.total_capacity, config.record_size, config.read_size),
+            tx,
             config.total_records,
+            records,
         ))
     }
 }
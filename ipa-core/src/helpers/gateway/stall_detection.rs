@@ -73,7 +73,7 @@ mod gateway {
     use super::{receive, send, AtomicUsize, Debug, Formatter, ObserveState, Observed, Weak};
     use crate::{
         helpers::{
-            gateway::{Gateway, ShardTransportImpl, State},
+            gateway::{Gateway, Progress, ShardTransportImpl, State},
             GatewayConfig, HelperChannelId, Message, MpcMessage, MpcReceivingEnd, MpcTransportImpl,
             Role, RoleAssignment, SendingEnd, ShardChannelId, ShardReceivingEnd, TotalRecords,
         },
@@ -99,6 +99,12 @@ mod gateway {
 
                 #[inline]
                 pub fn config(&self) -> &GatewayConfig;
+
+                #[inline]
+                pub fn propose_active_work(&self, current: NonZeroU32PowerOfTwo) -> Option<NonZeroU32PowerOfTwo>;
+
+                #[inline]
+                pub fn progress(&self) -> &Arc<Progress>;
             }
         }
 
@@ -3,8 +3,8 @@ use futures::Stream;
 
 use crate::{
     helpers::{
-        transport::routing::RouteId, MpcTransportImpl, NoResourceIdentifier, QueryIdBinding, Role,
-        RoleAssignment, RouteParams, StepBinding, Transport,
+        transport::routing::RouteId, HelperResponse, MpcTransportImpl, NoResourceIdentifier,
+        QueryIdBinding, Role, RoleAssignment, RouteParams, StepBinding, Transport,
     },
     protocol::{Gate, QueryId},
     sharding::ShardIndex,
@@ -60,7 +60,7 @@ impl Transport for RoleResolvingTransport {
         dest: Role,
         route: R,
         data: D,
-    ) -> Result<(), Self::Error>
+    ) -> Result<HelperResponse, Self::Error>
     where
         Option<QueryId>: From<Q>,
         Option<Gate>: From<S>,
@@ -91,4 +91,8 @@ impl Transport for RoleResolvingTransport {
 
         self.inner.receive(origin_helper, route)
     }
+
+    fn clear_streams(&self, query_id: QueryId) {
+        self.inner.clear_streams(query_id);
+    }
 }
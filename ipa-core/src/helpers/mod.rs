@@ -57,7 +57,8 @@ mod gateway_exports {
 }
 
 pub use cross_shard_prss::gen_and_distribute as setup_cross_shard_prss;
-pub use gateway::GatewayConfig;
+pub(crate) use gateway::MIN_ACTIVE_WORK;
+pub use gateway::{min_progress, GatewayConfig, PhaseProgress, Progress, QueryProgress};
 // TODO: this type should only be available within infra. Right now several infra modules
 // are exposed at the root level. That makes it impossible to have a proper hierarchy here.
 pub use gateway::{
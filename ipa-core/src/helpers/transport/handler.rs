@@ -7,14 +7,16 @@ use serde_json::json;
 use crate::{
     error::BoxError,
     helpers::{
-        query::PrepareQuery, transport::routing::Addr, BodyStream, HelperIdentity,
-        TransportIdentity,
+        query::{CompareStatusResponse, PrepareQuery},
+        transport::routing::Addr,
+        BodyStream, HelperIdentity, QueryProgress, TransportIdentity,
     },
     query::{
         NewQueryError, PrepareQueryError, ProtocolResult, QueryCompletionError, QueryInputError,
-        QueryKillStatus, QueryKilled, QueryStatus, QueryStatusError,
+        QueryKillStatus, QueryKilled, QueryMetricsError, QueryStatus, QueryStatusError,
     },
     sync::{Arc, Mutex, Weak},
+    telemetry::stats::QueryMetrics,
 };
 
 /// Represents some response sent from MPC helper acting on a given request. It is rudimental now
@@ -135,6 +137,27 @@ impl From<QueryStatus> for HelperResponse {
     }
 }
 
+impl From<(QueryStatus, Option<QueryProgress>)> for HelperResponse {
+    fn from((status, progress): (QueryStatus, Option<QueryProgress>)) -> Self {
+        let v = serde_json::to_vec(&json!({"status": status, "progress": progress})).unwrap();
+        Self { body: v }
+    }
+}
+
+impl From<CompareStatusResponse> for HelperResponse {
+    fn from(value: CompareStatusResponse) -> Self {
+        let v = serde_json::to_vec(&value).unwrap();
+        Self { body: v }
+    }
+}
+
+impl From<QueryMetrics> for HelperResponse {
+    fn from(value: QueryMetrics) -> Self {
+        let v = serde_json::to_vec(&value).unwrap();
+        Self { body: v }
+    }
+}
+
 impl From<QueryKilled> for HelperResponse {
     fn from(value: QueryKilled) -> Self {
         let v = serde_json::to_vec(&json!({"query_id": value.0, "status": "killed"})).unwrap();
@@ -171,6 +194,8 @@ pub enum Error {
     #[error(transparent)]
     QueryKill(#[from] QueryKillStatus),
     #[error(transparent)]
+    QueryMetrics(#[from] QueryMetricsError),
+    #[error(transparent)]
     DeserializationFailure(#[from] serde_json::Error),
     #[error("MalformedRequest: {0}")]
     BadRequest(BoxError),
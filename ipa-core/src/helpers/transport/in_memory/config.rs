@@ -1,3 +1,5 @@
+use std::{num::NonZeroU64, time::Duration};
+
 use crate::{
     helpers::{HelperIdentity, Role, RoleAssignment},
     protocol::Gate,
@@ -6,6 +8,7 @@ use crate::{
 };
 
 pub type DynStreamInterceptor = Arc<dyn StreamInterceptor<Context = InspectContext>>;
+pub type DynNetworkEmulator = Arc<dyn NetworkEmulator>;
 
 /// The interface for stream interceptors.
 ///
@@ -182,3 +185,83 @@ impl<F: Fn(&MaliciousHelperContext, &mut Vec<u8>) + Send + Sync> StreamIntercept
         }
     }
 }
+
+/// Conditions of a single link between two helpers or two shards.
+///
+/// By default, in-memory links deliver data instantly. Setting any of the fields
+/// makes the transport delay every chunk sent over the link:
+/// * the link can only transmit `bandwidth` bytes per second, chunks that don't fit
+///   wait in a queue.
+/// * after a chunk is transmitted, it takes `latency` plus a random delay in
+///   `[0, jitter]` for it to arrive.
+///
+/// Like TCP, a link never reorders chunks, so jitter can only delay them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LinkConditions {
+    /// One-way delay for every chunk sent over this link.
+    pub latency: Duration,
+    /// Upper bound for the random delay added to `latency`.
+    pub jitter: Duration,
+    /// Link capacity, in bytes per second. `None` means unlimited.
+    pub bandwidth: Option<NonZeroU64>,
+}
+
+impl LinkConditions {
+    /// Returns `true` if this link delivers data without any delay.
+    #[must_use]
+    pub fn is_instant(&self) -> bool {
+        self.latency.is_zero() && self.jitter.is_zero() && self.bandwidth.is_none()
+    }
+
+    /// Time it takes to push `len` bytes through this link.
+    #[must_use]
+    pub fn transmit_time(&self, len: usize) -> Duration {
+        self.bandwidth.map_or(Duration::ZERO, |bandwidth| {
+            let len = u128::from(u64::try_from(len).unwrap_or(u64::MAX));
+            let nanos = len * 1_000_000_000 / u128::from(bandwidth.get());
+            Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))
+        })
+    }
+}
+
+/// The interface for network emulators.
+///
+/// Similar to [`StreamInterceptor`], it is used in test infrastructure to make
+/// in-memory streams behave more like streams sent over a real network. The emulator
+/// is consulted once per stream and the conditions it returns are applied to all
+/// the data sent over that stream.
+pub trait NetworkEmulator: Send + Sync {
+    /// Returns the conditions of the link that carries the stream identified by `ctx`.
+    fn link(&self, ctx: &InspectContext) -> LinkConditions;
+}
+
+/// The same conditions for every link.
+impl NetworkEmulator for LinkConditions {
+    fn link(&self, _ctx: &InspectContext) -> LinkConditions {
+        *self
+    }
+}
+
+impl<F: Fn(&InspectContext) -> LinkConditions + Send + Sync> NetworkEmulator for F {
+    fn link(&self, ctx: &InspectContext) -> LinkConditions {
+        (self)(ctx)
+    }
+}
+
+/// The emulator that delivers everything instantly. This is the default
+/// for in-memory networks.
+#[inline]
+#[must_use]
+pub fn no_emulation() -> DynNetworkEmulator {
+    Arc::new(LinkConditions::default())
+}
+
+/// Emulates helpers talking over a wide area network, while shards of the same helper
+/// are connected with instant links, as they are expected to run in the same datacenter.
+#[must_use]
+pub fn wan(helper_link: LinkConditions) -> DynNetworkEmulator {
+    Arc::new(move |ctx: &InspectContext| match ctx {
+        InspectContext::MpcMessage { .. } => helper_link,
+        InspectContext::ShardMessage { .. } => LinkConditions::default(),
+    })
+}
@@ -0,0 +1,174 @@
+use std::{
+    cmp::max,
+    collections::VecDeque,
+    future::Future,
+    pin::Pin,
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+
+use ::tokio::time::{sleep_until, Instant, Sleep};
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use rand::{thread_rng, Rng};
+
+use crate::{error::BoxError, helpers::in_memory_config::LinkConditions};
+
+type StreamItem = Result<Bytes, BoxError>;
+
+/// Maximum number of chunks that can be in flight on a single link. This keeps
+/// backpressure working: senders can't push more data than that until the receiving
+/// side drains the link.
+const MAX_IN_FLIGHT: usize = 256;
+
+/// Stream adapter that delays chunks according to [`LinkConditions`].
+///
+/// Chunks are pulled from the inner stream as soon as they are available, so several of
+/// them can be in flight at the same time, and latency is paid once per round trip,
+/// not once per chunk.
+pub struct EmulatedLink<S> {
+    inner: S,
+    conditions: LinkConditions,
+    in_flight: VecDeque<(Instant, StreamItem)>,
+    inner_done: bool,
+    /// Time when the link finishes transmitting the last chunk taken from the inner stream.
+    link_free_at: Instant,
+    /// Delivery time of the last chunk. Chunks are never delivered out of order.
+    last_delivery: Instant,
+    timer: Option<Pin<Box<Sleep>>>,
+}
+
+impl<S: Stream<Item = StreamItem> + Unpin> EmulatedLink<S> {
+    pub fn new(inner: S, conditions: LinkConditions) -> Self {
+        let now = Instant::now();
+        Self {
+            inner,
+            conditions,
+            in_flight: VecDeque::new(),
+            inner_done: false,
+            link_free_at: now,
+            last_delivery: now,
+            timer: None,
+        }
+    }
+
+    fn delivery_time(&mut self, item: &StreamItem) -> Instant {
+        let len = item.as_ref().map_or(0, Bytes::len);
+        let start = max(Instant::now(), self.link_free_at);
+        self.link_free_at = start + self.conditions.transmit_time(len);
+
+        let jitter = if self.conditions.jitter.is_zero() {
+            self.conditions.jitter
+        } else {
+            thread_rng().gen_range(Duration::ZERO..=self.conditions.jitter)
+        };
+        self.last_delivery = max(
+            self.link_free_at + self.conditions.latency + jitter,
+            self.last_delivery,
+        );
+
+        self.last_delivery
+    }
+}
+
+impl<S: Stream<Item = StreamItem> + Unpin> Stream for EmulatedLink<S> {
+    type Item = StreamItem;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        while !this.inner_done && this.in_flight.len() < MAX_IN_FLIGHT {
+            match this.inner.poll_next_unpin(cx) {
+                Poll::Ready(Some(item)) => {
+                    let at = this.delivery_time(&item);
+                    this.in_flight.push_back((at, item));
+                }
+                Poll::Ready(None) => this.inner_done = true,
+                Poll::Pending => break,
+            }
+        }
+
+        let Some(&(at, _)) = this.in_flight.front() else {
+            return if this.inner_done {
+                Poll::Ready(None)
+            } else {
+                Poll::Pending
+            };
+        };
+
+        let timer = this.timer.get_or_insert_with(|| Box::pin(sleep_until(at)));
+        if timer.deadline() != at {
+            timer.as_mut().reset(at);
+        }
+        ready!(timer.as_mut().poll(cx));
+
+        Poll::Ready(this.in_flight.pop_front().map(|(_, item)| item))
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use std::{num::NonZeroU64, time::Duration};
+
+    use bytes::Bytes;
+    use futures::{stream, StreamExt};
+    use tokio::time::Instant;
+
+    use crate::helpers::{
+        in_memory_config::LinkConditions, transport::in_memory::emulation::EmulatedLink,
+    };
+
+    fn chunks(count: usize, len: usize) -> impl stream::Stream<Item = super::StreamItem> + Unpin {
+        stream::iter((0..count).map(move |_| Ok(Bytes::from(vec![0_u8; len]))))
+    }
+
+    // Tests run with paused time, so the runtime advances the clock to the next timer
+    // whenever it is idle and measured durations are exact.
+    #[tokio::test(start_paused = true)]
+    async fn latency_is_paid_once() {
+        let link = EmulatedLink::new(
+            chunks(10, 1),
+            LinkConditions {
+                latency: Duration::from_millis(50),
+                ..Default::default()
+            },
+        );
+
+        let start = Instant::now();
+        assert_eq!(10, link.collect::<Vec<_>>().await.len());
+        let elapsed = start.elapsed();
+        // paying latency for each chunk would take 500ms
+        assert_eq!(Duration::from_millis(50), elapsed);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn bandwidth_limits_throughput() {
+        let link = EmulatedLink::new(
+            chunks(4, 1000),
+            LinkConditions {
+                latency: Duration::from_millis(10),
+                bandwidth: NonZeroU64::new(100_000),
+                ..Default::default()
+            },
+        );
+
+        // 4000 bytes at 100 KB/s take 40ms to transmit, last one arrives 10ms after that.
+        let start = Instant::now();
+        assert_eq!(4, link.collect::<Vec<_>>().await.len());
+        assert_eq!(Duration::from_millis(50), start.elapsed());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn jitter_does_not_reorder() {
+        let link = EmulatedLink::new(
+            stream::iter((0..100_u8).map(|i| Ok(Bytes::from(vec![i])))),
+            LinkConditions {
+                latency: Duration::from_millis(1),
+                jitter: Duration::from_millis(20),
+                ..Default::default()
+            },
+        );
+
+        let received = link.map(|item| item.unwrap()[0]).collect::<Vec<_>>().await;
+        assert_eq!((0..100).collect::<Vec<_>>(), received);
+    }
+}
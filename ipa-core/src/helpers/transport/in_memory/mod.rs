@@ -1,4 +1,5 @@
 pub mod config;
+mod emulation;
 mod sharding;
 mod transport;
 
@@ -8,7 +9,8 @@ pub use transport::{Error as InMemoryTransportError, Setup};
 
 use crate::{
     helpers::{
-        in_memory_config::DynStreamInterceptor, transport::in_memory::config::passthrough,
+        in_memory_config::{DynNetworkEmulator, DynStreamInterceptor},
+        transport::in_memory::config::{no_emulation, passthrough},
         HandlerRef, HelperIdentity,
     },
     sharding::ShardIndex,
@@ -51,10 +53,24 @@ impl InMemoryMpcNetwork {
         handlers: [Option<HandlerRef>; 3],
         interceptor: &DynStreamInterceptor,
         shard: Option<ShardIndex>,
+    ) -> Self {
+        Self::with_network_emulator(handlers, interceptor, &no_emulation(), shard)
+    }
+
+    /// Construct an `InMemoryMpcNetwork` with a stream interceptor and links that behave
+    /// according to the conditions provided by `emulator`.
+    #[must_use]
+    pub fn with_network_emulator(
+        handlers: [Option<HandlerRef>; 3],
+        interceptor: &DynStreamInterceptor,
+        emulator: &DynNetworkEmulator,
+        shard: Option<ShardIndex>,
     ) -> Self {
         let [mut first, mut second, mut third]: [_; 3] = HelperIdentity::make_three().map(|i| {
             let mut config_builder = TransportConfigBuilder::for_helper(i);
-            config_builder.with_interceptor(interceptor);
+            config_builder
+                .with_interceptor(interceptor)
+                .with_network_emulator(emulator);
 
             Setup::with_config(i, config_builder.with_sharding(shard))
         });
@@ -1,6 +1,6 @@
 use crate::{
     helpers::{
-        in_memory_config::{passthrough, DynStreamInterceptor},
+        in_memory_config::{no_emulation, passthrough, DynNetworkEmulator, DynStreamInterceptor},
         transport::in_memory::transport::{InMemoryTransport, Setup, TransportConfigBuilder},
         HandlerBox, HelperIdentity, RequestHandler,
     },
@@ -30,7 +30,17 @@ impl InMemoryShardNetwork {
         shard_count: I,
         interceptor: &DynStreamInterceptor,
     ) -> Self {
-        let shard_network = Self::create_shard_connections(shard_count, interceptor).map(
+        Self::with_network_emulator(shard_count, interceptor, &no_emulation())
+    }
+
+    /// Creates shard connections that behave according to the conditions provided by
+    /// `emulator`.
+    pub fn with_network_emulator<I: Into<ShardIndex>>(
+        shard_count: I,
+        interceptor: &DynStreamInterceptor,
+        emulator: &DynNetworkEmulator,
+    ) -> Self {
+        let shard_network = Self::create_shard_connections(shard_count, interceptor, emulator).map(
             |(shard_connections, h)| {
                 shard_connections
                     .into_iter()
@@ -45,11 +55,14 @@ impl InMemoryShardNetwork {
     pub fn create_shard_connections<I: Into<ShardIndex>>(
         shard_count: I,
         interceptor: &DynStreamInterceptor,
+        emulator: &DynNetworkEmulator,
     ) -> [(Vec<Setup<ShardIndex>>, HelperIdentity); 3] {
         let shard_count = shard_count.into();
         HelperIdentity::make_three().map(|h| {
             let mut config_builder = TransportConfigBuilder::for_helper(h);
-            config_builder.with_interceptor(interceptor);
+            config_builder
+                .with_interceptor(interceptor)
+                .with_network_emulator(emulator);
 
             let mut shard_connections = shard_count
                 .iter()
@@ -76,7 +89,8 @@ impl InMemoryShardNetwork {
         I: Into<ShardIndex>,
         F: Fn(ShardIndex) -> Arc<dyn RequestHandler<ShardIndex>>,
     {
-        let connections = Self::create_shard_connections(shard_count, &passthrough());
+        let connections =
+            Self::create_shard_connections(shard_count, &passthrough(), &no_emulation());
         let shard_count = connections[0].0.len();
         let mut handlers = Vec::with_capacity(3 * shard_count);
         let shard_network = connections.map(|(shard_connections, h)| {
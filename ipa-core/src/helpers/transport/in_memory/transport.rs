@@ -21,8 +21,11 @@ use tracing::Instrument;
 use crate::{
     error::BoxError,
     helpers::{
-        in_memory_config::{self, DynStreamInterceptor},
-        transport::routing::{Addr, RouteId},
+        in_memory_config::{self, DynNetworkEmulator, DynStreamInterceptor},
+        transport::{
+            in_memory::emulation::EmulatedLink,
+            routing::{Addr, RouteId},
+        },
         ApiError, BodyStream, HandlerRef, HelperIdentity, HelperResponse, NoResourceIdentifier,
         QueryIdBinding, ReceiveRecords, RequestHandler, RouteParams, StepBinding, StreamCollection,
         Transport, TransportIdentity,
@@ -30,11 +33,13 @@ use crate::{
     protocol::{Gate, QueryId},
     sharding::ShardIndex,
     sync::{Arc, Weak},
+    telemetry::trace::{TraceContext, TRACEPARENT_FIELD, TRACE_TARGET},
 };
 
 type Packet<I> = (
     Addr<I>,
     InMemoryStream,
+    Option<TraceContext>,
     oneshot::Sender<Result<HelperResponse, ApiError>>,
 );
 type ConnectionTx<I> = Sender<Packet<I>>;
@@ -99,8 +104,9 @@ impl<I: TransportIdentity> InMemoryTransport<I> {
         tokio::spawn(
             {
                 let streams = self.record_streams.clone();
+                let identity = self.identity;
                 async move {
-                    while let Some((addr, stream, ack)) = rx.recv().await {
+                    while let Some((addr, stream, trace_context, ack)) = rx.recv().await {
                         tracing::trace!("received new message: {addr:?}");
 
                         let result = match addr.route {
@@ -115,13 +121,25 @@ impl<I: TransportIdentity> InMemoryTransport<I> {
                             | RouteId::PrepareQuery
                             | RouteId::QueryInput
                             | RouteId::QueryStatus
+                            | RouteId::QueryWait
                             | RouteId::CompleteQuery
                             | RouteId::KillQuery
-                            | RouteId::Metrics => {
+                            | RouteId::Metrics
+                            | RouteId::QueryMetrics => {
+                                // Same as the HTTP server, requests join the trace of the sender.
+                                let span = tracing::info_span!(
+                                    target: TRACE_TARGET,
+                                    "request",
+                                    id = ?identity,
+                                    route = ?addr.route,
+                                    { TRACEPARENT_FIELD } =
+                                        trace_context.as_ref().map(tracing::field::display),
+                                );
                                 handler
                                     .as_ref()
                                     .expect("Handler is set")
                                     .handle(addr, BodyStream::from_bytes_stream(stream))
+                                    .instrument(span)
                                     .await
                             }
                         };
@@ -182,7 +200,7 @@ impl<I: TransportIdentity> Transport for Weak<InMemoryTransport<I>> {
         dest: I,
         route: R,
         data: D,
-    ) -> Result<(), Error<I>>
+    ) -> Result<HelperResponse, Error<I>>
     where
         Option<QueryId>: From<Q>,
         Option<Gate>: From<S>,
@@ -196,19 +214,25 @@ impl<I: TransportIdentity> Transport for Weak<InMemoryTransport<I>> {
         let context =
             gate.map(|gate| dest.inspect_context(this.config.shard, this.config.identity, gate));
 
+        let link = context
+            .as_ref()
+            .map(|context| this.config.network_emulator.link(context))
+            .filter(|link| !link.is_instant());
+        let data = data.map({
+            move |mut chunk| {
+                if let Some(ref context) = context {
+                    this.config.stream_interceptor.peek(context, &mut chunk);
+                }
+                Ok(Bytes::from(chunk))
+            }
+        });
+        let stream = match link {
+            Some(link) => InMemoryStream::wrap(EmulatedLink::new(Box::pin(data), link)),
+            None => InMemoryStream::wrap(data),
+        };
+
         channel
-            .send((
-                addr,
-                InMemoryStream::wrap(data.map({
-                    move |mut chunk| {
-                        if let Some(ref context) = context {
-                            this.config.stream_interceptor.peek(context, &mut chunk);
-                        }
-                        Ok(Bytes::from(chunk))
-                    }
-                })),
-                ack_tx,
-            ))
+            .send((addr, stream, TraceContext::current(), ack_tx))
             .await
             .map_err(|_e| {
                 io::Error::new::<String>(io::ErrorKind::ConnectionAborted, "channel closed".into())
@@ -223,9 +247,7 @@ impl<I: TransportIdentity> Transport for Weak<InMemoryTransport<I>> {
             .map_err(|e| Error::Rejected {
                 dest,
                 inner: e.into(),
-            })?;
-
-        Ok(())
+            })
     }
 
     fn receive<R: RouteParams<NoResourceIdentifier, QueryId, Gate>>(
@@ -238,6 +260,12 @@ impl<I: TransportIdentity> Transport for Weak<InMemoryTransport<I>> {
             self.upgrade().unwrap().record_streams.clone(),
         )
     }
+
+    fn clear_streams(&self, query_id: QueryId) {
+        if let Some(this) = self.upgrade() {
+            this.record_streams.clear_query(query_id);
+        }
+    }
 }
 
 /// Convenience struct to support heterogeneous in-memory streams
@@ -353,18 +381,23 @@ mod tests {
         panic::AssertUnwindSafe,
         sync::{Mutex, Weak},
         task::Poll,
+        time::Duration,
     };
 
     use bytes::Bytes;
     use futures::{stream, Stream};
     use futures_util::{stream::poll_immediate, FutureExt, StreamExt};
-    use tokio::sync::{mpsc::channel, oneshot};
+    use tokio::{
+        sync::{mpsc::channel, oneshot},
+        time::Instant,
+    };
     use tokio_stream::wrappers::ReceiverStream;
     use typenum::Unsigned;
 
     use crate::{
         ff::{FieldType, Fp31, Serializable},
         helpers::{
+            in_memory_config::{passthrough, DynNetworkEmulator, LinkConditions},
             make_owned_handler,
             query::{PrepareQuery, QueryConfig, QueryType::TestMultiply},
             transport::{
@@ -391,7 +424,7 @@ mod tests {
     ) {
         let data = InMemoryStream::wrap(data.map(Bytes::from).map(Ok));
         let (tx, rx) = oneshot::channel();
-        sender.send((addr, data, tx)).await.unwrap();
+        sender.send((addr, data, None, tx)).await.unwrap();
         let _ = rx
             .await
             .map_err(|_e| Error::<I>::Io {
@@ -628,6 +661,42 @@ mod tests {
         assert_eq!(vec![vec![0, 1]], recv.collect::<Vec<_>>().await);
     }
 
+    #[tokio::test(start_paused = true)]
+    async fn emulated_latency() {
+        let latency = Duration::from_millis(50);
+        let emulator: DynNetworkEmulator = Arc::new(LinkConditions {
+            latency,
+            ..Default::default()
+        });
+        let network = InMemoryMpcNetwork::with_network_emulator(
+            InMemoryMpcNetwork::noop_handlers(),
+            &passthrough(),
+            &emulator,
+            None,
+        );
+        let gate = Gate::from(STEP);
+        let start = Instant::now();
+        network
+            .transport(HelperIdentity::ONE)
+            .send(
+                HelperIdentity::TWO,
+                (RouteId::Records, QueryId, gate.clone()),
+                stream::iter(vec![vec![1], vec![2]]),
+            )
+            .await
+            .unwrap();
+        let received = network
+            .transport(HelperIdentity::TWO)
+            .receive(HelperIdentity::ONE, (QueryId, gate))
+            .into_bytes_stream()
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(vec![vec![1], vec![2]], received);
+        // time is paused, so the clock only moves forward to fire emulation timers
+        assert_eq!(latency, start.elapsed());
+    }
+
     #[tokio::test]
     async fn peer_count() {
         let mpc_network = InMemoryMpcNetwork::default();
@@ -654,11 +723,13 @@ pub struct TransportConfig {
     pub shard: Option<ShardIndex>,
     pub identity: HelperIdentity,
     pub stream_interceptor: DynStreamInterceptor,
+    pub network_emulator: DynNetworkEmulator,
 }
 
 pub struct TransportConfigBuilder {
     identity: HelperIdentity,
     stream_interceptor: DynStreamInterceptor,
+    network_emulator: DynNetworkEmulator,
 }
 
 impl TransportConfigBuilder {
@@ -666,6 +737,7 @@ impl TransportConfigBuilder {
         Self {
             identity,
             stream_interceptor: in_memory_config::passthrough(),
+            network_emulator: in_memory_config::no_emulation(),
         }
     }
 
@@ -675,11 +747,18 @@ impl TransportConfigBuilder {
         self
     }
 
+    pub fn with_network_emulator(&mut self, emulator: &DynNetworkEmulator) -> &mut Self {
+        self.network_emulator = Arc::clone(emulator);
+
+        self
+    }
+
     pub fn with_sharding(&self, shard: Option<ShardIndex>) -> TransportConfig {
         TransportConfig {
             shard,
             identity: self.identity,
             stream_interceptor: Arc::clone(&self.stream_interceptor),
+            network_emulator: Arc::clone(&self.network_emulator),
         }
     }
 
@@ -688,6 +767,7 @@ impl TransportConfigBuilder {
             shard: None,
             identity: self.identity,
             stream_interceptor: Arc::clone(&self.stream_interceptor),
+            network_emulator: Arc::clone(&self.network_emulator),
         }
     }
 }
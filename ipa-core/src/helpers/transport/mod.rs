@@ -343,13 +343,14 @@ pub trait Transport: Clone + Send + Sync + 'static {
 
     /// Sends a new request to the given destination helper party.
     /// Depending on the specific request, it may or may not require acknowledgment by the remote
-    /// party
+    /// party. Requests that are answered with data, like [`RouteId::QueryStatus`], return it
+    /// inside [`HelperResponse`], others get an empty one.
     async fn send<D, Q, S, R>(
         &self,
         dest: Self::Identity,
         route: R,
         data: D,
-    ) -> Result<(), Self::Error>
+    ) -> Result<HelperResponse, Self::Error>
     where
         Option<QueryId>: From<Q>,
         Option<Gate>: From<S>,
@@ -366,12 +367,18 @@ pub trait Transport: Clone + Send + Sync + 'static {
         route: R,
     ) -> Self::RecordsStream;
 
+    /// Forgets the record streams received for the given query. Until query ids are unique
+    /// (TODO(615)), streams left over from a completed or killed query would otherwise be
+    /// mistaken for the streams of the next one.
+    fn clear_streams(&self, query_id: QueryId);
+
     /// Broadcasts a message to all peers, excluding this instance, collecting all failures and
     /// successes. This method waits for all responses and returns only when all peers responded.
+    /// If every peer accepted the request, their responses are returned.
     async fn broadcast<Q, S, R>(
         &self,
         route: R,
-    ) -> Result<(), BroadcastError<Self::Identity, Self::Error>>
+    ) -> Result<Vec<(Self::Identity, HelperResponse)>, BroadcastError<Self::Identity, Self::Error>>
     where
         Option<QueryId>: From<Q>,
         Option<Gate>: From<S>,
@@ -387,15 +394,17 @@ pub trait Transport: Clone + Send + Sync + 'static {
             );
         }
 
+        let mut responses = Vec::new();
         let mut errs = Vec::new();
-        while let Some(r) = futs.next().await {
-            if let Err(e) = r.1 {
-                errs.push((r.0, e));
+        while let Some((peer_identity, r)) = futs.next().await {
+            match r {
+                Ok(response) => responses.push((peer_identity, response)),
+                Err(e) => errs.push((peer_identity, e)),
             }
         }
 
         if errs.is_empty() {
-            Ok(())
+            Ok(responses)
         } else {
             Err(errs.into())
         }
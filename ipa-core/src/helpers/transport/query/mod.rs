@@ -3,6 +3,7 @@ mod hybrid;
 use std::{
     fmt::{Debug, Display, Formatter},
     num::NonZeroU32,
+    time::Duration,
 };
 
 pub use hybrid::HybridQueryParams;
@@ -12,7 +13,7 @@ use crate::{
     ff::FieldType,
     helpers::{
         transport::{routing::RouteId, BodyStream, NoQueryId, NoStep},
-        RoleAssignment, RouteParams,
+        QueryProgress, RoleAssignment, RouteParams,
     },
     protocol::QueryId,
     query::QueryStatus,
@@ -81,8 +82,8 @@ impl From<QuerySize> for usize {
     }
 }
 
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
-#[cfg_attr(test, derive(PartialEq, Eq))]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(test, derive(Eq))]
 pub struct QueryConfig {
     pub size: QuerySize,
     pub field_type: FieldType,
@@ -266,8 +267,79 @@ impl RouteParams<RouteId, QueryId, NoStep> for CompareStatusRequest {
     }
 }
 
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+/// Sent back by shards that agree with the status in [`CompareStatusRequest`]. Carries the
+/// progress of the query on that shard, which is [`None`] until it starts running there.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct CompareStatusResponse {
+    pub status: QueryStatus,
+    #[serde(default)]
+    pub progress: Option<QueryProgress>,
+}
+
+/// Asks a helper to terminate a query. Report collectors send it to the leader shard of any
+/// helper, which forwards it to its peer helpers and to all of its shards.
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct KillQuery {
+    pub query_id: QueryId,
+    /// Set when the request was forwarded by another helper or shard, so it is not forwarded
+    /// to peer helpers again.
+    #[serde(default)]
+    pub forwarded: bool,
+}
+
+impl RouteParams<RouteId, QueryId, NoStep> for KillQuery {
+    type Params = String;
+
+    fn resource_identifier(&self) -> RouteId {
+        RouteId::KillQuery
+    }
+
+    fn query_id(&self) -> QueryId {
+        self.query_id
+    }
+
+    fn gate(&self) -> NoStep {
+        NoStep
+    }
+
+    fn extra(&self) -> Self::Params {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+/// Asks the leader shard of a helper to respond with the query status once the query
+/// completes, fails or is killed, or once `timeout` elapses, whichever comes first.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct WaitQueryRequest {
+    pub query_id: QueryId,
+    pub timeout: Duration,
+}
+
+impl RouteParams<RouteId, QueryId, NoStep> for WaitQueryRequest {
+    type Params = String;
+
+    fn resource_identifier(&self) -> RouteId {
+        RouteId::QueryWait
+    }
+
+    fn query_id(&self) -> QueryId {
+        self.query_id
+    }
+
+    fn gate(&self) -> NoStep {
+        NoStep
+    }
+
+    fn extra(&self) -> Self::Params {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(test, derive(Eq))]
 pub enum QueryType {
     #[cfg(any(test, feature = "test-fixture", feature = "cli"))]
     TestMultiply,
@@ -277,7 +349,12 @@ pub enum QueryType {
     TestShardedShuffle,
     SemiHonestOprfIpa(IpaQueryConfig),
     MaliciousOprfIpa(IpaQueryConfig),
+    SemiHonestHybrid(HybridQueryParams),
     MaliciousHybrid(HybridQueryParams),
+    /// Reach and frequency histogram of impressions by breakdown key. At most 8 reports are
+    /// counted for every user, the rest are dropped.
+    MaliciousReachFrequency(HybridQueryParams),
+    MaliciousConversionLift(HybridQueryParams),
 }
 
 impl QueryType {
@@ -287,7 +364,10 @@ impl QueryType {
     pub const TEST_SHARDED_SHUFFLE_STR: &'static str = "test-sharded-shuffle";
     pub const SEMI_HONEST_OPRF_IPA_STR: &'static str = "semi-honest-oprf-ipa";
     pub const MALICIOUS_OPRF_IPA_STR: &'static str = "malicious-oprf-ipa";
+    pub const SEMI_HONEST_HYBRID_STR: &'static str = "semi-honest-hybrid";
     pub const MALICIOUS_HYBRID_STR: &'static str = "malicious-hybrid";
+    pub const MALICIOUS_REACH_FREQUENCY_STR: &'static str = "malicious-reach-frequency";
+    pub const MALICIOUS_CONVERSION_LIFT_STR: &'static str = "malicious-conversion-lift";
 }
 
 /// TODO: should this `AsRef` impl (used for `Substep`) take into account config of IPA?
@@ -302,7 +382,10 @@ impl AsRef<str> for QueryType {
             QueryType::TestShardedShuffle => Self::TEST_SHARDED_SHUFFLE_STR,
             QueryType::SemiHonestOprfIpa(_) => Self::SEMI_HONEST_OPRF_IPA_STR,
             QueryType::MaliciousOprfIpa(_) => Self::MALICIOUS_OPRF_IPA_STR,
+            QueryType::SemiHonestHybrid(_) => Self::SEMI_HONEST_HYBRID_STR,
             QueryType::MaliciousHybrid(_) => Self::MALICIOUS_HYBRID_STR,
+            QueryType::MaliciousReachFrequency(_) => Self::MALICIOUS_REACH_FREQUENCY_STR,
+            QueryType::MaliciousConversionLift(_) => Self::MALICIOUS_CONVERSION_LIFT_STR,
         }
     }
 }
@@ -314,6 +397,23 @@ pub enum DpMechanism {
     DiscreteLaplace { epsilon: f64 },
 }
 
+impl DpMechanism {
+    /// Splits the privacy budget of this mechanism evenly into `parts`, so that applying the
+    /// returned mechanism `parts` times has the same privacy cost as applying this one once.
+    #[must_use]
+    pub fn split(self, parts: u32) -> Self {
+        match self {
+            Self::NoDp => Self::NoDp,
+            Self::Binomial { epsilon } => Self::Binomial {
+                epsilon: epsilon / f64::from(parts),
+            },
+            Self::DiscreteLaplace { epsilon } => Self::DiscreteLaplace {
+                epsilon: epsilon / f64::from(parts),
+            },
+        }
+    }
+}
+
 #[cfg(test)]
 impl Eq for IpaQueryConfig {}
 
@@ -22,9 +22,13 @@ pub enum RouteId {
     ///
     /// We should've used a different `RouteId` to differentiate those
     QueryStatus,
+    /// Same response as [`Self::QueryStatus`], but held until the query completes, fails or
+    /// is killed, or until the timeout requested by the caller elapses.
+    QueryWait,
     CompleteQuery,
     KillQuery,
     Metrics,
+    QueryMetrics,
 }
 
 /// The header/metadata of the incoming request.
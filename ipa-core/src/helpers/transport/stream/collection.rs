@@ -106,6 +106,16 @@ impl<I: TransportIdentity, S: Stream> StreamCollection<I, S> {
         }
     }
 
+    /// Removes all streams that belong to the given query, leaving streams of other queries
+    /// intact.
+    ///
+    /// ## Panics
+    /// if mutex is poisoned.
+    pub fn clear_query(&self, query_id: QueryId) {
+        let mut streams = self.inner.lock().unwrap();
+        streams.retain(|(stream_query_id, _, _), _| *stream_query_id != query_id);
+    }
+
     /// Clears up this collection, leaving no streams inside it.
     ///
     /// ## Panics
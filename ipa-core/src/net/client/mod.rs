@@ -33,11 +33,15 @@ use crate::{
     },
     executor::IpaRuntime,
     helpers::{
-        query::{CompareStatusRequest, PrepareQuery, QueryConfig, QueryInput},
+        query::{CompareStatusRequest, KillQuery, PrepareQuery, QueryConfig, QueryInput},
         TransportIdentity,
     },
-    net::{error::ShardQueryStatusMismatchError, http_serde, Error, CRYPTO_PROVIDER},
+    net::{
+        error::ShardQueryStatusMismatchError, http_serde, CertificateReloader, Error,
+        CRYPTO_PROVIDER, HTTP_TRACEPARENT_HEADER,
+    },
     protocol::{Gate, QueryId},
+    telemetry::trace::TraceContext,
 };
 
 #[derive(Default)]
@@ -52,6 +56,13 @@ pub enum ClientIdentity<F: ConnectionFlavor = Helper> {
     /// This is only supported for HTTPS clients.
     Certificate((Vec<OwnedCertificate>, OwnedPrivateKey)),
 
+    /// Authenticate with the certificate currently held by [`CertificateReloader`]. Server
+    /// certificates are verified against the latest known certificates of the peer, so
+    /// rotated certificates are picked up by new connections.
+    ///
+    /// This is only supported for HTTPS clients.
+    Reloadable(Arc<CertificateReloader<F>>),
+
     /// Do not authenticate nor claim a helper identity.
     #[default]
     None,
@@ -86,6 +97,7 @@ impl<F: ConnectionFlavor> ClientIdentity<F> {
         match self {
             Self::Certificate((c, pk)) => Self::Certificate((c.clone(), pk.clone_key())),
             Self::Header(h) => Self::Header(*h),
+            Self::Reloadable(r) => Self::Reloadable(Arc::clone(r)),
             Self::None => Self::None,
         }
     }
@@ -205,7 +217,7 @@ impl<F: ConnectionFlavor> IpaHttpClient<F> {
             // This connector works for both http and https. A regular HttpConnector would suffice,
             // but would make the type of `self.client` variable.
             let auth_header = match identity {
-                ClientIdentity::Certificate(_) => {
+                ClientIdentity::Certificate(_) | ClientIdentity::Reloadable(_) => {
                     error!("certificate identity ignored for HTTP client");
                     None
                 }
@@ -228,12 +240,16 @@ impl<F: ConnectionFlavor> IpaHttpClient<F> {
             let builder = rustls::ClientConfig::builder_with_provider(Arc::clone(&CRYPTO_PROVIDER))
                 .with_safe_default_protocol_versions()
                 .expect("Default crypto provider should be valid");
-            let client_config = if let Some(certificate) = peer_config.certificate {
+            let client_config = if let ClientIdentity::Reloadable(certificates) = &identity {
+                certificates.client_config(&peer_config)
+            } else if peer_config.certificate.is_some() {
                 let cert_store = {
                     let mut store = RootCertStore::empty();
-                    store
-                        .add(certificate)
-                        .expect("Error adding Certificate, should be a valid Trust Anchor.");
+                    for certificate in peer_config.certificates() {
+                        store
+                            .add(certificate.clone())
+                            .expect("Error adding Certificate, should be a valid Trust Anchor.");
+                    }
                     store
                 };
 
@@ -247,6 +263,7 @@ impl<F: ConnectionFlavor> IpaHttpClient<F> {
                         builder.with_no_client_auth()
                     }
                     ClientIdentity::None => builder.with_no_client_auth(),
+                    ClientIdentity::Reloadable(_) => unreachable!(),
                 }
             } else {
                 builder.with_native_roots().unwrap().with_no_client_auth()
@@ -308,6 +325,11 @@ impl<F: ConnectionFlavor> IpaHttpClient<F> {
         if let Some((k, v)) = self.auth_header.clone() {
             req.headers_mut().insert(k, v);
         }
+        if let Some(v) =
+            TraceContext::current().and_then(|ctx| HeaderValue::try_from(ctx.to_string()).ok())
+        {
+            req.headers_mut().insert(HTTP_TRACEPARENT_HEADER.clone(), v);
+        }
         ResponseFuture {
             authority: self.authority.clone(),
             inner: self.client.request(req),
@@ -385,13 +407,29 @@ impl<F: ConnectionFlavor> IpaHttpClient<F> {
         resp_ok(resp).await
     }
 
+    /// Kill query API can be called on the leader shard by the report collector, which then
+    /// forwards it to its peer helpers and shards, marking the request as forwarded.
+    ///
+    /// # Errors
+    /// If the request has illegal arguments, or fails to be delivered
+    pub async fn kill_query(&self, data: KillQuery) -> Result<(), Error> {
+        let req = http_serde::query::kill::Request::new(data);
+        let req = req.try_into_http_request(self.scheme.clone(), self.authority.clone())?;
+        let resp = self.request(req).await?;
+        resp_ok(resp).await
+    }
+
     /// This API is used by leader shards in MPC to request query status information on peers.
     /// If a given peer has status that doesn't match the one provided by the leader, it responds
-    /// with 412 error and encodes its status inside the response body. Otherwise, 200 is returned.
+    /// with 412 error and encodes its status inside the response body. Otherwise, 200 is returned
+    /// along with the status and progress of the query on that peer.
     ///
     /// # Errors
     /// If the request has illegal arguments, or fails to be delivered
-    pub async fn status_match(&self, data: CompareStatusRequest) -> Result<(), Error> {
+    pub async fn status_match(
+        &self,
+        data: CompareStatusRequest,
+    ) -> Result<http_serde::query::status_match::ResponseBody, Error> {
         let req = http_serde::query::status_match::try_into_http_request(
             &data,
             self.scheme.clone(),
@@ -400,7 +438,10 @@ impl<F: ConnectionFlavor> IpaHttpClient<F> {
         let resp = self.request(req).await?;
 
         match resp.status() {
-            StatusCode::OK => Ok(()),
+            StatusCode::OK => {
+                let bytes = response_to_bytes(resp).await?;
+                Ok(serde_json::from_slice(&bytes)?)
+            }
             StatusCode::PRECONDITION_FAILED => {
                 let bytes = response_to_bytes(resp).await?;
                 let err = serde_json::from_slice::<ShardQueryStatusMismatchError>(&bytes)?;
@@ -476,15 +517,92 @@ impl IpaHttpClient<Helper> {
         &self,
         query_id: QueryId,
     ) -> Result<crate::query::QueryStatus, Error> {
+        self.query_status_with_progress(query_id)
+            .await
+            .map(|(status, _)| status)
+    }
+
+    /// Retrieve the status of a query along with its progress, once it started running.
+    ///
+    /// ## Errors
+    /// If the request has illegal arguments, or fails to deliver to helper
+    #[cfg(any(all(test, not(feature = "shuttle")), feature = "cli"))]
+    pub async fn query_status_with_progress(
+        &self,
+        query_id: QueryId,
+    ) -> Result<
+        (
+            crate::query::QueryStatus,
+            Option<crate::helpers::QueryProgress>,
+        ),
+        Error,
+    > {
         let req = http_serde::query::status::Request::new(query_id);
         let req = req.try_into_http_request(self.scheme.clone(), self.authority.clone())?;
 
         let resp = self.request(req).await?;
         if resp.status().is_success() {
             let bytes = response_to_bytes(resp).await?;
-            let http_serde::query::status::ResponseBody { status } =
+            let http_serde::query::status::ResponseBody { status, progress } =
                 serde_json::from_slice(&bytes)?;
-            Ok(status)
+            Ok((status, progress))
+        } else {
+            Err(Error::from_failed_resp(resp).await)
+        }
+    }
+
+    /// Waits up to `timeout` for a query to complete, fail or be killed, then retrieves its
+    /// status along with its progress, same as [`Self::query_status_with_progress`]. The helper
+    /// may respond earlier than `timeout` if it considers it too long.
+    ///
+    /// ## Errors
+    /// If the request has illegal arguments, or fails to deliver to helper
+    #[cfg(any(all(test, not(feature = "shuttle")), feature = "cli"))]
+    pub async fn wait_query(
+        &self,
+        query_id: QueryId,
+        timeout: std::time::Duration,
+    ) -> Result<
+        (
+            crate::query::QueryStatus,
+            Option<crate::helpers::QueryProgress>,
+        ),
+        Error,
+    > {
+        let req = crate::helpers::query::WaitQueryRequest { query_id, timeout };
+        let req = http_serde::query::wait::try_into_http_request(
+            &req,
+            self.scheme.clone(),
+            self.authority.clone(),
+        )?;
+
+        let resp = self.request(req).await?;
+        if resp.status().is_success() {
+            let bytes = response_to_bytes(resp).await?;
+            let http_serde::query::wait::ResponseBody { status, progress } =
+                serde_json::from_slice(&bytes)?;
+            Ok((status, progress))
+        } else {
+            Err(Error::from_failed_resp(resp).await)
+        }
+    }
+
+    /// Retrieve metrics collected while running a query.
+    ///
+    /// ## Errors
+    /// If the request has illegal arguments, or fails to deliver to helper
+    #[cfg(any(all(test, not(feature = "shuttle")), feature = "cli"))]
+    pub async fn query_metrics(
+        &self,
+        query_id: QueryId,
+    ) -> Result<crate::telemetry::stats::QueryMetrics, Error> {
+        let req = http_serde::query::metrics::Request::new(query_id);
+        let req = req.try_into_http_request(self.scheme.clone(), self.authority.clone())?;
+
+        let resp = self.request(req).await?;
+        if resp.status().is_success() {
+            let bytes = response_to_bytes(resp).await?;
+            Ok(serde_json::from_slice(&bytes)?)
         } else {
             Err(Error::from_failed_resp(resp).await)
         }
@@ -561,12 +679,14 @@ pub(crate) mod tests {
     use crate::{
         ff::{FieldType, Fp31},
         helpers::{
-            make_owned_handler, query::QueryType::TestMultiply, BytesStream, HelperIdentity,
-            HelperResponse, RequestHandler, RoleAssignment, MESSAGE_PAYLOAD_SIZE_BYTES,
+            make_owned_handler,
+            query::{QueryType::TestMultiply, WaitQueryRequest},
+            BytesStream, HelperIdentity, HelperResponse, RequestHandler, RoleAssignment,
+            MESSAGE_PAYLOAD_SIZE_BYTES,
         },
         net::test::TestServer,
         protocol::step::TestExecutionStep,
-        query::ProtocolResult,
+        query::{ProtocolResult, QueryStatus},
         secret_sharing::replicated::semi_honest::AdditiveShare as Replicated,
         sync::Arc,
     };
@@ -582,6 +702,7 @@ pub(crate) mod tests {
                 .parse()
                 .unwrap(),
             certificate: None,
+            previous_certificate: None,
             hpke_config: None,
         };
         let client = IpaHttpClient::new(
@@ -737,6 +858,27 @@ pub(crate) mod tests {
         .await;
     }
 
+    #[tokio::test]
+    async fn wait() {
+        let expected_timeout = std::time::Duration::from_secs(2);
+        let handler = move || {
+            make_owned_handler(move |addr, _| async move {
+                let req = addr.into::<WaitQueryRequest>().unwrap();
+                assert_eq!(req.query_id, QueryId);
+                assert_eq!(req.timeout, expected_timeout);
+
+                Ok(HelperResponse::from(QueryStatus::Completed))
+            })
+        };
+        let (status, progress) = test_query_command(
+            |client| async move { client.wait_query(QueryId, expected_timeout).await.unwrap() },
+            handler,
+        )
+        .await;
+        assert_eq!(QueryStatus::Completed, status);
+        assert_eq!(None, progress);
+    }
+
     #[tokio::test]
     async fn step() {
         let TestServer {
@@ -142,10 +142,22 @@ pub mod query {
                     let Query(q) = req.extract().await?;
                     Ok(QueryType::MaliciousOprfIpa(q))
                 }
+                QueryType::SEMI_HONEST_HYBRID_STR => {
+                    let Query(q) = req.extract().await?;
+                    Ok(QueryType::SemiHonestHybrid(q))
+                }
                 QueryType::MALICIOUS_HYBRID_STR => {
                     let Query(q) = req.extract().await?;
                     Ok(QueryType::MaliciousHybrid(q))
                 }
+                QueryType::MALICIOUS_REACH_FREQUENCY_STR => {
+                    let Query(q) = req.extract().await?;
+                    Ok(QueryType::MaliciousReachFrequency(q))
+                }
+                QueryType::MALICIOUS_CONVERSION_LIFT_STR => {
+                    let Query(q) = req.extract().await?;
+                    Ok(QueryType::MaliciousConversionLift(q))
+                }
                 other => Err(Error::bad_query_value("query_type", other)),
             }?;
             Ok(QueryConfigQueryParams(QueryConfig {
@@ -190,7 +202,10 @@ pub mod query {
 
                     Ok(())
                 }
-                QueryType::MaliciousHybrid(config) => {
+                QueryType::SemiHonestHybrid(config)
+                | QueryType::MaliciousHybrid(config)
+                | QueryType::MaliciousReachFrequency(config)
+                | QueryType::MaliciousConversionLift(config) => {
                     write!(
                         f,
                         "&max_breakdown_key={}&with_dp={}&epsilon={}",
@@ -321,6 +336,50 @@ pub mod query {
         pub const AXUM_PATH: &str = "/:query_id";
     }
 
+    pub mod wait {
+        use std::time::Duration;
+
+        use serde::{Deserialize, Serialize};
+
+        use crate::helpers::query::WaitQueryRequest;
+
+        #[derive(Serialize, Deserialize)]
+        pub struct WaitQueryString {
+            pub timeout_ms: u64,
+        }
+
+        impl WaitQueryString {
+            #[must_use]
+            pub fn timeout(&self) -> Duration {
+                Duration::from_millis(self.timeout_ms)
+            }
+        }
+
+        #[cfg(any(all(test, not(feature = "shuttle")), feature = "cli"))] // needed because client is blocking; remove when non-blocking
+        pub fn try_into_http_request(
+            req: &WaitQueryRequest,
+            scheme: axum::http::uri::Scheme,
+            authority: axum::http::uri::Authority,
+        ) -> crate::net::http_serde::OutgoingRequest {
+            let uri = axum::http::uri::Uri::builder()
+                .scheme(scheme)
+                .authority(authority)
+                .path_and_query(format!(
+                    "{}/{}/wait?timeout_ms={}",
+                    crate::net::http_serde::query::BASE_AXUM_PATH,
+                    req.query_id.as_ref(),
+                    req.timeout.as_millis(),
+                ))
+                .build()?;
+            Ok(hyper::Request::get(uri).body(axum::body::Body::empty())?)
+        }
+
+        /// Same as the status API response.
+        pub type ResponseBody = super::status::ResponseBody;
+
+        pub const AXUM_PATH: &str = "/:query_id/wait";
+    }
+
     pub mod input {
         use axum::{
             async_trait,
@@ -467,7 +526,7 @@ pub mod query {
         use serde::{Deserialize, Serialize};
 
         use crate::{
-            helpers::{routing::RouteId, HelperResponse, NoStep, RouteParams},
+            helpers::{routing::RouteId, HelperResponse, NoStep, QueryProgress, RouteParams},
             protocol::QueryId,
             query::QueryStatus,
         };
@@ -525,6 +584,9 @@ pub mod query {
         #[derive(Clone, Debug, Serialize, Deserialize)]
         pub struct ResponseBody {
             pub status: QueryStatus,
+            /// Present once the query is running on all shards.
+            #[serde(default)]
+            pub progress: Option<QueryProgress>,
         }
 
         impl From<HelperResponse> for ResponseBody {
@@ -596,11 +658,68 @@ pub mod query {
     pub mod kill {
         use serde::{Deserialize, Serialize};
 
+        use crate::{
+            helpers::{query::KillQuery, HelperResponse},
+            protocol::QueryId,
+        };
+
+        #[derive(Serialize, Deserialize)]
+        pub struct KillQueryString {
+            #[serde(default)]
+            pub forwarded: bool,
+        }
+
+        pub struct Request {
+            pub data: KillQuery,
+        }
+
+        impl Request {
+            pub fn new(data: KillQuery) -> Self {
+                Self { data }
+            }
+
+            pub fn try_into_http_request(
+                self,
+                scheme: axum::http::uri::Scheme,
+                authority: axum::http::uri::Authority,
+            ) -> crate::net::http_serde::OutgoingRequest {
+                let uri = axum::http::uri::Uri::builder()
+                    .scheme(scheme)
+                    .authority(authority)
+                    .path_and_query(format!(
+                        "{}/{}/kill?forwarded={}",
+                        crate::net::http_serde::query::BASE_AXUM_PATH,
+                        self.data.query_id.as_ref(),
+                        self.data.forwarded,
+                    ))
+                    .build()?;
+                Ok(hyper::Request::post(uri).body(axum::body::Body::empty())?)
+            }
+        }
+
+        #[derive(Clone, Debug, Serialize, Deserialize)]
+        pub struct ResponseBody {
+            pub query_id: QueryId,
+            pub status: String,
+        }
+
+        impl From<HelperResponse> for ResponseBody {
+            fn from(value: HelperResponse) -> Self {
+                serde_json::from_slice(value.into_body().as_slice()).unwrap()
+            }
+        }
+
+        pub const AXUM_PATH: &str = "/:query_id/kill";
+    }
+
+    pub mod metrics {
         use crate::{
             helpers::{routing::RouteId, HelperResponse, NoStep, RouteParams},
             protocol::QueryId,
+            telemetry::stats::QueryMetrics,
         };
 
+        #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
         pub struct Request {
             pub query_id: QueryId,
         }
@@ -609,7 +728,7 @@ pub mod query {
             type Params = String;
 
             fn resource_identifier(&self) -> RouteId {
-                RouteId::KillQuery
+                RouteId::QueryMetrics
             }
 
             fn query_id(&self) -> QueryId {
@@ -626,18 +745,12 @@ pub mod query {
         }
 
         impl Request {
-            /// Currently, it is only possible to kill
-            /// a query by issuing an HTTP request manually.
-            /// Maybe report collector can support this API,
-            /// but for now, only tests exercise this path
-            /// hence methods here are hidden behind feature
-            /// flags
-            #[cfg(all(test, unit_test))]
+            #[cfg(any(all(test, not(feature = "shuttle")), feature = "cli"))] // needed because client is blocking; remove when non-blocking
             pub fn new(query_id: QueryId) -> Self {
                 Self { query_id }
             }
 
-            #[cfg(all(test, unit_test))]
+            #[cfg(any(all(test, not(feature = "shuttle")), feature = "cli"))] // needed because client is blocking; remove when non-blocking
             pub fn try_into_http_request(
                 self,
                 scheme: axum::http::uri::Scheme,
@@ -647,20 +760,17 @@ pub mod query {
                     .scheme(scheme)
                     .authority(authority)
                     .path_and_query(format!(
-                        "{}/{}/kill",
+                        "{}/{}/metrics",
                         crate::net::http_serde::query::BASE_AXUM_PATH,
                         self.query_id.as_ref()
                     ))
                     .build()?;
-                Ok(hyper::Request::post(uri).body(axum::body::Body::empty())?)
+                Ok(hyper::Request::get(uri).body(axum::body::Body::empty())?)
             }
         }
 
-        #[derive(Clone, Debug, Serialize, Deserialize)]
-        pub struct ResponseBody {
-            pub query_id: QueryId,
-            pub status: String,
-        }
+        /// Metrics are sent as is, without any wrapping.
+        pub type ResponseBody = QueryMetrics;
 
         impl From<HelperResponse> for ResponseBody {
             fn from(value: HelperResponse) -> Self {
@@ -668,13 +778,19 @@ pub mod query {
             }
         }
 
-        pub const AXUM_PATH: &str = "/:query_id/kill";
+        pub const AXUM_PATH: &str = "/:query_id/metrics";
     }
 
     pub mod status_match {
         use serde::{Deserialize, Serialize};
 
-        use crate::{helpers::query::CompareStatusRequest, query::QueryStatus};
+        use crate::{
+            helpers::{
+                query::{CompareStatusRequest, CompareStatusResponse},
+                HelperResponse,
+            },
+            query::QueryStatus,
+        };
 
         #[derive(Serialize, Deserialize)]
         pub struct StatusQueryString {
@@ -706,12 +822,22 @@ pub mod query {
                     "{}/{}/status-match?{}",
                     crate::net::http_serde::query::BASE_AXUM_PATH,
                     req.query_id.as_ref(),
-                    StatusQueryString::from(req.status).url_encode(),
+                    StatusQueryString::from(req.status.clone()).url_encode(),
                 ))
                 .build()?;
             Ok(hyper::Request::get(uri).body(axum::body::Body::empty())?)
         }
 
+        /// Shards that agree with the requested status respond with their own status and
+        /// progress.
+        pub type ResponseBody = CompareStatusResponse;
+
+        impl From<HelperResponse> for ResponseBody {
+            fn from(value: HelperResponse) -> Self {
+                serde_json::from_slice(value.into_body().as_slice()).unwrap()
+            }
+        }
+
         pub const AXUM_PATH: &str = "/:query_id/status-match";
     }
 }
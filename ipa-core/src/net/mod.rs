@@ -22,11 +22,13 @@ pub mod query_input;
 mod server;
 #[cfg(all(test, not(feature = "shuttle")))]
 pub mod test;
+mod tls;
 mod transport;
 
 pub use client::{ClientIdentity, IpaHttpClient};
 pub use error::{Error, ShardError};
 pub use server::{IpaHttpServer, TracingSpanMaker};
+pub use tls::CertificateReloader;
 pub use transport::{HttpTransport, MpcHttpTransport, ShardHttpTransport};
 
 const APPLICATION_JSON: &str = "application/json";
@@ -34,6 +36,8 @@ const APPLICATION_OCTET_STREAM: &str = "application/octet-stream";
 static HTTP_HELPER_ID_HEADER: HeaderName = HeaderName::from_static("x-unverified-helper-identity");
 static HTTP_SHARD_INDEX_HEADER: HeaderName = HeaderName::from_static("x-unverified-shard-index");
 static HTTP_QUERY_INPUT_URL_HEADER: HeaderName = HeaderName::from_static("x-query-input-url");
+static HTTP_TRACEPARENT_HEADER: HeaderName =
+    HeaderName::from_static(crate::telemetry::trace::TRACEPARENT_HEADER);
 
 /// This has the same meaning as const defined in h2 crate, but we don't import it directly.
 /// According to the [`spec`] it cannot exceed 2^31 - 1.
@@ -1,24 +1,40 @@
-use axum::{extract::Path, routing::post, Extension, Json, Router};
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query},
+    routing::post,
+    Extension, Json, Router,
+};
 use hyper::StatusCode;
 
 use crate::{
-    helpers::{ApiError, BodyStream},
+    helpers::{query::KillQuery, ApiError, BodyStream},
     net::{
-        http_serde::query::kill::{self, Request},
+        http_serde::query::kill::{self, KillQueryString},
         server::Error,
-        transport::MpcHttpTransport,
+        ConnectionFlavor,
         Error::QueryIdNotFound,
+        HttpTransport,
     },
     protocol::QueryId,
     query::QueryKillStatus,
 };
 
-async fn handler(
-    transport: Extension<MpcHttpTransport>,
+/// Called by report collectors to kill a query on all helpers, and by leader shards to
+/// forward that request to their peer helpers and shards.
+async fn handler<F: ConnectionFlavor>(
+    transport: Extension<Arc<HttpTransport<F>>>,
     Path(query_id): Path<QueryId>,
+    Query(KillQueryString { forwarded }): Query<KillQueryString>,
 ) -> Result<Json<kill::ResponseBody>, Error> {
-    let req = Request { query_id };
-    match transport.dispatch(req, BodyStream::empty()).await {
+    let req = KillQuery {
+        query_id,
+        forwarded,
+    };
+    match Arc::clone(&transport)
+        .dispatch(req, BodyStream::empty())
+        .await
+    {
         Ok(state) => Ok(Json(kill::ResponseBody::from(state))),
         Err(ApiError::QueryKill(QueryKillStatus::NoSuchQuery(query_id))) => Err(
             Error::application(StatusCode::NOT_FOUND, QueryIdNotFound(query_id)),
@@ -27,9 +43,9 @@ async fn handler(
     }
 }
 
-pub fn router(transport: MpcHttpTransport) -> Router {
+pub fn router<F: ConnectionFlavor>(transport: Arc<HttpTransport<F>>) -> Router {
     Router::new()
-        .route(kill::AXUM_PATH, post(handler))
+        .route(kill::AXUM_PATH, post(handler::<F>))
         .layer(Extension(transport))
 }
 
@@ -44,6 +60,7 @@ mod tests {
     use crate::{
         helpers::{
             make_owned_handler,
+            query::KillQuery,
             routing::{Addr, RouteId},
             ApiError, BodyStream, HelperIdentity, HelperResponse,
         },
@@ -67,17 +84,28 @@ mod tests {
                     panic!("unexpected call: {addr:?}");
                 };
                 assert_eq!(addr.query_id, Some(expected_query_id));
+                assert!(addr.into::<KillQuery>().unwrap().forwarded);
                 Ok(HelperResponse::from(QueryKilled(expected_query_id)))
             },
         );
 
-        let req = http_serde::query::kill::Request::new(QueryId);
+        let req = http_serde::query::kill::Request::new(KillQuery {
+            query_id: QueryId,
+            forwarded: true,
+        });
         let req = req
             .try_into_http_request(Scheme::HTTP, Authority::from_static("localhost"))
             .unwrap();
         assert_success_with(req, handler).await;
     }
 
+    fn kill_query() -> KillQuery {
+        KillQuery {
+            query_id: QueryId,
+            forwarded: false,
+        }
+    }
+
     #[tokio::test]
     async fn no_such_query() {
         let handler = make_owned_handler(
@@ -86,7 +114,7 @@ mod tests {
             },
         );
 
-        let req = http_serde::query::kill::Request::new(QueryId)
+        let req = http_serde::query::kill::Request::new(kill_query())
             .try_into_http_request(Scheme::HTTP, Authority::from_static("localhost"))
             .unwrap();
         assert_fails_with_handler(req, handler, StatusCode::NOT_FOUND).await;
@@ -102,7 +130,7 @@ mod tests {
             },
         );
 
-        let req = http_serde::query::kill::Request::new(QueryId)
+        let req = http_serde::query::kill::Request::new(kill_query())
             .try_into_http_request(Scheme::HTTP, Authority::from_static("localhost"))
             .unwrap();
         assert_fails_with_handler(req, handler, StatusCode::INTERNAL_SERVER_ERROR).await;
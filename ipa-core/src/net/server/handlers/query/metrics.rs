@@ -0,0 +1,117 @@
+use axum::{extract::Path, routing::get, Extension, Json, Router};
+use hyper::StatusCode;
+
+use crate::{
+    helpers::{ApiError, BodyStream},
+    net::{
+        http_serde::query::metrics::{self, Request},
+        server::Error,
+        transport::MpcHttpTransport,
+        Error::QueryIdNotFound,
+    },
+    protocol::QueryId,
+    query::QueryMetricsError,
+};
+
+async fn handler(
+    transport: Extension<MpcHttpTransport>,
+    Path(query_id): Path<QueryId>,
+) -> Result<Json<metrics::ResponseBody>, Error> {
+    let req = Request { query_id };
+    match transport.dispatch(req, BodyStream::empty()).await {
+        Ok(resp) => Ok(Json(metrics::ResponseBody::from(resp))),
+        Err(ApiError::QueryMetrics(QueryMetricsError::NoSuchQuery(query_id))) => Err(
+            Error::application(StatusCode::NOT_FOUND, QueryIdNotFound(query_id)),
+        ),
+        Err(e) => Err(Error::application(StatusCode::INTERNAL_SERVER_ERROR, e)),
+    }
+}
+
+pub fn router(transport: MpcHttpTransport) -> Router {
+    Router::new()
+        .route(metrics::AXUM_PATH, get(handler))
+        .layer(Extension(transport))
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use axum::http::uri::{Authority, Scheme};
+    use hyper::StatusCode;
+
+    use crate::{
+        helpers::{
+            make_owned_handler,
+            routing::{Addr, RouteId},
+            ApiError, BodyStream, HelperIdentity, HelperResponse,
+        },
+        net::{
+            http_serde,
+            server::handlers::query::test_helpers::{
+                assert_fails_with_handler, assert_success_with,
+            },
+        },
+        protocol::QueryId,
+        query::QueryMetricsError,
+        telemetry::stats::{CounterSnapshot, QueryMetrics},
+    };
+
+    #[tokio::test]
+    async fn metrics_test() {
+        let expected_query_id = QueryId;
+        let handler = make_owned_handler(
+            move |addr: Addr<HelperIdentity>, _data: BodyStream| async move {
+                let RouteId::QueryMetrics = addr.route else {
+                    panic!("unexpected call");
+                };
+                assert_eq!(addr.query_id, Some(expected_query_id));
+                let mut metrics = QueryMetrics::default();
+                metrics.counters.insert(
+                    "records.sent".to_string(),
+                    CounterSnapshot {
+                        total: 5,
+                        ..Default::default()
+                    },
+                );
+                Ok(HelperResponse::from(metrics))
+            },
+        );
+
+        let req = http_serde::query::metrics::Request::new(QueryId);
+        let req = req
+            .try_into_http_request(Scheme::HTTP, Authority::from_static("localhost"))
+            .unwrap();
+        let body = assert_success_with(req, handler).await;
+        let metrics: QueryMetrics = serde_json::from_slice(&body).unwrap();
+        assert_eq!(5, metrics.get_counter("records.sent"));
+    }
+
+    #[tokio::test]
+    async fn no_such_query() {
+        let handler = make_owned_handler(
+            move |_addr: Addr<HelperIdentity>, _data: BodyStream| async move {
+                Err(QueryMetricsError::NoSuchQuery(QueryId).into())
+            },
+        );
+
+        let req = http_serde::query::metrics::Request::new(QueryId)
+            .try_into_http_request(Scheme::HTTP, Authority::from_static("localhost"))
+            .unwrap();
+        assert_fails_with_handler(req, handler, StatusCode::NOT_FOUND).await;
+    }
+
+    #[tokio::test]
+    async fn unknown_error() {
+        let handler = make_owned_handler(
+            move |_addr: Addr<HelperIdentity>, _data: BodyStream| async move {
+                Err(ApiError::DeserializationFailure(
+                    serde_json::from_str::<()>("not-a-json").unwrap_err(),
+                ))
+            },
+        );
+
+        let req = http_serde::query::metrics::Request::new(QueryId)
+            .try_into_http_request(Scheme::HTTP, Authority::from_static("localhost"))
+            .unwrap();
+        assert_fails_with_handler(req, handler, StatusCode::INTERNAL_SERVER_ERROR).await;
+    }
+}
@@ -1,11 +1,13 @@
 mod create;
 mod input;
 mod kill;
+mod metrics;
 mod prepare;
 mod results;
 mod status;
 mod status_match;
 mod step;
+mod wait;
 
 use std::marker::PhantomData;
 
@@ -38,7 +40,9 @@ pub fn query_router(transport: MpcHttpTransport) -> Router {
         .merge(create::router(transport.clone()))
         .merge(input::router(transport.clone()))
         .merge(status::router(transport.clone()))
-        .merge(kill::router(transport.clone()))
+        .merge(wait::router(transport.clone()))
+        .merge(kill::router(Arc::clone(&transport.inner_transport)))
+        .merge(metrics::router(transport.clone()))
         .merge(results::router(transport.inner_transport))
 }
 
@@ -62,6 +66,7 @@ pub fn s2s_router(transport: Arc<HttpTransport<Shard>>) -> Router {
         .merge(step::router(Arc::clone(&transport)))
         .merge(prepare::router(Arc::clone(&transport)))
         .merge(results::router(Arc::clone(&transport)))
+        .merge(kill::router(Arc::clone(&transport)))
         .merge(status_match::router(transport))
         .layer(layer_fn(HelperAuthentication::<_, Shard>::new))
 }
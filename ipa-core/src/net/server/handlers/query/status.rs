@@ -55,15 +55,16 @@ mod tests {
         let expected_status = QueryStatus::Running;
         let expected_query_id = QueryId;
 
-        let handler = make_owned_handler(
-            move |addr: Addr<HelperIdentity>, _data: BodyStream| async move {
+        let handler = make_owned_handler(move |addr: Addr<HelperIdentity>, _data: BodyStream| {
+            let expected_status = expected_status.clone();
+            async move {
                 let RouteId::QueryStatus = addr.route else {
                     panic!("unexpected call");
                 };
                 assert_eq!(addr.query_id, Some(expected_query_id));
                 Ok(HelperResponse::from(expected_status))
-            },
-        );
+            }
+        });
 
         let req = http_serde::query::status::Request::new(QueryId);
         let req = req
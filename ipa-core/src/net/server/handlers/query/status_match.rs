@@ -1,7 +1,7 @@
 use axum::{
     extract::{Path, Query},
     routing::get,
-    Extension, Router,
+    Extension, Json, Router,
 };
 use hyper::StatusCode;
 
@@ -23,13 +23,13 @@ async fn handler(
     transport: Extension<Arc<HttpTransport<Shard>>>,
     Path(query_id): Path<QueryId>,
     Query(StatusQueryString { status }): Query<StatusQueryString>,
-) -> Result<(), Error> {
+) -> Result<Json<status_match::ResponseBody>, Error> {
     let req = CompareStatusRequest { query_id, status };
     match Arc::clone(&transport)
         .dispatch(req, BodyStream::empty())
         .await
     {
-        Ok(_) => Ok(()),
+        Ok(resp) => Ok(Json(status_match::ResponseBody::from(resp))),
         Err(ApiError::QueryStatus(QueryStatusError::DifferentStatus { my_status, .. })) => {
             Err(crate::net::error::ShardQueryStatusMismatchError { actual: my_status }.into())
         }
@@ -56,9 +56,9 @@ mod tests {
     use crate::{
         helpers::{
             make_owned_handler,
-            query::CompareStatusRequest,
+            query::{CompareStatusRequest, CompareStatusResponse},
             routing::{Addr, RouteId},
-            ApiError, BodyStream, HelperResponse, RequestHandler,
+            ApiError, BodyStream, HelperResponse, QueryProgress, RequestHandler,
         },
         net::{
             error::ShardQueryStatusMismatchError,
@@ -95,17 +95,21 @@ mod tests {
     }
 
     fn handler_status_match(expected_status: QueryStatus) -> Arc<dyn RequestHandler<ShardIndex>> {
-        make_owned_handler(
-            move |addr: Addr<ShardIndex>, _data: BodyStream| async move {
+        make_owned_handler(move |addr: Addr<ShardIndex>, _data: BodyStream| {
+            let expected_status = expected_status.clone();
+            async move {
                 let RouteId::QueryStatus = addr.route else {
                     panic!("unexpected call");
                 };
                 let req = addr.into::<CompareStatusRequest>().unwrap();
                 assert_eq!(req.query_id, QueryId);
                 assert_eq!(req.status, expected_status);
-                Ok(HelperResponse::ok())
-            },
-        )
+                Ok(HelperResponse::from(CompareStatusResponse {
+                    status: expected_status,
+                    progress: Some(QueryProgress::default()),
+                }))
+            }
+        })
     }
 
     fn handler_status_mismatch(
@@ -113,8 +117,9 @@ mod tests {
     ) -> Arc<dyn RequestHandler<ShardIndex>> {
         assert_ne!(expected_status, QueryStatus::Running);
 
-        make_owned_handler(
-            move |addr: Addr<ShardIndex>, _data: BodyStream| async move {
+        make_owned_handler(move |addr: Addr<ShardIndex>, _data: BodyStream| {
+            let expected_status = expected_status.clone();
+            async move {
                 let RouteId::QueryStatus = addr.route else {
                     panic!("unexpected call");
                 };
@@ -125,14 +130,14 @@ mod tests {
                     my_status: QueryStatus::Running,
                     other_status: expected_status,
                 }))
-            },
-        )
+            }
+        })
     }
 
     #[tokio::test]
     async fn status_success() {
         let expected_status = QueryStatus::Running;
-        let req = authenticated(http_request(for_status(expected_status)));
+        let req = authenticated(http_request(for_status(expected_status.clone())));
 
         TestServer::<Shard>::oneshot_success(req, handler_status_match(expected_status)).await;
     }
@@ -141,22 +146,29 @@ mod tests {
     async fn status_client_success() {
         let expected_status = QueryStatus::Running;
         let test_server = TestServerBuilder::<Shard>::default()
-            .with_request_handler(handler_status_match(expected_status))
+            .with_request_handler(handler_status_match(expected_status.clone()))
             .build()
             .await;
 
-        test_server
+        let resp = test_server
             .client
-            .status_match(for_status(expected_status))
+            .status_match(for_status(expected_status.clone()))
             .await
             .unwrap();
+        assert_eq!(
+            CompareStatusResponse {
+                status: expected_status,
+                progress: Some(QueryProgress::default()),
+            },
+            resp
+        );
     }
 
     #[tokio::test]
     async fn status_client_mismatch() {
         let diff_status = QueryStatus::Preparing;
         let test_server = TestServerBuilder::<Shard>::default()
-            .with_request_handler(handler_status_mismatch(diff_status))
+            .with_request_handler(handler_status_mismatch(diff_status.clone()))
             .build()
             .await;
         let e = test_server
@@ -177,7 +189,7 @@ mod tests {
     #[tokio::test]
     async fn status_mismatch() {
         let req_status = QueryStatus::Completed;
-        let handler = handler_status_mismatch(req_status);
+        let handler = handler_status_mismatch(req_status.clone());
         let req = authenticated(http_request(for_status(req_status)));
 
         let resp = TestServer::<Shard>::oneshot(req, handler).await;
@@ -0,0 +1,133 @@
+use axum::{
+    extract::{Path, Query},
+    routing::get,
+    Extension, Json, Router,
+};
+use hyper::StatusCode;
+
+use crate::{
+    helpers::{query::WaitQueryRequest, ApiError, BodyStream},
+    net::{
+        http_serde::query::wait::{self, WaitQueryString},
+        server::Error,
+        transport::MpcHttpTransport,
+        Error::QueryIdNotFound,
+    },
+    protocol::QueryId,
+    query::QueryStatusError,
+};
+
+async fn handler(
+    transport: Extension<MpcHttpTransport>,
+    Path(query_id): Path<QueryId>,
+    Query(params): Query<WaitQueryString>,
+) -> Result<Json<wait::ResponseBody>, Error> {
+    let req = WaitQueryRequest {
+        query_id,
+        timeout: params.timeout(),
+    };
+    match transport.dispatch(req, BodyStream::empty()).await {
+        Ok(state) => Ok(Json(wait::ResponseBody::from(state))),
+        Err(ApiError::QueryStatus(QueryStatusError::NoSuchQuery(query_id))) => Err(
+            Error::application(StatusCode::NOT_FOUND, QueryIdNotFound(query_id)),
+        ),
+        Err(e) => Err(Error::application(StatusCode::INTERNAL_SERVER_ERROR, e)),
+    }
+}
+
+pub fn router(transport: MpcHttpTransport) -> Router {
+    Router::new()
+        .route(wait::AXUM_PATH, get(handler))
+        .layer(Extension(transport))
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use std::time::Duration;
+
+    use axum::{
+        body::Body,
+        http::uri::{Authority, Scheme},
+    };
+    use hyper::StatusCode;
+
+    use crate::{
+        helpers::{
+            make_owned_handler,
+            query::WaitQueryRequest,
+            routing::{Addr, RouteId},
+            BodyStream, HelperIdentity, HelperResponse,
+        },
+        net::{
+            http_serde,
+            server::handlers::query::test_helpers::{
+                assert_fails_with, assert_fails_with_handler, assert_success_with,
+            },
+        },
+        protocol::QueryId,
+        query::{QueryStatus, QueryStatusError},
+    };
+
+    #[tokio::test]
+    async fn wait_test() {
+        let expected_req = WaitQueryRequest {
+            query_id: QueryId,
+            timeout: Duration::from_millis(1500),
+        };
+
+        let handler = make_owned_handler({
+            let expected_req = expected_req.clone();
+            move |addr: Addr<HelperIdentity>, _data: BodyStream| {
+                let expected_req = expected_req.clone();
+                async move {
+                    let RouteId::QueryWait = addr.route else {
+                        panic!("unexpected call");
+                    };
+                    assert_eq!(addr.query_id, Some(expected_req.query_id));
+                    assert_eq!(expected_req, addr.into::<WaitQueryRequest>().unwrap());
+                    Ok(HelperResponse::from(QueryStatus::Completed))
+                }
+            }
+        });
+
+        let req = http_serde::query::wait::try_into_http_request(
+            &expected_req,
+            Scheme::HTTP,
+            Authority::from_static("localhost"),
+        )
+        .unwrap();
+        assert_success_with(req, handler).await;
+    }
+
+    #[tokio::test]
+    async fn no_such_query() {
+        let handler = make_owned_handler(
+            move |_addr: Addr<HelperIdentity>, _data: BodyStream| async move {
+                Err(QueryStatusError::NoSuchQuery(QueryId).into())
+            },
+        );
+
+        let req = http_serde::query::wait::try_into_http_request(
+            &WaitQueryRequest {
+                query_id: QueryId,
+                timeout: Duration::from_millis(1500),
+            },
+            Scheme::HTTP,
+            Authority::from_static("localhost"),
+        )
+        .unwrap();
+        assert_fails_with_handler(req, handler, StatusCode::NOT_FOUND).await;
+    }
+
+    #[tokio::test]
+    async fn missing_timeout() {
+        let uri = format!(
+            "http://localhost{}/{}/wait",
+            http_serde::query::BASE_AXUM_PATH,
+            QueryId.as_ref()
+        );
+        let req = hyper::Request::get(uri).body(Body::empty()).unwrap();
+
+        assert_fails_with(req, StatusCode::BAD_REQUEST).await;
+    }
+}
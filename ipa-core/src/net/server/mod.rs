@@ -2,7 +2,6 @@ mod config;
 mod handlers;
 
 use std::{
-    borrow::Cow,
     io,
     marker::PhantomData,
     net::{Ipv4Addr, SocketAddr, TcpListener},
@@ -11,11 +10,11 @@ use std::{
 };
 
 use ::tokio::{
-    fs,
     io::{AsyncRead, AsyncWrite},
     net::TcpStream,
 };
 use axum::{
+    body::Body,
     http::HeaderValue,
     response::{IntoResponse, Response},
     routing::IntoMakeService,
@@ -33,26 +32,31 @@ use futures::{
 };
 use hyper::{body::Incoming, Request};
 use ipa_metrics::counter;
-use rustls::{server::WebPkiClientVerifier, RootCertStore};
+use rustls_pki_types::CertificateDer;
 use tokio_rustls::server::TlsStream;
 use tower::{layer::layer_fn, Service};
-use tower_http::trace::TraceLayer;
+use tower_http::{
+    classify::{ServerErrorsAsFailures, SharedClassifier},
+    trace::TraceLayer,
+};
 use tracing::{error, Span};
 
 use super::{transport::MpcHttpTransport, HttpTransport, Shard};
 use crate::{
-    config::{
-        NetworkConfig, OwnedCertificate, OwnedPrivateKey, PeerConfig, ServerConfig, TlsConfig,
-    },
+    config::{NetworkConfig, PeerConfig, ServerConfig},
     error::BoxError,
     executor::{IpaJoinHandle, IpaRuntime},
     helpers::TransportIdentity,
     net::{
-        parse_certificate_and_private_key_bytes, server::config::HttpServerConfig,
+        server::config::HttpServerConfig,
+        tls::{client_verifier, load_certificate_and_key, CertificateReloader},
         ConnectionFlavor, Error, Helper, CRYPTO_PROVIDER,
     },
     sync::Arc,
-    telemetry::metrics::{web::RequestProtocolVersion, REQUESTS_RECEIVED},
+    telemetry::{
+        metrics::{web::RequestProtocolVersion, REQUESTS_RECEIVED},
+        trace::{TRACEPARENT_FIELD, TRACE_TARGET},
+    },
 };
 
 pub trait TracingSpanMaker: Send + Sync + Clone + 'static {
@@ -86,6 +90,7 @@ impl TracingSpanMaker for () {
 pub struct IpaHttpServer<F: ConnectionFlavor> {
     config: ServerConfig,
     network_config: NetworkConfig<F>,
+    certificates: Option<Arc<CertificateReloader<F>>>,
     router: Router,
 }
 
@@ -102,6 +107,7 @@ impl IpaHttpServer<Helper> {
         IpaHttpServer {
             config,
             network_config,
+            certificates: None,
             router,
         }
     }
@@ -118,12 +124,22 @@ impl IpaHttpServer<Shard> {
         IpaHttpServer {
             config,
             network_config,
+            certificates: None,
             router,
         }
     }
 }
 
 impl<F: ConnectionFlavor> IpaHttpServer<F> {
+    /// Makes this server take its certificate and certificates of its peers from `certificates`,
+    /// instead of the server and network configuration. That allows rotating certificates
+    /// without restarting the server.
+    #[must_use]
+    pub fn with_certificate_reloader(mut self, certificates: Arc<CertificateReloader<F>>) -> Self {
+        self.certificates = Some(certificates);
+        self
+    }
+
     #[cfg(all(test, unit_test))]
     pub(crate) async fn handle_req(
         &self,
@@ -160,7 +176,7 @@ impl<F: ConnectionFlavor> IpaHttpServer<F> {
         #[cfg(not(test))]
         const BIND_ADDRESS: Ipv4Addr = Ipv4Addr::UNSPECIFIED;
 
-        let svc = self.router.clone().layer(
+        let svc = self.router.clone().layer(trace_context_layer()).layer(
             TraceLayer::new_for_http()
                 .make_span_with(move |_request: &hyper::Request<_>| tracing.make_span())
                 .on_request(|request: &hyper::Request<_>, _: &Span| {
@@ -191,14 +207,14 @@ impl<F: ConnectionFlavor> IpaHttpServer<F> {
                 spawn_server(runtime, axum_server::bind(addr), handle.clone(), svc).await
             }
             (false, Some(listener)) => {
-                let rustls_config = rustls_config(&self.config, self.network_config.vec_peers())
+                let rustls_config = self
+                    .rustls_config()
                     .await
                     .expect("invalid TLS configuration");
                 spawn_server(
                     runtime,
-                    axum_server::from_tcp_rustls(listener, rustls_config).map(|a| {
-                        ClientCertRecognizingAcceptor::new(a, self.network_config.clone())
-                    }),
+                    axum_server::from_tcp_rustls(listener, rustls_config)
+                        .map(|a| ClientCertRecognizingAcceptor::new(a, self.peer_certificates())),
                     handle.clone(),
                     svc.into_make_service(),
                 )
@@ -206,14 +222,14 @@ impl<F: ConnectionFlavor> IpaHttpServer<F> {
             }
             (false, None) => {
                 let addr = SocketAddr::new(BIND_ADDRESS.into(), self.config.port.unwrap_or(0));
-                let rustls_config = rustls_config(&self.config, self.network_config.vec_peers())
+                let rustls_config = self
+                    .rustls_config()
                     .await
                     .expect("invalid TLS configuration");
                 spawn_server(
                     runtime,
-                    axum_server::bind_rustls(addr, rustls_config).map(|a| {
-                        ClientCertRecognizingAcceptor::new(a, self.network_config.clone())
-                    }),
+                    axum_server::bind_rustls(addr, rustls_config)
+                        .map(|a| ClientCertRecognizingAcceptor::new(a, self.peer_certificates())),
                     handle.clone(),
                     svc.into_make_service(),
                 )
@@ -237,6 +253,66 @@ impl<F: ConnectionFlavor> IpaHttpServer<F> {
         );
         (bound_addr, task_handle)
     }
+
+    /// Create a `RustlsConfig` for this server.
+    ///
+    /// `RustlsConfig` is an axum type. The native rustls configuration is `rustls::ServerConfig`.
+    /// Since we have particular needs related to client certificates, we build a native rustls
+    /// config, and then convert it into the axum config type.
+    ///
+    /// # Errors
+    /// If there is a problem with the TLS configuration.
+    async fn rustls_config(&self) -> Result<RustlsConfig, BoxError> {
+        let mut config = match &self.certificates {
+            Some(certificates) => certificates.server_config(),
+            None => rustls_config(&self.config, self.network_config.vec_peers()).await?,
+        };
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+        Ok(RustlsConfig::from_config(Arc::new(config)))
+    }
+
+    fn peer_certificates(&self) -> PeerCertificates<F> {
+        match &self.certificates {
+            Some(certificates) => PeerCertificates::Reloadable(Arc::clone(certificates)),
+            None => PeerCertificates::Static(self.network_config.clone()),
+        }
+    }
+}
+
+type TraceContextLayer = TraceLayer<
+    SharedClassifier<ServerErrorsAsFailures>,
+    fn(&Request<Body>) -> Span,
+    (),
+    (),
+    (),
+    (),
+    (),
+>;
+
+/// Creates a layer that attaches every request to the distributed trace, if the caller
+/// propagated the trace context in the request headers. See [`crate::telemetry::trace`].
+fn trace_context_layer() -> TraceContextLayer {
+    fn make_span(request: &Request<Body>) -> Span {
+        tracing::info_span!(
+            target: TRACE_TARGET,
+            "request",
+            method = %request.method(),
+            path = request.uri().path(),
+            { TRACEPARENT_FIELD } = request
+                .headers()
+                .get(&super::HTTP_TRACEPARENT_HEADER)
+                .and_then(|v| v.to_str().ok()),
+        )
+    }
+
+    TraceLayer::new_for_http()
+        .make_span_with(make_span as fn(&Request<Body>) -> Span)
+        .on_request(())
+        .on_response(())
+        .on_body_chunk(())
+        .on_eos(())
+        .on_failure(())
 }
 
 /// Spawns a new server with the given configuration.
@@ -268,68 +344,42 @@ where
     })
 }
 
-async fn certificate_and_key(
-    config: &ServerConfig,
-) -> Result<(Vec<OwnedCertificate>, OwnedPrivateKey), BoxError> {
-    let (cert, key) = match &config.tls {
-        None => return Err("missing TLS configuration".into()),
-        Some(TlsConfig::Inline {
-            certificate,
-            private_key,
-        }) => (
-            Cow::Borrowed(certificate.as_bytes()),
-            Cow::Borrowed(private_key.as_bytes()),
-        ),
-        Some(TlsConfig::File {
-            certificate_file,
-            private_key_file,
-        }) => {
-            let cert = fs::read(certificate_file).await?;
-            let key = fs::read(private_key_file).await?;
-            (Cow::Owned(cert), Cow::Owned(key))
-        }
-    };
-    parse_certificate_and_private_key_bytes(&mut cert.as_ref(), &mut key.as_ref())
-        .map_err(BoxError::from)
-}
-
-/// Create a `RustlsConfig` for the `ServerConfig`.
-///
-/// `RustlsConfig` is an axum type. The native rustls configuration is `rustls::ServerConfig`, which
-/// we import as `RustlsServerConfig`. Since we have particular needs related to client
-/// certificates, we build a native rustls config, and then convert it into the axum config type.
+/// Create a native rustls configuration from the `ServerConfig`, trusting client certificates
+/// of `peers`.
 ///
 /// # Errors
 /// If there is a problem with the TLS configuration.
 async fn rustls_config(
     config: &ServerConfig,
-    certs: Vec<PeerConfig>,
-) -> Result<RustlsConfig, BoxError> {
-    let (cert, key) = certificate_and_key(config).await?;
-
-    let mut trusted_certs = RootCertStore::empty();
-    for cert in certs.into_iter().filter_map(|peer| peer.certificate) {
-        // Note that this uses `webpki::TrustAnchor::try_from_cert_der`, which *does not* validate
-        // the certificate. That is not required for security, but might be desirable to flag
-        // configuration errors.
-        trusted_certs.add(cert)?;
-    }
-    let client_verifier = WebPkiClientVerifier::builder_with_provider(
-        trusted_certs.into(),
-        Arc::clone(&CRYPTO_PROVIDER),
+    peers: Vec<PeerConfig>,
+) -> Result<rustls::ServerConfig, BoxError> {
+    let tls = config.tls.as_ref().ok_or("missing TLS configuration")?;
+    let (cert, key) = load_certificate_and_key(tls).await?;
+
+    Ok(
+        rustls::ServerConfig::builder_with_provider(Arc::clone(&CRYPTO_PROVIDER))
+            .with_safe_default_protocol_versions()
+            .expect("Default crypto provider should be valid")
+            .with_client_cert_verifier(client_verifier(&peers)?)
+            .with_single_cert(cert, key)?,
     )
-    .allow_unauthenticated()
-    .build()
-    .expect("Error building client verifier, should specify valid Trust Anchors");
-    let mut config = rustls::ServerConfig::builder_with_provider(Arc::clone(&CRYPTO_PROVIDER))
-        .with_safe_default_protocol_versions()
-        .expect("Default crypto provider should be valid")
-        .with_client_cert_verifier(client_verifier)
-        .with_single_cert(cert, key)?;
-
-    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
-
-    Ok(RustlsConfig::from_config(Arc::new(config)))
+}
+
+/// Certificates used to identify peers that connect to the server.
+enum PeerCertificates<F: ConnectionFlavor> {
+    /// Loaded once, when the server starts.
+    Static(NetworkConfig<F>),
+    /// Kept up to date by [`CertificateReloader`].
+    Reloadable(Arc<CertificateReloader<F>>),
+}
+
+impl<F: ConnectionFlavor> PeerCertificates<F> {
+    fn identify_cert(&self, cert: Option<&CertificateDer>) -> Option<F::Identity> {
+        match self {
+            Self::Static(network_config) => network_config.identify_cert(cert),
+            Self::Reloadable(certificates) => certificates.identify_cert(cert),
+        }
+    }
 }
 
 /// Axum `Extension` indicating the authenticated remote identity, if any. This can be either a
@@ -367,14 +417,14 @@ impl<I: TransportIdentity> TryFrom<&HeaderValue> for ClientIdentity<I> {
 #[derive(Clone)]
 struct ClientCertRecognizingAcceptor<F: ConnectionFlavor> {
     inner: RustlsAcceptor,
-    network_config: Arc<NetworkConfig<F>>,
+    peer_certificates: Arc<PeerCertificates<F>>,
 }
 
 impl<F: ConnectionFlavor> ClientCertRecognizingAcceptor<F> {
-    fn new(inner: RustlsAcceptor, network_config: NetworkConfig<F>) -> Self {
+    fn new(inner: RustlsAcceptor, peer_certificates: PeerCertificates<F>) -> Self {
         Self {
             inner,
-            network_config: Arc::new(network_config),
+            peer_certificates: Arc::new(peer_certificates),
         }
     }
 }
@@ -391,7 +441,7 @@ where
 
     fn accept(&self, stream: I, service: S) -> Self::Future {
         let acceptor = self.inner.clone();
-        let network_config = Arc::clone(&self.network_config);
+        let peer_certificates = Arc::clone(&self.peer_certificates);
 
         Box::pin(async move {
             let (stream, service) = acceptor.accept(stream, service).await.map_err(|err| {
@@ -411,7 +461,7 @@ where
                 .1
                 .peer_certificates()
                 .and_then(<[_]>::first);
-            let option_id: Option<F::Identity> = network_config.identify_cert(opt_cert);
+            let option_id: Option<F::Identity> = peer_certificates.identify_cert(opt_cert);
             let client_id = option_id.map(ClientIdentity);
             let service = SetClientIdentityFromCertificate {
                 inner: service,
@@ -129,6 +129,7 @@ impl<F: ConnectionFlavor> TestNetwork<F> {
                 PeerConfig {
                     url,
                     certificate,
+                    previous_certificate: None,
                     hpke_config,
                 }
             })
@@ -899,7 +900,7 @@ xYxSeDvd5vt4ROlqgvLMcOOUjbFF7YAT6g==
 ",
 ];
 
-static TEST_CERTS_DER: Lazy<[CertificateDer; 6]> = Lazy::new(|| {
+pub(super) static TEST_CERTS_DER: Lazy<[CertificateDer; 6]> = Lazy::new(|| {
     TEST_CERTS.map(|mut pem| rustls_pemfile::certs(&mut pem).flatten().next().unwrap())
 });
 
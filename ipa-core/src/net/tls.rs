@@ -0,0 +1,561 @@
+//! Rotation of TLS certificates without restarting helpers.
+//!
+//! [`CertificateReloader`] holds the certificate and private key of this helper together with
+//! certificates of its peers. Servers and clients consult it on every TLS handshake, so once
+//! it is reloaded, new connections use the new certificates. Connections that are already
+//! established are not affected, and queries running over them continue uninterrupted.
+
+use std::{
+    borrow::Cow,
+    fmt::{Debug, Formatter},
+    path::PathBuf,
+    sync::{Arc, Mutex, RwLock, Weak},
+    time::{Duration, SystemTime},
+};
+
+use rustls::{
+    client::{
+        danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+        ResolvesClientCert, WebPkiServerVerifier,
+    },
+    crypto::{verify_tls12_signature, verify_tls13_signature},
+    pki_types::{CertificateDer, ServerName, UnixTime},
+    server::{
+        danger::{ClientCertVerified, ClientCertVerifier},
+        ClientHello, ResolvesServerCert, WebPkiClientVerifier,
+    },
+    sign::CertifiedKey,
+    DigitallySignedStruct, DistinguishedName, RootCertStore, SignatureScheme,
+};
+use tokio::fs;
+
+use crate::{
+    config::{NetworkConfig, OwnedCertificate, OwnedPrivateKey, PeerConfig, TlsConfig},
+    error::BoxError,
+    executor::{IpaJoinHandle, IpaRuntime},
+    net::{parse_certificate_and_private_key_bytes, ConnectionFlavor, CRYPTO_PROVIDER},
+};
+
+type NetworkParser<F> = dyn Fn(&str) -> Result<NetworkConfig<F>, BoxError> + Send + Sync;
+
+/// Reads the certificate chain and private key referenced by `tls`.
+///
+/// ## Errors
+/// If files can't be read or don't contain a valid certificate and key.
+pub(super) async fn load_certificate_and_key(
+    tls: &TlsConfig,
+) -> Result<(Vec<OwnedCertificate>, OwnedPrivateKey), BoxError> {
+    let (cert, key) = match tls {
+        TlsConfig::Inline {
+            certificate,
+            private_key,
+        } => (
+            Cow::Borrowed(certificate.as_bytes()),
+            Cow::Borrowed(private_key.as_bytes()),
+        ),
+        TlsConfig::File {
+            certificate_file,
+            private_key_file,
+        } => {
+            let cert = fs::read(certificate_file).await?;
+            let key = fs::read(private_key_file).await?;
+            (Cow::Owned(cert), Cow::Owned(key))
+        }
+    };
+    parse_certificate_and_private_key_bytes(&mut cert.as_ref(), &mut key.as_ref())
+        .map_err(BoxError::from)
+}
+
+/// Builds a verifier that accepts client certificates of all `peers`, including the previous
+/// ones. Clients that don't present a certificate are accepted, but remain unauthenticated.
+///
+/// ## Errors
+/// If any of the peer certificates can't be used as a trust anchor.
+pub(super) fn client_verifier(
+    peers: &[PeerConfig],
+) -> Result<Arc<dyn ClientCertVerifier>, BoxError> {
+    let mut trusted_certs = RootCertStore::empty();
+    for cert in peers.iter().flat_map(PeerConfig::certificates) {
+        // Note that this uses `webpki::TrustAnchor::try_from_cert_der`, which *does not* validate
+        // the certificate. That is not required for security, but might be desirable to flag
+        // configuration errors.
+        trusted_certs.add(cert.clone())?;
+    }
+
+    Ok(WebPkiClientVerifier::builder_with_provider(
+        trusted_certs.into(),
+        Arc::clone(&CRYPTO_PROVIDER),
+    )
+    .allow_unauthenticated()
+    .build()?)
+}
+
+/// Certificates and keys currently in use.
+struct Material<F: ConnectionFlavor> {
+    key: Arc<CertifiedKey>,
+    network: NetworkConfig<F>,
+    client_verifier: Arc<dyn ClientCertVerifier>,
+}
+
+impl<F: ConnectionFlavor> Material<F> {
+    async fn load(tls: &TlsConfig, network: NetworkConfig<F>) -> Result<Self, BoxError> {
+        let (cert, key) = load_certificate_and_key(tls).await?;
+        let key = CertifiedKey::from_der(cert, key, &CRYPTO_PROVIDER)?;
+        let client_verifier = client_verifier(&network.peers)?;
+
+        Ok(Self {
+            key: Arc::new(key),
+            network,
+            client_verifier,
+        })
+    }
+}
+
+/// Source of TLS certificates for a helper that can be reloaded while the helper is running.
+///
+/// It watches the certificate and private key files of this helper and, optionally, the network
+/// configuration file that contains certificates of its peers. When any of them changes,
+/// [`reload_if_changed`] loads all of them again. If the new files are not valid, the current
+/// certificates remain in use.
+///
+/// To make rotation seamless, a peer must first publish its new certificate in the network
+/// configuration, keeping the old one as [`PeerConfig::previous_certificate`]. After all helpers
+/// picked up the change, the peer can start using the new certificate.
+///
+/// [`reload_if_changed`]: Self::reload_if_changed
+pub struct CertificateReloader<F: ConnectionFlavor> {
+    tls: TlsConfig,
+    network_file: Option<(PathBuf, Box<NetworkParser<F>>)>,
+    modified: Mutex<Vec<Option<SystemTime>>>,
+    current: RwLock<Arc<Material<F>>>,
+}
+
+impl<F: ConnectionFlavor> CertificateReloader<F> {
+    /// Loads the certificate and private key of this helper.
+    ///
+    /// ## Errors
+    /// If the certificate or key can't be loaded, or if peer certificates in `network`
+    /// are not valid.
+    ///
+    /// ## Panics
+    /// If the internal mutex is poisoned.
+    pub async fn new(tls: TlsConfig, network: NetworkConfig<F>) -> Result<Self, BoxError> {
+        let material = Material::load(&tls, network).await?;
+        let this = Self {
+            tls,
+            network_file: None,
+            modified: Mutex::default(),
+            current: RwLock::new(Arc::new(material)),
+        };
+        *this.modified.lock().unwrap() = this.modified_times();
+
+        Ok(this)
+    }
+
+    /// Also reloads peer certificates when the network configuration file at `path` changes.
+    /// `parse` turns the contents of that file into the network configuration for this helper.
+    ///
+    /// ## Panics
+    /// If the internal mutex is poisoned.
+    #[must_use]
+    pub fn with_network_file<P>(mut self, path: PathBuf, parse: P) -> Self
+    where
+        P: Fn(&str) -> Result<NetworkConfig<F>, BoxError> + Send + Sync + 'static,
+    {
+        self.network_file = Some((path, Box::new(parse)));
+        *self.modified.lock().unwrap() = self.modified_times();
+        self
+    }
+
+    /// Reloads all certificates if any of the watched files have been modified since the last
+    /// time they were loaded. Returns `true` if certificates were reloaded.
+    ///
+    /// ## Errors
+    /// If files have been modified, but the new content is not valid. Certificates that are
+    /// currently in use remain unchanged in this case.
+    ///
+    /// ## Panics
+    /// If the internal mutex is poisoned.
+    pub async fn reload_if_changed(&self) -> Result<bool, BoxError> {
+        let modified = self.modified_times();
+        if *self.modified.lock().unwrap() == modified {
+            return Ok(false);
+        }
+
+        self.reload().await?;
+        *self.modified.lock().unwrap() = modified;
+
+        Ok(true)
+    }
+
+    /// Unconditionally reloads all certificates.
+    ///
+    /// ## Errors
+    /// If certificates, private key or network configuration are not valid. Certificates that
+    /// are currently in use remain unchanged in this case.
+    ///
+    /// ## Panics
+    /// If the internal lock is poisoned.
+    pub async fn reload(&self) -> Result<(), BoxError> {
+        let network = match &self.network_file {
+            Some((path, parse)) => parse(&fs::read_to_string(path).await?)?,
+            None => self.current().network.clone(),
+        };
+        let material = Material::load(&self.tls, network).await?;
+        *self.current.write().unwrap() = Arc::new(material);
+
+        Ok(())
+    }
+
+    /// Checks watched files every `interval` and reloads certificates when they change. The
+    /// task stops once this reloader is dropped.
+    pub fn watch(self: &Arc<Self>, runtime: &IpaRuntime, interval: Duration) -> IpaJoinHandle<()> {
+        let this = Arc::downgrade(self);
+        runtime.spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                let Some(this) = Weak::upgrade(&this) else {
+                    break;
+                };
+                match this.reload_if_changed().await {
+                    Ok(true) => tracing::info!("TLS certificates reloaded"),
+                    Ok(false) => {}
+                    Err(e) => {
+                        tracing::error!("failed to reload TLS certificates, keeping current: {e}");
+                    }
+                }
+            }
+        })
+    }
+
+    /// Returns the identity of the peer that presented `cert`. See
+    /// [`NetworkConfig::identify_cert`].
+    #[must_use]
+    pub fn identify_cert(&self, cert: Option<&CertificateDer>) -> Option<F::Identity> {
+        self.current().network.identify_cert(cert)
+    }
+
+    /// Configuration for servers that authenticate with the current certificate of this helper
+    /// and accept current and previous certificates of peers.
+    pub(super) fn server_config(self: &Arc<Self>) -> rustls::ServerConfig {
+        rustls::ServerConfig::builder_with_provider(Arc::clone(&CRYPTO_PROVIDER))
+            .with_safe_default_protocol_versions()
+            .expect("Default crypto provider should be valid")
+            .with_client_cert_verifier(Arc::new(ReloadingClientVerifier(Arc::clone(self))))
+            .with_cert_resolver(Arc::clone(self) as Arc<dyn ResolvesServerCert>)
+    }
+
+    /// Configuration for clients that connect to `peer`. See [`server_config`] for details.
+    ///
+    /// [`server_config`]: Self::server_config
+    ///
+    /// ## Panics
+    /// If `peer` is not part of the network this reloader was created with.
+    pub(super) fn client_config(self: &Arc<Self>, peer: &PeerConfig) -> rustls::ClientConfig {
+        let index = self
+            .current()
+            .network
+            .peers
+            .iter()
+            .position(|p| p.url.authority() == peer.url.authority())
+            .unwrap_or_else(|| panic!("{} is not a known peer", peer.url));
+        rustls::ClientConfig::builder_with_provider(Arc::clone(&CRYPTO_PROVIDER))
+            .with_safe_default_protocol_versions()
+            .expect("Default crypto provider should be valid")
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(PeerServerVerifier {
+                reloader: Arc::clone(self),
+                index,
+            }))
+            .with_client_cert_resolver(Arc::clone(self) as Arc<dyn ResolvesClientCert>)
+    }
+
+    fn current(&self) -> Arc<Material<F>> {
+        Arc::clone(&self.current.read().unwrap())
+    }
+
+    fn modified_times(&self) -> Vec<Option<SystemTime>> {
+        let tls_files = match &self.tls {
+            TlsConfig::File {
+                certificate_file,
+                private_key_file,
+            } => vec![certificate_file, private_key_file],
+            TlsConfig::Inline { .. } => Vec::new(),
+        };
+        tls_files
+            .into_iter()
+            .chain(self.network_file.iter().map(|(path, _)| path))
+            .map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+            .collect()
+    }
+}
+
+impl<F: ConnectionFlavor> Debug for CertificateReloader<F> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CertificateReloader")
+            .field("tls", &self.tls)
+            .field("network_file", &self.network_file.as_ref().map(|(p, _)| p))
+            .finish_non_exhaustive()
+    }
+}
+
+impl<F: ConnectionFlavor> ResolvesServerCert for CertificateReloader<F> {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(Arc::clone(&self.current().key))
+    }
+}
+
+impl<F: ConnectionFlavor> ResolvesClientCert for CertificateReloader<F> {
+    fn resolve(
+        &self,
+        _root_hint_subjects: &[&[u8]],
+        _sigschemes: &[SignatureScheme],
+    ) -> Option<Arc<CertifiedKey>> {
+        Some(Arc::clone(&self.current().key))
+    }
+
+    fn has_certs(&self) -> bool {
+        true
+    }
+}
+
+/// Verifies client certificates against the peer certificates currently known to the reloader.
+#[derive(Debug)]
+struct ReloadingClientVerifier<F: ConnectionFlavor>(Arc<CertificateReloader<F>>);
+
+impl<F: ConnectionFlavor> ClientCertVerifier for ReloadingClientVerifier<F> {
+    fn offer_client_auth(&self) -> bool {
+        self.0.current().client_verifier.offer_client_auth()
+    }
+
+    fn client_auth_mandatory(&self) -> bool {
+        self.0.current().client_verifier.client_auth_mandatory()
+    }
+
+    fn root_hint_subjects(&self) -> &[DistinguishedName] {
+        // Hints can't be borrowed from material that may be swapped at any time. Without
+        // them, clients send the certificate they have, which is what helpers do anyway.
+        &[]
+    }
+
+    fn verify_client_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        now: UnixTime,
+    ) -> Result<ClientCertVerified, rustls::Error> {
+        self.0
+            .current()
+            .client_verifier
+            .verify_client_cert(end_entity, intermediates, now)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.0
+            .current()
+            .client_verifier
+            .verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.0
+            .current()
+            .client_verifier
+            .verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.current().client_verifier.supported_verify_schemes()
+    }
+}
+
+/// Verifies the server certificate of a single peer against its current and previous
+/// certificates known to the reloader.
+#[derive(Debug)]
+struct PeerServerVerifier<F: ConnectionFlavor> {
+    reloader: Arc<CertificateReloader<F>>,
+    index: usize,
+}
+
+impl<F: ConnectionFlavor> ServerCertVerifier for PeerServerVerifier<F> {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let material = self.reloader.current();
+        let mut roots = RootCertStore::empty();
+        if let Some(peer) = material.network.peers.get(self.index) {
+            for cert in peer.certificates() {
+                roots.add(cert.clone())?;
+            }
+        }
+        let verifier = WebPkiServerVerifier::builder_with_provider(
+            Arc::new(roots),
+            Arc::clone(&CRYPTO_PROVIDER),
+        )
+        .build()
+        .map_err(|e| rustls::Error::General(e.to_string()))?;
+
+        verifier.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &CRYPTO_PROVIDER.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &CRYPTO_PROVIDER.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        CRYPTO_PROVIDER
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use std::{fs, path::Path};
+
+    use rustls::client::ResolvesClientCert;
+    use tempfile::TempDir;
+
+    use super::CertificateReloader;
+    use crate::{
+        config::{ClientConfig, NetworkConfig, OwnedCertificate, PeerConfig, TlsConfig},
+        helpers::{HelperIdentity, TransportIdentity},
+        net::{
+            test::{get_test_certificate_and_key, TEST_CERTS_DER},
+            Helper,
+        },
+        sharding::{ShardIndex, ShardedHelperIdentity},
+    };
+
+    /// Test certificates are issued per helper and shard. Shard 1 certificates play the role
+    /// of rotated ones here.
+    fn id(helper: HelperIdentity, rotated: bool) -> ShardedHelperIdentity {
+        ShardedHelperIdentity::new(helper, ShardIndex::from(u32::from(rotated)))
+    }
+
+    fn cert(helper: HelperIdentity, rotated: bool) -> OwnedCertificate {
+        TEST_CERTS_DER[id(helper, rotated)].clone()
+    }
+
+    fn write_identity(dir: &Path, helper: HelperIdentity, rotated: bool) -> TlsConfig {
+        let (cert, key) = get_test_certificate_and_key(id(helper, rotated));
+        let certificate_file = dir.join("cert.pem");
+        let private_key_file = dir.join("key.pem");
+        fs::write(&certificate_file, cert).unwrap();
+        fs::write(&private_key_file, key).unwrap();
+
+        TlsConfig::File {
+            certificate_file,
+            private_key_file,
+        }
+    }
+
+    fn network(rotated_two: bool) -> NetworkConfig<Helper> {
+        let peers = HelperIdentity::make_three().map(|helper| {
+            let mut peer = PeerConfig::new(
+                format!("https://localhost:{}", 3000 + helper.as_index())
+                    .parse()
+                    .unwrap(),
+                Some(cert(helper, false)),
+            );
+            if rotated_two && helper == HelperIdentity::TWO {
+                peer.previous_certificate = peer.certificate.take();
+                peer.certificate = Some(cert(helper, true));
+            }
+            peer
+        });
+        NetworkConfig::new_mpc(peers.to_vec(), ClientConfig::default())
+    }
+
+    fn own_cert(reloader: &CertificateReloader<Helper>) -> OwnedCertificate {
+        ResolvesClientCert::resolve(reloader, &[], &[])
+            .unwrap()
+            .cert[0]
+            .clone()
+    }
+
+    #[tokio::test]
+    async fn reloads_own_certificate() {
+        let dir = TempDir::new().unwrap();
+        let tls = write_identity(dir.path(), HelperIdentity::ONE, false);
+        let reloader = CertificateReloader::new(tls, network(false)).await.unwrap();
+        assert!(!reloader.reload_if_changed().await.unwrap());
+        assert_eq!(cert(HelperIdentity::ONE, false), own_cert(&reloader));
+
+        write_identity(dir.path(), HelperIdentity::ONE, true);
+        reloader.reload().await.unwrap();
+        assert_eq!(cert(HelperIdentity::ONE, true), own_cert(&reloader));
+    }
+
+    #[tokio::test]
+    async fn keeps_certificate_if_reload_fails() {
+        let dir = TempDir::new().unwrap();
+        let tls = write_identity(dir.path(), HelperIdentity::ONE, false);
+        let reloader = CertificateReloader::new(tls, network(false)).await.unwrap();
+
+        fs::write(dir.path().join("key.pem"), b"garbage").unwrap();
+        reloader.reload().await.unwrap_err();
+        assert_eq!(cert(HelperIdentity::ONE, false), own_cert(&reloader));
+    }
+
+    #[tokio::test]
+    async fn reloads_peer_certificates() {
+        let dir = TempDir::new().unwrap();
+        let tls = write_identity(dir.path(), HelperIdentity::ONE, false);
+        let network_file = dir.path().join("network.toml");
+        fs::write(&network_file, "rotated").unwrap();
+        let reloader = CertificateReloader::new(tls, network(false))
+            .await
+            .unwrap()
+            .with_network_file(network_file, |content| {
+                assert_eq!("rotated", content);
+                Ok(network(true))
+            });
+        let identify = |rotated| reloader.identify_cert(Some(&cert(HelperIdentity::TWO, rotated)));
+        assert_eq!(None, identify(true));
+
+        reloader.reload().await.unwrap();
+        // both old and new certificates are accepted during rotation
+        assert_eq!(Some(HelperIdentity::TWO), identify(true));
+        assert_eq!(Some(HelperIdentity::TWO), identify(false));
+    }
+}
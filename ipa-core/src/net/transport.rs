@@ -79,7 +79,7 @@ impl<F: ConnectionFlavor> HttpTransport<F> {
         dest: F::Identity,
         route: R,
         data: D,
-    ) -> Result<(), Error>
+    ) -> Result<HelperResponse, Error>
     where
         Option<QueryId>: From<Q>,
         Option<Gate>: From<S>,
@@ -100,25 +100,34 @@ impl<F: ConnectionFlavor> HttpTransport<F> {
                 self.http_runtime
                     .spawn(resp_future.map_err(Into::into).and_then(resp_ok))
                     .await?;
-                Ok(())
+                Ok(HelperResponse::ok())
             }
             RouteId::PrepareQuery => {
                 let req = serde_json::from_str(route.extra().borrow()).unwrap();
-                self.clients[client_ix].prepare_query(req).await
+                self.clients[client_ix].prepare_query(req).await?;
+                Ok(HelperResponse::ok())
             }
             RouteId::CompleteQuery => {
                 let query_id = <Option<QueryId>>::from(route.query_id())
                     .expect("query_id is required to call complete query API");
-                self.clients[client_ix].complete_query(query_id).await
+                self.clients[client_ix].complete_query(query_id).await?;
+                Ok(HelperResponse::ok())
             }
             RouteId::QueryStatus => {
                 let req = serde_json::from_str(route.extra().borrow())?;
-                self.clients[client_ix].status_match(req).await
+                let resp = self.clients[client_ix].status_match(req).await?;
+                Ok(HelperResponse::from(resp))
+            }
+            RouteId::KillQuery => {
+                let req = serde_json::from_str(route.extra().borrow())?;
+                self.clients[client_ix].kill_query(req).await?;
+                Ok(HelperResponse::ok())
             }
             evt @ (RouteId::QueryInput
             | RouteId::ReceiveQuery
-            | RouteId::KillQuery
-            | RouteId::Metrics) => {
+            | RouteId::QueryWait
+            | RouteId::Metrics
+            | RouteId::QueryMetrics) => {
                 unimplemented!(
                     "attempting to send client-specific request {evt:?} to another helper"
                 )
@@ -300,7 +309,7 @@ impl Transport for MpcHttpTransport {
         dest: Self::Identity,
         route: R,
         data: D,
-    ) -> Result<(), Error>
+    ) -> Result<HelperResponse, Error>
     where
         Option<QueryId>: From<Q>,
         Option<Gate>: From<S>,
@@ -315,6 +324,10 @@ impl Transport for MpcHttpTransport {
     ) -> Self::RecordsStream {
         self.inner_transport.receive(from, &route)
     }
+
+    fn clear_streams(&self, query_id: QueryId) {
+        self.inner_transport.record_streams.clear_query(query_id);
+    }
 }
 
 impl ShardHttpTransport {
@@ -373,7 +386,7 @@ impl Transport for ShardHttpTransport {
         dest: Self::Identity,
         route: R,
         data: D,
-    ) -> Result<(), Self::Error>
+    ) -> Result<HelperResponse, Self::Error>
     where
         Option<QueryId>: From<Q>,
         Option<Gate>: From<S>,
@@ -398,11 +411,18 @@ impl Transport for ShardHttpTransport {
     ) -> Self::RecordsStream {
         self.inner_transport.receive(from, &route)
     }
+
+    fn clear_streams(&self, query_id: QueryId) {
+        self.inner_transport.record_streams.clear_query(query_id);
+    }
 }
 
 #[cfg(all(test, web_test, descriptive_gate))]
 mod tests {
-    use std::{iter::repeat, task::Poll};
+    use std::{
+        iter::{repeat, zip},
+        task::Poll,
+    };
 
     use bytes::Bytes;
     use futures::stream::{poll_immediate, StreamExt};
@@ -419,7 +439,7 @@ mod tests {
         helpers::{
             make_owned_handler,
             query::{
-                QueryInput,
+                KillQuery, QueryInput,
                 QueryType::{TestMultiply, TestShardedShuffle},
             },
         },
@@ -427,6 +447,7 @@ mod tests {
             client::ClientIdentity,
             test::{TestConfig, TestConfigBuilder, TestServer},
         },
+        query::QueryStatus,
         secret_sharing::{replicated::semi_honest::AdditiveShare, IntoShares},
         test_fixture::Reconstruct,
         HelperApp,
@@ -698,6 +719,92 @@ mod tests {
         test_sharded_shuffle(&clients).await;
     }
 
+    /// Killing a query through one helper kills it on every helper and shard, so the next
+    /// query can run.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn kill_propagates_to_all_helpers_and_shards() {
+        let conf = TestConfigBuilder::default()
+            .with_shard_count(2)
+            .with_disable_https_option(true)
+            .build();
+        let (clients, _helpers) = make_clients_and_helpers(conf).await;
+
+        let query_id = clients[0][0]
+            .create_query(QueryConfig::new(TestShardedShuffle, FieldType::Fp31, 10).unwrap())
+            .await
+            .unwrap();
+        clients[0][0]
+            .kill_query(KillQuery {
+                query_id,
+                forwarded: false,
+            })
+            .await
+            .unwrap();
+
+        // Leader shards report the status aggregated across all shards of their helper.
+        for client in &clients[0] {
+            assert_eq!(
+                QueryStatus::Killed,
+                client.query_status(query_id).await.unwrap()
+            );
+        }
+
+        test_sharded_shuffle(&clients).await;
+    }
+
+    /// Helpers that received their inputs wait for records from the helper that did not.
+    /// Killing the query through that helper must release them, so the next query can use
+    /// the same streams.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn kill_releases_peers_blocked_in_receive() {
+        let conf = TestConfigBuilder::default()
+            .with_disable_https_option(true)
+            .build();
+        let (clients, _helpers) = make_clients_and_helpers(conf).await;
+        let [leader, peers @ ..] = &clients[0];
+
+        let query_id = leader
+            .create_query(QueryConfig::new(TestMultiply, FieldType::Fp31, 1).unwrap())
+            .await
+            .unwrap();
+        let one = Fp31::try_from(1u128).unwrap();
+        let [_, h2_input, h3_input] = (one, one)
+            .share()
+            .map(|shares| BodyStream::from_serializable_iter([shares.0, shares.1]));
+        for (client, input_stream) in zip(peers, [h2_input, h3_input]) {
+            client
+                .query_input(QueryInput::Inline {
+                    query_id,
+                    input_stream,
+                })
+                .await
+                .unwrap();
+        }
+        // Peers block on receiving from the leader, which never starts the query.
+        for client in peers {
+            assert_eq!(
+                QueryStatus::Running,
+                client.query_status(query_id).await.unwrap()
+            );
+        }
+
+        leader
+            .kill_query(KillQuery {
+                query_id,
+                forwarded: false,
+            })
+            .await
+            .unwrap();
+        for client in peers {
+            assert_eq!(
+                QueryStatus::Killed,
+                client.query_status(query_id).await.unwrap()
+            );
+        }
+
+        test_multiply_single_shard(&clients).await;
+    }
+
     #[tokio::test]
     async fn peer_count() {
         fn new_transport<F: ConnectionFlavor>(identity: F::Identity) -> Arc<HttpTransport<F>> {
@@ -249,7 +249,7 @@ where
     C: Context + 'fut,
     V: SharedValue + Vectorizable<N>,
 {
-    use futures::future::try_join;
+    use futures::future::{join, try_join};
 
     let left = share.left_arr();
     let right = share.right_arr();
@@ -277,16 +277,23 @@ where
     if Some(ctx.role()) == excluded {
         Ok(None)
     } else {
-        let (share_from_left, share_from_right) = try_join(
+        // A share that cannot be deserialized is as much of an attack as two shares that
+        // differ, so it is reported the same way.
+        match join(
             left_receiver.receive(record_id),
             right_receiver.receive(record_id),
         )
-        .await?;
-
-        if share_from_left == share_from_right {
-            Ok(Some(share_from_left + left + right))
-        } else {
-            Err(Error::MaliciousRevealFailed)
+        .await
+        {
+            (Ok(share_from_left), Ok(share_from_right)) if share_from_left == share_from_right => {
+                Ok(Some(share_from_left + left + right))
+            }
+            (Ok(_), Ok(_))
+            | (Err(crate::helpers::Error::DeserializeFailed { .. }), _)
+            | (_, Err(crate::helpers::Error::DeserializeFailed { .. })) => {
+                Err(Error::MaliciousRevealFailed)
+            }
+            (Err(e), _) | (_, Err(e)) => Err(e.into()),
         }
     }
 }
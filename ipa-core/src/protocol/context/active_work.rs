@@ -0,0 +1,157 @@
+use futures_util::future::try_join4;
+use ipa_step::{Step, StepNarrow};
+
+use crate::{
+    error::Error,
+    ff::{boolean_array::BA8, U128Conversions},
+    helpers::{Direction, TotalRecords, MIN_ACTIVE_WORK},
+    protocol::{context::Context, Gate, RecordId},
+    utils::NonZeroU32PowerOfTwo,
+};
+
+/// Contexts that can change the amount of active work used by the steps that follow.
+pub trait AdaptiveActiveWork: Context + Sized {
+    /// Amount of active work this helper would like to use for the next steps or `None`
+    /// if adaptive active work is disabled. See [`Gateway::propose_active_work`] for details.
+    ///
+    /// [`Gateway::propose_active_work`]: crate::helpers::Gateway::propose_active_work
+    fn propose_active_work(&self) -> Option<NonZeroU32PowerOfTwo>;
+
+    #[must_use]
+    fn with_active_work(self, active_work: NonZeroU32PowerOfTwo) -> Self;
+}
+
+/// Agrees with other helpers on the amount of active work and returns a context that uses
+/// it. Every helper sends its proposal to both peers and then picks the smallest value
+/// among the three, so all helpers end up with the same value.
+///
+/// Proposals are based on the backpressure measured by the gateway since the query
+/// started. If adaptive active work is disabled, the context is returned as is, without
+/// talking to other helpers. For this reason, all helpers must be configured the same way.
+///
+/// ## Errors
+/// If communication with other helpers fails or they propose a value that is not a valid
+/// amount of active work.
+///
+/// ## Panics
+/// Does not panic: the agreed value is always a power of two that fits into `u32`.
+pub async fn adapt_active_work<C, S>(ctx: C, step: &S) -> Result<C, Error>
+where
+    C: AdaptiveActiveWork,
+    S: Step + ?Sized,
+    Gate: StepNarrow<S>,
+{
+    let Some(proposal) = ctx.propose_active_work() else {
+        return Ok(ctx);
+    };
+    let mine = proposal.get().ilog2();
+
+    let exchange_ctx = ctx.narrow(step).set_total_records(TotalRecords::ONE);
+    let left = exchange_ctx.role().peer(Direction::Left);
+    let right = exchange_ctx.role().peer(Direction::Right);
+    let msg = BA8::truncate_from(mine);
+    let ((), (), from_left, from_right) = try_join4(
+        exchange_ctx.send_channel(left).send(RecordId::FIRST, msg),
+        exchange_ctx.send_channel(right).send(RecordId::FIRST, msg),
+        exchange_ctx
+            .recv_channel::<BA8>(left)
+            .receive(RecordId::FIRST),
+        exchange_ctx
+            .recv_channel::<BA8>(right)
+            .receive(RecordId::FIRST),
+    )
+    .await?;
+
+    let min = MIN_ACTIVE_WORK.ilog2();
+    let mut agreed = mine.max(min);
+    for (peer, theirs) in [(left, from_left), (right, from_right)] {
+        let theirs = u32::try_from(theirs.as_u128()).unwrap();
+        if !(min..u32::BITS).contains(&theirs) {
+            return Err(Error::InvalidQueryParameter(
+                format!("{peer:?} proposed active work 2^{theirs}, expected at least {MIN_ACTIVE_WORK} and less than 2^{}", u32::BITS).into(),
+            ));
+        }
+        agreed = agreed.min(theirs);
+    }
+    let agreed = NonZeroU32PowerOfTwo::try_from(1_usize << agreed).unwrap();
+    tracing::info!("helpers agreed to use active work {agreed}, proposed {proposal}");
+
+    Ok(ctx.with_active_work(agreed))
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use crate::{
+        ff::{Field, Fp31},
+        helpers::GatewayConfig,
+        protocol::{
+            basics::SecureMul,
+            context::{active_work::adapt_active_work, Context},
+            step::ProtocolStep,
+            RecordId,
+        },
+        seq_join::SeqJoin,
+        telemetry::metrics::RECORDS_SENT,
+        test_executor::run,
+        test_fixture::{Runner, TestWorld, TestWorldConfig},
+    };
+
+    #[test]
+    fn keeps_active_work_if_not_adaptive() {
+        run(|| async {
+            let world = TestWorld::new_with(
+                TestWorldConfig {
+                    gateway_config: GatewayConfig {
+                        active: 16.try_into().unwrap(),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                }
+                .enable_metrics(),
+            );
+            let result = world
+                .semi_honest((Fp31::ONE, Fp31::ONE), |ctx, (a, b)| async move {
+                    let ctx = adapt_active_work(ctx.clone(), &ProtocolStep::Test)
+                        .await
+                        .unwrap();
+                    a.multiply(&b, ctx.set_total_records(1), RecordId::FIRST)
+                        .await
+                        .unwrap();
+                    ctx.active_work().get()
+                })
+                .await;
+
+            assert_eq!([16, 16, 16], result);
+            // helpers don't need to talk to each other to keep using the same value,
+            // so only the multiplication sends data.
+            world
+                .metrics_snapshot()
+                .assert_metric(RECORDS_SENT)
+                .total(3);
+        });
+    }
+
+    #[test]
+    fn agrees_on_active_work() {
+        run(|| async {
+            let world = TestWorld::new_with(TestWorldConfig {
+                gateway_config: GatewayConfig {
+                    adaptive_active_work: true,
+                    ..Default::default()
+                },
+                ..Default::default()
+            });
+            let result = world
+                .malicious((), |ctx, ()| async move {
+                    let ctx = adapt_active_work(ctx.clone(), &ProtocolStep::Test)
+                        .await
+                        .unwrap();
+                    ctx.active_work().get()
+                })
+                .await;
+
+            assert_eq!(result[0], result[1]);
+            assert_eq!(result[1], result[2]);
+        });
+    }
+}
@@ -3,6 +3,7 @@ use std::{collections::BTreeMap, fmt::Debug, future::ready};
 use async_trait::async_trait;
 use bitvec::prelude::{BitArray, BitSlice, Lsb0};
 use futures::{stream, Future, FutureExt, Stream, StreamExt};
+use ipa_metrics::counter;
 use ipa_step::StepNarrow;
 
 use crate::{
@@ -28,6 +29,7 @@ use crate::{
     seq_join::{seq_join, SeqJoin},
     sharding::ShardBinding,
     sync::{Arc, Mutex},
+    telemetry::{labels::STEP, metrics::DZKP_PROOFS_VERIFIED, trace::TRACE_TARGET},
 };
 
 pub type Array256Bit = BitArray<[u8; 32], Lsb0>;
@@ -581,6 +583,7 @@ impl Batch {
 
     /// ## Panics
     /// If `usize` to `u128` conversion fails.
+    #[tracing::instrument(target = TRACE_TARGET, name = "validate", skip_all, fields(gate = %ctx.gate().as_ref(), batch = batch_index))]
     pub(super) async fn validate<B: ShardBinding>(
         self,
         ctx: Base<'_, B>,
@@ -669,7 +672,10 @@ impl Batch {
                 &challenges_for_left_prover,
                 &challenges_for_right_prover,
             )
-            .await
+            .await?;
+        counter!(DZKP_PROOFS_VERIFIED, 1, STEP => ctx.gate());
+
+        Ok(())
     }
 }
 
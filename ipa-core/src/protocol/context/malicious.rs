@@ -22,8 +22,9 @@ use crate::{
             step::UpgradeStep,
             upgrade::Upgradable,
             validator::{self, BatchValidator},
-            Base, Context as ContextTrait, InstrumentedSequentialSharedRandomness, ShardedContext,
-            SpecialAccessToUpgradedContext, UpgradableContext, UpgradedContext,
+            AdaptiveActiveWork, Base, Context as ContextTrait,
+            InstrumentedSequentialSharedRandomness, ShardedContext, SpecialAccessToUpgradedContext,
+            UpgradableContext, UpgradedContext,
         },
         prss::{Endpoint as PrssEndpoint, FromPrss},
         Gate, RecordId,
@@ -191,6 +192,18 @@ impl<B: ShardBinding> SeqJoin for Context<'_, B> {
     }
 }
 
+impl<B: ShardBinding> AdaptiveActiveWork for Context<'_, B> {
+    fn propose_active_work(&self) -> Option<NonZeroU32PowerOfTwo> {
+        self.inner.propose_active_work()
+    }
+
+    fn with_active_work(self, active_work: NonZeroU32PowerOfTwo) -> Self {
+        Self {
+            inner: self.inner.with_active_work(active_work),
+        }
+    }
+}
+
 impl<B: ShardBinding> Debug for Context<'_, B> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "MaliciousContext")
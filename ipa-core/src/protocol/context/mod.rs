@@ -1,3 +1,4 @@
+pub mod active_work;
 pub mod dzkp_field;
 pub mod dzkp_malicious;
 pub mod dzkp_semi_honest;
@@ -13,6 +14,7 @@ pub mod validator;
 
 use std::{collections::HashMap, num::NonZeroUsize, pin::pin};
 
+pub use active_work::{adapt_active_work, AdaptiveActiveWork};
 use async_trait::async_trait;
 pub use dzkp_malicious::DZKPUpgraded as DZKPUpgradedMaliciousContext;
 pub use dzkp_semi_honest::DZKPUpgraded as DZKPUpgradedSemiHonestContext;
@@ -352,6 +354,16 @@ impl<B: ShardBinding> SeqJoin for Base<'_, B> {
     }
 }
 
+impl<B: ShardBinding> AdaptiveActiveWork for Base<'_, B> {
+    fn propose_active_work(&self) -> Option<NonZeroU32PowerOfTwo> {
+        self.inner.gateway.propose_active_work(self.active_work)
+    }
+
+    fn with_active_work(self, active_work: NonZeroU32PowerOfTwo) -> Self {
+        self.set_active_work(active_work)
+    }
+}
+
 #[derive(Clone)]
 struct Inner<'a> {
     pub prss: &'a PrssEndpoint,
@@ -412,6 +424,67 @@ where
     let (_, Some(input_len)) = input.size_hint() else {
         panic!("input stream must have size upper bound for resharding to work")
     };
+    let shard_records_est = {
+        let v = input_len / usize::from(ctx.shard_count());
+        // this gives us ~ 1.25 capacity, very close to 1.26 overhead estimated
+        // If 25% extra capacity becomes a problem and number of events/shards is not close
+        // to the worst case, this can be tuned down to 1.01
+        v + v / 4
+    };
+
+    // This contains the deterministic order of events after resharding is complete.
+    // Each shard will hold the records in this order:
+    // [shard_0_records], [shard_1_records], ..., [shard_N].
+    // There is no reason why this strategy was chosen. As long as it is consistent across helpers,
+    // other ways to build the total order work too. For example, we could put records with
+    // record_id = 0 first, then records with record_id = 1, etc.
+    let mut r: Vec<Vec<_>> = ctx
+        .shard_count()
+        .iter()
+        .map(|_| Vec::with_capacity(shard_records_est))
+        .collect();
+
+    reshard_try_stream_for_each(ctx, input, shard_picker, |shard_id, m| {
+        r[usize::from(shard_id)].push(m);
+        Ok(())
+    })
+    .await?;
+
+    Ok(r.into_iter().flatten().collect())
+}
+
+/// Same as [`reshard_try_stream`], except that the records that end up on this shard are not
+/// collected into a vector. Instead, `f` is called for every one of them as soon as it arrives,
+/// together with the shard it came from.
+///
+/// Records from the same shard are passed to `f` in the order that shard sent them, but records
+/// from different shards are interleaved in the order they arrive, which is not the same on
+/// all helpers. Callers that need a consistent order must order the records by the source
+/// shard, as [`reshard_try_stream`] does.
+///
+/// ## Panics
+/// When `shard_picker` returns an out-of-bounds index or if the input stream size
+/// upper bound is not known. The latter may be the case for infinite streams.
+///
+/// ## Errors
+/// If cross-shard communication fails, if an input stream yields an `Err` element or
+/// if `f` fails.
+pub async fn reshard_try_stream_for_each<L, K, C, S, F>(
+    ctx: C,
+    input: L,
+    shard_picker: S,
+    mut f: F,
+) -> Result<(), crate::error::Error>
+where
+    L: Stream<Item = Result<K, crate::error::Error>>,
+    S: Fn(C, RecordId, &K) -> ShardIndex,
+    K: Message + Clone,
+    C: ShardedContext,
+    F: FnMut(ShardIndex, K) -> Result<(), crate::error::Error>,
+{
+    let (_, Some(input_len)) = input.size_hint() else {
+        panic!("input stream must have size upper bound for resharding to work")
+    };
 
     // We set channels capacity to be at least 1 to be able to open send channels to all peers.
     // It is prohibited to create them if total records is not set. We also over-provision here
@@ -497,25 +570,6 @@ where
         },
     )
     .fuse();
-    let shard_records_est = {
-        let v = input_len / usize::from(ctx.shard_count());
-        // this gives us ~ 1.25 capacity, very close to 1.26 overhead estimated
-        // If 25% extra capacity becomes a problem and number of events/shards is not close
-        // to the worst case, this can be tuned down to 1.01
-        v + v / 4
-    };
-
-    // This contains the deterministic order of events after resharding is complete.
-    // Each shard will hold the records in this order:
-    // [shard_0_records], [shard_1_records], ..., [shard_N].
-    // There is no reason why this strategy was chosen. As long as it is consistent across helpers,
-    // other ways to build the total order work too. For example, we could put records with
-    // record_id = 0 first, then records with record_id = 1, etc.
-    let mut r: Vec<Vec<_>> = ctx
-        .shard_count()
-        .iter()
-        .map(|_| Vec::with_capacity(shard_records_est))
-        .collect();
 
     // Interleave send and receive streams to ensure the backpressure does not block the flow.
     // For example, if this shard just sends all the data and then receives, the flow control from
@@ -526,11 +580,11 @@ where
 
     while let Some((shard_id, v)) = send_recv.try_next().await? {
         if let Some(m) = v {
-            r[usize::from(shard_id)].push(m);
+            f(shard_id, m)?;
         }
     }
 
-    Ok(r.into_iter().flatten().collect())
+    Ok(())
 }
 
 /// Provides the same functionality as [`reshard_try_stream`] on
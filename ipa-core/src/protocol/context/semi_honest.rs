@@ -17,10 +17,10 @@ use crate::{
     protocol::{
         context::{
             dzkp_validator::SemiHonestDZKPValidator, step::MaliciousProtocolStep,
-            upgrade::Upgradable, validator::SemiHonest as Validator, Base, Context as _,
-            InstrumentedIndexedSharedRandomness, InstrumentedSequentialSharedRandomness,
-            MaliciousProtocolSteps, ShardedContext, SpecialAccessToUpgradedContext,
-            UpgradableContext, UpgradedContext,
+            upgrade::Upgradable, validator::SemiHonest as Validator, AdaptiveActiveWork, Base,
+            Context as _, InstrumentedIndexedSharedRandomness,
+            InstrumentedSequentialSharedRandomness, MaliciousProtocolSteps, ShardedContext,
+            SpecialAccessToUpgradedContext, UpgradableContext, UpgradedContext,
         },
         prss::Endpoint as PrssEndpoint,
         Gate, RecordId,
@@ -31,6 +31,7 @@ use crate::{
     },
     seq_join::SeqJoin,
     sharding::{NotSharded, ShardBinding, ShardConfiguration, ShardIndex, Sharded},
+    utils::NonZeroU32PowerOfTwo,
 };
 
 #[derive(Clone)]
@@ -182,6 +183,18 @@ impl<B: ShardBinding> SeqJoin for Context<'_, B> {
     }
 }
 
+impl<B: ShardBinding> AdaptiveActiveWork for Context<'_, B> {
+    fn propose_active_work(&self) -> Option<NonZeroU32PowerOfTwo> {
+        self.inner.propose_active_work()
+    }
+
+    fn with_active_work(self, active_work: NonZeroU32PowerOfTwo) -> Self {
+        Self {
+            inner: self.inner.with_active_work(active_work),
+        }
+    }
+}
+
 impl<B: ShardBinding> Debug for Context<'_, B> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("SemiHonestContext")
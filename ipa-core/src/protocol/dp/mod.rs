@@ -4,6 +4,7 @@ pub mod step;
 use std::{convert::Infallible, f64};
 
 use futures_util::{stream, StreamExt};
+use ipa_step::{Step, StepNarrow};
 use rand_core::{CryptoRng, RngCore};
 
 use crate::{
@@ -27,7 +28,7 @@ use crate::{
             step::IpaPrfStep,
         },
         prss::{FromPrss, SharedRandomness},
-        BooleanProtocols, RecordId,
+        BooleanProtocols, Gate, RecordId,
     },
     secret_sharing::{
         replicated::{semi_honest::AdditiveShare as Replicated, ReplicatedSecretSharing},
@@ -225,7 +226,6 @@ where
 /// # Panics
 /// may panic from asserts down in  `gen_binomial_noise`
 ///
-#[allow(clippy::too_many_lines)]
 pub async fn dp_for_histogram<C, const B: usize, OV, const SS_BITS: usize>(
     ctx: C,
     histogram_bin_values: BitDecomposed<Replicated<Boolean, B>>,
@@ -242,10 +242,46 @@ where
     BitDecomposed<Replicated<Boolean, B>>:
         for<'a> TransposeFrom<&'a [Replicated<OV>; B], Error = Infallible>,
 {
-    let steps = MaliciousProtocolSteps {
-        protocol: &IpaPrfStep::DifferentialPrivacy,
-        validate: &IpaPrfStep::DifferentialPrivacyValidate,
-    };
+    dp_for_histogram_with_steps::<_, _, B, OV, SS_BITS>(
+        ctx,
+        MaliciousProtocolSteps {
+            protocol: &IpaPrfStep::DifferentialPrivacy,
+            validate: &IpaPrfStep::DifferentialPrivacyValidate,
+        },
+        histogram_bin_values,
+        dp_params,
+    )
+    .await
+}
+
+/// Same as [`dp_for_histogram`], but noise generation runs under the given `steps`
+/// instead of `IpaPrfStep::DifferentialPrivacy`. `steps.protocol` must have
+/// `DPStep` as its child step.
+///
+/// # Errors
+/// See [`dp_for_histogram`].
+/// # Panics
+/// See [`dp_for_histogram`].
+#[allow(clippy::too_many_lines)]
+pub async fn dp_for_histogram_with_steps<C, S, const B: usize, OV, const SS_BITS: usize>(
+    ctx: C,
+    steps: MaliciousProtocolSteps<'_, S>,
+    histogram_bin_values: BitDecomposed<Replicated<Boolean, B>>,
+    dp_params: DpMechanism,
+) -> Result<Vec<Replicated<OV>>, Error>
+where
+    C: UpgradableContext,
+    S: Step + ?Sized,
+    Gate: StepNarrow<S>,
+    Boolean: Vectorizable<B> + FieldSimd<B>,
+    BitDecomposed<Replicated<Boolean, B>>: FromPrss<usize>,
+    OV: BooleanArray + U128Conversions,
+    Replicated<Boolean, B>: BooleanProtocols<DZKPUpgraded<C>, B>,
+    Vec<Replicated<OV>>:
+        for<'a> TransposeFrom<&'a BitDecomposed<Replicated<Boolean, B>>, Error = LengthError>,
+    BitDecomposed<Replicated<Boolean, B>>:
+        for<'a> TransposeFrom<&'a [Replicated<OV>; B], Error = Infallible>,
+{
     match dp_params {
         DpMechanism::NoDp => Ok(Vec::transposed_from(&histogram_bin_values)?),
         DpMechanism::Binomial { epsilon } => {
@@ -338,7 +374,9 @@ where
             let dp_validator = ctx.dzkp_validator(steps, 1);
 
             let noised_output = apply_laplace_noise_pass::<_, OV, B>(
-                &dp_validator.context().narrow(&DPStep::LaplacePass1),
+                &dp_validator
+                    .context()
+                    .narrow::<DPStep>(&DPStep::LaplacePass1),
                 histogram_bin_values,
                 Role::H1,
                 &noise_params,
@@ -346,7 +384,9 @@ where
             .await?;
 
             let noised_output = apply_laplace_noise_pass::<_, OV, B>(
-                &dp_validator.context().narrow(&DPStep::LaplacePass2),
+                &dp_validator
+                    .context()
+                    .narrow::<DPStep>(&DPStep::LaplacePass2),
                 noised_output,
                 Role::H2,
                 &noise_params,
@@ -354,7 +394,9 @@ where
             .await?;
 
             let noised_output = apply_laplace_noise_pass::<_, OV, B>(
-                &dp_validator.context().narrow(&DPStep::LaplacePass3),
+                &dp_validator
+                    .context()
+                    .narrow::<DPStep>(&DPStep::LaplacePass3),
                 noised_output,
                 Role::H3,
                 &noise_params,
@@ -594,6 +636,52 @@ pub fn binomial_noise_mean_std(noise_params: &NoiseParams) -> (f64, f64) {
     (mean, standard_deviation)
 }
 
+/// Standard deviation of the noise [`dp_for_histogram`] adds to every bin of a histogram with
+/// `dimensions` bins, when a user contributes at most `per_user_credit_cap` to it. That is
+/// `2^SS_BITS` for the `SS_BITS` the histogram was computed with.
+///
+/// # Errors
+/// If `epsilon` is out of range for the mechanism.
+pub fn histogram_noise_std(
+    dp_params: DpMechanism,
+    per_user_credit_cap: u32,
+    dimensions: u32,
+) -> Result<f64, Error> {
+    match dp_params {
+        DpMechanism::NoDp => Ok(0.0),
+        DpMechanism::Binomial { epsilon } => {
+            if epsilon <= 0.0 || epsilon > MAX_EPSILON {
+                return Err(EpsilonOutOfBounds);
+            }
+            let noise_params = NoiseParams {
+                epsilon,
+                per_user_credit_cap,
+                ell_1_sensitivity: f64::from(per_user_credit_cap),
+                ell_2_sensitivity: f64::from(per_user_credit_cap),
+                ell_infty_sensitivity: f64::from(per_user_credit_cap),
+                dimensions: f64::from(dimensions),
+                ..Default::default()
+            };
+            Ok(binomial_noise_mean_std(&noise_params).1)
+        }
+        DpMechanism::DiscreteLaplace { epsilon } => {
+            let noise_params = NoiseParams {
+                epsilon,
+                per_user_credit_cap,
+                ..Default::default()
+            };
+            let (_, std) = OPRFPaddingDp::new(
+                noise_params.epsilon,
+                noise_params.delta,
+                noise_params.per_user_credit_cap,
+            )?
+            .mean_and_std();
+            // Each of the three passes adds an independent sample.
+            Ok(3.0_f64.sqrt() * std)
+        }
+    }
+}
+
 #[cfg(all(test, unit_test))]
 mod test {
 
@@ -609,7 +697,7 @@ mod test {
         protocol::{
             dp::{
                 apply_dp_noise, delta_constraint, dp_for_histogram, epsilon_constraint, error,
-                find_smallest_num_bernoulli, gen_binomial_noise, NoiseParams,
+                find_smallest_num_bernoulli, gen_binomial_noise, histogram_noise_std, NoiseParams,
                 ShiftedTruncatedDiscreteLaplace,
             },
             ipa_prf::oprf_padding::insecure::OPRFPaddingDp,
@@ -783,6 +871,26 @@ mod test {
         let eps = epsilon_constraint(num_bernoulli, &noise_params);
         assert!(eps > 0.6375 && eps < 0.6376, "eps = {eps}");
     }
+    #[test]
+    fn test_histogram_noise_std() {
+        assert_eq!(0.0, histogram_noise_std(DpMechanism::NoDp, 8, 256).unwrap());
+
+        let (_, std) = OPRFPaddingDp::new(2.0, 1e-6, 8).unwrap().mean_and_std();
+        let noise_std =
+            histogram_noise_std(DpMechanism::DiscreteLaplace { epsilon: 2.0 }, 8, 256).unwrap();
+        assert!(
+            (noise_std - 3.0_f64.sqrt() * std).abs() < 1e-9,
+            "{noise_std}"
+        );
+        // less privacy budget means more noise
+        assert!(
+            histogram_noise_std(DpMechanism::DiscreteLaplace { epsilon: 1.0 }, 8, 256).unwrap()
+                > noise_std
+        );
+
+        assert!(histogram_noise_std(DpMechanism::Binomial { epsilon: 0.0 }, 8, 256).is_err());
+    }
+
     #[test]
     fn test_num_bernoulli_simple_aggregation_case() {
         // test with success_prob = 1/2
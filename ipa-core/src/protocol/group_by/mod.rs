@@ -0,0 +1,878 @@
+//! Generic GROUP BY / sum aggregation over secret-shared records.
+//!
+//! The protocols in this module take secret shares of `(group key, values)` tuples and produce
+//! one histogram per value column, where each bucket is a group key and holds the sum of all
+//! the values for that key. Group keys are assumed to be dense, in the range `0..B`.
+//!
+//! This is the aggregation half of the hybrid protocol, lifted out so that other private
+//! measurement queries can reuse it. To use it, implement [`GroupByRecord`] for the record type,
+//! or use [`GroupByInputRow`].
+pub(crate) mod step;
+
+use std::{convert::Infallible, iter::repeat_n, ops::Add, pin::pin};
+
+use futures::stream;
+use futures_util::{StreamExt, TryStreamExt};
+use generic_array::ArrayLength;
+use tracing::{info_span, Instrument};
+
+use crate::{
+    error::{Error, LengthError, UnwrapInfallible},
+    ff::{
+        boolean::Boolean,
+        boolean_array::{BooleanArray, BooleanArrayReader, BooleanArrayWriter, BA112},
+        Serializable, U128Conversions,
+    },
+    helpers::{query::DpMechanism, Direction, TotalRecords},
+    protocol::{
+        basics::{
+            reveal,
+            shard_fin::{FinalizerContext, Histogram},
+            Reveal,
+        },
+        context::{
+            dzkp_validator::DZKPValidator, prss::InstrumentedSequentialSharedRandomness, Context,
+            DZKPUpgraded, MaliciousProtocolSteps, ShardedContext, UpgradableContext,
+        },
+        dp::dp_for_histogram_with_steps,
+        group_by::step::{GroupByDpStep as DpStep, GroupByStep as Step, GroupSumStep},
+        hybrid::step::FinalizeSteps,
+        ipa_prf::{
+            aggregation::{aggregate_values, aggregate_values_proof_chunk, AGGREGATE_DEPTH},
+            oprf_padding::{
+                apply_dp_padding, insecure::OPRFPaddingDp, AggregationPadding, Paddable,
+                PaddingParameters,
+            },
+            shuffle::{MaliciousShuffleable, ShardedShuffle, Shuffleable},
+        },
+        prss::FromPrss,
+        BooleanProtocols, RecordId,
+    },
+    secret_sharing::{
+        replicated::{semi_honest::AdditiveShare as Replicated, ReplicatedSecretSharing},
+        BitDecomposed, FieldSimd, SharedValue, TransposeFrom, Vectorizable,
+    },
+    seq_join::seq_join,
+    sharding::ShardIndex,
+};
+
+/// A secret-shared record that can be grouped by a revealed key.
+///
+/// Records go through the shuffle before their group keys are revealed, and dummy records are
+/// added for every group key to hide the size of each group, so implementors must also be
+/// [`MaliciousShuffleable`] and [`Paddable`].
+pub trait GroupByRecord: MaliciousShuffleable + Paddable {
+    /// The group key. Revealed values must be less than the number of buckets.
+    type Key: BooleanArray + U128Conversions;
+    /// The type of each value that is summed.
+    type Value: BooleanArray + U128Conversions;
+
+    /// The number of values each record carries. It is limited by the number of steps
+    /// reserved for value columns, currently 8.
+    const VALUE_COUNT: usize;
+
+    fn group_key(&self) -> &Replicated<Self::Key>;
+
+    /// Consumes the record, returning exactly [`Self::VALUE_COUNT`] values.
+    fn into_values(self) -> impl Iterator<Item = Replicated<Self::Value>>;
+}
+
+/// A general purpose [`GroupByRecord`] holding a group key and `N` values.
+///
+/// The key and all the values are packed into a single `BA112` for the shuffle, so
+/// `K::BITS + N * V::BITS` must not exceed 112.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GroupByInputRow<K: SharedValue, V: SharedValue, const N: usize> {
+    pub group_key: Replicated<K>,
+    pub values: [Replicated<V>; N],
+}
+
+impl<K: SharedValue, V: SharedValue, const N: usize> Default for GroupByInputRow<K, V, N> {
+    fn default() -> Self {
+        Self {
+            group_key: Replicated::ZERO,
+            values: std::array::from_fn(|_| Replicated::ZERO),
+        }
+    }
+}
+
+impl<K, V, const N: usize> GroupByInputRow<K, V, N>
+where
+    K: BooleanArray,
+    V: BooleanArray,
+{
+    fn join_fields(group_key: &K, values: &[V; N]) -> <Self as Shuffleable>::Share {
+        let mut share = <Self as Shuffleable>::Share::ZERO;
+
+        let writer = BooleanArrayWriter::new(&mut share).write(group_key);
+        values.iter().fold(writer, BooleanArrayWriter::write);
+
+        share
+    }
+
+    fn split_fields(share: &<Self as Shuffleable>::Share) -> (K, [V; N]) {
+        let (group_key, bits) = BooleanArrayReader::new(share).read();
+        let mut bits = Some(bits);
+        let values = std::array::from_fn(|_| {
+            let (value, rest) = bits.take().unwrap().read();
+            bits = Some(rest);
+            value
+        });
+        (group_key, values)
+    }
+}
+
+impl<K, V, const N: usize> Shuffleable for GroupByInputRow<K, V, N>
+where
+    K: BooleanArray,
+    V: BooleanArray,
+{
+    type Share = BA112;
+
+    fn left(&self) -> Self::Share {
+        Self::join_fields(
+            &self.group_key.left(),
+            &self.values.each_ref().map(ReplicatedSecretSharing::left),
+        )
+    }
+
+    fn right(&self) -> Self::Share {
+        Self::join_fields(
+            &self.group_key.right(),
+            &self.values.each_ref().map(ReplicatedSecretSharing::right),
+        )
+    }
+
+    fn new(l: Self::Share, r: Self::Share) -> Self {
+        debug_assert!(
+            K::BITS + u32::try_from(N).unwrap() * V::BITS <= Self::Share::BITS,
+            "share type {} is too small",
+            std::any::type_name::<Self::Share>(),
+        );
+
+        let (key_l, values_l) = Self::split_fields(&l);
+        let (key_r, values_r) = Self::split_fields(&r);
+        let mut values_r = values_r.into_iter();
+
+        Self {
+            group_key: ReplicatedSecretSharing::new(key_l, key_r),
+            values: values_l
+                .map(|value_l| ReplicatedSecretSharing::new(value_l, values_r.next().unwrap())),
+        }
+    }
+}
+
+impl<K, V, const N: usize> Paddable for GroupByInputRow<K, V, N>
+where
+    K: BooleanArray + U128Conversions,
+    V: BooleanArray,
+{
+    /// Adds a random number of dummy rows for every group key. Dummy rows have a
+    /// secret sharing of zero for all the values.
+    fn add_padding_items<VC: Extend<Self>, const B: usize>(
+        direction_to_excluded_helper: Direction,
+        padding_input_rows: &mut VC,
+        padding_params: &PaddingParameters,
+        rng: &mut InstrumentedSequentialSharedRandomness,
+    ) -> Result<u32, Error> {
+        let mut total_number_of_fake_rows = 0;
+        match padding_params.aggregation_padding {
+            AggregationPadding::NoAggPadding => {}
+            AggregationPadding::Parameters {
+                aggregation_epsilon,
+                aggregation_delta,
+                aggregation_padding_sensitivity,
+            } => {
+                let aggregation_padding = OPRFPaddingDp::new(
+                    aggregation_epsilon,
+                    aggregation_delta,
+                    aggregation_padding_sensitivity,
+                )?;
+                for group_key in 0..u32::try_from(B).unwrap() {
+                    let sample = aggregation_padding.sample(rng);
+                    total_number_of_fake_rows += sample;
+
+                    let group_key = K::truncate_from(u128::from(group_key));
+                    let group_key_shares = match direction_to_excluded_helper {
+                        Direction::Left => ReplicatedSecretSharing::new(K::ZERO, group_key),
+                        Direction::Right => ReplicatedSecretSharing::new(group_key, K::ZERO),
+                    };
+                    padding_input_rows.extend(repeat_n(
+                        Self {
+                            group_key: group_key_shares,
+                            ..Self::default()
+                        },
+                        sample as usize,
+                    ));
+                }
+            }
+        }
+        Ok(total_number_of_fake_rows)
+    }
+
+    fn add_zero_shares<VC: Extend<Self>>(
+        padding_input_rows: &mut VC,
+        total_number_of_fake_rows: u32,
+    ) {
+        padding_input_rows.extend(repeat_n(
+            Self::default(),
+            total_number_of_fake_rows as usize,
+        ));
+    }
+}
+
+impl<K, V, const N: usize> GroupByRecord for GroupByInputRow<K, V, N>
+where
+    K: BooleanArray + U128Conversions,
+    V: BooleanArray + U128Conversions,
+{
+    type Key = K;
+    type Value = V;
+    const VALUE_COUNT: usize = N;
+
+    fn group_key(&self) -> &Replicated<K> {
+        &self.group_key
+    }
+
+    fn into_values(self) -> impl Iterator<Item = Replicated<V>> {
+        self.values.into_iter()
+    }
+}
+
+/// GROUP BY with sum, revealing the group keys.
+///
+/// The protocol involves these steps:
+/// 1. Add dummy records for every group key (see [`apply_dp_padding`]), so that the number of
+///    records in each group is differentially private.
+/// 2. Shuffle the records across all shards, so that revealed keys can't be linked back to
+///    the input.
+/// 3. Reveal group keys.
+/// 4. Add all values for each group, separately for each value column.
+///
+/// Returns one histogram per value column. Each histogram has `HV::BITS` bits and `B`
+/// buckets. Sums saturate at the maximum value of `HV`. On a sharded network, every shard
+/// holds a partial histogram; see [`group_by_sum_with_dp`] to combine them.
+///
+/// This protocol explicitly manages proof batches for DZKP-based malicious security by
+/// processing chunks of values from `intermediate_results.chunks()`. Procession
+/// through record IDs is not uniform for all of the gates in the protocol. The first
+/// layer of the reduction adds N pairs of records, the second layer adds N/2 pairs of
+/// records, etc. This has a few consequences:
+///   * We must specify a batch size of `usize::MAX` when calling `dzkp_validator`.
+///   * We must track record IDs across chunks, so that subsequent chunks can
+///     start from the last record ID that was used in the previous chunk.
+///   * Because the first record ID in the proof batch is set implicitly, we must
+///     guarantee that it submits multiplication intermediates before any other
+///     record. This is currently ensured by the serial operation of the aggregation
+///     protocol (i.e. by not using `seq_join`).
+///
+/// # Errors
+/// Propagates errors from padding, shuffle, reveal and aggregation. Returns an error if a
+/// revealed group key is not less than `B`.
+///
+/// # Panics
+/// If `R::VALUE_COUNT` exceeds the number of steps reserved for value columns.
+#[tracing::instrument(name = "group_by_sum", skip_all, fields(total = records.len()))]
+pub async fn group_by_sum<C, R, HV, const B: usize>(
+    ctx: C,
+    records: Vec<R>,
+    padding_params: &PaddingParameters,
+) -> Result<Vec<BitDecomposed<Replicated<Boolean, B>>>, Error>
+where
+    C: UpgradableContext + ShardedShuffle + ShardedContext,
+    R: GroupByRecord,
+    Boolean: FieldSimd<B>,
+    Replicated<Boolean, B>: BooleanProtocols<DZKPUpgraded<C>, B>,
+    Replicated<R::Key>: Reveal<DZKPUpgraded<C>, Output = <R::Key as Vectorizable<1>>::Array>,
+    HV: BooleanArray + U128Conversions,
+    BitDecomposed<Replicated<Boolean, B>>:
+        for<'a> TransposeFrom<&'a [Replicated<R::Value>; B], Error = Infallible>,
+{
+    // Bounds-checked step constructors make sure there is a step for every value column
+    // before any work is done.
+    let sum_steps = (0..R::VALUE_COUNT).map(Step::sum).collect::<Vec<_>>();
+
+    // With more than one shard, this shard must take part in the shuffle even if it has
+    // nothing to contribute, and it may receive values from other shards.
+    if records.is_empty() && ctx.shard_count() == ShardIndex::from(1) {
+        return Ok(zero_histograms::<R, HV, B>());
+    }
+
+    let padded_records =
+        apply_dp_padding::<_, R, B>(ctx.narrow(&Step::PaddingDp), records, padding_params).await?;
+
+    let shuffled_records = ctx
+        .narrow(&Step::Shuffle)
+        .sharded_shuffle(padded_records)
+        .instrument(info_span!("shuffle_group_by_inputs"))
+        .await?;
+    if shuffled_records.is_empty() {
+        return Ok(zero_histograms::<R, HV, B>());
+    }
+
+    // Revealing the group keys doesn't do any multiplies, so won't make it as far as
+    // doing a proof, but we need the validator to obtain an upgraded malicious context.
+    let validator = ctx.clone().dzkp_validator(
+        MaliciousProtocolSteps {
+            protocol: &Step::Reveal,
+            validate: &Step::RevealValidate,
+        },
+        usize::MAX,
+    );
+    let grouped_values = reveal_group_keys(&validator.context(), shuffled_records).await?;
+    validator.validate().await?;
+
+    let mut histograms = Vec::with_capacity(R::VALUE_COUNT);
+    for (step, values) in sum_steps.iter().zip(grouped_values) {
+        histograms.push(sum_groups::<_, R::Value, HV, B>(ctx.narrow(step), values.into()).await?);
+    }
+
+    Ok(histograms)
+}
+
+/// GROUP BY with sum, followed by DP noise.
+///
+/// Runs [`group_by_sum`], combines the partial histograms from all shards on the leader
+/// shard and adds DP noise to every value column. `SS_BITS` bounds the contribution of a
+/// single record to each bucket and is used to calibrate the noise.
+///
+/// A single record may contribute to every value column, so the privacy budget in `dp_params`
+/// is split evenly across the `R::VALUE_COUNT` columns, each of which is noised independently.
+///
+/// Returns one histogram per value column. Only the leader shard returns the
+/// histograms, other shards return empty vectors.
+///
+/// # Errors
+/// Propagates errors from [`group_by_sum`], shard finalization and DP noise generation.
+///
+/// # Panics
+/// See [`group_by_sum`].
+pub async fn group_by_sum_with_dp<C, R, HV, const SS_BITS: usize, const B: usize>(
+    ctx: C,
+    records: Vec<R>,
+    padding_params: &PaddingParameters,
+    dp_params: DpMechanism,
+) -> Result<Vec<Vec<Replicated<HV>>>, Error>
+where
+    C: UpgradableContext
+        + ShardedShuffle
+        + ShardedContext
+        + FinalizerContext<FinalizingContext = DZKPUpgraded<C>>,
+    R: GroupByRecord,
+    Boolean: FieldSimd<B>,
+    Replicated<Boolean, B>: BooleanProtocols<DZKPUpgraded<C>, B>,
+    Replicated<R::Key>: Reveal<DZKPUpgraded<C>, Output = <R::Key as Vectorizable<1>>::Array>,
+    HV: BooleanArray + U128Conversions,
+    <HV as Serializable>::Size: Add<<HV as Serializable>::Size, Output: ArrayLength>,
+    BitDecomposed<Replicated<Boolean, B>>: FromPrss<usize>,
+    BitDecomposed<Replicated<Boolean, B>>:
+        for<'a> TransposeFrom<&'a [Replicated<R::Value>; B], Error = Infallible>,
+    BitDecomposed<Replicated<Boolean, B>>:
+        for<'a> TransposeFrom<&'a [Replicated<HV>; B], Error = Infallible>,
+    BitDecomposed<Replicated<Boolean, B>>:
+        for<'a> TransposeFrom<&'a Vec<Replicated<HV>>, Error = LengthError>,
+    Vec<Replicated<HV>>:
+        for<'a> TransposeFrom<&'a BitDecomposed<Replicated<Boolean, B>>, Error = LengthError>,
+    DZKPUpgraded<C>: ShardedContext,
+{
+    let histograms =
+        group_by_sum::<_, R, HV, B>(ctx.narrow(&DpStep::GroupBy), records, padding_params).await?;
+
+    let column_dp_params = dp_params
+        .split(u32::try_from(R::VALUE_COUNT).expect("number of value columns fits in u32"));
+    let mut noisy_histograms = Vec::with_capacity(histograms.len());
+    for (column, histogram) in histograms.into_iter().enumerate() {
+        let finalized_histogram = ctx
+            .narrow(&DpStep::finalize(column))
+            .finalize(
+                MaliciousProtocolSteps {
+                    protocol: &FinalizeSteps::Add,
+                    validate: &FinalizeSteps::Validate,
+                },
+                Histogram::<HV, B>::from(histogram),
+            )
+            .await?;
+
+        noisy_histograms.push(if ctx.is_leader() {
+            dp_for_histogram_with_steps::<_, _, B, HV, SS_BITS>(
+                ctx.clone(),
+                MaliciousProtocolSteps {
+                    protocol: &DpStep::noise(column),
+                    validate: &DpStep::noise_validate(column),
+                },
+                finalized_histogram.values,
+                column_dp_params,
+            )
+            .await?
+        } else {
+            finalized_histogram.compose()
+        });
+    }
+
+    Ok(noisy_histograms)
+}
+
+fn zero_histograms<R: GroupByRecord, HV: BooleanArray, const B: usize>(
+) -> Vec<BitDecomposed<Replicated<Boolean, B>>>
+where
+    Boolean: FieldSimd<B>,
+{
+    (0..R::VALUE_COUNT)
+        .map(|_| {
+            BitDecomposed::new(repeat_n(
+                Replicated::<Boolean, B>::ZERO,
+                usize::try_from(HV::BITS).unwrap(),
+            ))
+        })
+        .collect()
+}
+
+/// Transforms the group keys from secret shares into revealed `usize`s.
+/// The output has one [`ValueHistogram`] per value column, each holding
+/// the values of that column grouped by their key.
+#[tracing::instrument(name = "reveal_group_keys", skip_all, fields(
+    total = records.len(),
+))]
+async fn reveal_group_keys<C, R, const B: usize>(
+    parent_ctx: &C,
+    records: Vec<R>,
+) -> Result<Vec<ValueHistogram<R::Value, B>>, Error>
+where
+    C: Context,
+    R: GroupByRecord,
+    Boolean: FieldSimd<B>,
+    Replicated<R::Key>: Reveal<C, Output = <R::Key as Vectorizable<1>>::Array>,
+{
+    let reveal_ctx = parent_ctx.set_total_records(TotalRecords::specified(records.len())?);
+
+    let reveal_work = stream::iter(records).enumerate().map(|(i, record)| {
+        let record_id = RecordId::from(i);
+        let reveal_ctx = reveal_ctx.clone();
+        async move {
+            let revealed_key = reveal(reveal_ctx, record_id, record.group_key()).await?;
+            let revealed_key = R::Key::from_array(&revealed_key);
+            Ok::<_, Error>((revealed_key.as_u128(), record))
+        }
+    });
+    let mut grouped_values = (0..R::VALUE_COUNT)
+        .map(|_| ValueHistogram::<R::Value, B>::new())
+        .collect::<Vec<_>>();
+    let mut stream = pin!(seq_join(reveal_ctx.active_work(), reveal_work));
+    while let Some((key, record)) = stream.try_next().await? {
+        for (column, value) in grouped_values.iter_mut().zip(record.into_values()) {
+            column.push(key, value)?;
+        }
+    }
+
+    Ok(grouped_values)
+}
+
+/// Adds up the values in each group. The reduction runs in layers until a single
+/// histogram is left, with a DZKP batch per chunk of each layer.
+async fn sum_groups<C, V, HV, const B: usize>(
+    ctx: C,
+    mut intermediate_results: Vec<BitDecomposed<Replicated<Boolean, B>>>,
+) -> Result<BitDecomposed<Replicated<Boolean, B>>, Error>
+where
+    C: UpgradableContext,
+    V: BooleanArray,
+    HV: BooleanArray + U128Conversions,
+    Boolean: FieldSimd<B>,
+    Replicated<Boolean, B>: BooleanProtocols<DZKPUpgraded<C>, B>,
+{
+    // Any real-world aggregation should be able to complete in two layers (two
+    // iterations of the `while` loop below). Tests with small `TARGET_PROOF_SIZE`
+    // may exceed that.
+    let mut depth = 0;
+    let agg_proof_chunk = aggregate_values_proof_chunk(B, usize::try_from(V::BITS).unwrap());
+
+    while intermediate_results.len() > 1 {
+        let mut record_ids = [RecordId::FIRST; AGGREGATE_DEPTH];
+        let mut next_intermediate_results = Vec::new();
+        for (chunk_counter, chunk) in intermediate_results.chunks(agg_proof_chunk).enumerate() {
+            let chunk_len = chunk.len();
+            let validator = ctx.clone().dzkp_validator(
+                MaliciousProtocolSteps {
+                    protocol: &GroupSumStep::Aggregate(depth),
+                    validate: &GroupSumStep::AggregateValidate(depth),
+                },
+                usize::MAX, // See note about batching on `group_by_sum`.
+            );
+            let result = aggregate_values::<_, HV, B>(
+                validator.context(),
+                stream::iter(chunk).map(|v| Ok(v.clone())).boxed(),
+                chunk_len,
+                Some(&mut record_ids),
+            )
+            .await?;
+            validator.validate_indexed(chunk_counter).await?;
+            next_intermediate_results.push(result);
+        }
+        depth += 1;
+        intermediate_results = next_intermediate_results;
+    }
+
+    let mut result = intermediate_results
+        .into_iter()
+        .next()
+        .expect("aggregation input must not be empty");
+    result.resize(
+        usize::try_from(HV::BITS).unwrap(),
+        Replicated::<Boolean, B>::ZERO,
+    );
+    Ok(result)
+}
+
+/// Helper type that holds all the values of one column, grouped by their
+/// key. The main functionality is to turn into a stream that can be given to
+/// [`aggregate_values`].
+struct ValueHistogram<V: BooleanArray, const B: usize> {
+    values: [Vec<Replicated<V>>; B],
+    max_len: usize,
+}
+
+impl<V: BooleanArray, const B: usize> ValueHistogram<V, B> {
+    fn new() -> Self {
+        Self {
+            values: std::array::from_fn(|_| vec![]),
+            max_len: 0,
+        }
+    }
+
+    fn push(&mut self, key: u128, value: Replicated<V>) -> Result<(), Error> {
+        let values = usize::try_from(key)
+            .ok()
+            .and_then(|key| self.values.get_mut(key))
+            .ok_or(Error::GroupKeyOutOfRange { key, buckets: B })?;
+        values.push(value);
+        self.max_len = self.max_len.max(values.len());
+
+        Ok(())
+    }
+}
+
+impl<V: BooleanArray, const B: usize> From<ValueHistogram<V, B>>
+    for Vec<BitDecomposed<Replicated<Boolean, B>>>
+where
+    Boolean: FieldSimd<B>,
+    BitDecomposed<Replicated<Boolean, B>>:
+        for<'a> TransposeFrom<&'a [Replicated<V>; B], Error = Infallible>,
+{
+    fn from(mut grouped: ValueHistogram<V, B>) -> Vec<BitDecomposed<Replicated<Boolean, B>>> {
+        let iter = (0..grouped.max_len).map(move |_| {
+            let slice: [Replicated<V>; B] = grouped
+                .values
+                .each_mut()
+                .map(|values| values.pop().unwrap_or(Replicated::ZERO));
+
+            BitDecomposed::transposed_from(&slice).unwrap_infallible()
+        });
+        iter.collect()
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use futures::TryFutureExt;
+    use rand::{seq::SliceRandom, Rng};
+
+    use crate::{
+        error::Error,
+        ff::{
+            boolean::Boolean,
+            boolean_array::{BA16, BA3, BA32, BA5, BA8},
+            U128Conversions,
+        },
+        helpers::query::DpMechanism,
+        protocol::{
+            dp::histogram_noise_std,
+            group_by::{group_by_sum, group_by_sum_with_dp, GroupByInputRow},
+            ipa_prf::{oprf_padding::PaddingParameters, shuffle::Shuffleable},
+        },
+        secret_sharing::{
+            replicated::semi_honest::AdditiveShare as Replicated, BitDecomposed, IntoShares,
+            TransposeFrom,
+        },
+        test_executor::run,
+        test_fixture::{Reconstruct, Runner, TestWorld, TestWorldConfig, WithShards},
+    };
+
+    type TestRow = GroupByInputRow<BA5, BA3, 2>;
+
+    #[derive(Clone, Copy, Debug)]
+    struct TestGroupByRow {
+        group_key: u128,
+        values: [u128; 2],
+    }
+
+    impl IntoShares<TestRow> for TestGroupByRow {
+        fn share_with<R: Rng>(self, rng: &mut R) -> [TestRow; 3] {
+            let [k0, k1, k2] = BA5::truncate_from(self.group_key).share_with(rng);
+            let [a0, a1, a2] = BA3::truncate_from(self.values[0]).share_with(rng);
+            let [b0, b1, b2] = BA3::truncate_from(self.values[1]).share_with(rng);
+            [
+                TestRow {
+                    group_key: k0,
+                    values: [a0, b0],
+                },
+                TestRow {
+                    group_key: k1,
+                    values: [a1, b1],
+                },
+                TestRow {
+                    group_key: k2,
+                    values: [a2, b2],
+                },
+            ]
+        }
+    }
+
+    /// Generates rows for 32 groups, along with the expected sums for both value columns.
+    fn inputs_and_expectation<R: Rng>(mut rng: R) -> (Vec<TestGroupByRow>, [Vec<u128>; 2]) {
+        let mut inputs = Vec::new();
+        for group_key in 0..32 {
+            for _ in 0..rng.gen_range(0..6) {
+                inputs.push(TestGroupByRow {
+                    group_key,
+                    values: [rng.gen_range(0..8), rng.gen_range(0..8)],
+                });
+            }
+        }
+        let mut expectation = [vec![0; 32], vec![0; 32]];
+        for row in &inputs {
+            let group_key = usize::try_from(row.group_key).unwrap();
+            expectation[0][group_key] += row.values[0];
+            expectation[1][group_key] += row.values[1];
+        }
+        inputs.shuffle(&mut rng);
+        (inputs, expectation)
+    }
+
+    /// Adds up the partial histograms from all shards, one column at a time.
+    fn sum_over_shards(results: Vec<Vec<Vec<BA8>>>) -> [Vec<u128>; 2] {
+        let mut sums = [vec![0; 32], vec![0; 32]];
+        for shard in results {
+            for (sum, column) in sums.iter_mut().zip(shard) {
+                for (a, b) in sum.iter_mut().zip(column) {
+                    *a += b.as_u128();
+                }
+            }
+        }
+        sums
+    }
+
+    #[test]
+    fn shuffle_share_roundtrip() {
+        let row = TestRow {
+            group_key: Replicated::new(BA5::truncate_from(17_u128), BA5::truncate_from(3_u128)),
+            values: [
+                Replicated::new(BA3::truncate_from(5_u128), BA3::truncate_from(1_u128)),
+                Replicated::new(BA3::truncate_from(2_u128), BA3::truncate_from(7_u128)),
+            ],
+        };
+
+        assert_eq!(row, TestRow::new(row.left(), row.right()));
+    }
+
+    #[test]
+    fn semi_honest() {
+        run(|| async {
+            let world = TestWorld::<WithShards<2>>::with_shards(TestWorldConfig::default());
+            let (inputs, expectation) = inputs_and_expectation(world.rng());
+            let result: Vec<Vec<Vec<BA8>>> = world
+                .semi_honest(inputs.into_iter(), |ctx, rows| async move {
+                    group_by_sum::<_, TestRow, BA8, 32>(ctx, rows, &PaddingParameters::relaxed())
+                        .map_ok(|columns| {
+                            columns
+                                .iter()
+                                .map(|d: &BitDecomposed<Replicated<Boolean, 32>>| {
+                                    Vec::transposed_from(d).unwrap()
+                                })
+                                .collect::<Vec<_>>()
+                        })
+                        .await
+                        .unwrap()
+                })
+                .await
+                .into_iter()
+                .map(|shard| {
+                    let [h1, h2, h3] = shard;
+                    (0..2)
+                        .map(|column| {
+                            [h1[column].clone(), h2[column].clone(), h3[column].clone()]
+                                .reconstruct()
+                        })
+                        .collect()
+                })
+                .collect();
+
+            assert_eq!(sum_over_shards(result), expectation);
+        });
+    }
+
+    #[test]
+    fn malicious_with_dp() {
+        run(|| async {
+            let world = TestWorld::<WithShards<2>>::with_shards(TestWorldConfig::default());
+            let (inputs, expectation) = inputs_and_expectation(world.rng());
+            let result: Vec<Vec<Vec<BA8>>> = world
+                .malicious(inputs.into_iter(), |ctx, rows| async move {
+                    group_by_sum_with_dp::<_, TestRow, BA8, 3, 32>(
+                        ctx,
+                        rows,
+                        &PaddingParameters::relaxed(),
+                        DpMechanism::NoDp,
+                    )
+                    .await
+                    .unwrap()
+                })
+                .await
+                .into_iter()
+                .map(|shard| {
+                    let [h1, h2, h3] = shard;
+                    (0..h1.len())
+                        .map(|column| {
+                            [h1[column].clone(), h2[column].clone(), h3[column].clone()]
+                                .reconstruct()
+                        })
+                        .collect()
+                })
+                .collect();
+
+            // Only the leader shard holds the result.
+            assert_eq!(result.iter().filter(|shard| shard[0].is_empty()).count(), 1);
+            assert_eq!(sum_over_shards(result), expectation);
+        });
+    }
+
+    #[test]
+    fn dp_budget_is_split_across_columns() {
+        type WideRow = GroupByInputRow<BA8, BA3, 8>;
+        const EPSILON: f64 = 1.0;
+
+        run(|| async {
+            let world = TestWorld::<WithShards<1>>::with_shards(TestWorldConfig::default());
+            let result = world
+                .malicious(Vec::<BA8>::new().into_iter(), |ctx, keys| async move {
+                    assert!(keys.is_empty());
+                    group_by_sum_with_dp::<_, WideRow, BA32, 3, 256>(
+                        ctx,
+                        Vec::new(),
+                        &PaddingParameters::no_padding(),
+                        DpMechanism::DiscreteLaplace { epsilon: EPSILON },
+                    )
+                    .await
+                    .unwrap()
+                })
+                .await;
+
+            // With no input, the histograms hold nothing but noise, which may be negative.
+            let [h1, h2, h3] = result.into_iter().next().unwrap();
+            let noise = (0..h1.len())
+                .flat_map(|column| {
+                    [h1[column].clone(), h2[column].clone(), h3[column].clone()]
+                        .reconstruct()
+                        .into_iter()
+                        .map(|v| {
+                            let v = f64::from(u32::try_from(v.as_u128()).unwrap());
+                            if v < 2_f64.powi(31) {
+                                v
+                            } else {
+                                v - 2_f64.powi(32)
+                            }
+                        })
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(noise.len(), 8 * 256);
+
+            #[allow(clippy::cast_precision_loss)]
+            let n = noise.len() as f64;
+            let mean = noise.iter().sum::<f64>() / n;
+            let std = (noise.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n).sqrt();
+
+            // Every column gets an eighth of the budget. Noising each column with the full
+            // budget would give an 8 times smaller standard deviation.
+            let expected = histogram_noise_std(
+                DpMechanism::DiscreteLaplace {
+                    epsilon: EPSILON / 8.0,
+                },
+                8,
+                256,
+            )
+            .unwrap();
+            assert!(
+                (std / expected - 1.0).abs() < 0.15,
+                "noise std {std} does not match the expected {expected}"
+            );
+        });
+    }
+
+    #[test]
+    fn empty() {
+        run(|| async {
+            let world = TestWorld::<WithShards<1>>::with_shards(TestWorldConfig::default());
+            let result = world
+                .semi_honest(
+                    Vec::<TestGroupByRow>::new().into_iter(),
+                    |ctx, rows| async move {
+                        group_by_sum::<_, TestRow, BA16, 32>(
+                            ctx,
+                            rows,
+                            &PaddingParameters::no_padding(),
+                        )
+                        .await
+                        .unwrap()
+                    },
+                )
+                .await;
+
+            for shard in result {
+                for helper in shard {
+                    assert_eq!(helper.len(), 2);
+                    assert!(helper.iter().all(|column| column.len() == 16));
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn group_key_out_of_range() {
+        type WideKeyRow = GroupByInputRow<BA8, BA3, 1>;
+
+        run(|| async {
+            let world = TestWorld::<WithShards<1>>::with_shards(TestWorldConfig::default());
+            let result = world
+                .semi_honest(
+                    [BA8::truncate_from(40_u128)].into_iter(),
+                    |ctx, keys| async move {
+                        let rows = keys
+                            .into_iter()
+                            .map(|group_key| WideKeyRow {
+                                group_key,
+                                values: [Replicated::ZERO],
+                            })
+                            .collect();
+                        group_by_sum::<_, WideKeyRow, BA8, 32>(
+                            ctx,
+                            rows,
+                            &PaddingParameters::no_padding(),
+                        )
+                        .await
+                    },
+                )
+                .await;
+
+            for shard in result {
+                for helper in shard {
+                    assert!(matches!(
+                        helper,
+                        Err(Error::GroupKeyOutOfRange {
+                            key: 40,
+                            buckets: 32
+                        })
+                    ));
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,37 @@
+use ipa_step_derive::CompactStep;
+
+#[derive(CompactStep)]
+pub(crate) enum GroupByStep {
+    #[step(child = crate::protocol::ipa_prf::oprf_padding::step::PaddingDpStep, name="padding_dp")]
+    PaddingDp,
+    #[step(child = crate::protocol::ipa_prf::shuffle::step::ShardedShuffleStep)]
+    Shuffle,
+    Reveal,
+    #[step(child = crate::protocol::context::step::DzkpValidationProtocolStep)]
+    RevealValidate, // only partly used -- see code
+    /// Sums one column of values. The count limits how many values a record can carry.
+    #[step(count = 8, child = GroupSumStep, name = "sum")]
+    Sum(usize),
+}
+
+/// Steps of `group_by_sum_with_dp`. Value columns are finalized and noised one at a time,
+/// so the counts match the one of [`GroupByStep::Sum`].
+#[derive(CompactStep)]
+pub(crate) enum GroupByDpStep {
+    #[step(child = GroupByStep)]
+    GroupBy,
+    #[step(count = 8, child = crate::protocol::hybrid::step::FinalizeSteps)]
+    Finalize(usize),
+    #[step(count = 8, child = crate::protocol::dp::step::DPStep, name = "dp")]
+    Noise(usize),
+    #[step(count = 8, child = crate::protocol::context::step::DzkpValidationProtocolStep)]
+    NoiseValidate(usize),
+}
+
+#[derive(CompactStep)]
+pub(crate) enum GroupSumStep {
+    #[step(count = 4, child = crate::protocol::ipa_prf::aggregation::step::AggregateChunkStep, name = "chunks")]
+    Aggregate(usize),
+    #[step(count = 4, child = crate::protocol::context::step::DzkpValidationProtocolStep)]
+    AggregateValidate(usize),
+}
@@ -1,26 +1,40 @@
-use std::collections::BTreeMap;
+use std::{cmp::max, collections::BTreeMap, iter::zip};
 
 use futures::{stream, StreamExt, TryStreamExt};
+use typenum::Const;
 
 use crate::{
-    error::Error,
-    ff::{boolean::Boolean, boolean_array::BooleanArray, ArrayAccess},
-    helpers::TotalRecords,
+    error::{Error, LengthError},
+    ff::{boolean::Boolean, boolean_array::BooleanArray, Serializable},
+    helpers::{
+        stream::{div_round_up, TryFlattenItersExt},
+        TotalRecords,
+    },
     protocol::{
         boolean::step::EightBitStep,
         context::{
             dzkp_validator::{validated_seq_join, DZKPValidator, TARGET_PROOF_SIZE},
             Context, DZKPUpgraded, MaliciousProtocolSteps, ShardedContext, UpgradableContext,
         },
-        hybrid::step::{AggregateReportsStep, HybridStep},
-        ipa_prf::boolean_ops::addition_sequential::integer_add,
+        hybrid::{
+            spill::SpilledReports,
+            step::{AggregateReportsStep, HybridStep},
+        },
+        ipa_prf::{boolean_ops::addition_sequential::integer_add, AGG_CHUNK},
         BooleanProtocols,
     },
     report::hybrid::{AggregateableHybridReport, PrfHybridReport},
-    secret_sharing::replicated::semi_honest::AdditiveShare as Replicated,
+    secret_sharing::{
+        replicated::semi_honest::AdditiveShare as Replicated, BitDecomposed, TransposeFrom,
+    },
     utils::non_zero_prev_power_of_two,
 };
 
+/// Maximum number of reports a shard groups in memory. Shards with larger inputs spill their
+/// reports to disk in partitions of about this size while resharding, and aggregate them
+/// with [`aggregate_spilled_reports`].
+pub const IN_MEMORY_GROUPING_LIMIT: usize = 1 << 24;
+
 enum MatchEntry<BK, V>
 where
     BK: BooleanArray,
@@ -66,8 +80,8 @@ where
 /// This would put the sum of conversion values into `breakdown_key` 0. As this is undetectable,
 /// this makes `breakdown_key = 0` *unreliable*.
 ///
-/// Note: Possible Perf opportunity by removing the `collect()`.
-/// See [#1443](https://github.com/private-attribution/ipa/issues/1443).
+/// This keeps every report in memory. Large inputs are grouped with
+/// [`group_report_pairs_ordered_external`] instead.
 ///
 /// *Note*: In order to add the pairs, the vector of pairs must be in the same order across all
 /// three helpers. A standard `HashMap` uses system randomness for insertion placement, so we
@@ -96,11 +110,107 @@ where
         .collect::<Vec<_>>()
 }
 
+/// Same as [`group_report_pairs_ordered`], except that reports are read from disk, where they
+/// are range partitioned by the oprf of the `match_key`. Partitions are grouped one at a
+/// time, so only a single partition of reports is in memory while grouping. Partitions cover
+/// disjoint and increasing ranges of `match_keys`, so the pairs are returned in exactly the
+/// same order as [`group_report_pairs_ordered`] returns them for the resharded reports, on all
+/// three helpers.
+///
+/// ## Errors
+/// If reading reports back from disk fails.
+fn group_report_pairs_ordered_external<BK, V>(
+    spilled: SpilledReports<BK, V>,
+) -> Result<Vec<[AggregateableHybridReport<BK, V>; 2]>, Error>
+where
+    BK: BooleanArray,
+    V: BooleanArray,
+    PrfHybridReport<BK, V>: Serializable,
+{
+    if spilled.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut pairs = Vec::new();
+    for partition in spilled.into_partitions() {
+        pairs.extend(group_report_pairs_ordered(partition?));
+    }
+
+    Ok(pairs)
+}
+
+/// This function takes in a vector of `PrfHybridReports`, groups them by the oprf of the `match_key`,
+/// and collects the reports of every `match_key` into a vector.
+///
+/// Unlike [`group_report_pairs_ordered`], every `match_key` is kept, regardless of how many
+/// reports it provided. Only the first `max_reports_per_match_key` reports are kept for each
+/// `match_key`, the rest are dropped.
+///
+/// *Note*: As with [`group_report_pairs_ordered`], the groups are returned in the same order
+/// on all three helpers.
+pub(crate) fn group_reports_ordered<BK, V>(
+    reports: Vec<PrfHybridReport<BK, V>>,
+    max_reports_per_match_key: usize,
+) -> Vec<Vec<AggregateableHybridReport<BK, V>>>
+where
+    BK: BooleanArray,
+    V: BooleanArray,
+{
+    let mut reports_by_matchkey: BTreeMap<u64, Vec<AggregateableHybridReport<BK, V>>> =
+        BTreeMap::new();
+
+    for report in reports {
+        let entry = reports_by_matchkey.entry(report.match_key).or_default();
+        if entry.len() < max_reports_per_match_key {
+            entry.push(report.into());
+        }
+    }
+
+    reports_by_matchkey.into_values().collect()
+}
+
+/// Returns a suitable proof chunk size (in records) for use with [`aggregate_reports`].
+///
+/// Every record adds `AGG_CHUNK` pairs of breakdown keys and values, which takes
+/// `AGG_CHUNK` * (`BK::BITS` + `V::BITS`) multiplications. There is also a constraint on proof
+/// chunks to be powers of two, and we don't want to compute a proof chunk of zero when
+/// `TARGET_PROOF_SIZE` is smaller for tests.
+fn aggregate_reports_proof_chunk<BK: BooleanArray, V: BooleanArray>() -> usize {
+    non_zero_prev_power_of_two(max(
+        2,
+        TARGET_PROOF_SIZE / AGG_CHUNK / (BK::BITS as usize + V::BITS as usize),
+    ))
+}
+
+/// Transposes one field of a chunk of report pairs into a vector of bits, padding the chunk
+/// with zeros to `AGG_CHUNK` pairs.
+fn transpose_pair_field<BK, V, T, F>(
+    pairs: &[[AggregateableHybridReport<BK, V>; 2]],
+    field: F,
+) -> Result<BitDecomposed<Replicated<Boolean, AGG_CHUNK>>, LengthError>
+where
+    BK: BooleanArray,
+    V: BooleanArray,
+    T: BooleanArray,
+    F: Fn(&[AggregateableHybridReport<BK, V>; 2]) -> Replicated<T>,
+    BitDecomposed<Replicated<Boolean, AGG_CHUNK>>:
+        for<'a> TransposeFrom<&'a Vec<Replicated<T>>, Error = LengthError>,
+{
+    let mut column = pairs.iter().map(field).collect::<Vec<_>>();
+    column.resize(AGG_CHUNK, Replicated::ZERO);
+    BitDecomposed::transposed_from(&column)
+}
+
 /// This protocol is used to aggregate `PRFHybridReports` and returns `AggregateableHybridReports`.
 /// It groups all the reports by the PRF of the `match_key`, finds all reports from `match_keys`
 /// with that provided exactly 2 reports, then adds those 2 reports.
-/// TODO (Performance opportunity): These additions are not currently vectorized.
-/// We are currently deferring that work until the protocol is complete.
+///
+/// The additions are vectorized: pairs are processed in chunks of `AGG_CHUNK`, the breakdown
+/// keys and values of every chunk are transposed into vectors of bits and added with
+/// `AGG_CHUNK`-wide shares. The last chunk is padded with zeros.
+///
+/// Shards with more than [`IN_MEMORY_GROUPING_LIMIT`] reports group them on disk with
+/// [`aggregate_spilled_reports`] instead.
 pub async fn aggregate_reports<BK, V, C>(
     ctx: C,
     reports: Vec<PrfHybridReport<BK, V>>,
@@ -109,69 +219,157 @@ where
     C: UpgradableContext + ShardedContext,
     BK: BooleanArray,
     V: BooleanArray,
-    Replicated<Boolean>: BooleanProtocols<DZKPUpgraded<C>>,
+    Replicated<Boolean, AGG_CHUNK>: BooleanProtocols<DZKPUpgraded<C>, AGG_CHUNK>,
+    BitDecomposed<Replicated<Boolean, AGG_CHUNK>>:
+        for<'a> TransposeFrom<&'a Vec<Replicated<BK>>, Error = LengthError>,
+    BitDecomposed<Replicated<Boolean, AGG_CHUNK>>:
+        for<'a> TransposeFrom<&'a Vec<Replicated<V>>, Error = LengthError>,
+    Vec<Replicated<BK>>: for<'a> TransposeFrom<
+        &'a BitDecomposed<Replicated<Boolean, AGG_CHUNK>>,
+        Error = LengthError,
+    >,
+    Vec<Replicated<V>>: for<'a> TransposeFrom<
+        &'a BitDecomposed<Replicated<Boolean, AGG_CHUNK>>,
+        Error = LengthError,
+    >,
 {
-    let report_pairs = group_report_pairs_ordered(reports);
+    add_report_pairs(ctx, group_report_pairs_ordered(reports)).await
+}
 
-    let chunk_size =
-        non_zero_prev_power_of_two(TARGET_PROOF_SIZE / (BK::BITS as usize + V::BITS as usize));
+/// Same as [`aggregate_reports`] for reports that were spilled to disk while resharding, see
+/// [`compute_prf_and_spill`]. Only one partition of reports is kept in memory while they are
+/// grouped by the PRF of the `match_key`, see [`group_report_pairs_ordered_external`].
+///
+/// [`compute_prf_and_spill`]: crate::protocol::hybrid::oprf::compute_prf_and_spill
+pub async fn aggregate_spilled_reports<BK, V, C>(
+    ctx: C,
+    reports: SpilledReports<BK, V>,
+) -> Result<Vec<AggregateableHybridReport<BK, V>>, Error>
+where
+    C: UpgradableContext + ShardedContext,
+    BK: BooleanArray,
+    V: BooleanArray,
+    PrfHybridReport<BK, V>: Serializable,
+    Replicated<Boolean, AGG_CHUNK>: BooleanProtocols<DZKPUpgraded<C>, AGG_CHUNK>,
+    BitDecomposed<Replicated<Boolean, AGG_CHUNK>>:
+        for<'a> TransposeFrom<&'a Vec<Replicated<BK>>, Error = LengthError>,
+    BitDecomposed<Replicated<Boolean, AGG_CHUNK>>:
+        for<'a> TransposeFrom<&'a Vec<Replicated<V>>, Error = LengthError>,
+    Vec<Replicated<BK>>: for<'a> TransposeFrom<
+        &'a BitDecomposed<Replicated<Boolean, AGG_CHUNK>>,
+        Error = LengthError,
+    >,
+    Vec<Replicated<V>>: for<'a> TransposeFrom<
+        &'a BitDecomposed<Replicated<Boolean, AGG_CHUNK>>,
+        Error = LengthError,
+    >,
+{
+    add_report_pairs(ctx, group_report_pairs_ordered_external(reports)?).await
+}
 
-    let ctx = ctx.set_total_records(TotalRecords::specified(report_pairs.len())?);
+/// Adds the breakdown keys and values of every pair of reports, see [`aggregate_reports`].
+pub async fn add_report_pairs<BK, V, C>(
+    ctx: C,
+    report_pairs: Vec<[AggregateableHybridReport<BK, V>; 2]>,
+) -> Result<Vec<AggregateableHybridReport<BK, V>>, Error>
+where
+    C: UpgradableContext + ShardedContext,
+    BK: BooleanArray,
+    V: BooleanArray,
+    Replicated<Boolean, AGG_CHUNK>: BooleanProtocols<DZKPUpgraded<C>, AGG_CHUNK>,
+    BitDecomposed<Replicated<Boolean, AGG_CHUNK>>:
+        for<'a> TransposeFrom<&'a Vec<Replicated<BK>>, Error = LengthError>,
+    BitDecomposed<Replicated<Boolean, AGG_CHUNK>>:
+        for<'a> TransposeFrom<&'a Vec<Replicated<V>>, Error = LengthError>,
+    Vec<Replicated<BK>>: for<'a> TransposeFrom<
+        &'a BitDecomposed<Replicated<Boolean, AGG_CHUNK>>,
+        Error = LengthError,
+    >,
+    Vec<Replicated<V>>: for<'a> TransposeFrom<
+        &'a BitDecomposed<Replicated<Boolean, AGG_CHUNK>>,
+        Error = LengthError,
+    >,
+{
+    // resharding by the PRF of match keys may leave a shard without any pairs
+    if report_pairs.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let ctx = ctx.set_total_records(TotalRecords::specified(div_round_up(
+        report_pairs.len(),
+        Const::<AGG_CHUNK>,
+    ))?);
 
     let dzkp_validator = ctx.dzkp_validator(
         MaliciousProtocolSteps {
             protocol: &HybridStep::GroupBySum,
             validate: &HybridStep::GroupBySumValidate,
         },
-        chunk_size,
+        aggregate_reports_proof_chunk::<BK, V>(),
     );
 
     let agg_ctx = dzkp_validator.context();
 
-    let agg_work = stream::iter(report_pairs)
+    let agg_work = stream::iter(report_pairs.chunks(AGG_CHUNK))
         .enumerate()
-        .map(|(idx, reports)| {
+        .map(|(idx, pairs)| {
             let agg_ctx = agg_ctx.clone();
             async move {
-                let (breakdown_key, _) = integer_add::<_, EightBitStep, 1>(
+                let (breakdown_keys, _) = integer_add::<_, EightBitStep, AGG_CHUNK>(
                     agg_ctx.narrow(&AggregateReportsStep::AddBK),
                     idx.into(),
-                    &reports[0].breakdown_key.to_bits(),
-                    &reports[1].breakdown_key.to_bits(),
+                    &transpose_pair_field(pairs, |pair| pair[0].breakdown_key.clone())?,
+                    &transpose_pair_field(pairs, |pair| pair[1].breakdown_key.clone())?,
                 )
                 .await?;
-                let (value, _) = integer_add::<_, EightBitStep, 1>(
+                let (values, _) = integer_add::<_, EightBitStep, AGG_CHUNK>(
                     agg_ctx.narrow(&AggregateReportsStep::AddV),
                     idx.into(),
-                    &reports[0].value.to_bits(),
-                    &reports[1].value.to_bits(),
+                    &transpose_pair_field(pairs, |pair| pair[0].value.clone())?,
+                    &transpose_pair_field(pairs, |pair| pair[1].value.clone())?,
                 )
                 .await?;
-                Ok::<_, Error>(AggregateableHybridReport::<BK, V> {
-                    match_key: (),
-                    breakdown_key: breakdown_key.collect_bits(),
-                    value: value.collect_bits(),
-                })
+
+                let breakdown_keys = Vec::<Replicated<BK>>::transposed_from(&breakdown_keys)?;
+                let values = Vec::<Replicated<V>>::transposed_from(&values)?;
+                Ok::<_, Error>(
+                    zip(breakdown_keys, values)
+                        .take(pairs.len())
+                        .map(
+                            |(breakdown_key, value)| AggregateableHybridReport::<BK, V> {
+                                match_key: (),
+                                breakdown_key,
+                                value,
+                            },
+                        )
+                        .collect::<Vec<_>>(),
+                )
             }
         });
 
     validated_seq_join(dzkp_validator, agg_work)
+        .try_flatten_iters()
         .try_collect()
         .await
 }
 
 #[cfg(all(test, unit_test))]
 pub mod test {
-    use rand::Rng;
+    use std::{iter::zip, num::NonZeroUsize};
+
+    use rand::{thread_rng, Rng};
 
-    use super::{aggregate_reports, group_report_pairs_ordered};
+    use super::{
+        aggregate_reports, aggregate_spilled_reports, group_report_pairs_ordered,
+        group_report_pairs_ordered_external, group_reports_ordered,
+    };
     use crate::{
         ff::{
             boolean_array::{BA3, BA8},
             U128Conversions,
         },
         helpers::Role,
-        protocol::hybrid::step::AggregateReportsStep,
+        protocol::hybrid::{spill::SpilledReports, step::AggregateReportsStep},
         report::hybrid::{
             AggregateableHybridReport, IndistinguishableHybridReport, PrfHybridReport,
         },
@@ -410,69 +608,85 @@ pub mod test {
     #[test]
     fn aggregate_reports_test() {
         run(|| async {
-            let records = get_records();
-            let expected = vec![
-                TestAggregateableHybridReport {
-                    match_key: (),
-                    value: 1,
-                    breakdown_key: 45,
-                },
-                TestAggregateableHybridReport {
-                    match_key: (),
-                    value: 7,
-                    breakdown_key: 0,
-                },
-                TestAggregateableHybridReport {
-                    match_key: (),
-                    value: 2,
-                    breakdown_key: 56,
-                },
-            ];
-
-            let world = TestWorld::<WithShards<SHARDS>>::with_shards(TestWorldConfig::default());
-
-            let results: Vec<[Vec<AggregateableHybridReport<BA8, BA3>>; 3]> = world
-                .malicious(records.clone().into_iter(), |ctx, input| {
-                    let match_keys = match ctx.shard_id() {
-                        ShardIndex::FIRST => SHARD1_MKS,
-                        SECOND_SHARD => SHARD2_MKS,
-                        _ => panic!("invalid shard_id"),
-                    };
-                    async move {
-                        let indistinguishable_reports: Vec<
-                            IndistinguishableHybridReport<BA8, BA3>,
-                        > = input.iter().map(|r| r.clone().into()).collect::<Vec<_>>();
+            for spill in [false, true] {
+                aggregate_reports_test_impl(spill).await;
+            }
+        });
+    }
 
-                        let prf_reports: Vec<PrfHybridReport<BA8, BA3>> = indistinguishable_reports
-                            .iter()
-                            .zip(match_keys)
-                            .map(|(indist_report, match_key)| PrfHybridReport {
-                                match_key,
-                                value: indist_report.value.clone(),
-                                breakdown_key: indist_report.breakdown_key.clone(),
-                            })
-                            .collect::<Vec<_>>();
+    async fn aggregate_reports_test_impl(spill: bool) {
+        let records = get_records();
+        let expected = vec![
+            TestAggregateableHybridReport {
+                match_key: (),
+                value: 1,
+                breakdown_key: 45,
+            },
+            TestAggregateableHybridReport {
+                match_key: (),
+                value: 7,
+                breakdown_key: 0,
+            },
+            TestAggregateableHybridReport {
+                match_key: (),
+                value: 2,
+                breakdown_key: 56,
+            },
+        ];
 
+        let world = TestWorld::<WithShards<SHARDS>>::with_shards(TestWorldConfig::default());
+
+        let results: Vec<[Vec<AggregateableHybridReport<BA8, BA3>>; 3]> = world
+            .malicious(records.clone().into_iter(), |ctx, input| {
+                let match_keys = match ctx.shard_id() {
+                    ShardIndex::FIRST => SHARD1_MKS,
+                    SECOND_SHARD => SHARD2_MKS,
+                    _ => panic!("invalid shard_id"),
+                };
+                async move {
+                    let indistinguishable_reports: Vec<IndistinguishableHybridReport<BA8, BA3>> =
+                        input.iter().map(|r| r.clone().into()).collect::<Vec<_>>();
+
+                    let prf_reports: Vec<PrfHybridReport<BA8, BA3>> = indistinguishable_reports
+                        .iter()
+                        .zip(match_keys)
+                        .map(|(indist_report, match_key)| PrfHybridReport {
+                            match_key,
+                            value: indist_report.value.clone(),
+                            breakdown_key: indist_report.breakdown_key.clone(),
+                        })
+                        .collect::<Vec<_>>();
+
+                    if spill {
+                        let mut spilled =
+                            SpilledReports::new(NonZeroUsize::new(2).unwrap()).unwrap();
+                        for report in &prf_reports {
+                            spilled.push(ShardIndex::FIRST, report).unwrap();
+                        }
+                        aggregate_spilled_reports(ctx.clone(), spilled)
+                            .await
+                            .unwrap()
+                    } else {
                         aggregate_reports(ctx.clone(), prf_reports).await.unwrap()
                     }
-                })
-                .await;
+                }
+            })
+            .await;
+
+        let results: Vec<TestAggregateableHybridReport> = results
+            .into_iter()
+            .flat_map(|shard_result| {
+                shard_result[0]
+                    .clone()
+                    .into_iter()
+                    .zip(shard_result[1].clone())
+                    .zip(shard_result[2].clone())
+                    .map(|((r1, r2), r3)| [&r1, &r2, &r3].reconstruct())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
 
-            let results: Vec<TestAggregateableHybridReport> = results
-                .into_iter()
-                .flat_map(|shard_result| {
-                    shard_result[0]
-                        .clone()
-                        .into_iter()
-                        .zip(shard_result[1].clone())
-                        .zip(shard_result[2].clone())
-                        .map(|((r1, r2), r3)| [&r1, &r2, &r3].reconstruct())
-                        .collect::<Vec<_>>()
-                })
-                .collect::<Vec<_>>();
-
-            assert_eq!(results, expected);
-        });
+        assert_eq!(results, expected);
     }
 
     fn build_prf_hybrid_report(
@@ -533,6 +747,73 @@ pub mod test {
         assert_eq!(results, expected);
     }
 
+    #[test]
+    fn group_reports_external() {
+        let mut rng = thread_rng();
+        // few distinct match keys, spread over the whole range of u64, so that there are
+        // singles, pairs and triples in every partition
+        let match_keys = (0..50).map(|_| rng.gen::<u64>()).collect::<Vec<_>>();
+        let reports = (0..200)
+            .map(|_| {
+                build_prf_hybrid_report(
+                    match_keys[rng.gen_range(0..match_keys.len())],
+                    rng.gen(),
+                    rng.gen(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        // reports arrive from shards in a different order on every helper, but the shard
+        // they came from sets their order after resharding
+        let sources = (0..reports.len())
+            .map(|_| rng.gen_range(0..4))
+            .collect::<Vec<u32>>();
+        let mut resharded = zip(&sources, &reports).collect::<Vec<_>>();
+        resharded.sort_by_key(|(source, _)| **source);
+        let resharded = resharded
+            .into_iter()
+            .map(|(_, report)| report.clone())
+            .collect::<Vec<_>>();
+
+        for partitions in [1, 3, 16] {
+            let mut spilled = SpilledReports::new(NonZeroUsize::new(partitions).unwrap()).unwrap();
+            for (&source, report) in zip(&sources, &reports) {
+                spilled.push(ShardIndex::from(source), report).unwrap();
+            }
+            assert_eq!(
+                group_report_pairs_ordered(resharded.clone()),
+                group_report_pairs_ordered_external(spilled).unwrap(),
+            );
+        }
+    }
+
+    #[test]
+    fn group_all_reports() {
+        let reports = vec![
+            build_prf_hybrid_report(42, 0, 3),
+            build_prf_hybrid_report(17, 0, 13),
+            build_prf_hybrid_report(42, 0, 4),
+            build_prf_hybrid_report(31, 0, 2),
+            build_prf_hybrid_report(31, 0, 4),
+            build_prf_hybrid_report(31, 0, 6), // dropped
+        ];
+
+        let expected = vec![
+            vec![build_aggregateable_report(0, 13)],
+            vec![
+                build_aggregateable_report(0, 2),
+                build_aggregateable_report(0, 4),
+            ],
+            vec![
+                build_aggregateable_report(0, 3),
+                build_aggregateable_report(0, 4),
+            ],
+        ];
+
+        let results = group_reports_ordered(reports, 2);
+        assert_eq!(results, expected);
+    }
+
     /// This test checks that the sharded malicious `aggregate_reports` fails
     /// under a simple bit flip attack by H1.
     #[test]
@@ -0,0 +1,370 @@
+use std::{collections::BTreeMap, iter};
+
+use futures::stream;
+use tracing::{info_span, Instrument};
+
+use crate::{
+    error::Error,
+    ff::{
+        boolean::Boolean, boolean_array::BooleanArray, curve_points::RP25519,
+        ec_prime_field::Fp25519, Serializable, U128Conversions,
+    },
+    protocol::{
+        basics::{BooleanProtocols, Reveal},
+        context::{
+            reshard_try_stream, DZKPUpgraded, MacUpgraded, ShardedContext, UpgradableContext,
+        },
+        hybrid::{
+            oprf::{compute_prf, MatchKey, CONV_CHUNK, PRF_CHUNK},
+            step::HybridStep,
+        },
+        ipa_prf::{
+            oprf_padding::{apply_dp_padding, PaddingParameters},
+            prf_eval::PrfSharing,
+            shuffle::{MaliciousShuffleable, ShardedShuffle},
+        },
+        prss::FromPrss,
+    },
+    report::hybrid::{AggregateableHybridReport, MultiKeyHybridReport, PrfMultiKeyHybridReport},
+    secret_sharing::{replicated::semi_honest::AdditiveShare as Replicated, Vectorizable},
+};
+
+/// Runs a single pass of [`cross_device_hybrid_protocol`]: pads and shuffles the reports,
+/// computes the OPRF of their first match key and reshards them by it, then returns the pairs
+/// of reports that share the first match key, and the reports that didn't get paired.
+///
+/// The first match key is used up by this pass. The match keys of unpaired reports are
+/// shifted by one, so that their second match key is the first one for the next pass, and
+/// the last match key becomes absent. Dummy reports added by padding only have a first
+/// match key, so they never get paired in later passes.
+///
+/// ## Errors
+/// If padding, shuffling, PRF evaluation or resharding fails.
+///
+/// [`cross_device_hybrid_protocol`]: crate::protocol::hybrid::cross_device_hybrid_protocol
+#[allow(clippy::type_complexity)]
+pub async fn join_on_first_match_key<C, BK, V, const N: usize, const B: usize>(
+    ctx: C,
+    input_rows: Vec<MultiKeyHybridReport<BK, V, N>>,
+    dp_padding_params: &PaddingParameters,
+) -> Result<
+    (
+        Vec<[AggregateableHybridReport<BK, V>; 2]>,
+        Vec<MultiKeyHybridReport<BK, V, N>>,
+    ),
+    Error,
+>
+where
+    C: UpgradableContext + ShardedShuffle + ShardedContext,
+    BK: BooleanArray + U128Conversions,
+    V: BooleanArray,
+    Replicated<Boolean, CONV_CHUNK>: BooleanProtocols<DZKPUpgraded<C>, CONV_CHUNK>,
+    Replicated<Fp25519, PRF_CHUNK>:
+        PrfSharing<MacUpgraded<C, Fp25519>, PRF_CHUNK, Field = Fp25519> + FromPrss,
+    Replicated<RP25519, PRF_CHUNK>:
+        Reveal<MacUpgraded<C, Fp25519>, Output = <RP25519 as Vectorizable<PRF_CHUNK>>::Array>,
+    MultiKeyHybridReport<BK, V, N>: MaliciousShuffleable,
+    PrfMultiKeyHybridReport<BK, V, N>: Serializable,
+{
+    let padded_input_rows = apply_dp_padding::<_, MultiKeyHybridReport<BK, V, N>, B>(
+        ctx.narrow(&HybridStep::PaddingDp),
+        input_rows,
+        dp_padding_params,
+    )
+    .await?;
+
+    let shuffled_input_rows = ctx
+        .narrow(&HybridStep::InputShuffle)
+        .sharded_shuffle(padded_input_rows)
+        .instrument(info_span!("shuffle_inputs"))
+        .await?;
+
+    // The first key is a sharing of zero, its PRF value identifies absent match keys.
+    let first_match_keys = iter::once(Replicated::<MatchKey>::ZERO)
+        .chain(
+            shuffled_input_rows
+                .iter()
+                .map(|row| row.match_key[0].clone()),
+        )
+        .collect::<Vec<_>>();
+    let prf_of_match_keys = compute_prf(ctx.clone(), &first_match_keys, Clone::clone).await?;
+    drop(first_match_keys);
+    let absent = prf_of_match_keys[0];
+
+    let report_stream = stream::iter(prf_of_match_keys[1..].iter().zip(shuffled_input_rows).map(
+        |(&prf_of_match_key, input)| {
+            let mut match_keys = input.match_key;
+            match_keys.rotate_left(1);
+            match_keys[N - 1] = Replicated::ZERO;
+            Ok(PrfMultiKeyHybridReport {
+                match_key: (prf_of_match_key, match_keys),
+                value: input.value,
+                breakdown_key: input.breakdown_key,
+            })
+        },
+    ));
+
+    // reshard reports based on OPRF values. This ensures at the end of this function
+    // reports with the same value end up on the same shard.
+    let sharded_reports = reshard_try_stream(
+        ctx.narrow(&HybridStep::ReshardByPrf),
+        report_stream,
+        |ctx, _, report| report.match_key.0 % ctx.shard_count(),
+    )
+    .await?;
+
+    Ok(split_report_pairs(sharded_reports, absent))
+}
+
+/// Groups the reports by the OPRF of their first match key and splits them into the pairs
+/// of reports that share it and all the other reports. As with single key reports, only
+/// groups with exactly two reports are paired. Reports with an absent match key are never
+/// paired.
+///
+/// The pairs and the unpaired reports are returned in the same order on all three helpers.
+#[allow(clippy::type_complexity)]
+fn split_report_pairs<BK, V, const N: usize>(
+    reports: Vec<PrfMultiKeyHybridReport<BK, V, N>>,
+    absent: u64,
+) -> (
+    Vec<[AggregateableHybridReport<BK, V>; 2]>,
+    Vec<MultiKeyHybridReport<BK, V, N>>,
+)
+where
+    BK: BooleanArray,
+    V: BooleanArray,
+{
+    let mut reports_by_matchkey: BTreeMap<u64, Vec<MultiKeyHybridReport<BK, V, N>>> =
+        BTreeMap::new();
+    for report in reports {
+        let (prf_of_match_key, match_keys) = report.match_key;
+        reports_by_matchkey
+            .entry(prf_of_match_key)
+            .or_default()
+            .push(MultiKeyHybridReport {
+                match_key: match_keys,
+                value: report.value,
+                breakdown_key: report.breakdown_key,
+            });
+    }
+
+    let mut pairs = Vec::new();
+    let mut unpaired = Vec::new();
+    for (prf_of_match_key, reports) in reports_by_matchkey {
+        match <[_; 2]>::try_from(reports) {
+            Ok(pair) if prf_of_match_key != absent => {
+                pairs.push(pair.map(|report| AggregateableHybridReport {
+                    match_key: (),
+                    value: report.value,
+                    breakdown_key: report.breakdown_key,
+                }));
+            }
+            Ok(pair) => unpaired.extend(pair),
+            Err(reports) => unpaired.extend(reports),
+        }
+    }
+
+    (pairs, unpaired)
+}
+
+#[cfg(all(test, unit_test, feature = "in-memory-infra"))]
+mod tests {
+    use std::time::Duration;
+
+    use ipa_step::StepNarrow;
+
+    use super::split_report_pairs;
+    use crate::{
+        ff::{
+            boolean_array::{BA16, BA3, BA64, BA8},
+            U128Conversions,
+        },
+        helpers::query::DpMechanism,
+        protocol::{
+            hybrid::cross_device_hybrid_protocol,
+            ipa_prf::oprf_padding::PaddingParameters,
+            step::{DeadCodeStep, ProtocolStep},
+            Gate,
+        },
+        report::hybrid::PrfMultiKeyHybridReport,
+        secret_sharing::{
+            replicated::{semi_honest::AdditiveShare as Replicated, ReplicatedSecretSharing},
+            SharedValue,
+        },
+        test_executor::run,
+        test_fixture::{
+            hybrid::TestMultiKeyHybridReport, Reconstruct, Runner, TestWorld, TestWorldConfig,
+            WithShards,
+        },
+    };
+
+    fn prf_report(
+        prf_of_match_key: u64,
+        next_match_key: u8,
+        breakdown_key: u8,
+    ) -> PrfMultiKeyHybridReport<BA8, BA3, 2> {
+        let next_match_key = Replicated::new(BA64::truncate_from(next_match_key), BA64::ZERO);
+        PrfMultiKeyHybridReport {
+            match_key: (prf_of_match_key, [next_match_key, Replicated::ZERO]),
+            value: Replicated::ZERO,
+            breakdown_key: Replicated::new(BA8::truncate_from(breakdown_key), BA8::ZERO),
+        }
+    }
+
+    #[test]
+    fn splits_pairs_from_unpaired_reports() {
+        const ABSENT: u64 = 100;
+        let reports = vec![
+            prf_report(7, 1, 1),
+            prf_report(ABSENT, 2, 2),
+            prf_report(5, 3, 3),
+            prf_report(7, 4, 4),
+            prf_report(ABSENT, 5, 5),
+            prf_report(9, 6, 6),
+            prf_report(9, 7, 7),
+            prf_report(9, 8, 8),
+        ];
+
+        let (pairs, unpaired) = split_report_pairs(reports, ABSENT);
+
+        let breakdown_key = |bk: &Replicated<BA8>| bk.left().as_u128();
+        assert_eq!(
+            vec![[1, 4]],
+            pairs
+                .iter()
+                .map(|pair| pair.each_ref().map(|r| breakdown_key(&r.breakdown_key)))
+                .collect::<Vec<_>>()
+        );
+        // a group with two absent match keys is not a pair
+        assert_eq!(
+            vec![3, 6, 7, 8, 2, 5],
+            unpaired
+                .iter()
+                .map(|r| breakdown_key(&r.breakdown_key))
+                .collect::<Vec<_>>()
+        );
+        // unpaired reports keep their remaining match keys
+        assert!(unpaired
+            .iter()
+            .all(|r| r.match_key[0].left().as_u128() == breakdown_key(&r.breakdown_key)));
+    }
+
+    fn report(match_key: [u64; 2], breakdown_key: u32, value: u32) -> TestMultiKeyHybridReport<2> {
+        TestMultiKeyHybridReport {
+            match_key,
+            value,
+            breakdown_key,
+        }
+    }
+
+    #[test]
+    fn cross_device_attribution() {
+        run(|| async {
+            const SHARDS: usize = 2;
+            let world: TestWorld<WithShards<SHARDS>> = TestWorld::with_shards(TestWorldConfig {
+                initial_gate: Some(
+                    Gate::default()
+                        .narrow(&ProtocolStep::DeadCode)
+                        .narrow(&DeadCodeStep::CrossDeviceHybrid),
+                ),
+                timeout: Some(Duration::from_secs(60)),
+                ..Default::default()
+            });
+
+            let records = vec![
+                // the impression has email and phone, the conversion only has the phone
+                report([100, 200], 3, 0),
+                report([0, 200], 0, 2),
+                // different emails on two devices, joined on the phone
+                report([300, 400], 5, 0),
+                report([350, 400], 0, 4),
+                // joined on the email, the phone is not used again
+                report([600, 700], 7, 0),
+                report([600, 700], 0, 1),
+                report([0, 700], 0, 5),
+                // the phone of one report is the email of the other, these are never joined
+                report([800, 900], 9, 0),
+                report([900, 0], 0, 6),
+                // no impression
+                report([1000, 1100], 0, 6),
+                // no conversion
+                report([0, 1200], 11, 0),
+            ];
+
+            let results = world
+                .malicious(records.into_iter(), |ctx, reports| async move {
+                    cross_device_hybrid_protocol::<_, BA8, BA3, BA16, 2, 3, 256>(
+                        ctx,
+                        reports,
+                        DpMechanism::NoDp,
+                        PaddingParameters::no_padding(),
+                    )
+                    .await
+                    .unwrap()
+                })
+                .await;
+
+            let mut expected = vec![0_u128; 256];
+            expected[3] = 2;
+            expected[5] = 4;
+            expected[7] = 1;
+            assert_eq!(
+                expected,
+                results[0]
+                    .reconstruct()
+                    .iter()
+                    .map(U128Conversions::as_u128)
+                    .collect::<Vec<_>>(),
+            );
+            assert!(results[1].reconstruct().is_empty());
+        });
+    }
+
+    #[test]
+    fn cross_device_attribution_with_padding() {
+        run(|| async {
+            const SHARDS: usize = 2;
+            let world: TestWorld<WithShards<SHARDS>> = TestWorld::with_shards(TestWorldConfig {
+                initial_gate: Some(
+                    Gate::default()
+                        .narrow(&ProtocolStep::DeadCode)
+                        .narrow(&DeadCodeStep::CrossDeviceHybrid),
+                ),
+                timeout: Some(Duration::from_secs(60)),
+                ..Default::default()
+            });
+
+            let records = vec![
+                report([100, 200], 3, 0),
+                report([0, 200], 0, 2),
+                report([600, 700], 7, 0),
+                report([600, 0], 0, 1),
+            ];
+
+            let results = world
+                .semi_honest(records.into_iter(), |ctx, reports| async move {
+                    cross_device_hybrid_protocol::<_, BA8, BA3, BA16, 2, 3, 256>(
+                        ctx,
+                        reports,
+                        DpMechanism::NoDp,
+                        PaddingParameters::relaxed(),
+                    )
+                    .await
+                    .unwrap()
+                })
+                .await;
+
+            let mut expected = vec![0_u128; 256];
+            expected[3] = 2;
+            expected[7] = 1;
+            assert_eq!(
+                expected,
+                results[0]
+                    .reconstruct()
+                    .iter()
+                    .map(U128Conversions::as_u128)
+                    .collect::<Vec<_>>(),
+            );
+        });
+    }
+}
@@ -1,9 +1,12 @@
 pub(crate) mod agg;
 pub(crate) mod breakdown_reveal;
+pub(crate) mod cross_device;
 pub(crate) mod oprf;
+pub(crate) mod reach_frequency;
+pub(crate) mod spill;
 pub(crate) mod step;
 
-use std::{convert::Infallible, ops::Add};
+use std::{convert::Infallible, num::NonZeroUsize, ops::Add};
 
 use generic_array::ArrayLength;
 use tracing::{info_span, Instrument};
@@ -25,26 +28,61 @@ use crate::{
         },
         dp::dp_for_histogram,
         hybrid::{
-            agg::aggregate_reports,
+            agg::{
+                add_report_pairs, aggregate_reports, aggregate_spilled_reports,
+                IN_MEMORY_GROUPING_LIMIT,
+            },
             breakdown_reveal::breakdown_reveal_aggregation,
-            oprf::{compute_prf_and_reshard, BreakdownKey, CONV_CHUNK, PRF_CHUNK},
-            step::{FinalizeSteps, HybridStep as Step},
+            cross_device::join_on_first_match_key,
+            oprf::{
+                compute_prf_and_reshard, compute_prf_and_spill, BreakdownKey, CONV_CHUNK, PRF_CHUNK,
+            },
+            step::{CrossDeviceStep, FinalizeSteps, HybridStep as Step},
         },
         ipa_prf::{
             oprf_padding::{apply_dp_padding, PaddingParameters},
             prf_eval::PrfSharing,
-            shuffle::ShardedShuffle,
+            shuffle::{MaliciousShuffleable, ShardedShuffle},
+            AGG_CHUNK,
         },
         prss::FromPrss,
         BooleanProtocols,
     },
-    report::hybrid::{IndistinguishableHybridReport, PrfHybridReport},
+    report::hybrid::{
+        AggregateableHybridReport, IndistinguishableHybridReport, MultiKeyHybridReport,
+        PrfHybridReport, PrfMultiKeyHybridReport,
+    },
     secret_sharing::{
         replicated::semi_honest::AdditiveShare as Replicated, BitDecomposed, FieldSimd,
         TransposeFrom, Vectorizable,
     },
+    telemetry::trace::phase_span,
 };
 
+/// A two-dimensional histogram with the conversion totals of a lift experiment, split by
+/// the group of the attributed impression.
+///
+/// Conversion lift queries run [`hybrid_protocol`] on reports built with
+/// [`IndistinguishableHybridReport::from_lift_report`], which stores the group of a lift
+/// impression in the most significant bit of its breakdown key. The lower half of the
+/// resulting histogram has the totals for the control group and the upper half the totals
+/// for the test group.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LiftHistogram<T> {
+    pub control: Vec<T>,
+    pub test: Vec<T>,
+}
+
+impl<T> From<Vec<T>> for LiftHistogram<T> {
+    fn from(mut histogram: Vec<T>) -> Self {
+        let test = histogram.split_off(histogram.len() / 2);
+        Self {
+            control: histogram,
+            test,
+        }
+    }
+}
+
 /// The Hybrid Protocol
 ///
 /// This protocol takes in a [`Vec<IndistinguishableHybridReport<BK, V>>`]
@@ -105,6 +143,19 @@ where
         for<'a> TransposeFrom<&'a Vec<Replicated<HV>>, Error = LengthError>,
     Vec<Replicated<HV>>:
         for<'a> TransposeFrom<&'a BitDecomposed<Replicated<Boolean, B>>, Error = LengthError>,
+    Replicated<Boolean, AGG_CHUNK>: BooleanProtocols<DZKPUpgraded<C>, AGG_CHUNK>,
+    BitDecomposed<Replicated<Boolean, AGG_CHUNK>>:
+        for<'a> TransposeFrom<&'a Vec<Replicated<BK>>, Error = LengthError>,
+    BitDecomposed<Replicated<Boolean, AGG_CHUNK>>:
+        for<'a> TransposeFrom<&'a Vec<Replicated<V>>, Error = LengthError>,
+    Vec<Replicated<BK>>: for<'a> TransposeFrom<
+        &'a BitDecomposed<Replicated<Boolean, AGG_CHUNK>>,
+        Error = LengthError,
+    >,
+    Vec<Replicated<V>>: for<'a> TransposeFrom<
+        &'a BitDecomposed<Replicated<Boolean, AGG_CHUNK>>,
+        Error = LengthError,
+    >,
     DZKPUpgraded<C>: ShardedContext,
 {
     if input_rows.is_empty() {
@@ -117,23 +168,207 @@ where
         input_rows,
         &dp_padding_params,
     )
+    .instrument(phase_span("padding"))
     .await?;
 
     let shuffled_input_rows = ctx
         .narrow(&Step::InputShuffle)
         .sharded_shuffle(padded_input_rows)
         .instrument(info_span!("shuffle_inputs"))
+        .instrument(phase_span("shuffle"))
         .await?;
 
-    let sharded_reports = compute_prf_and_reshard(ctx.clone(), shuffled_input_rows).await?;
+    // Shards with too many reports to group them in memory write them to disk while resharding.
+    // All helpers have the same number of rows on a shard, so they all make the same choice.
+    let aggregated_reports = if shuffled_input_rows.len() > IN_MEMORY_GROUPING_LIMIT {
+        let partitions =
+            NonZeroUsize::new(shuffled_input_rows.len().div_ceil(IN_MEMORY_GROUPING_LIMIT))
+                .expect("there is at least one report");
+        let spilled = compute_prf_and_spill(ctx.clone(), shuffled_input_rows, partitions)
+            .instrument(phase_span("prf"))
+            .await?;
+        aggregate_spilled_reports::<BK, V, C>(ctx.clone(), spilled)
+            .instrument(phase_span("aggregate_pairs"))
+            .await?
+    } else {
+        let sharded_reports = compute_prf_and_reshard(ctx.clone(), shuffled_input_rows)
+            .instrument(phase_span("prf"))
+            .await?;
+        aggregate_reports::<BK, V, C>(ctx.clone(), sharded_reports)
+            .instrument(phase_span("aggregate_pairs"))
+            .await?
+    };
 
-    let aggregated_reports = aggregate_reports::<BK, V, C>(ctx.clone(), sharded_reports).await?;
+    reveal_breakdowns_and_finalize::<_, BK, V, HV, SS_BITS, B>(
+        ctx,
+        aggregated_reports,
+        dp_params,
+        &dp_padding_params,
+    )
+    .await
+}
 
+/// The Hybrid Protocol for reports with up to `N` match keys
+///
+/// This is the same as [`hybrid_protocol`], except that every report carries up to `N`
+/// match key candidates of different kinds, for example a hashed email and a hashed phone
+/// number, so that the reports of a user with more than one device can be joined. The
+/// candidates are ordered by priority and reports are joined on the first kind of match key
+/// they share:
+/// 1. Reports are joined on their first match key, exactly like [`hybrid_protocol`] joins
+///    them: reports are padded, shuffled and grouped by the OPRF of that match key. Every
+///    group with exactly two reports is a matched pair.
+/// 2. All the other reports are padded and shuffled again, and joined on the OPRF of their
+///    second match key, computed with a fresh OPRF key.
+/// 3. And so on, one pass for every kind of match key.
+/// 4. The matched pairs of all passes are aggregated, their breakdown keys are revealed and
+///    DP noise is added as in [`hybrid_protocol`].
+///
+/// Match keys are never linked in the clear. Every pass reveals the OPRF of a single match
+/// key per report, and because reports are shuffled before every pass and every pass uses its
+/// own OPRF key, the OPRF values revealed in different passes can't be attributed to the same
+/// report. Each pass reveals the sizes of its groups, which are protected by DP padding the
+/// same way as they are in [`hybrid_protocol`].
+///
+/// A match key that is a sharing of zero is absent. Reports are never joined on an absent
+/// match key, and the number of reports without a match key of each kind is revealed.
+/// Reports only match on the same kind of match key, a phone number is never joined with
+/// an email.
+///
+/// # Errors
+/// Propagates errors from config issues or while running the protocol
+/// # Panics
+/// Propagates errors from config issues or while running the protocol
+pub async fn cross_device_hybrid_protocol<
+    'ctx,
+    C,
+    BK,
+    V,
+    HV,
+    const N: usize,
+    const SS_BITS: usize,
+    const B: usize,
+>(
+    ctx: C,
+    input_rows: Vec<MultiKeyHybridReport<BK, V, N>>,
+    dp_params: DpMechanism,
+    dp_padding_params: PaddingParameters,
+) -> Result<Vec<Replicated<HV>>, Error>
+where
+    C: UpgradableContext
+        + 'ctx
+        + ShardedShuffle
+        + ShardedContext
+        + FinalizerContext<FinalizingContext = DZKPUpgraded<C>>,
+    BK: BreakdownKey<B>,
+    V: BooleanArray + U128Conversions,
+    HV: BooleanArray + U128Conversions,
+    <HV as Serializable>::Size: Add<<HV as Serializable>::Size, Output: ArrayLength>,
+    Boolean: FieldSimd<B>,
+    Replicated<Boolean, CONV_CHUNK>: BooleanProtocols<DZKPUpgraded<C>, CONV_CHUNK>,
+    Replicated<Fp25519, PRF_CHUNK>:
+        PrfSharing<MacUpgraded<C, Fp25519>, PRF_CHUNK, Field = Fp25519> + FromPrss,
+    Replicated<RP25519, PRF_CHUNK>:
+        Reveal<MacUpgraded<C, Fp25519>, Output = <RP25519 as Vectorizable<PRF_CHUNK>>::Array>,
+    PrfHybridReport<BK, V>: Serializable,
+    Replicated<Boolean>: BooleanProtocols<DZKPUpgraded<C>>,
+    Replicated<Boolean, B>: BooleanProtocols<DZKPUpgraded<C>, B>,
+    Replicated<BK>: BooleanArrayMul<DZKPUpgraded<C>>
+        + Reveal<DZKPUpgraded<C>, Output = <BK as Vectorizable<1>>::Array>,
+    BitDecomposed<Replicated<Boolean, B>>:
+        for<'a> TransposeFrom<&'a [Replicated<V>; B], Error = Infallible>,
+    BitDecomposed<Replicated<Boolean, B>>:
+        for<'a> TransposeFrom<&'a [Replicated<HV>; B], Error = Infallible>,
+    BitDecomposed<Replicated<Boolean, B>>:
+        for<'a> TransposeFrom<&'a Vec<Replicated<HV>>, Error = LengthError>,
+    Vec<Replicated<HV>>:
+        for<'a> TransposeFrom<&'a BitDecomposed<Replicated<Boolean, B>>, Error = LengthError>,
+    Replicated<Boolean, AGG_CHUNK>: BooleanProtocols<DZKPUpgraded<C>, AGG_CHUNK>,
+    BitDecomposed<Replicated<Boolean, AGG_CHUNK>>:
+        for<'a> TransposeFrom<&'a Vec<Replicated<BK>>, Error = LengthError>,
+    BitDecomposed<Replicated<Boolean, AGG_CHUNK>>:
+        for<'a> TransposeFrom<&'a Vec<Replicated<V>>, Error = LengthError>,
+    Vec<Replicated<BK>>: for<'a> TransposeFrom<
+        &'a BitDecomposed<Replicated<Boolean, AGG_CHUNK>>,
+        Error = LengthError,
+    >,
+    Vec<Replicated<V>>: for<'a> TransposeFrom<
+        &'a BitDecomposed<Replicated<Boolean, AGG_CHUNK>>,
+        Error = LengthError,
+    >,
+    MultiKeyHybridReport<BK, V, N>: MaliciousShuffleable,
+    PrfMultiKeyHybridReport<BK, V, N>: Serializable,
+    DZKPUpgraded<C>: ShardedContext,
+{
+    if input_rows.is_empty() {
+        return Ok(vec![Replicated::ZERO; B]);
+    }
+
+    let mut unmatched_rows = input_rows;
+    let mut report_pairs = Vec::new();
+    for pass in 0..N {
+        let (pairs, unmatched) = join_on_first_match_key::<_, BK, V, N, B>(
+            ctx.narrow(&CrossDeviceStep::MatchKeyPass(pass)),
+            unmatched_rows,
+            &dp_padding_params,
+        )
+        .instrument(info_span!("match_key_pass", pass))
+        .await?;
+        report_pairs.extend(pairs);
+        unmatched_rows = unmatched;
+    }
+    drop(unmatched_rows);
+
+    let ctx = ctx.narrow(&CrossDeviceStep::Aggregate);
+    let aggregated_reports = add_report_pairs::<BK, V, C>(ctx.clone(), report_pairs).await?;
+
+    reveal_breakdowns_and_finalize::<_, BK, V, HV, SS_BITS, B>(
+        ctx,
+        aggregated_reports,
+        dp_params,
+        &dp_padding_params,
+    )
+    .await
+}
+
+/// Reveals the breakdown keys of the aggregated reports, sums the values by breakdown key
+/// across all shards and adds DP noise to the histogram on the leader shard.
+async fn reveal_breakdowns_and_finalize<'ctx, C, BK, V, HV, const SS_BITS: usize, const B: usize>(
+    ctx: C,
+    aggregated_reports: Vec<AggregateableHybridReport<BK, V>>,
+    dp_params: DpMechanism,
+    dp_padding_params: &PaddingParameters,
+) -> Result<Vec<Replicated<HV>>, Error>
+where
+    C: UpgradableContext
+        + 'ctx
+        + ShardedShuffle
+        + ShardedContext
+        + FinalizerContext<FinalizingContext = DZKPUpgraded<C>>,
+    BK: BreakdownKey<B>,
+    V: BooleanArray + U128Conversions,
+    HV: BooleanArray + U128Conversions,
+    <HV as Serializable>::Size: Add<<HV as Serializable>::Size, Output: ArrayLength>,
+    Boolean: FieldSimd<B>,
+    Replicated<Boolean>: BooleanProtocols<DZKPUpgraded<C>>,
+    Replicated<Boolean, B>: BooleanProtocols<DZKPUpgraded<C>, B>,
+    Replicated<BK>: Reveal<DZKPUpgraded<C>, Output = <BK as Vectorizable<1>>::Array>,
+    BitDecomposed<Replicated<Boolean, B>>:
+        for<'a> TransposeFrom<&'a [Replicated<V>; B], Error = Infallible>,
+    BitDecomposed<Replicated<Boolean, B>>:
+        for<'a> TransposeFrom<&'a [Replicated<HV>; B], Error = Infallible>,
+    BitDecomposed<Replicated<Boolean, B>>:
+        for<'a> TransposeFrom<&'a Vec<Replicated<HV>>, Error = LengthError>,
+    Vec<Replicated<HV>>:
+        for<'a> TransposeFrom<&'a BitDecomposed<Replicated<Boolean, B>>, Error = LengthError>,
+    DZKPUpgraded<C>: ShardedContext,
+{
     let histogram = breakdown_reveal_aggregation::<C, BK, V, HV, B>(
         ctx.narrow(&Step::Aggregate),
         aggregated_reports,
-        &dp_padding_params,
+        dp_padding_params,
     )
+    .instrument(phase_span("breakdown_reveal"))
     .await?;
 
     let histogram: Histogram<HV, B> = Histogram::from(histogram);
@@ -147,10 +382,13 @@ where
             },
             histogram,
         )
+        .instrument(phase_span("finalize"))
         .await?;
 
     let noisy_histogram = if ctx.is_leader() {
-        dp_for_histogram::<_, B, HV, SS_BITS>(ctx, finalized_histogram.values, dp_params).await?
+        dp_for_histogram::<_, B, HV, SS_BITS>(ctx, finalized_histogram.values, dp_params)
+            .instrument(phase_span("dp"))
+            .await?
     } else {
         finalized_histogram.compose()
     };
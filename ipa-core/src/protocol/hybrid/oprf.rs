@@ -1,6 +1,6 @@
-use std::cmp::max;
+use std::{cmp::max, num::NonZeroUsize};
 
-use futures::{stream, StreamExt, TryStreamExt};
+use futures::{stream, Stream, StreamExt, TryStreamExt};
 use typenum::Const;
 
 use crate::{
@@ -20,14 +20,14 @@ use crate::{
         basics::{BooleanProtocols, Reveal},
         context::{
             dzkp_validator::{DZKPValidator, TARGET_PROOF_SIZE},
-            reshard_try_stream, DZKPUpgraded, MacUpgraded, MaliciousProtocolSteps, ShardedContext,
-            ShardedUpgradedMaliciousContext, UpgradableContext, UpgradedMaliciousContext,
-            Validator,
+            reshard_try_stream, reshard_try_stream_for_each, DZKPUpgraded, MacUpgraded,
+            MaliciousProtocolSteps, ShardedContext, ShardedUpgradedMaliciousContext,
+            UpgradableContext, UpgradedMaliciousContext, Validator,
         },
-        hybrid::step::HybridStep,
+        hybrid::{spill::SpilledReports, step::HybridStep},
         ipa_prf::{
             boolean_ops::convert_to_fp25519,
-            prf_eval::{eval_dy_prf, PrfSharing},
+            prf_eval::{collect_prfs, eval_dy_prf, PrfSharing},
         },
         prss::{FromPrss, SharedRandomness},
         BasicProtocols, RecordId,
@@ -115,9 +115,114 @@ where
         Reveal<MacUpgraded<C, Fp25519>, Output = <RP25519 as Vectorizable<PRF_CHUNK>>::Array>,
     PrfHybridReport<BK, V>: Serializable,
 {
-    let conv_records =
-        TotalRecords::specified(div_round_up(input_rows.len(), Const::<CONV_CHUNK>))?;
-    let eval_records = TotalRecords::specified(div_round_up(input_rows.len(), Const::<PRF_CHUNK>))?;
+    let report_stream = compute_prf_reports(ctx.clone(), input_rows).await?;
+
+    // reshard reports based on OPRF values. This ensures at the end of this function
+    // reports with the same value end up on the same shard.
+    reshard_try_stream(
+        ctx.narrow(&HybridStep::ReshardByPrf),
+        report_stream,
+        |ctx, _, report| report.match_key % ctx.shard_count(),
+    )
+    .await
+}
+
+/// Same as [`compute_prf_and_reshard`], except that the reports that end up on this shard are
+/// written to disk as soon as they arrive, range partitioned by their PRF into `partitions`
+/// files. This keeps inputs that are too large to be grouped in memory out of memory
+/// altogether, see [`SpilledReports`].
+///
+/// ## Errors
+/// If the PRF evaluation or resharding fails, or if reports cannot be written to disk.
+pub async fn compute_prf_and_spill<C, BK, V>(
+    ctx: C,
+    input_rows: Vec<IndistinguishableHybridReport<BK, V>>,
+    partitions: NonZeroUsize,
+) -> Result<SpilledReports<BK, V>, Error>
+where
+    C: UpgradableContext + ShardedContext,
+    BK: BooleanArray,
+    V: BooleanArray,
+    Replicated<Boolean, CONV_CHUNK>: BooleanProtocols<DZKPUpgraded<C>, CONV_CHUNK>,
+    Replicated<Fp25519, PRF_CHUNK>:
+        PrfSharing<MacUpgraded<C, Fp25519>, PRF_CHUNK, Field = Fp25519> + FromPrss,
+    Replicated<RP25519, PRF_CHUNK>:
+        Reveal<MacUpgraded<C, Fp25519>, Output = <RP25519 as Vectorizable<PRF_CHUNK>>::Array>,
+    PrfHybridReport<BK, V>: Serializable,
+{
+    let report_stream = compute_prf_reports(ctx.clone(), input_rows).await?;
+
+    let mut spilled = SpilledReports::new(partitions)?;
+    reshard_try_stream_for_each(
+        ctx.narrow(&HybridStep::ReshardByPrf),
+        report_stream,
+        |ctx, _, report| report.match_key % ctx.shard_count(),
+        |source, report| Ok(spilled.push(source, &report)?),
+    )
+    .await?;
+    tracing::info!(
+        "spilled {} reports to disk in {partitions} partitions",
+        spilled.len()
+    );
+
+    Ok(spilled)
+}
+
+/// Computes the PRF of the match key of every input row, and returns the rows with their
+/// match key replaced by its PRF.
+async fn compute_prf_reports<C, BK, V>(
+    ctx: C,
+    input_rows: Vec<IndistinguishableHybridReport<BK, V>>,
+) -> Result<impl Stream<Item = Result<PrfHybridReport<BK, V>, Error>>, Error>
+where
+    C: UpgradableContext + ShardedContext,
+    BK: BooleanArray,
+    V: BooleanArray,
+    Replicated<Boolean, CONV_CHUNK>: BooleanProtocols<DZKPUpgraded<C>, CONV_CHUNK>,
+    Replicated<Fp25519, PRF_CHUNK>:
+        PrfSharing<MacUpgraded<C, Fp25519>, PRF_CHUNK, Field = Fp25519> + FromPrss,
+    Replicated<RP25519, PRF_CHUNK>:
+        Reveal<MacUpgraded<C, Fp25519>, Output = <RP25519 as Vectorizable<PRF_CHUNK>>::Array>,
+    PrfHybridReport<BK, V>: Serializable,
+{
+    let prf_of_match_keys =
+        compute_prf(ctx.clone(), &input_rows, |row| row.match_key.clone()).await?;
+
+    Ok(stream::iter(
+        prf_of_match_keys
+            .into_iter()
+            .zip(input_rows)
+            .map(|(prf_of_match_key, input)| {
+                Ok(PrfHybridReport {
+                    match_key: prf_of_match_key,
+                    value: input.value,
+                    breakdown_key: input.breakdown_key,
+                })
+            }),
+    ))
+}
+
+/// This computes the Dodis-Yampolsky PRF value on the match key of every input row
+/// and reveals it. The output has the PRF values in the order of `input`.
+///
+/// All PRF values computed with the same context use the same PRF key, so this function
+/// must be called only once per context. Narrow the context to get an independent PRF key.
+///
+/// ## Errors
+/// If the conversion or evaluation of the PRF fails, including failed validation.
+pub async fn compute_prf<C, T, F>(ctx: C, input: &[T], match_key: F) -> Result<Vec<u64>, Error>
+where
+    C: UpgradableContext + ShardedContext,
+    T: Clone + Default + Sync,
+    F: Fn(&T) -> Replicated<MatchKey> + Sync,
+    Replicated<Boolean, CONV_CHUNK>: BooleanProtocols<DZKPUpgraded<C>, CONV_CHUNK>,
+    Replicated<Fp25519, PRF_CHUNK>:
+        PrfSharing<MacUpgraded<C, Fp25519>, PRF_CHUNK, Field = Fp25519> + FromPrss,
+    Replicated<RP25519, PRF_CHUNK>:
+        Reveal<MacUpgraded<C, Fp25519>, Output = <RP25519 as Vectorizable<PRF_CHUNK>>::Array>,
+{
+    let conv_records = TotalRecords::specified(div_round_up(input.len(), Const::<CONV_CHUNK>))?;
+    let eval_records = TotalRecords::specified(div_round_up(input.len(), Const::<PRF_CHUNK>))?;
     let convert_ctx = ctx.set_total_records(conv_records);
 
     let validator = convert_ctx.dzkp_validator(
@@ -128,21 +233,19 @@ where
         conv_proof_chunk(),
     );
     let m_ctx = validator.context();
+    let match_key = &match_key;
 
     let curve_pts = seq_join(
         m_ctx.active_work(),
-        process_slice_by_chunks(
-            &input_rows,
-            move |idx, records: ChunkData<_, CONV_CHUNK>| {
-                let record_id = RecordId::from(idx);
-                let input_match_keys: &dyn Fn(usize) -> Replicated<MatchKey> =
-                    &|i| records[i].match_key.clone();
-                let match_keys =
-                    BitDecomposed::<Replicated<Boolean, 256>>::transposed_from(input_match_keys)
-                        .unwrap_infallible();
-                convert_to_fp25519::<_, CONV_CHUNK, PRF_CHUNK>(m_ctx.clone(), record_id, match_keys)
-            },
-        ),
+        process_slice_by_chunks(input, move |idx, records: ChunkData<_, CONV_CHUNK>| {
+            let record_id = RecordId::from(idx);
+            let input_match_keys: &dyn Fn(usize) -> Replicated<MatchKey> =
+                &|i| match_key(&records[i]);
+            let match_keys =
+                BitDecomposed::<Replicated<Boolean, 256>>::transposed_from(input_match_keys)
+                    .unwrap_infallible();
+            convert_to_fp25519::<_, CONV_CHUNK, PRF_CHUNK>(m_ctx.clone(), record_id, match_keys)
+        }),
     )
     .map_ok(Chunk::unpack::<PRF_CHUNK>)
     .try_flatten_iters()
@@ -157,36 +260,22 @@ where
         .validator::<Fp25519>();
     let eval_ctx = validator.context();
 
-    let prf_of_match_keys = seq_join(
-        ctx.active_work(),
-        stream::iter(curve_pts).enumerate().map(|(i, curve_pts)| {
-            let record_id = RecordId::from(i);
-            let eval_ctx = eval_ctx.clone();
-            let prf_key = &prf_key;
-            curve_pts
-                .then(move |pts| eval_dy_prf::<_, PRF_CHUNK>(eval_ctx, record_id, prf_key, pts))
-        }),
+    let prfs = collect_prfs(
+        eval_ctx.clone(),
+        seq_join(
+            ctx.active_work(),
+            stream::iter(curve_pts).enumerate().map(|(i, curve_pts)| {
+                let record_id = RecordId::from(i);
+                let eval_ctx = eval_ctx.clone();
+                let prf_key = &prf_key;
+                curve_pts
+                    .then(move |pts| eval_dy_prf::<_, PRF_CHUNK>(eval_ctx, record_id, prf_key, pts))
+            }),
+        ),
     )
-    .try_flatten_iters();
-
-    let report_stream = prf_of_match_keys
-        .zip(stream::iter(input_rows))
-        // map from (Result<X>, T) to Result<(X, T)>
-        .map(|(mk, input)| mk.map(|mk| (mk, input)))
-        .map_ok(|(prf_of_match_key, input)| PrfHybridReport {
-            match_key: prf_of_match_key,
-            value: input.value,
-            breakdown_key: input.breakdown_key,
-        });
+    .await?;
 
-    // reshard reports based on OPRF values. This ensures at the end of this function
-    // reports with the same value end up on the same shard.
-    reshard_try_stream(
-        ctx.narrow(&HybridStep::ReshardByPrf),
-        report_stream,
-        |ctx, _, report| report.match_key % ctx.shard_count(),
-    )
-    .await
+    Ok(prfs.into_iter().flatten().take(input.len()).collect())
 }
 
 /// generates PRF key k as secret sharing over Fp25519
@@ -210,13 +299,63 @@ mod test {
     use ipa_step::StepNarrow;
 
     use crate::{
+        error::Error,
         ff::boolean_array::{BA3, BA8},
-        protocol::{hybrid::oprf::compute_prf_and_reshard, step::ProtocolStep, Gate},
+        helpers::{in_memory_config::MaliciousHelper, Role},
+        protocol::{
+            context::step::MaliciousProtocolStep, hybrid::oprf::compute_prf_and_reshard,
+            ipa_prf::step::PrfStep, step::ProtocolStep, Gate,
+        },
         report::hybrid::{IndistinguishableHybridReport, PrfHybridReport},
         test_executor::run,
         test_fixture::{hybrid::TestHybridRecord, Runner, TestWorld, TestWorldConfig, WithShards},
     };
 
+    /// H3 sends an inconsistent share of `z` to H1 only. H2 sees a consistent reveal, but must
+    /// abort with H1 instead of moving on to resharding.
+    #[test]
+    fn abort_on_reveal_failure() {
+        run(|| async {
+            let mut config = TestWorldConfig {
+                initial_gate: Some(Gate::default().narrow(&ProtocolStep::Hybrid)),
+                timeout: Some(Duration::from_secs(60)),
+                ..Default::default()
+            };
+            let step = format!(
+                "{}/{}",
+                MaliciousProtocolStep::MaliciousProtocol.as_ref(),
+                PrfStep::Revealz.as_ref()
+            );
+            config.stream_interceptor =
+                MaliciousHelper::new(Role::H3, config.role_assignment(), move |ctx, data| {
+                    if ctx.gate.as_ref().contains(&step) && ctx.dest == Role::H1 {
+                        data[0] ^= 1;
+                    }
+                });
+            let world: TestWorld<WithShards<1>> = TestWorld::with_shards(config);
+
+            let records = (0..10).map(|i| TestHybridRecord::TestImpression {
+                match_key: 1000 + i,
+                breakdown_key: 1,
+                key_id: 0,
+            });
+
+            let results = world
+                .malicious(records, |ctx, reports| async move {
+                    let ind_reports = reports
+                        .into_iter()
+                        .map(IndistinguishableHybridReport::<BA8, BA3>::from)
+                        .collect();
+                    compute_prf_and_reshard(ctx, ind_reports).await
+                })
+                .await;
+
+            for result in results.into_iter().flatten() {
+                assert!(matches!(result, Err(Error::MaliciousRevealFailed)));
+            }
+        });
+    }
+
     #[test]
     #[allow(clippy::too_many_lines)]
     fn hybrid_oprf() {
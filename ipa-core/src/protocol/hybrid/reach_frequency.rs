@@ -0,0 +1,397 @@
+//! Reach and frequency measurement.
+//!
+//! For every breakdown key, this protocol counts the number of distinct users that were
+//! exposed to it (reach), along with how many impressions each of those users saw
+//! (frequency). The output is a histogram with [`FREQUENCY_BUCKETS`] buckets per breakdown key:
+//! bucket `f` counts the users who saw exactly `f + 1` impressions, except for the last one
+//! which counts users with [`FREQUENCY_BUCKETS`] or more impressions. Reach is the sum of all
+//! the buckets for a breakdown key.
+//!
+//! The protocol re-uses the first half of the hybrid protocol to compute an OPRF of every
+//! match key and to bring all the reports of a user to the same shard. From there:
+//! 1. Reports are grouped by user (see [`group_reports_ordered`]).
+//! 2. For every report, the breakdown key is compared against the breakdown keys of all the
+//!    other reports of the same user. This gives a one-hot encoding of its frequency bucket, which
+//!    is zeroed unless the report is the first one for its breakdown key, so each user is
+//!    counted once per breakdown key.
+//! 3. Frequency buckets are summed by breakdown key and DP noise is added (see
+//!    [`group_by_sum_with_dp`]).
+//!
+//! Revealing the OPRF of match keys leaks the number of reports for every user, which is
+//! protected by the same DP padding the hybrid protocol uses. No other information is
+//! revealed beyond the breakdown key of each report, after it has been shuffled.
+use std::{collections::BTreeMap, convert::Infallible, iter::zip, ops::Add};
+
+use futures::{stream, StreamExt, TryStreamExt};
+use generic_array::ArrayLength;
+use tracing::{info_span, Instrument};
+
+use crate::{
+    error::{Error, LengthError},
+    ff::{
+        boolean::Boolean,
+        boolean_array::{BooleanArray, BA3, BA8},
+        curve_points::RP25519,
+        ec_prime_field::Fp25519,
+        ArrayAccess, Serializable, U128Conversions,
+    },
+    helpers::{query::DpMechanism, TotalRecords},
+    protocol::{
+        basics::{select, shard_fin::FinalizerContext, BooleanArrayMul, Reveal, ShareKnownValue},
+        boolean::{or::or, step::EightBitStep, NBitStep},
+        context::{
+            dzkp_validator::{validated_seq_join, DZKPValidator, TARGET_PROOF_SIZE},
+            Context, DZKPUpgraded, MacUpgraded, MaliciousProtocolSteps, ShardedContext,
+            UpgradableContext,
+        },
+        group_by::{group_by_sum_with_dp, GroupByInputRow},
+        hybrid::{
+            agg::group_reports_ordered,
+            oprf::{compute_prf_and_reshard, BreakdownKey, CONV_CHUNK, PRF_CHUNK},
+            step::{FrequencyStep, HybridStep as Step, UserFrequencyStep},
+        },
+        ipa_prf::{
+            oprf_padding::{apply_dp_padding, PaddingParameters},
+            prf_eval::PrfSharing,
+            shuffle::ShardedShuffle,
+        },
+        prss::FromPrss,
+        BooleanProtocols, RecordId,
+    },
+    report::hybrid::{AggregateableHybridReport, IndistinguishableHybridReport, PrfHybridReport},
+    secret_sharing::{
+        replicated::{semi_honest::AdditiveShare as Replicated, ReplicatedSecretSharing},
+        BitDecomposed, FieldSimd, SharedValue, TransposeFrom, Vectorizable,
+    },
+    utils::non_zero_prev_power_of_two,
+};
+
+/// The maximum number of reports considered for a single user. Any reports beyond this
+/// limit are dropped, which bounds the contribution of a user to the histogram. Which reports
+/// are kept is arbitrary, as reports are shuffled before they are grouped by user.
+///
+/// The query is not rejected when a user has more reports: that would reveal to the report
+/// collector that such a user exists, which is not protected by DP.
+///
+/// The step counts in [`FrequencyStep`] and [`UserFrequencyStep`] depend on this value.
+pub const MAX_USER_REPORTS: usize = 8;
+
+/// The number of frequency buckets reported for every breakdown key. The last bucket
+/// counts users with this many impressions or more.
+pub const FREQUENCY_BUCKETS: usize = MAX_USER_REPORTS;
+
+/// One-hot encoding of a frequency bucket: bit `f` is set if the user saw `f + 1` impressions.
+type FrequencyBuckets = BA8;
+
+/// The input to aggregation: one row per report, holding a one-hot encoding of the frequency
+/// bucket that the report contributes to.
+pub type FrequencyRecord<BK> = GroupByInputRow<BK, BA3, FREQUENCY_BUCKETS>;
+
+/// The Reach and Frequency Protocol
+///
+/// This protocol takes in a [`Vec<IndistinguishableHybridReport<BK, V>>`] of impressions and
+/// computes a frequency histogram for every breakdown key. See the [module docs](self) for
+/// an overview.
+///
+/// The result has [`FREQUENCY_BUCKETS`] values for each of the `B` breakdown keys, with all the
+/// buckets of a breakdown key next to each other. Only the leader shard returns the
+/// result, other shards return an empty vector.
+///
+/// *Note*: At most [`MAX_USER_REPORTS`] reports are counted for every user, the rest are
+/// silently dropped.
+///
+/// *Note*: Values of the input reports are ignored. They are set to 1 before padding, to tell
+/// real reports apart from the dummies added by DP padding, which have a value of 0 and must
+/// not be counted. Conversion reports are indistinguishable
+/// from impressions and have breakdown key 0, so an honest report collector must only submit
+/// impressions to this query.
+///
+/// # Errors
+/// Propagates errors from config issues or while running the protocol
+/// # Panics
+/// If `BK` is wider than 8 bits.
+pub async fn reach_frequency_protocol<'ctx, C, BK, V, HV, const SS_BITS: usize, const B: usize>(
+    ctx: C,
+    input_rows: Vec<IndistinguishableHybridReport<BK, V>>,
+    dp_params: DpMechanism,
+    dp_padding_params: PaddingParameters,
+) -> Result<Vec<Replicated<HV>>, Error>
+where
+    C: UpgradableContext
+        + 'ctx
+        + ShardedShuffle
+        + ShardedContext
+        + FinalizerContext<FinalizingContext = DZKPUpgraded<C>>,
+    BK: BreakdownKey<B>,
+    V: BooleanArray + U128Conversions,
+    HV: BooleanArray + U128Conversions,
+    <HV as Serializable>::Size: Add<<HV as Serializable>::Size, Output: ArrayLength>,
+    Boolean: FieldSimd<B>,
+    Replicated<Boolean, CONV_CHUNK>: BooleanProtocols<DZKPUpgraded<C>, CONV_CHUNK>,
+    Replicated<Fp25519, PRF_CHUNK>:
+        PrfSharing<MacUpgraded<C, Fp25519>, PRF_CHUNK, Field = Fp25519> + FromPrss,
+    Replicated<RP25519, PRF_CHUNK>:
+        Reveal<MacUpgraded<C, Fp25519>, Output = <RP25519 as Vectorizable<PRF_CHUNK>>::Array>,
+    PrfHybridReport<BK, V>: Serializable,
+    Replicated<Boolean>: BooleanProtocols<DZKPUpgraded<C>>,
+    Replicated<Boolean, B>: BooleanProtocols<DZKPUpgraded<C>, B>,
+    Replicated<BK>: Reveal<DZKPUpgraded<C>, Output = <BK as Vectorizable<1>>::Array>,
+    Replicated<FrequencyBuckets>: BooleanArrayMul<DZKPUpgraded<C>>,
+    BitDecomposed<Replicated<Boolean, B>>: FromPrss<usize>,
+    BitDecomposed<Replicated<Boolean, B>>:
+        for<'a> TransposeFrom<&'a [Replicated<BA3>; B], Error = Infallible>,
+    BitDecomposed<Replicated<Boolean, B>>:
+        for<'a> TransposeFrom<&'a [Replicated<HV>; B], Error = Infallible>,
+    BitDecomposed<Replicated<Boolean, B>>:
+        for<'a> TransposeFrom<&'a Vec<Replicated<HV>>, Error = LengthError>,
+    Vec<Replicated<HV>>:
+        for<'a> TransposeFrom<&'a BitDecomposed<Replicated<Boolean, B>>, Error = LengthError>,
+    DZKPUpgraded<C>: ShardedContext,
+{
+    if input_rows.is_empty() {
+        return Ok(vec![Replicated::ZERO; B * FREQUENCY_BUCKETS]);
+    }
+
+    let real_report = Replicated::share_known_value(&ctx, V::truncate_from(1_u128));
+    let input_rows = input_rows
+        .into_iter()
+        .map(|row| IndistinguishableHybridReport {
+            value: real_report.clone(),
+            ..row
+        })
+        .collect();
+
+    // Apply DP padding for OPRF
+    let padded_input_rows = apply_dp_padding::<_, IndistinguishableHybridReport<BK, V>, B>(
+        ctx.narrow(&Step::PaddingDp),
+        input_rows,
+        &dp_padding_params,
+    )
+    .await?;
+
+    let shuffled_input_rows = ctx
+        .narrow(&Step::InputShuffle)
+        .sharded_shuffle(padded_input_rows)
+        .instrument(info_span!("shuffle_inputs"))
+        .await?;
+
+    let sharded_reports = compute_prf_and_reshard(ctx.clone(), shuffled_input_rows).await?;
+
+    let frequency_records =
+        count_frequencies::<_, BK, V>(ctx.narrow(&Step::CountFrequency), sharded_reports).await?;
+
+    let histograms = group_by_sum_with_dp::<_, FrequencyRecord<BK>, HV, SS_BITS, B>(
+        ctx.narrow(&Step::AggregateWithDp),
+        frequency_records,
+        &dp_padding_params,
+        dp_params,
+    )
+    .await?;
+
+    // Histograms are per frequency bucket, the result is laid out per breakdown key.
+    let breakdowns = histograms.first().map_or(0, Vec::len);
+    Ok((0..breakdowns)
+        .flat_map(|breakdown_key| {
+            histograms
+                .iter()
+                .map(move |histogram| histogram[breakdown_key].clone())
+        })
+        .collect())
+}
+
+/// Groups reports by user and computes the frequency bucket for each of them. Users are
+/// processed in batches by the number of reports they have.
+async fn count_frequencies<C, BK, V>(
+    ctx: C,
+    reports: Vec<PrfHybridReport<BK, V>>,
+) -> Result<Vec<FrequencyRecord<BK>>, Error>
+where
+    C: UpgradableContext,
+    BK: BooleanArray,
+    V: BooleanArray + U128Conversions,
+    Replicated<Boolean>: BooleanProtocols<DZKPUpgraded<C>>,
+    Replicated<FrequencyBuckets>: BooleanArrayMul<DZKPUpgraded<C>>,
+{
+    assert!(
+        BK::BITS <= EightBitStep::BITS,
+        "Up to {max_bits} bit breakdown keys are supported, but was given {bits} bits",
+        max_bits = EightBitStep::BITS,
+        bits = BK::BITS,
+    );
+
+    let mut users_by_report_count =
+        BTreeMap::<usize, Vec<Vec<AggregateableHybridReport<BK, V>>>>::new();
+    for user_reports in group_reports_ordered(reports, MAX_USER_REPORTS) {
+        users_by_report_count
+            .entry(user_reports.len())
+            .or_default()
+            .push(user_reports);
+    }
+
+    let mut records = Vec::new();
+    for (report_count, users) in users_by_report_count {
+        if report_count == 1 {
+            // The only report of a user is always the first one for its breakdown key.
+            records.extend(users.into_iter().flatten().map(|report| {
+                let buckets = first_bucket(&report.value);
+                frequency_record(report.breakdown_key, &buckets)
+            }));
+            continue;
+        }
+
+        let pairs = report_count * (report_count - 1);
+        let multiplications =
+            pairs / 2 * (BK::BITS as usize - 1) + pairs * FrequencyBuckets::BITS as usize;
+        let chunk_size = non_zero_prev_power_of_two(TARGET_PROOF_SIZE / multiplications);
+
+        let ctx = ctx.set_total_records(TotalRecords::specified(users.len())?);
+        let dzkp_validator = ctx.dzkp_validator(
+            MaliciousProtocolSteps {
+                protocol: &FrequencyStep::Users(report_count),
+                validate: &FrequencyStep::UsersValidate(report_count),
+            },
+            chunk_size,
+        );
+        let user_ctx = dzkp_validator.context();
+
+        let work = stream::iter(users)
+            .enumerate()
+            .map(|(idx, reports)| user_frequencies(user_ctx.clone(), RecordId::from(idx), reports));
+        let user_records: Vec<_> = validated_seq_join(dzkp_validator, work)
+            .try_collect()
+            .await?;
+        records.extend(user_records.into_iter().flatten());
+    }
+
+    Ok(records)
+}
+
+/// Computes the frequency bucket of every report of a single user.
+///
+/// A report starts in the first bucket (or in none, for padding dummies) and is moved up one bucket for every later report with
+/// the same breakdown key. If an earlier report has the same breakdown key, it is that one
+/// that represents the user, and this report is dropped by zeroing all of its buckets.
+async fn user_frequencies<C, BK, V>(
+    ctx: C,
+    record_id: RecordId,
+    reports: Vec<AggregateableHybridReport<BK, V>>,
+) -> Result<Vec<FrequencyRecord<BK>>, Error>
+where
+    C: Context,
+    BK: BooleanArray,
+    V: BooleanArray + U128Conversions,
+    Replicated<Boolean>: BooleanProtocols<C>,
+    Replicated<FrequencyBuckets>: BooleanArrayMul<C>,
+{
+    let n = reports.len();
+    let pairs = (0..n)
+        .flat_map(|i| (i + 1..n).map(move |j| (i, j)))
+        .collect::<Vec<_>>();
+    let equal = ctx
+        .parallel_join(pairs.iter().enumerate().map(|(pair, &(i, j))| {
+            breakdown_keys_equal(
+                ctx.narrow(&UserFrequencyStep::Compare(pair)),
+                record_id,
+                &reports[i].breakdown_key,
+                &reports[j].breakdown_key,
+            )
+        }))
+        .await?;
+
+    let mut same_key = vec![vec![None; n]; n];
+    for (&(i, j), is_equal) in zip(&pairs, equal) {
+        same_key[i][j] = Some(is_equal.clone());
+        same_key[j][i] = Some(is_equal);
+    }
+
+    ctx.parallel_join(
+        zip(reports, &same_key)
+            .enumerate()
+            .map(|(i, (report, same_key))| {
+                let ctx = ctx.clone();
+                let mut buckets = first_bucket(&report.value);
+                async move {
+                    for (j, is_equal) in same_key
+                        .iter()
+                        .enumerate()
+                        .filter_map(|(j, is_equal)| Some((j, is_equal.as_ref()?)))
+                    {
+                        let tally = i * (MAX_USER_REPORTS - 1) + if j < i { j } else { j - 1 };
+                        let if_equal = if j < i {
+                            Replicated::ZERO
+                        } else {
+                            next_bucket(&buckets)
+                        };
+                        buckets = select(
+                            ctx.narrow(&UserFrequencyStep::Tally(tally)),
+                            record_id,
+                            is_equal,
+                            &if_equal,
+                            &buckets,
+                        )
+                        .await?;
+                    }
+                    Ok::<_, Error>(frequency_record(report.breakdown_key, &buckets))
+                }
+            }),
+    )
+    .await
+}
+
+/// Returns a sharing of 1 if `a` and `b` are equal, and a sharing of 0 otherwise.
+async fn breakdown_keys_equal<C, BK>(
+    ctx: C,
+    record_id: RecordId,
+    a: &Replicated<BK>,
+    b: &Replicated<BK>,
+) -> Result<Replicated<Boolean>, Error>
+where
+    C: Context,
+    BK: BooleanArray,
+    Replicated<Boolean>: BooleanProtocols<C>,
+{
+    let mut bits = (a + b).to_bits().into_iter();
+    let mut differ = bits.next().unwrap_or(Replicated::ZERO);
+    for (i, bit) in bits.enumerate() {
+        differ = or(ctx.narrow(&EightBitStep::from(i)), record_id, &differ, &bit).await?;
+    }
+
+    Ok(!differ)
+}
+
+/// Places a report in the first frequency bucket if it is a real report, i.e. its value is
+/// 1. Padding dummies have a value of 0 and end up in no bucket at all.
+fn first_bucket<V: BooleanArray + U128Conversions>(
+    value: &Replicated<V>,
+) -> Replicated<FrequencyBuckets> {
+    let low_bit = |value: V| FrequencyBuckets::truncate_from(value.as_u128() & 1);
+
+    Replicated::new(low_bit(value.left()), low_bit(value.right()))
+}
+
+/// Moves a one-hot encoded frequency up by one bucket, saturating at the last bucket. This is
+/// a linear operation, so it is done locally.
+fn next_bucket(buckets: &Replicated<FrequencyBuckets>) -> Replicated<FrequencyBuckets> {
+    let last_bucket = 1_u128 << (FREQUENCY_BUCKETS - 1);
+    let shift = |buckets: FrequencyBuckets| {
+        let buckets = buckets.as_u128();
+        FrequencyBuckets::truncate_from((buckets << 1) ^ (buckets & last_bucket))
+    };
+
+    Replicated::new(shift(buckets.left()), shift(buckets.right()))
+}
+
+fn frequency_record<BK: BooleanArray>(
+    breakdown_key: Replicated<BK>,
+    buckets: &Replicated<FrequencyBuckets>,
+) -> FrequencyRecord<BK> {
+    let bucket =
+        |buckets: FrequencyBuckets, f: usize| BA3::truncate_from((buckets.as_u128() >> f) & 1);
+
+    FrequencyRecord {
+        group_key: breakdown_key,
+        values: std::array::from_fn(|f| {
+            Replicated::new(bucket(buckets.left(), f), bucket(buckets.right(), f))
+        }),
+    }
+}
@@ -0,0 +1,211 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Seek, Write},
+    marker::PhantomData,
+    num::NonZeroUsize,
+};
+
+use generic_array::GenericArray;
+use typenum::Unsigned;
+
+use crate::{
+    ff::{boolean_array::BooleanArray, Serializable},
+    report::hybrid::PrfHybridReport,
+    sharding::ShardIndex,
+};
+
+/// Reports on disk, range partitioned by the PRF of their `match_key`.
+///
+/// Partition `i` holds every report whose PRF falls into the `i`-th of `partitions` equally
+/// sized ranges of `u64`. Within a partition, reports are returned ordered by the shard they
+/// came from and, for reports from the same shard, in the order they were pushed. This is the
+/// order that [`reshard_try_stream`] returns reports in, even though reports from different
+/// shards arrive in a different order on every helper. Reading the partitions back in order
+/// therefore visits `match_keys` in ascending order, and reports with the same `match_key` in
+/// the order resharding gives them. This is the same order a `BTreeMap` over all the reports
+/// has, so grouping one partition at a time gives identical results on all three helpers,
+/// while only one partition needs to be in memory.
+///
+/// PRF values are uniformly distributed, so partitions are roughly the same size.
+/// The files are anonymous temporary files that are removed as soon as they are dropped.
+///
+/// Reports are written as they are, without encryption. The files hold nothing but this
+/// helper's replicated shares, which do not reveal anything about the reports without the
+/// shares of another helper, and PRF values of match keys that were already revealed to this
+/// helper. This is the same data the helper keeps in memory for smaller inputs, so the
+/// temporary directory must be on storage that is as trusted as the helper's memory.
+///
+/// [`reshard_try_stream`]: crate::protocol::context::reshard_try_stream
+pub struct SpilledReports<BK, V> {
+    partitions: Vec<BufWriter<File>>,
+    len: usize,
+    _phantom: PhantomData<(BK, V)>,
+}
+
+impl<BK, V> SpilledReports<BK, V>
+where
+    BK: BooleanArray,
+    V: BooleanArray,
+    PrfHybridReport<BK, V>: Serializable,
+{
+    /// Creates `partitions` empty temporary files.
+    ///
+    /// ## Errors
+    /// If the temporary files cannot be created.
+    pub fn new(partitions: NonZeroUsize) -> io::Result<Self> {
+        Ok(Self {
+            partitions: (0..partitions.get())
+                .map(|_| tempfile::tempfile().map(BufWriter::new))
+                .collect::<io::Result<_>>()?,
+            len: 0,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Number of reports spilled so far.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn partition(&self, match_key: u64) -> usize {
+        // Multiplying by the number of partitions and keeping the top 64 bits maps `u64` onto
+        // `0..partitions` while preserving order.
+        usize::try_from((u128::from(match_key) * self.partitions.len() as u128) >> 64).unwrap()
+    }
+
+    /// Appends `report`, received from shard `source`, to the partition of its `match_key`.
+    ///
+    /// ## Errors
+    /// If writing to the temporary file fails.
+    pub fn push(&mut self, source: ShardIndex, report: &PrfHybridReport<BK, V>) -> io::Result<()> {
+        let mut buf = GenericArray::default();
+        report.serialize(&mut buf);
+        let partition = self.partition(report.match_key);
+        let partition = &mut self.partitions[partition];
+        partition.write_all(&u32::from(source).to_le_bytes())?;
+        partition.write_all(&buf)?;
+        self.len += 1;
+
+        Ok(())
+    }
+
+    /// Returns the reports of every partition, in ascending order of their `match_key` ranges.
+    /// Partitions are read lazily, one at a time.
+    ///
+    /// ## Errors
+    /// If flushing or reading a temporary file fails or if a report cannot be deserialized.
+    pub fn into_partitions(self) -> impl Iterator<Item = io::Result<Vec<PrfHybridReport<BK, V>>>> {
+        self.partitions.into_iter().map(|writer| {
+            let mut file = writer
+                .into_inner()
+                .map_err(io::IntoInnerError::into_error)?;
+            let size = usize::try_from(file.stream_position()?).unwrap();
+            file.rewind()?;
+
+            let record_size =
+                size_of::<u32>() + <PrfHybridReport<BK, V> as Serializable>::Size::USIZE;
+            let mut reader = BufReader::new(file);
+            let mut source = [0; size_of::<u32>()];
+            let mut buf = GenericArray::default();
+            let mut reports = Vec::with_capacity(size / record_size);
+            for _ in 0..size / record_size {
+                reader.read_exact(&mut source)?;
+                reader.read_exact(&mut buf)?;
+                reports.push((
+                    u32::from_le_bytes(source),
+                    PrfHybridReport::<BK, V>::deserialize(&buf)
+                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+                ));
+            }
+            // stable, so reports from the same shard stay in the order they were pushed
+            reports.sort_by_key(|(source, _)| *source);
+
+            Ok(reports.into_iter().map(|(_, report)| report).collect())
+        })
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use super::SpilledReports;
+    use crate::{
+        ff::{
+            boolean_array::{BA3, BA8},
+            U128Conversions,
+        },
+        report::hybrid::PrfHybridReport,
+        secret_sharing::replicated::{
+            semi_honest::AdditiveShare as Replicated, ReplicatedSecretSharing,
+        },
+        sharding::ShardIndex,
+    };
+
+    fn report(match_key: u64, value: u128) -> PrfHybridReport<BA8, BA3> {
+        PrfHybridReport {
+            match_key,
+            value: Replicated::new(BA3::truncate_from(value), BA3::truncate_from(0_u128)),
+            breakdown_key: Replicated::new(BA8::truncate_from(0_u128), BA8::truncate_from(0_u128)),
+        }
+    }
+
+    #[test]
+    fn partitions_by_match_key_range() {
+        let reports = vec![
+            report(u64::MAX, 1),
+            report(7, 2),
+            report(u64::MAX / 2, 3),
+            report(u64::MAX / 4 * 3, 4),
+            report(7, 5),
+            report(0, 6),
+        ];
+
+        let mut spilled = SpilledReports::new(NonZeroUsize::new(4).unwrap()).unwrap();
+        for report in &reports {
+            spilled.push(ShardIndex::FIRST, report).unwrap();
+        }
+        assert_eq!(reports.len(), spilled.len());
+
+        let partitions = spilled
+            .into_partitions()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            vec![
+                vec![reports[1].clone(), reports[4].clone(), reports[5].clone()],
+                vec![reports[2].clone()],
+                vec![reports[3].clone()],
+                vec![reports[0].clone()],
+            ],
+            partitions,
+        );
+    }
+
+    #[test]
+    fn orders_by_source_shard() {
+        let reports = (0..6).map(|i| report(7, i)).collect::<Vec<_>>();
+
+        let mut spilled = SpilledReports::new(NonZeroUsize::new(2).unwrap()).unwrap();
+        for (source, report) in [2, 0, 1, 0, 2, 1].into_iter().zip(&reports) {
+            spilled.push(ShardIndex::from(source), report).unwrap();
+        }
+        assert_eq!(reports.len(), spilled.len());
+
+        let partitions = spilled
+            .into_partitions()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            vec![
+                [1, 3, 2, 5, 0, 4].map(|i| reports[i].clone()).to_vec(),
+                Vec::new(),
+            ],
+            partitions,
+        );
+    }
+}
@@ -19,12 +19,30 @@ pub(crate) enum HybridStep {
     GroupBySum,
     #[step(child = crate::protocol::context::step::DzkpValidationProtocolStep)]
     GroupBySumValidate,
-    #[step(child = AggregationStep)]
+    #[step(child = FrequencyStep)]
+    CountFrequency,
+    #[step(child = crate::protocol::group_by::step::GroupByStep)]
     Aggregate,
+    #[step(child = crate::protocol::group_by::step::GroupByDpStep)]
+    AggregateWithDp,
     #[step(child = FinalizeSteps)]
     Finalize,
 }
 
+/// Reports with several match keys are joined in one pass per match key, see
+/// [`cross_device_hybrid_protocol`]. Every pass runs the same steps as the hybrid protocol
+/// up to resharding by PRF, and the pairs found by all passes are aggregated together.
+///
+/// [`cross_device_hybrid_protocol`]: crate::protocol::hybrid::cross_device_hybrid_protocol
+#[derive(CompactStep)]
+pub(crate) enum CrossDeviceStep {
+    /// The count must be at least the number of match keys per report.
+    #[step(count = 2, child = HybridStep)]
+    MatchKeyPass(usize),
+    #[step(child = HybridStep)]
+    Aggregate,
+}
+
 #[derive(CompactStep)]
 pub(crate) enum AggregateReportsStep {
     #[step(child = crate::protocol::boolean::step::EightBitStep)]
@@ -33,6 +51,27 @@ pub(crate) enum AggregateReportsStep {
     AddV,
 }
 
+/// Users are processed in batches by the number of reports they have, so that all the
+/// users in a batch go through the same steps.
+#[derive(CompactStep)]
+pub(crate) enum FrequencyStep {
+    /// The count must be greater than `reach_frequency::MAX_USER_REPORTS`.
+    #[step(count = 9, child = UserFrequencyStep)]
+    Users(usize),
+    #[step(count = 9, child = crate::protocol::context::step::DzkpValidationProtocolStep)]
+    UsersValidate(usize),
+}
+
+#[derive(CompactStep)]
+pub(crate) enum UserFrequencyStep {
+    /// One step for every pair of reports of a single user.
+    #[step(count = 28, child = crate::protocol::boolean::step::EightBitStep)]
+    Compare(usize),
+    /// One step for every ordered pair of reports of a single user.
+    #[step(count = 56)]
+    Tally(usize),
+}
+
 #[derive(CompactStep)]
 pub(crate) enum FinalizeSteps {
     #[step(child = crate::protocol::ipa_prf::boolean_ops::step::SaturatedAdditionStep)]
@@ -40,18 +79,3 @@ pub(crate) enum FinalizeSteps {
     #[step(child = crate::protocol::context::step::DzkpValidationProtocolStep)]
     Validate,
 }
-
-#[derive(CompactStep)]
-pub(crate) enum AggregationStep {
-    #[step(child = crate::protocol::ipa_prf::oprf_padding::step::PaddingDpStep, name="padding_dp")]
-    PaddingDp,
-    #[step(child = crate::protocol::ipa_prf::shuffle::step::ShardedShuffleStep)]
-    Shuffle,
-    Reveal,
-    #[step(child = crate::protocol::context::step::DzkpValidationProtocolStep)]
-    RevealValidate, // only partly used -- see code
-    #[step(count = 4, child = crate::protocol::ipa_prf::aggregation::step::AggregateChunkStep, name = "chunks")]
-    Aggregate(usize),
-    #[step(count = 4, child = crate::protocol::context::step::DzkpValidationProtocolStep)]
-    AggregateValidate(usize),
-}
@@ -22,28 +22,40 @@ use crate::{
         TotalRecords,
     },
     protocol::{
-        basics::{BooleanArrayMul, BooleanProtocols, Reveal},
+        basics::{
+            shard_fin::{FinalizerContext, Histogram},
+            BooleanArrayMul, BooleanProtocols, Reveal,
+        },
         context::{
+            adapt_active_work,
             dzkp_validator::{DZKPValidator, TARGET_PROOF_SIZE},
-            DZKPUpgraded, MacUpgraded, MaliciousProtocolSteps, UpgradableContext,
+            reshard_iter, AdaptiveActiveWork, DZKPUpgraded, MacUpgraded, MaliciousProtocolSteps,
+            ShardedContext, UpgradableContext,
+        },
+        hybrid::{
+            breakdown_reveal::breakdown_reveal_aggregation,
+            oprf::gen_prf_key as gen_sharded_prf_key, step::FinalizeSteps,
         },
         ipa_prf::{
             boolean_ops::convert_to_fp25519,
             oprf_padding::apply_dp_padding,
-            prf_eval::{eval_dy_prf, gen_prf_key},
+            prf_eval::{collect_prfs, eval_dy_prf, gen_prf_key},
             prf_sharding::{
-                attribute_cap_aggregate, histograms_ranges_sortkeys, PrfShardedIpaInputRow,
+                attribute_cap, attribute_cap_aggregate, histograms_ranges_sortkeys,
+                PrfShardedIpaInputRow,
             },
-            step::IpaPrfStep,
+            shuffle::ShardedShuffle,
         },
         prss::FromPrss,
         RecordId,
     },
+    report::hybrid::AggregateableHybridReport,
     secret_sharing::{
         replicated::{semi_honest::AdditiveShare as Replicated, ReplicatedSecretSharing},
         BitDecomposed, FieldSimd, SharedValue, TransposeFrom, Vectorizable,
     },
     seq_join::seq_join,
+    telemetry::trace::phase_span,
     utils::non_zero_prev_power_of_two,
 };
 
@@ -63,6 +75,7 @@ pub use malicious_security::{
     CompressedProofGenerator, FirstProofGenerator, LagrangeTable, ProverTableIndices,
     VerifierTableIndices,
 };
+pub use quicksort::quicksort_by_key;
 pub use shuffle::Shuffle;
 
 /// Match key type
@@ -330,7 +343,7 @@ pub async fn oprf_ipa<'ctx, C, BK, TV, HV, TS, const SS_BITS: usize, const B: us
     dp_padding_params: PaddingParameters,
 ) -> Result<Vec<Replicated<HV>>, Error>
 where
-    C: UpgradableContext + 'ctx + Shuffle,
+    C: UpgradableContext + AdaptiveActiveWork + 'ctx + Shuffle,
     BK: BreakdownKey<B>,
     TV: BooleanArray + U128Conversions,
     HV: BooleanArray + U128Conversions,
@@ -374,14 +387,23 @@ where
         input_rows,
         &dp_padding_params,
     )
+    .instrument(phase_span("padding"))
     .await?;
 
     let shuffled = ctx
         .narrow(&Step::Shuffle)
         .shuffle(padded_input_rows)
         .instrument(info_span!("shuffle_inputs"))
+        .instrument(phase_span("shuffle"))
         .await?;
-    let mut prfd_inputs = compute_prf_for_inputs(ctx.clone(), &shuffled).await?;
+    let prf_key = gen_prf_key(&ctx.narrow(&Step::PrfKeyGen));
+    let mut prfd_inputs = compute_prf_for_inputs(ctx.clone(), &shuffled, &prf_key)
+        .instrument(phase_span("prf"))
+        .await?;
+
+    // By now helpers have seen enough traffic to tell whether the network or the compute
+    // is the bottleneck, so they can agree on the active work for the rest of the protocol.
+    let ctx = adapt_active_work(ctx, &Step::AdaptActiveWork).await?;
 
     prfd_inputs.sort_by(|a, b| a.prf_of_match_key.cmp(&b.prf_of_match_key));
 
@@ -397,6 +419,7 @@ where
         |x| &x.sort_key,
         ranges,
     )
+    .instrument(phase_span("sort"))
     .await?;
 
     let output_histogram = attribute_cap_aggregate::<_, _, _, _, _, SS_BITS, B>(
@@ -406,13 +429,173 @@ where
         &row_count_histogram,
         &dp_padding_params,
     )
+    .instrument(phase_span("attribution"))
     .await?;
 
     let noisy_output_histogram =
-        dp_for_histogram::<_, B, HV, SS_BITS>(ctx, output_histogram, dp_params).await?;
+        dp_for_histogram::<_, B, HV, SS_BITS>(ctx, output_histogram, dp_params)
+            .instrument(phase_span("dp"))
+            .await?;
     Ok(noisy_output_histogram)
 }
 
+/// Sharded IPA OPRF Protocol
+///
+/// Computes the same result as [`oprf_ipa`], but each shard of a helper only holds a part of
+/// the input. Steps that need to see all records of a user are run after records are
+/// resharded by the OPRF of their match key.
+/// 1. Generates "dummy records" on every shard and shuffles the input across all shards
+/// 2. Computes an OPRF of the match keys, using a key shared by all shards, and reveals it
+/// 3. Sends every record to the shard that owns its OPRF value, so all records of a user end
+///    up on the same shard
+/// 4. Each shard sorts its users' records by timestamp, attributes trigger events to source
+///    events and caps each user's total contribution
+/// 5. Aggregates the contributions of all users by breakdown key, revealing breakdowns after
+///    shuffling the contributions across all shards
+/// 6. Shards send their histograms to the leader shard that adds them together
+/// 7. The leader adds random noise to the total for each breakdown key
+///
+/// Only the leader shard returns the histogram; other shards return an empty vector.
+/// # Errors
+/// Propagates errors from config issues or while running the protocol
+/// # Panics
+/// Propagates errors from config issues or while running the protocol
+pub async fn sharded_oprf_ipa<'ctx, C, BK, TV, HV, TS, const SS_BITS: usize, const B: usize>(
+    ctx: C,
+    input_rows: Vec<OPRFIPAInputRow<BK, TV, TS>>,
+    attribution_window_seconds: Option<NonZeroU32>,
+    dp_params: DpMechanism,
+    dp_padding_params: PaddingParameters,
+) -> Result<Vec<Replicated<HV>>, Error>
+where
+    C: UpgradableContext
+        + AdaptiveActiveWork
+        + ShardedShuffle
+        + ShardedContext
+        + FinalizerContext<FinalizingContext = DZKPUpgraded<C>>
+        + 'ctx,
+    BK: BreakdownKey<B>,
+    TV: BooleanArray + U128Conversions,
+    HV: BooleanArray + U128Conversions,
+    TS: BooleanArray + U128Conversions,
+    <HV as Serializable>::Size: Add<<HV as Serializable>::Size, Output: ArrayLength>,
+    Boolean: FieldSimd<B>,
+    PrfShardedIpaInputRow<BK, TV, TS>: Serializable,
+    Replicated<Boolean>: BooleanProtocols<DZKPUpgraded<C>>,
+    Replicated<Boolean, B>: BooleanProtocols<DZKPUpgraded<C>, B>,
+    Replicated<Boolean, CONV_CHUNK>: BooleanProtocols<DZKPUpgraded<C>, CONV_CHUNK>,
+    Replicated<Boolean, SORT_CHUNK>: BooleanProtocols<DZKPUpgraded<C>, SORT_CHUNK>,
+    Replicated<Fp25519, PRF_CHUNK>:
+        PrfSharing<MacUpgraded<C, Fp25519>, PRF_CHUNK, Field = Fp25519> + FromPrss,
+    Replicated<RP25519, PRF_CHUNK>:
+        Reveal<MacUpgraded<C, Fp25519>, Output = <RP25519 as Vectorizable<PRF_CHUNK>>::Array>,
+    Replicated<BK>: BooleanArrayMul<DZKPUpgraded<C>>
+        + Reveal<DZKPUpgraded<C>, Output = <BK as Vectorizable<1>>::Array>,
+    Replicated<TS>: BooleanArrayMul<DZKPUpgraded<C>>,
+    Replicated<TV>: BooleanArrayMul<DZKPUpgraded<C>>,
+    BitDecomposed<Replicated<Boolean, B>>:
+        for<'a> TransposeFrom<&'a [Replicated<TV>; B], Error = Infallible>,
+    BitDecomposed<Replicated<Boolean, B>>:
+        for<'a> TransposeFrom<&'a [Replicated<HV>; B], Error = Infallible>,
+    BitDecomposed<Replicated<Boolean, B>>:
+        for<'a> TransposeFrom<&'a Vec<Replicated<HV>>, Error = LengthError>,
+    Vec<Replicated<HV>>:
+        for<'a> TransposeFrom<&'a BitDecomposed<Replicated<Boolean, B>>, Error = LengthError>,
+    DZKPUpgraded<C>: ShardedContext,
+{
+    // Unlike `oprf_ipa`, this protocol can't return early if this shard has no input, because
+    // other shards expect it to participate in shuffling, resharding and finalization.
+    let padded_input_rows = apply_dp_padding::<_, OPRFIPAInputRow<BK, TV, TS>, B>(
+        ctx.narrow(&Step::PaddingDp),
+        input_rows,
+        &dp_padding_params,
+    )
+    .instrument(phase_span("padding"))
+    .await?;
+
+    let shuffled = ctx
+        .narrow(&Step::ShardedShuffle)
+        .sharded_shuffle(padded_input_rows)
+        .instrument(info_span!("shuffle_inputs"))
+        .instrument(phase_span("shuffle"))
+        .await?;
+    let prf_key = gen_sharded_prf_key(&ctx.narrow(&Step::PrfKeyGen));
+    let prfd_inputs = compute_prf_for_inputs(ctx.clone(), &shuffled, &prf_key)
+        .instrument(phase_span("prf"))
+        .await?;
+
+    // Records of the same user end up on the same shard, so attribution does not need to
+    // communicate with other shards.
+    let mut prfd_inputs = reshard_iter(
+        ctx.narrow(&Step::ReshardByPrf),
+        prfd_inputs,
+        |ctx, _, row| row.prf_of_match_key % ctx.shard_count(),
+    )
+    .instrument(phase_span("reshard"))
+    .await?;
+
+    let ctx = adapt_active_work(ctx, &Step::AdaptActiveWork).await?;
+
+    prfd_inputs.sort_by_key(|row| row.prf_of_match_key);
+
+    let (row_count_histogram, ranges) = histograms_ranges_sortkeys(&mut prfd_inputs);
+    if row_count_histogram.len() > 1 {
+        quicksort_ranges_by_key_insecure(
+            ctx.narrow(&Step::SortByTimestamp),
+            &mut prfd_inputs,
+            false,
+            |x| &x.sort_key,
+            ranges,
+        )
+        .instrument(phase_span("sort"))
+        .await?;
+    }
+
+    let user_contributions = attribute_cap::<_, BK, TV, TS, SS_BITS, B>(
+        ctx.narrow(&Step::Attribution),
+        prfd_inputs,
+        attribution_window_seconds,
+        &row_count_histogram,
+    )
+    .instrument(phase_span("attribution"))
+    .await?;
+
+    let histogram = breakdown_reveal_aggregation::<_, BK, TV, HV, B>(
+        ctx.narrow(&Step::ShardedAggregate),
+        user_contributions
+            .into_iter()
+            .map(|contribution| AggregateableHybridReport {
+                match_key: (),
+                breakdown_key: contribution.attributed_breakdown_key_bits,
+                value: contribution.capped_attributed_trigger_value,
+            })
+            .collect(),
+        &dp_padding_params,
+    )
+    .instrument(phase_span("breakdown_reveal"))
+    .await?;
+
+    let finalized_histogram = ctx
+        .narrow(&Step::Finalize)
+        .finalize(
+            MaliciousProtocolSteps {
+                protocol: &FinalizeSteps::Add,
+                validate: &FinalizeSteps::Validate,
+            },
+            Histogram::<HV, B>::from(histogram),
+        )
+        .instrument(phase_span("finalize"))
+        .await?;
+
+    if ctx.is_leader() {
+        dp_for_histogram::<_, B, HV, SS_BITS>(ctx, finalized_histogram.values, dp_params)
+            .instrument(phase_span("dp"))
+            .await
+    } else {
+        Ok(finalized_histogram.compose())
+    }
+}
+
 /// Returns a suitable proof chunk size (in records) for use with `convert_to_fp25519`.
 ///
 /// We expect 2*256 = 512 gates in total for two additions per conversion. The
@@ -429,6 +612,7 @@ fn conv_proof_chunk() -> usize {
 async fn compute_prf_for_inputs<C, BK, TV, TS>(
     ctx: C,
     input_rows: &[OPRFIPAInputRow<BK, TV, TS>],
+    prf_key: &Replicated<Fp25519>,
 ) -> Result<Vec<PrfShardedIpaInputRow<BK, TV, TS>>, Error>
 where
     C: UpgradableContext,
@@ -472,24 +656,24 @@ where
     .try_collect::<Vec<_>>()
     .await?;
 
-    let prf_key = gen_prf_key(&ctx.narrow(&IpaPrfStep::PrfKeyGen));
     let validator = ctx
         .narrow(&Step::EvalPrf)
         .set_total_records(eval_records)
         .validator::<Fp25519>();
     let eval_ctx = validator.context();
 
-    let prf_of_match_keys = seq_join(
-        ctx.active_work(),
-        stream::iter(curve_pts).enumerate().map(|(i, curve_pts)| {
-            let record_id = RecordId::from(i);
-            let eval_ctx = eval_ctx.clone();
-            let prf_key = &prf_key;
-            curve_pts
-                .then(move |pts| eval_dy_prf::<_, PRF_CHUNK>(eval_ctx, record_id, prf_key, pts))
-        }),
+    let prf_of_match_keys = collect_prfs(
+        eval_ctx.clone(),
+        seq_join(
+            ctx.active_work(),
+            stream::iter(curve_pts).enumerate().map(|(i, curve_pts)| {
+                let record_id = RecordId::from(i);
+                let eval_ctx = eval_ctx.clone();
+                curve_pts
+                    .then(move |pts| eval_dy_prf::<_, PRF_CHUNK>(eval_ctx, record_id, prf_key, pts))
+            }),
+        ),
     )
-    .try_collect::<Vec<_>>()
     .await?;
 
     Ok(zip(input_rows, prf_of_match_keys.into_iter().flatten())
@@ -525,11 +709,13 @@ pub mod tests {
         helpers::query::DpMechanism,
         protocol::{
             dp::NoiseParams,
-            ipa_prf::{oprf_ipa, oprf_padding::PaddingParameters},
+            ipa_prf::{oprf_ipa, oprf_padding::PaddingParameters, sharded_oprf_ipa},
         },
         sharding::NotSharded,
         test_executor::run,
-        test_fixture::{ipa::TestRawDataRecord, Reconstruct, Runner, TestWorld, TestWorldConfig},
+        test_fixture::{
+            ipa::TestRawDataRecord, Reconstruct, Runner, TestWorld, TestWorldConfig, WithShards,
+        },
     };
 
     fn test_input(
@@ -635,6 +821,88 @@ pub mod tests {
         });
     }
 
+    fn sharded_records() -> Vec<TestRawDataRecord> {
+        vec![
+            test_input(0, 12345, false, 1, 0),
+            test_input(5, 12345, false, 2, 0),
+            test_input(10, 12345, true, 0, 5),
+            test_input(0, 68362, false, 1, 0),
+            test_input(20, 68362, true, 0, 2),
+            test_input(0, 43962, false, 3, 0),
+            test_input(7, 43962, true, 0, 4),
+        ]
+    }
+
+    /// Checks that the leader shard holds the expected histogram and all other shards
+    /// return nothing.
+    fn assert_sharded_result(mut results: Vec<Vec<BA8>>, expected: &[u128]) {
+        let mut leader = results.remove(0);
+        assert!(results.iter().all(Vec::is_empty));
+        leader.truncate(expected.len());
+        assert_eq!(
+            leader.iter().map(|&v| v.as_u128()).collect::<Vec<_>>(),
+            expected,
+        );
+    }
+
+    #[test]
+    fn sharded_semi_honest() {
+        const SHARDS: usize = 2;
+        const EXPECTED: &[u128] = &[0, 2, 5, 4, 0, 0, 0, 0];
+
+        run(|| async {
+            let world = TestWorld::<WithShards<SHARDS>>::with_shards(TestWorldConfig::default());
+
+            let results: Vec<_> = world
+                .semi_honest(
+                    sharded_records().into_iter(),
+                    |ctx, input_rows| async move {
+                        sharded_oprf_ipa::<_, BA5, BA3, BA8, BA20, 5, 32>(
+                            ctx,
+                            input_rows,
+                            None,
+                            DpMechanism::NoDp,
+                            PaddingParameters::no_padding(),
+                        )
+                        .await
+                        .unwrap()
+                    },
+                )
+                .await
+                .reconstruct();
+            assert_sharded_result(results, EXPECTED);
+        });
+    }
+
+    #[test]
+    fn sharded_malicious() {
+        const SHARDS: usize = 2;
+        const EXPECTED: &[u128] = &[0, 2, 5, 4, 0, 0, 0, 0];
+
+        run(|| async {
+            let world = TestWorld::<WithShards<SHARDS>>::with_shards(TestWorldConfig::default());
+
+            let results: Vec<_> = world
+                .malicious(
+                    sharded_records().into_iter(),
+                    |ctx, input_rows| async move {
+                        sharded_oprf_ipa::<_, BA5, BA3, BA8, BA20, 5, 32>(
+                            ctx,
+                            input_rows,
+                            None,
+                            DpMechanism::NoDp,
+                            PaddingParameters::no_padding(),
+                        )
+                        .await
+                        .unwrap()
+                    },
+                )
+                .await
+                .reconstruct();
+            assert_sharded_result(results, EXPECTED);
+        });
+    }
+
     #[test]
     fn semi_honest_with_dp() {
         const SS_BITS: usize = 1;
@@ -2,7 +2,7 @@ pub(crate) mod distributions;
 pub mod insecure;
 pub mod step;
 
-use std::iter::{repeat, repeat_with};
+use std::iter::{repeat, repeat_n, repeat_with};
 
 #[cfg(any(test, feature = "test-fixture", feature = "cli"))]
 pub use insecure::DiscreteDp as InsecureDiscreteDp;
@@ -30,7 +30,7 @@ use crate::{
         },
         RecordId,
     },
-    report::hybrid::IndistinguishableHybridReport,
+    report::hybrid::{IndistinguishableHybridReport, MultiKeyHybridReport},
     secret_sharing::{
         replicated::{semi_honest::AdditiveShare, ReplicatedSecretSharing},
         SharedValue,
@@ -200,6 +200,53 @@ where
     }
 }
 
+impl<BK, V, const N: usize> Paddable for MultiKeyHybridReport<BK, V, N>
+where
+    BK: BooleanArray + U128Conversions,
+    V: BooleanArray,
+{
+    /// Pads the collection with the same dummy reports as single key reports do. The
+    /// random match key of a dummy is its first candidate and all other candidates are absent,
+    /// so a dummy added for one pass of the cross-device protocol is never paired in the
+    /// following passes.
+    fn add_padding_items<VC: Extend<Self>, const B: usize>(
+        direction_to_excluded_helper: Direction,
+        padding_input_rows: &mut VC,
+        padding_params: &PaddingParameters,
+        rng: &mut InstrumentedSequentialSharedRandomness,
+    ) -> Result<u32, Error> {
+        let mut single_key_rows = Vec::<IndistinguishableHybridReport<BK, V>>::new();
+        let total_number_of_fake_rows = IndistinguishableHybridReport::add_padding_items::<_, B>(
+            direction_to_excluded_helper,
+            &mut single_key_rows,
+            padding_params,
+            rng,
+        )?;
+        padding_input_rows.extend(single_key_rows.into_iter().map(|row| {
+            let mut match_keys = [AdditiveShare::<BA64>::ZERO; N];
+            match_keys[0] = row.match_key;
+            MultiKeyHybridReport {
+                match_key: match_keys,
+                value: row.value,
+                breakdown_key: row.breakdown_key,
+            }
+        }));
+        Ok(total_number_of_fake_rows)
+    }
+
+    /// Given an extendable collection of `MultiKeyHybridReport`s,
+    /// this function ads `total_number_of_fake_rows` of Reports with zeros in all fields.
+    fn add_zero_shares<VC: Extend<Self>>(
+        padding_input_rows: &mut VC,
+        total_number_of_fake_rows: u32,
+    ) {
+        padding_input_rows.extend(repeat_n(
+            MultiKeyHybridReport::<BK, V, N>::ZERO,
+            total_number_of_fake_rows as usize,
+        ));
+    }
+}
+
 impl<BK, V> Paddable for IndistinguishableHybridReport<BK, V, ()>
 where
     BK: BooleanArray + U128Conversions,
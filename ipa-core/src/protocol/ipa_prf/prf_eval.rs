@@ -1,15 +1,19 @@
 use std::iter::zip;
 
-use futures::future::try_join;
+use futures::{
+    future::{join, try_join4},
+    Stream, StreamExt,
+};
 
 use crate::{
     error::Error,
-    ff::{curve_points::RP25519, ec_prime_field::Fp25519},
+    ff::{boolean::Boolean, curve_points::RP25519, ec_prime_field::Fp25519},
+    helpers::{Direction, TotalRecords},
     protocol::{
         basics::{reveal, Reveal, SecureMul},
         context::{
-            upgrade::Upgradable, UpgradableContext, UpgradedContext, UpgradedMaliciousContext,
-            UpgradedSemiHonestContext,
+            upgrade::Upgradable, Context, UpgradableContext, UpgradedContext,
+            UpgradedMaliciousContext, UpgradedSemiHonestContext,
         },
         ipa_prf::step::PrfStep as Step,
         prss::{FromPrss, SharedRandomness},
@@ -101,6 +105,9 @@ where
 /// PRF key k needs to be generated using `gen_prf_key`
 ///  x is the match key in Fp25519 format
 /// outputs a u64 as specified in `protocol/prf_sharding/mod.rs`, all parties learn the output
+///
+/// An inconsistent malicious reveal is only detected by the helper that received the
+/// inconsistent shares. Use [`collect_prfs`] to make all helpers agree on the outcome.
 /// # Errors
 /// Propagates errors from multiplications, reveal and scalar multiplication
 /// # Panics
@@ -137,14 +144,20 @@ where
 
     // validate everything before reveal
     ctx.validate_record(record_id).await?;
+    // both reveals must run to completion, even if one of them fails, so that peers receive
+    // all the messages for this record.
     let (gr, z): (
         <RP25519 as Vectorizable<N>>::Array,
         <Fp25519 as Vectorizable<N>>::Array,
-    ) = try_join(
+    ) = match join(
         reveal(ctx.narrow(&Step::RevealR), record_id, &sh_gr),
         reveal(ctx.narrow(&Step::Revealz), record_id, &y),
     )
-    .await?;
+    .await
+    {
+        (Ok(gr), Ok(z)) => (gr, z),
+        (Err(e), _) | (_, Err(e)) => return Err(e),
+    };
 
     //compute R^(1/z) to u64
     let inv_z = crate::ff::ec_prime_field::batch_invert::<N>(&z);
@@ -155,9 +168,88 @@ where
         .expect("iteration over arrays"))
 }
 
+/// Collects the output of [`eval_dy_prf`] for every record, then makes all helpers agree on
+/// whether the evaluation succeeded, see [`agree_on_prfs`]. `ctx` must be the context that
+/// was passed to [`eval_dy_prf`].
+///
+/// Unlike `try_collect`, this keeps polling the remaining records after one of them fails.
+/// Stopping at the first failed record is not safe: messages are only delivered once a channel
+/// buffer is full or every record was sent, so peers that keep going could wait for messages
+/// that are never sent.
+///
+/// ## Errors
+/// The first error returned by any of the records, or [`Error::MaliciousRevealFailed`] if
+/// the evaluation failed on a peer.
+pub async fn collect_prfs<C, S, T>(ctx: C, prfs: S) -> Result<Vec<T>, Error>
+where
+    C: Context,
+    S: Stream<Item = Result<T, Error>>,
+{
+    let prfs = prfs
+        .fold(Ok(Vec::new()), |acc, prf| async move {
+            match (acc, prf) {
+                (Ok(mut acc), Ok(prf)) => {
+                    acc.push(prf);
+                    Ok(acc)
+                }
+                (Err(e), _) | (Ok(_), Err(e)) => Err(e),
+            }
+        })
+        .await;
+
+    agree_on_prfs(ctx.narrow(&Step::RevealCheck), prfs).await
+}
+
+/// Malicious reveal only lets the helper that received inconsistent shares detect the attack.
+/// If that helper stopped on its own, its peers would keep going with PRF values that are
+/// not consistent across helpers. Instead, once all records are evaluated, every helper tells
+/// both of its peers whether its evaluation failed, and all of them abort if any did. This
+/// takes a single round for all the records. A malicious helper may lie about its own check,
+/// but an honest helper that detected the attack always makes the other honest helper abort.
+///
+/// Every helper sends its flag, whatever made its evaluation fail, so that its peers are not
+/// left waiting for it. Peers can't tell why a helper failed and report
+/// [`Error::MaliciousRevealFailed`].
+async fn agree_on_prfs<C: Context, T>(ctx: C, prfs: Result<T, Error>) -> Result<T, Error> {
+    // `ctx` already has the number of records of the evaluation, this sends a single one.
+    let ctx = ctx.set_total_records(TotalRecords::Indeterminate);
+    let record_id = RecordId::FIRST;
+    let failed = prfs.is_err();
+
+    let left = ctx.role().peer(Direction::Left);
+    let right = ctx.role().peer(Direction::Right);
+    let (left_sender, right_sender) = (
+        ctx.send_channel::<Boolean>(left),
+        ctx.send_channel::<Boolean>(right),
+    );
+    let (left_receiver, right_receiver) = (
+        ctx.recv_channel::<Boolean>(left),
+        ctx.recv_channel::<Boolean>(right),
+    );
+    let exchanged = try_join4(
+        left_sender.send(record_id, Boolean::from(failed)),
+        right_sender.send(record_id, Boolean::from(failed)),
+        left_receiver.receive(record_id),
+        right_receiver.receive(record_id),
+    )
+    .await;
+
+    match (prfs, exchanged) {
+        (Err(e), _) => Err(e),
+        (Ok(_), Err(e)) => Err(e.into()),
+        (Ok(prfs), Ok(((), (), left_failed, right_failed))) => {
+            if bool::from(left_failed) || bool::from(right_failed) {
+                Err(Error::MaliciousRevealFailed)
+            } else {
+                Ok(prfs)
+            }
+        }
+    }
+}
+
 #[cfg(all(test, unit_test))]
 mod test {
-    use futures_util::future::try_join_all;
+    use futures_util::{stream, StreamExt};
     use rand::Rng;
 
     use crate::{
@@ -166,9 +258,11 @@ mod test {
         helpers::{in_memory_config::MaliciousHelper, Role},
         protocol::{
             basics::Reveal,
-            context::{Context, MacUpgraded, UpgradableContext, Validator},
+            context::{
+                step::MaliciousProtocolStep, Context, MacUpgraded, UpgradableContext, Validator,
+            },
             ipa_prf::{
-                prf_eval::{eval_dy_prf, PrfSharing},
+                prf_eval::{collect_prfs, eval_dy_prf, PrfSharing},
                 step::PrfStep,
             },
         },
@@ -197,12 +291,16 @@ mod test {
         let ctx = ctx.set_total_records(input_match_keys.len());
         let validator = ctx.validator::<Fp25519>();
         let ctx = validator.context();
-        let futures = input_match_keys
-            .into_iter()
+        let prfs = stream::iter(input_match_keys)
             .enumerate()
-            .map(|(i, x)| eval_dy_prf(ctx.clone(), i.into(), &prf_key, x));
+            .map(|(i, x)| eval_dy_prf(ctx.clone(), i.into(), &prf_key, x))
+            .buffered(usize::MAX);
 
-        Ok(try_join_all(futures).await?.into_iter().flatten().collect())
+        Ok(collect_prfs(ctx.clone(), prfs)
+            .await?
+            .into_iter()
+            .flatten()
+            .collect())
     }
 
     ///defining test input struct
@@ -319,14 +417,12 @@ mod test {
 
     #[test]
     fn malicious_attack_resistant() {
-        const STEPS: [&PrfStep; 3] = [
+        const STEPS: [&PrfStep; 5] = [
             &PrfStep::UpgradeY,
             &PrfStep::UpgradeMask,
             &PrfStep::MultMaskWithPRFInput,
-            // TODO errors during malicious reveal does not terminate the execution, some helpers
-            // make progress and get stuck waiting for messages.
-            // &PrfStep::RevealR,
-            // &PrfStep::Revealz,
+            &PrfStep::RevealR,
+            &PrfStep::Revealz,
         ];
         run(|| async move {
             for attacker_role in Role::all() {
@@ -335,8 +431,14 @@ mod test {
                     config.stream_interceptor = MaliciousHelper::new(
                         *attacker_role,
                         config.role_assignment(),
-                        |ctx, data| {
-                            if ctx.gate.as_ref().contains(step.as_ref()) {
+                        move |ctx, data| {
+                            // the validator has a `reveal_r` step of its own, only attack the
+                            // steps of the PRF evaluation.
+                            if ctx.gate.as_ref().contains(&format!(
+                                "{}/{}",
+                                MaliciousProtocolStep::MaliciousProtocol.as_ref(),
+                                step.as_ref()
+                            )) {
                                 data[0] = !data[0];
                             }
                         },
@@ -351,7 +453,11 @@ mod test {
 
                                 match compute_match_key_pseudonym(ctx, prf_key, match_key_shares).await {
                                     Ok(_) if my_role == *attacker_role => {}
-                                    Err(Error::MaliciousSecurityCheckFailed | Error::MaliciousRevealFailed) => {}
+                                    Err(
+                                        Error::MaliciousSecurityCheckFailed
+                                        | Error::ParallelDZKPValidationFailed
+                                        | Error::MaliciousRevealFailed,
+                                    ) => {}
                                     Ok(_) | Err(_) => {
                                         panic!(
                                             "Malicious validation check passed when it shouldn't have"
@@ -1,8 +1,9 @@
 use std::{
     convert::Infallible,
-    iter::{self, repeat_n, zip},
+    iter::{repeat_n, zip},
+    mem::size_of,
     num::NonZeroU32,
-    ops::{Not, Range},
+    ops::{Add, Not, Range},
 };
 
 use futures::{
@@ -11,13 +12,16 @@ use futures::{
     FutureExt, Stream, StreamExt, TryStreamExt,
 };
 
+use generic_array::{ArrayLength, GenericArray};
+use typenum::{Unsigned, U10};
+
 use super::aggregation::breakdown_reveal::breakdown_reveal_aggregation;
 use crate::{
     error::{Error, LengthError},
     ff::{
         boolean::Boolean,
         boolean_array::{BooleanArray, BA32, BA7},
-        ArrayAccess, Field, U128Conversions,
+        ArrayAccess, Field, Serializable, U128Conversions,
     },
     helpers::{stream::TryFlattenItersExt, TotalRecords},
     protocol::{
@@ -58,7 +62,7 @@ use crate::{
 pub mod feature_label_dot_product;
 pub(crate) mod step;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct PrfShardedIpaInputRow<BK: SharedValue, TV: SharedValue, TS: SharedValue> {
     pub prf_of_match_key: u64,
     pub is_trigger_bit: Replicated<Boolean>,
@@ -96,6 +100,97 @@ where
     }
 }
 
+/// Rows are sent to other shards before the sort key is computed, so it is not serialized.
+impl<BK: SharedValue, TV: SharedValue, TS: SharedValue> Serializable
+    for PrfShardedIpaInputRow<BK, TV, TS>
+where
+    Replicated<BK>: Serializable,
+    Replicated<TV>: Serializable,
+    Replicated<TS>: Serializable,
+    <Replicated<BK> as Serializable>::Size: Add<U10>,
+    <Replicated<TV> as Serializable>::Size:
+        Add<<<Replicated<BK> as Serializable>::Size as Add<U10>>::Output>,
+    <Replicated<TS> as Serializable>::Size: Add<
+        <<Replicated<TV> as Serializable>::Size as Add<
+            <<Replicated<BK> as Serializable>::Size as Add<U10>>::Output,
+        >>::Output,
+    >,
+    <<Replicated<TS> as Serializable>::Size as Add<
+        <<Replicated<TV> as Serializable>::Size as Add<
+            <<Replicated<BK> as Serializable>::Size as Add<U10>>::Output,
+        >>::Output,
+    >>::Output: ArrayLength,
+{
+    type Size = <<Replicated<TS> as Serializable>::Size as Add<
+        <<Replicated<TV> as Serializable>::Size as Add<
+            <<Replicated<BK> as Serializable>::Size as Add<U10>>::Output,
+        >>::Output,
+    >>::Output;
+    type DeserializationError = Error;
+
+    fn serialize(&self, buf: &mut GenericArray<u8, Self::Size>) {
+        let prf_sz = size_of::<u64>();
+        let it_sz = <Replicated<Boolean> as Serializable>::Size::USIZE;
+        let bk_sz = <Replicated<BK> as Serializable>::Size::USIZE;
+        let tv_sz = <Replicated<TV> as Serializable>::Size::USIZE;
+        let ts_sz = <Replicated<TS> as Serializable>::Size::USIZE;
+
+        buf[..prf_sz].copy_from_slice(&self.prf_of_match_key.to_le_bytes());
+        let mut offset = prf_sz;
+        self.is_trigger_bit.serialize(GenericArray::from_mut_slice(
+            &mut buf[offset..offset + it_sz],
+        ));
+        offset += it_sz;
+        self.breakdown_key.serialize(GenericArray::from_mut_slice(
+            &mut buf[offset..offset + bk_sz],
+        ));
+        offset += bk_sz;
+        self.trigger_value.serialize(GenericArray::from_mut_slice(
+            &mut buf[offset..offset + tv_sz],
+        ));
+        offset += tv_sz;
+        self.timestamp.serialize(GenericArray::from_mut_slice(
+            &mut buf[offset..offset + ts_sz],
+        ));
+    }
+
+    fn deserialize(buf: &GenericArray<u8, Self::Size>) -> Result<Self, Self::DeserializationError> {
+        let prf_sz = size_of::<u64>();
+        let it_sz = <Replicated<Boolean> as Serializable>::Size::USIZE;
+        let bk_sz = <Replicated<BK> as Serializable>::Size::USIZE;
+        let tv_sz = <Replicated<TV> as Serializable>::Size::USIZE;
+        let ts_sz = <Replicated<TS> as Serializable>::Size::USIZE;
+
+        let prf_of_match_key = u64::from_le_bytes(buf[..prf_sz].try_into().unwrap());
+        let mut offset = prf_sz;
+        let is_trigger_bit = Replicated::<Boolean>::deserialize(GenericArray::from_slice(
+            &buf[offset..offset + it_sz],
+        ))
+        .map_err(|e| Error::ParseError(e.into()))?;
+        offset += it_sz;
+        let breakdown_key =
+            Replicated::<BK>::deserialize(GenericArray::from_slice(&buf[offset..offset + bk_sz]))
+                .map_err(|e| Error::ParseError(e.into()))?;
+        offset += bk_sz;
+        let trigger_value =
+            Replicated::<TV>::deserialize(GenericArray::from_slice(&buf[offset..offset + tv_sz]))
+                .map_err(|e| Error::ParseError(e.into()))?;
+        offset += tv_sz;
+        let timestamp =
+            Replicated::<TS>::deserialize(GenericArray::from_slice(&buf[offset..offset + ts_sz]))
+                .map_err(|e| Error::ParseError(e.into()))?;
+
+        Ok(Self {
+            prf_of_match_key,
+            is_trigger_bit,
+            breakdown_key,
+            trigger_value,
+            timestamp,
+            sort_key: Replicated::ZERO,
+        })
+    }
+}
+
 impl<BK: SharedValue, TS: SharedValue, TV: SharedValue> GroupingKey
     for PrfShardedIpaInputRow<BK, TV, TS>
 {
@@ -499,6 +594,59 @@ where
     Vec<Replicated<HV>>:
         for<'a> TransposeFrom<&'a BitDecomposed<Replicated<Boolean, B>>, Error = LengthError>,
 {
+    let user_contributions = attribute_cap::<_, BK, TV, TS, SS_BITS, B>(
+        sh_ctx.clone(),
+        input_rows,
+        attribution_window_seconds,
+        histogram,
+    )
+    .await?;
+    if user_contributions.is_empty() {
+        return Ok(BitDecomposed::new(repeat_n(
+            Replicated::<Boolean, B>::ZERO,
+            B,
+        )));
+    }
+
+    breakdown_reveal_aggregation::<_, BK, TV, HV, B>(
+        sh_ctx.narrow(&Step::Aggregate),
+        user_contributions,
+        padding_parameters,
+    )
+    .await
+}
+
+/// Attribution and per-user capping part of [`attribute_cap_aggregate`].
+///
+/// It does not require communication with other shards, so sharded protocols use it to
+/// attribute the users that reside on each shard and aggregate the results across all
+/// shards afterwards. Returns an empty vector if there are no users with more than one
+/// record.
+///
+/// # Errors
+/// Propagates errors from multiplications
+/// # Panics
+/// Propagates errors from multiplications
+pub async fn attribute_cap<'ctx, C, BK, TV, TS, const SS_BITS: usize, const B: usize>(
+    sh_ctx: C,
+    input_rows: Vec<PrfShardedIpaInputRow<BK, TV, TS>>,
+    attribution_window_seconds: Option<NonZeroU32>,
+    histogram: &[usize],
+) -> Result<Vec<SecretSharedAttributionOutputs<BK, TV>>, Error>
+where
+    C: UpgradableContext + 'ctx,
+    BK: BreakdownKey<B>,
+    TV: BooleanArray + U128Conversions,
+    TS: BooleanArray + U128Conversions,
+    Replicated<Boolean>: BooleanProtocols<DZKPUpgraded<C>>,
+    Replicated<BK>: BooleanArrayMul<DZKPUpgraded<C>>,
+    Replicated<TS>: BooleanArrayMul<DZKPUpgraded<C>>,
+    Replicated<TV>: BooleanArrayMul<DZKPUpgraded<C>>,
+{
+    if histogram.len() < 2 {
+        return Ok(Vec::new());
+    }
+
     // Get the validator and context to use for Boolean multiplication operations.
     // Record IDs count users. The maximum number of multiplications per record (user) is:
     // (max_events - 1) * multiplictions_per_record, because the attribution circuit is
@@ -527,9 +675,7 @@ where
     // Chunk the incoming stream of records into stream of vectors of records with the same PRF
     let mut input_stream = stream::iter(input_rows);
     let Some(first_row) = input_stream.next().await else {
-        return Ok(BitDecomposed::new(
-            iter::repeat(Replicated::<Boolean, B>::ZERO).take(B),
-        ));
+        return Ok(Vec::new());
     };
     let rows_chunked_by_user = chunk_rows_by_user(input_stream, first_row);
 
@@ -543,13 +689,7 @@ where
         attribution_window_seconds,
     );
 
-    let user_contributions = flattened_user_results.try_collect::<Vec<_>>().await?;
-    breakdown_reveal_aggregation::<_, BK, TV, HV, B>(
-        sh_ctx.narrow(&Step::Aggregate),
-        user_contributions,
-        padding_parameters,
-    )
-    .await
+    flattened_user_results.try_collect::<Vec<_>>().await
 }
 
 #[tracing::instrument(name = "attribute_cap", skip_all, fields(unique_match_keys = input.len()))]
@@ -884,17 +1024,19 @@ where
 pub mod tests {
     use std::{iter::repeat_n, num::NonZeroU32};
 
+    use generic_array::GenericArray;
+
     use super::{AttributionOutputs, PrfShardedIpaInputRow};
     use crate::{
         ff::{
             boolean::Boolean,
             boolean_array::{BooleanArray, BA16, BA20, BA3, BA5, BA8},
-            Field, U128Conversions,
+            Field, Serializable, U128Conversions,
         },
         protocol::ipa_prf::{
             oprf_padding::PaddingParameters, prf_sharding::attribute_cap_aggregate,
         },
-        rand::Rng,
+        rand::{thread_rng, Rng},
         secret_sharing::{
             replicated::semi_honest::AdditiveShare as Replicated, IntoShares, SharedValue,
             TransposeFrom,
@@ -1292,4 +1434,21 @@ pub mod tests {
             );
         });
     }
+
+    #[test]
+    fn serialize_input_row() {
+        let rng = &mut thread_rng();
+        let input = oprf_test_input_with_timestamp::<BA8>(rng.gen(), true, 17, 5, 12345);
+        for row in input.share_with(rng) {
+            let mut buf = GenericArray::default();
+            row.serialize(&mut buf);
+            let restored = PrfShardedIpaInputRow::<BA8, BA3, BA20>::deserialize(&buf).unwrap();
+            assert_eq!(row.prf_of_match_key, restored.prf_of_match_key);
+            assert_eq!(row.is_trigger_bit, restored.is_trigger_bit);
+            assert_eq!(row.breakdown_key, restored.breakdown_key);
+            assert_eq!(row.trigger_value, restored.trigger_value);
+            assert_eq!(row.timestamp, restored.timestamp);
+            assert_eq!(Replicated::ZERO, restored.sort_key);
+        }
+    }
 }
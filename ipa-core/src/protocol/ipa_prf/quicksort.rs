@@ -1,4 +1,4 @@
-use std::{convert::Infallible, mem, ops::Range};
+use std::{convert::Infallible, marker::PhantomData, mem, ops::Range};
 
 use bitvec::prelude::{BitVec, Lsb0};
 use futures::stream::{self, repeat, StreamExt, TryStreamExt};
@@ -6,13 +6,13 @@ use typenum::Const;
 
 use crate::{
     error::{Error, LengthError, UnwrapInfallible},
-    ff::{boolean::Boolean, boolean_array::BooleanArray, Expand},
+    ff::{boolean::Boolean, boolean_array::BooleanArray, Expand, U128Conversions},
     helpers::{
         stream::{div_round_up, process_stream_by_chunks, ChunkBuffer, TryFlattenItersExt},
         TotalRecords,
     },
     protocol::{
-        basics::reveal,
+        basics::{reveal, ShareKnownValue},
         boolean::{step::ThirtyTwoBitStep, NBitStep},
         context::{
             dzkp_validator::{validated_seq_join, DZKPValidator, TARGET_PROOF_SIZE},
@@ -20,14 +20,15 @@ use crate::{
         },
         ipa_prf::{
             boolean_ops::comparison_and_subtraction_sequential::compare_gt,
-            step::{QuicksortPassStep, QuicksortStep as Step},
+            shuffle::{MaliciousShuffleShare, Shuffle, ShuffleShare, Shuffleable},
+            step::{QuicksortPassStep, QuicksortStep as Step, SecureSortStep},
             SORT_CHUNK,
         },
         BooleanProtocols, RecordId,
     },
     secret_sharing::{
-        replicated::semi_honest::AdditiveShare, BitDecomposed, SharedValue, TransposeFrom,
-        Vectorizable,
+        replicated::{semi_honest::AdditiveShare, ReplicatedSecretSharing},
+        BitDecomposed, SharedValue, TransposeFrom, Vectorizable,
     },
     seq_join::seq_join,
     utils::non_zero_prev_power_of_two,
@@ -102,6 +103,182 @@ fn quicksort_proof_chunk(key_bits: usize) -> usize {
     non_zero_prev_power_of_two(TARGET_PROOF_SIZE / key_bits / SORT_CHUNK)
 }
 
+/// A row paired with its unique sort key. Both are packed into a single `W` value, so they
+/// can go through the shuffle together.
+#[derive(Clone, Default)]
+struct KeyedRow<S, UK: SharedValue, W> {
+    row: S,
+    key: AdditiveShare<UK>,
+    phantom_data: PhantomData<W>,
+}
+
+impl<S, UK, W> KeyedRow<S, UK, W>
+where
+    S: Shuffleable,
+    S::Share: BooleanArray,
+    UK: BooleanArray,
+    W: BooleanArray,
+{
+    fn pack(row: &S::Share, key: &UK) -> W {
+        let (row, key) = (row.as_bitslice(), key.as_bitslice());
+        let mut packed = W::ZERO;
+        packed.as_mut_bitslice()[..row.len()].copy_from_bitslice(row);
+        packed.as_mut_bitslice()[row.len()..row.len() + key.len()].copy_from_bitslice(key);
+        packed
+    }
+
+    fn unpack(packed: &W) -> (S::Share, UK) {
+        let row_bits = usize::try_from(<S::Share as SharedValue>::BITS).unwrap();
+        let key_bits = usize::try_from(UK::BITS).unwrap();
+        let packed = packed.as_bitslice();
+        (
+            S::Share::try_from(&packed[..row_bits]).unwrap(),
+            UK::try_from(&packed[row_bits..row_bits + key_bits]).unwrap(),
+        )
+    }
+}
+
+impl<S, UK, W> Shuffleable for KeyedRow<S, UK, W>
+where
+    S: Shuffleable,
+    S::Share: BooleanArray,
+    UK: BooleanArray,
+    W: BooleanArray + ShuffleShare,
+{
+    type Share = W;
+
+    fn left(&self) -> Self::Share {
+        Self::pack(&self.row.left(), &self.key.left())
+    }
+
+    fn right(&self) -> Self::Share {
+        Self::pack(&self.row.right(), &self.key.right())
+    }
+
+    fn new(l: Self::Share, r: Self::Share) -> Self {
+        let (row_l, key_l) = Self::unpack(&l);
+        let (row_r, key_r) = Self::unpack(&r);
+        Self {
+            row: S::new(row_l, row_r),
+            key: ReplicatedSecretSharing::new(key_l, key_r),
+            phantom_data: PhantomData,
+        }
+    }
+}
+
+/// Appends `counter` to the key, so that the key occupies the most significant bits and
+/// the counter breaks ties between equal keys.
+fn unique_key<C, K, UK>(ctx: &C, key: &AdditiveShare<K>, counter: usize) -> AdditiveShare<UK>
+where
+    C: Context,
+    K: BooleanArray,
+    UK: BooleanArray + U128Conversions,
+{
+    let counter_bits = usize::try_from(UK::BITS - K::BITS).unwrap();
+    let counter = AdditiveShare::<UK>::share_known_value(ctx, UK::truncate_from(counter as u128));
+    let append = |mut counter: UK, key: K| {
+        counter.as_mut_bitslice()[counter_bits..].copy_from_bitslice(key.as_bitslice());
+        counter
+    };
+
+    ReplicatedSecretSharing::new(
+        append(counter.left(), key.left()),
+        append(counter.right(), key.right()),
+    )
+}
+
+/// Secure quicksort using MPC comparisons and a key extraction function `get_key`.
+///
+/// Unlike [`quicksort_ranges_by_key_insecure`], this sort does not leak anything about
+/// the keys, even if they are not unique. Before sorting, every key is extended with a unique
+/// counter (its position in `list`) and the rows are shuffled together with the extended keys.
+/// The comparisons revealed by quicksort are then a function of the shuffle permutation and the
+/// relative order of the rows in `list`, but not of whether the keys are equal.
+///
+/// The extended key is `UK`. Its most significant `K::BITS` bits hold the key and the
+/// remaining bits hold the counter. `W` is used to carry a row and its extended key through
+/// the shuffle, so it must be large enough to hold `S::Share` and `UK`.
+///
+/// Set `desc` to `true` for descending ordering.
+///
+/// For malicious contexts, the shuffle is a malicious shuffle and the comparisons are
+/// verified with DZKPs.
+///
+/// # Errors
+/// Will propagate errors from shuffle, transport and a few typecasts
+///
+/// # Panics
+/// If `UK` can't hold `K` and a counter for every row in `list`, if `UK` is wider than 32 bits,
+/// or if `W` can't hold `S::Share` and `UK`.
+pub async fn quicksort_by_key<C, S, K, UK, W, F>(
+    ctx: C,
+    list: Vec<S>,
+    desc: bool,
+    get_key: F,
+) -> Result<Vec<S>, Error>
+where
+    C: UpgradableContext + Shuffle,
+    S: Shuffleable + Clone + Default,
+    S::Share: BooleanArray,
+    F: Fn(&S) -> &AdditiveShare<K>,
+    K: BooleanArray,
+    UK: BooleanArray + U128Conversions,
+    W: BooleanArray + ShuffleShare + MaliciousShuffleShare,
+    AdditiveShare<Boolean, SORT_CHUNK>: BooleanProtocols<DZKPUpgraded<C>, SORT_CHUNK>,
+    BitDecomposed<AdditiveShare<Boolean, SORT_CHUNK>>:
+        for<'a> TransposeFrom<&'a [AdditiveShare<UK>; SORT_CHUNK], Error = Infallible>,
+{
+    assert!(
+        K::BITS < UK::BITS && list.len() <= 1 << (UK::BITS - K::BITS),
+        "{} bits are not enough to hold a {}-bit key and a counter for {} rows",
+        UK::BITS,
+        K::BITS,
+        list.len()
+    );
+    assert!(
+        <S::Share as SharedValue>::BITS + UK::BITS <= <W as SharedValue>::BITS,
+        "{} bits are not enough to hold a {}-bit row and a {}-bit key",
+        <W as SharedValue>::BITS,
+        <S::Share as SharedValue>::BITS,
+        UK::BITS
+    );
+
+    let keyed_rows = list
+        .into_iter()
+        .enumerate()
+        .map(|(i, row)| KeyedRow::<S, UK, W> {
+            key: unique_key(&ctx, get_key(&row), i),
+            row,
+            phantom_data: PhantomData,
+        })
+        .collect::<Vec<_>>();
+
+    let mut shuffled = ctx
+        .narrow(&SecureSortStep::Shuffle)
+        .shuffle(keyed_rows)
+        .await?;
+
+    #[allow(clippy::single_range_in_vec_init)]
+    let ranges = if shuffled.is_empty() {
+        Vec::new()
+    } else {
+        vec![0..shuffled.len()]
+    };
+    quicksort_ranges_by_key_insecure(
+        ctx.narrow(&SecureSortStep::Sort),
+        &mut shuffled,
+        desc,
+        |keyed_row| &keyed_row.key,
+        ranges,
+    )
+    .await?;
+
+    Ok(shuffled
+        .into_iter()
+        .map(|keyed_row| keyed_row.row)
+        .collect())
+}
+
 /// Insecure quicksort using MPC comparisons and a key extraction function `get_key`.
 ///
 /// `get_key` takes as input an element in the slice and outputs the key by which we sort by
@@ -111,6 +288,7 @@ fn quicksort_proof_chunk(key_bits: usize) -> usize {
 /// Set `desc` to `true` for descending ordering.
 ///
 /// This version of quicksort is insecure because it does not enforce the uniqueness of the sorted elements.
+/// Use [`quicksort_by_key`] when keys may repeat.
 /// To see why this leaks information: take a list with all elements having equal values.
 /// Quicksort for that list runs in time `O(n^2)` while for unique elements, where
 /// it is only expected to run in time `O(n log n)`.
@@ -276,19 +454,31 @@ where
 pub mod tests {
     use std::{
         cmp::{min, Ordering},
-        iter::{repeat, repeat_with},
+        collections::BTreeMap,
+        iter::{repeat, repeat_n, repeat_with},
     };
 
     use ipa_step_derive::CompactStep;
     use rand::Rng;
 
     use crate::{
-        ff::{boolean_array::BA32, U128Conversions},
-        protocol::{context::Context, ipa_prf::quicksort::quicksort_ranges_by_key_insecure},
+        ff::{
+            boolean_array::{BA16, BA32, BA64},
+            U128Conversions,
+        },
+        protocol::{
+            context::Context,
+            ipa_prf::quicksort::{quicksort_by_key, quicksort_ranges_by_key_insecure},
+        },
         rand::thread_rng,
         secret_sharing::{replicated::semi_honest::AdditiveShare, IntoShares},
+        telemetry::{
+            labels,
+            metrics::{BYTES_SENT, RECORDS_SENT},
+            stats::QueryMetrics,
+        },
         test_executor::run,
-        test_fixture::{Reconstruct, Runner, TestWorld},
+        test_fixture::{Reconstruct, Runner, TestWorld, TestWorldConfig},
     };
 
     type TestSortKey = BA32;
@@ -774,4 +964,118 @@ pub mod tests {
             }
         });
     }
+
+    /// Sorts `records` with [`quicksort_by_key`] using a 16-bit key, a 16-bit tiebreak counter
+    /// and `BA64` to carry both through the shuffle.
+    async fn secure_sort(
+        world: &TestWorld,
+        records: Vec<BA16>,
+        desc: bool,
+        malicious: bool,
+    ) -> Vec<u128> {
+        let sorted: Vec<BA16> = if malicious {
+            world
+                .malicious(records.into_iter(), |ctx, r| async move {
+                    quicksort_by_key::<_, _, BA16, BA32, BA64, _>(ctx, r, desc, |x| x)
+                        .await
+                        .unwrap()
+                })
+                .await
+                .reconstruct()
+        } else {
+            world
+                .semi_honest(records.into_iter(), |ctx, r| async move {
+                    quicksort_by_key::<_, _, BA16, BA32, BA64, _>(ctx, r, desc, |x| x)
+                        .await
+                        .unwrap()
+                })
+                .await
+                .reconstruct()
+        };
+
+        sorted.into_iter().map(|x| x.as_u128()).collect()
+    }
+
+    #[test]
+    fn test_quicksort_by_key() {
+        run(|| async move {
+            let world = TestWorld::default();
+            let mut rng = thread_rng();
+
+            for malicious in [false, true] {
+                for desc in [false, true] {
+                    // few distinct values, so most of the keys repeat
+                    let records: Vec<BA16> =
+                        repeat_with(|| BA16::truncate_from(rng.gen_range(0..4_u128)))
+                            .take(40)
+                            .collect();
+
+                    let mut expected: Vec<u128> =
+                        records.iter().map(U128Conversions::as_u128).collect();
+                    expected.sort_unstable();
+                    if desc {
+                        expected.reverse();
+                    }
+
+                    assert_eq!(
+                        secure_sort(&world, records, desc, malicious).await,
+                        expected
+                    );
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn test_quicksort_by_key_empty() {
+        run(|| async move {
+            let world = TestWorld::default();
+            for malicious in [false, true] {
+                assert!(secure_sort(&world, Vec::new(), false, malicious)
+                    .await
+                    .is_empty());
+            }
+        });
+    }
+
+    /// The communication of the secure sort must not depend on whether the keys repeat.
+    /// Sorting a list of identical keys and a list of distinct keys in the same order, with
+    /// the same randomness, must send exactly the same records and bytes on every step and
+    /// from every helper.
+    #[test]
+    fn test_quicksort_by_key_communication_independent_of_duplicates() {
+        async fn communication(
+            seed: u64,
+            records: Vec<BA16>,
+            malicious: bool,
+        ) -> Vec<BTreeMap<String, u64>> {
+            let world =
+                TestWorld::new_with(TestWorldConfig::default().with_seed(seed).enable_metrics());
+            secure_sort(&world, records, false, malicious).await;
+
+            let snapshot = QueryMetrics::from(&world.metrics_snapshot());
+            [RECORDS_SENT, BYTES_SENT]
+                .into_iter()
+                .flat_map(|metric| {
+                    [labels::STEP, labels::ROLE]
+                        .map(|dimension| snapshot.get_dimension(metric, dimension))
+                })
+                .collect()
+        }
+
+        run(|| async move {
+            const COUNT: u128 = 50;
+            let seed = thread_rng().gen();
+            let identical = repeat_n(BA16::truncate_from(7_u128), usize::try_from(COUNT).unwrap())
+                .collect::<Vec<_>>();
+            let distinct = (0..COUNT).map(BA16::truncate_from).collect::<Vec<_>>();
+
+            for malicious in [false, true] {
+                let identical_comm = communication(seed, identical.clone(), malicious).await;
+                let distinct_comm = communication(seed, distinct.clone(), malicious).await;
+                assert!(identical_comm.iter().all(|values| !values.is_empty()));
+                assert_eq!(identical_comm, distinct_comm);
+            }
+        });
+    }
 }
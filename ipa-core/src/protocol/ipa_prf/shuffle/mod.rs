@@ -20,7 +20,7 @@ pub(crate) mod step; // must be pub(crate) for compact gate gen
 use base::shuffle_protocol as base_shuffle;
 use malicious::{malicious_sharded_shuffle, malicious_shuffle};
 use sharded::shuffle as sharded_shuffle;
-pub use sharded::{MaliciousShuffleable, Shuffleable};
+pub use sharded::{MaliciousShuffleShare, MaliciousShuffleable, ShuffleShare, Shuffleable};
 
 use crate::sharding::NotSharded;
 
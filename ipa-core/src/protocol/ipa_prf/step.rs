@@ -6,6 +6,8 @@ pub(crate) enum IpaPrfStep {
     PaddingDp,
     #[step(child = crate::protocol::ipa_prf::shuffle::step::OPRFShuffleStep)]
     Shuffle,
+    #[step(child = crate::protocol::ipa_prf::shuffle::step::ShardedShuffleStep)]
+    ShardedShuffle,
     #[step(child = crate::protocol::ipa_prf::boolean_ops::step::Fp25519ConversionStep)]
     ConvertFp25519,
     #[step(child = crate::protocol::context::step::DzkpValidationProtocolStep)]
@@ -13,10 +15,16 @@ pub(crate) enum IpaPrfStep {
     PrfKeyGen,
     #[step(child = crate::protocol::context::step::MaliciousProtocolStep)]
     EvalPrf,
+    ReshardByPrf,
+    AdaptActiveWork,
     #[step(child = QuicksortStep)]
     SortByTimestamp,
     #[step(child = crate::protocol::ipa_prf::prf_sharding::step::AttributionStep)]
     Attribution,
+    #[step(child = crate::protocol::group_by::step::GroupByStep)]
+    ShardedAggregate,
+    #[step(child = crate::protocol::hybrid::step::FinalizeSteps)]
+    Finalize,
     #[step(child = crate::protocol::dp::step::DPStep, name = "dp")]
     DifferentialPrivacy,
     #[step(child = crate::protocol::context::step::DzkpValidationProtocolStep)]
@@ -32,6 +40,14 @@ pub(crate) enum QuicksortStep {
     QuicksortPassValidate(usize),
 }
 
+#[derive(CompactStep)]
+pub(crate) enum SecureSortStep {
+    #[step(child = crate::protocol::ipa_prf::shuffle::step::OPRFShuffleStep)]
+    Shuffle,
+    #[step(child = QuicksortStep)]
+    Sort,
+}
+
 #[derive(CompactStep)]
 pub(crate) enum QuicksortPassStep {
     #[step(child = crate::protocol::boolean::step::ThirtyTwoBitStep)]
@@ -50,4 +66,5 @@ pub(crate) enum PrfStep {
     MultMaskWithPRFInput,
     RevealR,
     Revealz,
+    RevealCheck,
 }
@@ -41,6 +41,10 @@ pub enum DeadCodeStep {
     FeatureLabelDotProduct,
     #[step(child = crate::protocol::ipa_prf::boolean_ops::step::MultiplicationStep)]
     Multiplication,
+    #[step(child = crate::protocol::ipa_prf::step::SecureSortStep)]
+    SecureSort,
+    #[step(child = crate::protocol::hybrid::step::CrossDeviceStep)]
+    CrossDeviceHybrid,
 }
 
 #[derive(CompactStep)]
@@ -0,0 +1,697 @@
+//! Dry-run cost estimation for queries.
+//!
+//! MPC protocols are data-oblivious: the number of records and bytes helpers exchange and the
+//! amount of randomness they draw depend on the query parameters and on the number of input rows,
+//! never on the input values. This makes it possible to predict what a large query is going to
+//! cost each helper from the metrics collected while running the same query on small inputs.
+//!
+//! Each small run is captured as a [`Calibration`]. [`CostModel`] fits a linear model (a cost per
+//! input row plus a fixed cost) for every counter and every helper, and uses it to build a
+//! [`CostEstimate`] for any query size, without running MPC.
+//!
+//! Metrics are recorded per gate. The model predicts the number of records every gate sends
+//! and receives, and uses the record size observed for that gate to turn it into bytes. Gates
+//! are resolved against the compact-gate step tree from `ipa-step`, which lists every gate the
+//! protocol can use, so that runs with descriptive and compact gates produce the same model.
+//! The tree is also used to break down the estimate by protocol phase: the top two levels of the
+//! step tree below the root.
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fmt::{Display, Formatter},
+    sync::OnceLock,
+};
+
+use ipa_step::CompactStep;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    helpers::query::{QueryConfig, QuerySize},
+    protocol::step::ProtocolStep,
+    telemetry::{
+        labels,
+        metrics::{
+            BYTES_RECEIVED, BYTES_SENT, DZKP_PROOFS_VERIFIED, INDEXED_PRSS_GENERATED,
+            QUERY_ELAPSED_MS, RECORDS_RECEIVED, RECORDS_SENT, SEQUENTIAL_PRSS_GENERATED,
+        },
+        stats::QueryMetrics,
+    },
+};
+
+/// Phase that gates missing from the step tree are attributed to.
+const UNKNOWN_PHASE: &str = "other";
+
+#[derive(thiserror::Error, Debug)]
+pub enum EstimateError {
+    #[error("at least one calibration run is required")]
+    NoCalibration,
+    #[error("calibration runs were collected for {calibrated:?}, but {requested:?} was requested")]
+    QueryMismatch {
+        calibrated: QueryConfig,
+        requested: QueryConfig,
+    },
+    #[error("calibration runs were collected with {calibrated} shards per helper, got a run with {requested}")]
+    ShardCountMismatch { calibrated: usize, requested: usize },
+}
+
+/// Metrics collected by every helper while running a query, used to calibrate [`CostModel`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Calibration {
+    pub config: QueryConfig,
+    pub shard_count: usize,
+    /// Metrics of each helper, added up across all of its shards.
+    pub helpers: [QueryMetrics; 3],
+}
+
+impl Calibration {
+    /// Creates a calibration run from the metrics reported by every shard of every helper.
+    /// `shards` is indexed by shard first, then by helper, the same way clients are organized.
+    #[must_use]
+    pub fn new(config: QueryConfig, shards: &[[QueryMetrics; 3]]) -> Self {
+        let mut helpers: [QueryMetrics; 3] = Default::default();
+        for shard in shards {
+            for (helper, metrics) in helpers.iter_mut().zip(shard) {
+                helper.merge(metrics);
+            }
+        }
+
+        Self {
+            config,
+            shard_count: shards.len(),
+            helpers,
+        }
+    }
+}
+
+/// Predicted cost of running a query for a single helper, summed across all of its shards.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct HelperCost {
+    pub bytes_sent: u64,
+    pub records_sent: u64,
+    /// Number of values drawn from indexed and sequential PRSS.
+    pub prss_draws: u64,
+    pub dzkp_proofs: u64,
+    pub elapsed_ms: u64,
+    /// The largest volume this helper sends and receives within a single protocol phase.
+    /// Every phase materializes the records it exchanges before the next one starts, so this
+    /// approximates the memory footprint of the query.
+    pub peak_memory_bytes: u64,
+    pub bytes_sent_per_phase: BTreeMap<String, u64>,
+    pub records_sent_per_gate: BTreeMap<String, u64>,
+}
+
+/// Predicted cost of running a query on each helper.
+#[derive(Clone, Debug, Serialize)]
+pub struct CostEstimate {
+    pub config: QueryConfig,
+    pub shard_count: usize,
+    pub helpers: [HelperCost; 3],
+}
+
+/// Linear model of a single counter: `per_record * query_size + fixed`.
+#[derive(Clone, Copy, Debug, Default)]
+struct Linear {
+    per_record: f64,
+    fixed: f64,
+}
+
+impl Linear {
+    /// Least squares fit over `(query size, value)` points. If all points have the same size,
+    /// there is no way to tell fixed costs apart, so the value is assumed to be proportional
+    /// to the query size.
+    #[allow(clippy::cast_precision_loss)]
+    fn fit<I: IntoIterator<Item = (QuerySize, u64)>>(points: I) -> Self {
+        let points = points
+            .into_iter()
+            .map(|(size, value)| (f64::from(u32::from(size)), value as f64))
+            .collect::<Vec<_>>();
+        let n = points.len() as f64;
+        let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+        let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+        let var_x = points
+            .iter()
+            .map(|(x, _)| (x - mean_x).powi(2))
+            .sum::<f64>();
+
+        if var_x < f64::EPSILON {
+            Self {
+                per_record: mean_y / mean_x,
+                fixed: 0.0,
+            }
+        } else {
+            let cov = points
+                .iter()
+                .map(|(x, y)| (x - mean_x) * (y - mean_y))
+                .sum::<f64>();
+            let per_record = cov / var_x;
+            Self {
+                per_record,
+                fixed: mean_y - per_record * mean_x,
+            }
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn predict(&self, size: QuerySize) -> u64 {
+        let value = (self.per_record * f64::from(u32::from(size)) + self.fixed).round();
+        if value > 0.0 {
+            value as u64
+        } else {
+            0
+        }
+    }
+}
+
+/// Gates of the protocol step tree, as enumerated by [`CompactStep`] for [`ProtocolStep`].
+/// Gate names are stored without the leading slash compact gates use.
+struct StepTree {
+    gates: HashSet<String>,
+}
+
+impl StepTree {
+    fn get() -> &'static Self {
+        static TREE: OnceLock<StepTree> = OnceLock::new();
+        TREE.get_or_init(|| Self {
+            gates: (0..ProtocolStep::STEP_COUNT)
+                .map(ProtocolStep::step_string)
+                .collect(),
+        })
+    }
+
+    /// Returns the name of the gate in the step tree. Descriptive gates include the name of the
+    /// root step, compact gates start with a slash. Both resolve to the same name.
+    fn resolve<'a>(&self, gate: &'a str) -> Option<&'a str> {
+        let path = gate.trim_start_matches('/');
+        let path = path.strip_prefix("protocol/").unwrap_or(path);
+        self.gates.contains(path).then_some(path)
+    }
+
+    /// Returns the phase a gate belongs to: its ancestor two levels below the root of the tree.
+    fn phase<'a>(&self, gate: &'a str) -> &'a str {
+        let Some(path) = self.resolve(gate) else {
+            return UNKNOWN_PHASE;
+        };
+        match path.match_indices('/').nth(1) {
+            Some((end, _)) => &path[..end],
+            None => path,
+        }
+    }
+}
+
+/// Records and bytes a single gate sends and receives.
+#[derive(Default)]
+struct GateSample {
+    records_sent: u64,
+    bytes_sent: u64,
+    records_received: u64,
+    bytes_received: u64,
+}
+
+/// Values of the counters the model is built from, extracted from the metrics of one helper.
+struct Sample {
+    prss_draws: u64,
+    dzkp_proofs: u64,
+    elapsed_ms: u64,
+    gates: BTreeMap<String, GateSample>,
+}
+
+impl From<&QueryMetrics> for Sample {
+    fn from(metrics: &QueryMetrics) -> Self {
+        let tree = StepTree::get();
+        let mut gates = BTreeMap::<String, GateSample>::new();
+        let mut add = |counter: &str, field: fn(&mut GateSample) -> &mut u64| {
+            for (gate, value) in metrics.get_dimension(counter, labels::STEP) {
+                let gate = tree.resolve(&gate).map_or(gate.clone(), str::to_string);
+                *field(gates.entry(gate).or_default()) += value;
+            }
+        };
+        add(RECORDS_SENT, |g| &mut g.records_sent);
+        add(BYTES_SENT, |g| &mut g.bytes_sent);
+        add(RECORDS_RECEIVED, |g| &mut g.records_received);
+        add(BYTES_RECEIVED, |g| &mut g.bytes_received);
+
+        Self {
+            prss_draws: metrics.get_counter(INDEXED_PRSS_GENERATED)
+                + metrics.get_counter(SEQUENTIAL_PRSS_GENERATED),
+            dzkp_proofs: metrics.get_counter(DZKP_PROOFS_VERIFIED),
+            elapsed_ms: metrics.get_counter(QUERY_ELAPSED_MS),
+            gates,
+        }
+    }
+}
+
+/// The number of records a gate exchanges grows with the query size, but the size of each
+/// record is fixed by the protocol, so only record counts are extrapolated.
+struct GateModel {
+    records_sent: Linear,
+    records_received: Linear,
+    sent_record_size: f64,
+    received_record_size: f64,
+}
+
+impl GateModel {
+    fn fit(gate: &str, samples: &[(QuerySize, Sample)]) -> Self {
+        let gate_samples = || {
+            samples
+                .iter()
+                .filter_map(|(n, s)| s.gates.get(gate).map(|g| (*n, g)))
+        };
+        let record_size = |records: fn(&GateSample) -> u64, bytes: fn(&GateSample) -> u64| {
+            let (records, bytes) =
+                gate_samples().fold((0, 0), |(r, b), (_, g)| (r + records(g), b + bytes(g)));
+            #[allow(clippy::cast_precision_loss)]
+            if records == 0 {
+                0.0
+            } else {
+                bytes as f64 / records as f64
+            }
+        };
+        let fit = |records: fn(&GateSample) -> u64| {
+            Linear::fit(
+                samples
+                    .iter()
+                    .map(|(n, s)| (*n, s.gates.get(gate).map_or(0, records))),
+            )
+        };
+
+        Self {
+            records_sent: fit(|g| g.records_sent),
+            records_received: fit(|g| g.records_received),
+            sent_record_size: record_size(|g| g.records_sent, |g| g.bytes_sent),
+            received_record_size: record_size(|g| g.records_received, |g| g.bytes_received),
+        }
+    }
+}
+
+struct HelperModel {
+    prss_draws: Linear,
+    dzkp_proofs: Linear,
+    elapsed_ms: Linear,
+    gates: BTreeMap<String, GateModel>,
+}
+
+impl HelperModel {
+    fn fit(samples: &[(QuerySize, Sample)]) -> Self {
+        let fit = |f: fn(&Sample) -> u64| Linear::fit(samples.iter().map(|(n, s)| (*n, f(s))));
+        let gates = samples
+            .iter()
+            .flat_map(|(_, s)| s.gates.keys())
+            .collect::<BTreeSet<_>>();
+
+        Self {
+            prss_draws: fit(|s| s.prss_draws),
+            dzkp_proofs: fit(|s| s.dzkp_proofs),
+            elapsed_ms: fit(|s| s.elapsed_ms),
+            gates: gates
+                .into_iter()
+                .map(|gate| (gate.clone(), GateModel::fit(gate, samples)))
+                .collect(),
+        }
+    }
+
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    fn predict(&self, size: QuerySize) -> HelperCost {
+        let tree = StepTree::get();
+        let mut cost = HelperCost {
+            prss_draws: self.prss_draws.predict(size),
+            dzkp_proofs: self.dzkp_proofs.predict(size),
+            elapsed_ms: self.elapsed_ms.predict(size),
+            ..HelperCost::default()
+        };
+        let mut phase_volume = BTreeMap::<&str, u64>::new();
+        for (gate, model) in &self.gates {
+            let records_sent = model.records_sent.predict(size);
+            let bytes_sent = (records_sent as f64 * model.sent_record_size).round() as u64;
+            let bytes_received = (model.records_received.predict(size) as f64
+                * model.received_record_size)
+                .round() as u64;
+            let phase = tree.phase(gate);
+
+            cost.records_sent += records_sent;
+            cost.bytes_sent += bytes_sent;
+            *cost
+                .bytes_sent_per_phase
+                .entry(phase.to_string())
+                .or_default() += bytes_sent;
+            *phase_volume.entry(phase).or_default() += bytes_sent + bytes_received;
+            if records_sent > 0 {
+                cost.records_sent_per_gate
+                    .insert(gate.clone(), records_sent);
+            }
+        }
+        cost.peak_memory_bytes = phase_volume.into_values().max().unwrap_or_default();
+
+        cost
+    }
+}
+
+/// Predicts the cost of a query from the metrics of small runs of the same query.
+///
+/// Runs of at least two different sizes are required to account for costs that do not depend
+/// on the number of rows, such as PRSS setup. The model extrapolates linearly, so costs that
+/// grow faster than that (sorting, for example) are underestimated. Gates that are only used
+/// by larger queries are not accounted for either. Calibrating with runs closer to the target
+/// size reduces the error.
+pub struct CostModel {
+    config: QueryConfig,
+    shard_count: usize,
+    helpers: [HelperModel; 3],
+}
+
+impl CostModel {
+    /// Fits the model to the given calibration runs.
+    ///
+    /// ## Errors
+    /// If there are no calibration runs or if they were collected for different queries or
+    /// with different number of shards.
+    pub fn fit(calibrations: &[Calibration]) -> Result<Self, EstimateError> {
+        let first = calibrations.first().ok_or(EstimateError::NoCalibration)?;
+        for calibration in calibrations {
+            if !same_query(&first.config, &calibration.config) {
+                return Err(EstimateError::QueryMismatch {
+                    calibrated: first.config,
+                    requested: calibration.config,
+                });
+            }
+            if first.shard_count != calibration.shard_count {
+                return Err(EstimateError::ShardCountMismatch {
+                    calibrated: first.shard_count,
+                    requested: calibration.shard_count,
+                });
+            }
+        }
+
+        let helpers = [0, 1, 2].map(|i| {
+            HelperModel::fit(
+                &calibrations
+                    .iter()
+                    .map(|c| (c.config.size, Sample::from(&c.helpers[i])))
+                    .collect::<Vec<_>>(),
+            )
+        });
+
+        Ok(Self {
+            config: first.config,
+            shard_count: first.shard_count,
+            helpers,
+        })
+    }
+
+    /// Returns the configuration of the query this model was calibrated for.
+    #[must_use]
+    pub fn config(&self) -> &QueryConfig {
+        &self.config
+    }
+
+    /// Predicts the cost of running the given query.
+    ///
+    /// ## Errors
+    /// If the query differs from the one this model was calibrated for in anything but size.
+    pub fn estimate(&self, config: &QueryConfig) -> Result<CostEstimate, EstimateError> {
+        if !same_query(&self.config, config) {
+            return Err(EstimateError::QueryMismatch {
+                calibrated: self.config,
+                requested: *config,
+            });
+        }
+
+        Ok(CostEstimate {
+            config: *config,
+            shard_count: self.shard_count,
+            helpers: self.helpers.each_ref().map(|h| h.predict(config.size)),
+        })
+    }
+}
+
+fn same_query(a: &QueryConfig, b: &QueryConfig) -> bool {
+    a.field_type == b.field_type && a.query_type == b.query_type
+}
+
+/// Formats the number of bytes using binary prefixes.
+#[allow(clippy::cast_precision_loss)]
+fn fmt_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.2} {}", UNITS[unit])
+    }
+}
+
+impl Display for CostEstimate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Estimated cost of {} query with {} rows, {} shard(s) per helper",
+            self.config.query_type.as_ref(),
+            u32::from(self.config.size),
+            self.shard_count
+        )?;
+
+        let mut table = comfy_table::Table::new();
+        table.set_header(vec!["", "H1", "H2", "H3"]);
+        let mut add_row = |name: &str, f: &dyn Fn(&HelperCost) -> String| {
+            let mut row = vec![name.to_string()];
+            row.extend(self.helpers.iter().map(f));
+            table.add_row(row);
+        };
+        add_row("bytes sent", &|h| fmt_bytes(h.bytes_sent));
+        add_row("records sent", &|h| h.records_sent.to_string());
+        add_row("PRSS draws", &|h| h.prss_draws.to_string());
+        add_row("DZKP proofs", &|h| h.dzkp_proofs.to_string());
+        add_row("elapsed, s", &|h| (h.elapsed_ms / 1000).to_string());
+        add_row("peak phase volume (memory)", &|h| {
+            fmt_bytes(h.peak_memory_bytes)
+        });
+        writeln!(f, "{table}")?;
+
+        let phases = self
+            .helpers
+            .iter()
+            .flat_map(|h| h.bytes_sent_per_phase.keys())
+            .collect::<BTreeSet<_>>();
+        if !phases.is_empty() {
+            let mut table = comfy_table::Table::new();
+            table.set_header(vec!["bytes sent per phase", "H1", "H2", "H3"]);
+            for phase in phases {
+                let mut row = vec![phase.clone()];
+                row.extend(
+                    self.helpers.iter().map(|h| {
+                        fmt_bytes(h.bytes_sent_per_phase.get(phase).copied().unwrap_or(0))
+                    }),
+                );
+                table.add_row(row);
+            }
+            writeln!(f, "{table}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::{
+        ff::{FieldType, Fp31, U128Conversions},
+        helpers::query::{QueryConfig, QueryType},
+        protocol::{basics::SecureMul, context::Context, RecordId},
+        query::estimate::{fmt_bytes, Calibration, CostModel, EstimateError, StepTree},
+        seq_join::SeqJoin,
+        telemetry::{
+            labels,
+            metrics::{
+                BYTES_RECEIVED, BYTES_SENT, INDEXED_PRSS_GENERATED, RECORDS_RECEIVED, RECORDS_SENT,
+            },
+            stats::{CounterSnapshot, QueryMetrics},
+        },
+        test_fixture::{Runner, TestWorld, TestWorldConfig},
+    };
+
+    fn config(size: usize) -> QueryConfig {
+        QueryConfig::new(QueryType::TestMultiply, FieldType::Fp31, size).unwrap()
+    }
+
+    /// Metrics of a helper that sent `records` records of 4 bytes in two gates and received
+    /// the same number of records in one of them.
+    fn metrics(records: u64) -> QueryMetrics {
+        let counter = |total: u64, gates: &[&str]| CounterSnapshot {
+            total: total * gates.len() as u64,
+            dimensions: [(
+                labels::STEP.to_string(),
+                gates.iter().map(|g| ((*g).to_string(), total)).collect(),
+            )]
+            .into(),
+        };
+
+        let mut metrics = QueryMetrics::default();
+        let sent = ["protocol/prss", "protocol/hybrid/reshard_by_tag"];
+        let received = ["protocol/hybrid/reshard_by_tag"];
+        for (name, value, gates) in [
+            (RECORDS_SENT, records, &sent[..]),
+            (BYTES_SENT, 4 * records, &sent[..]),
+            (RECORDS_RECEIVED, records, &received[..]),
+            (BYTES_RECEIVED, 4 * records, &received[..]),
+        ] {
+            metrics
+                .counters
+                .insert(name.to_string(), counter(value, gates));
+        }
+        metrics
+    }
+
+    #[test]
+    fn fixed_and_per_record_costs() {
+        // 20 records to set things up, 5 records per row
+        let calibrations = [10, 20, 40].map(|size| {
+            Calibration::new(
+                config(size),
+                &[[0, 1, 2].map(|_| metrics(20 + 5 * size as u64))],
+            )
+        });
+
+        let model = CostModel::fit(&calibrations).unwrap();
+        let estimate = model.estimate(&config(1_000_000)).unwrap();
+        for helper in estimate.helpers {
+            assert_eq!(10_000_040, helper.records_sent);
+            assert_eq!(40_000_160, helper.bytes_sent);
+            // reshard sends and receives
+            assert_eq!(40_000_160, helper.peak_memory_bytes);
+            assert_eq!(
+                BTreeMap::from([
+                    ("hybrid/reshard_by_tag".to_string(), 20_000_080),
+                    ("prss".to_string(), 20_000_080),
+                ]),
+                helper.bytes_sent_per_phase
+            );
+            assert_eq!(
+                BTreeMap::from([
+                    ("hybrid/reshard_by_tag".to_string(), 5_000_020),
+                    ("prss".to_string(), 5_000_020),
+                ]),
+                helper.records_sent_per_gate
+            );
+        }
+    }
+
+    #[test]
+    fn single_size_is_proportional() {
+        let calibration = Calibration::new(config(10), &[[0, 1, 2].map(|_| metrics(50))]);
+
+        let model = CostModel::fit(&[calibration]).unwrap();
+        let estimate = model.estimate(&config(100)).unwrap();
+        assert_eq!(4000, estimate.helpers[0].bytes_sent);
+    }
+
+    #[test]
+    fn shards_are_added_up() {
+        let calibration = Calibration::new(
+            config(10),
+            &[
+                [0, 1, 2].map(|_| metrics(50)),
+                [0, 1, 2].map(|_| metrics(5)),
+            ],
+        );
+
+        assert_eq!(2, calibration.shard_count);
+        assert_eq!(440, calibration.helpers[2].get_counter(BYTES_SENT));
+    }
+
+    #[test]
+    fn rejects_different_queries() {
+        let multiply = Calibration::new(config(10), &[[0, 1, 2].map(|_| metrics(50))]);
+        let add = Calibration::new(
+            QueryConfig::new(QueryType::TestAddInPrimeField, FieldType::Fp31, 10).unwrap(),
+            &[[0, 1, 2].map(|_| metrics(50))],
+        );
+
+        assert!(matches!(
+            CostModel::fit(&[]),
+            Err(EstimateError::NoCalibration)
+        ));
+        assert!(matches!(
+            CostModel::fit(&[multiply.clone(), add.clone()]),
+            Err(EstimateError::QueryMismatch { .. })
+        ));
+        assert!(matches!(
+            CostModel::fit(&[multiply]).unwrap().estimate(&add.config),
+            Err(EstimateError::QueryMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn phases() {
+        let tree = StepTree::get();
+        assert_eq!(
+            "hybrid/eval_prf",
+            tree.phase("protocol/hybrid/eval_prf/malicious_protocol")
+        );
+        assert_eq!(
+            "hybrid/eval_prf",
+            tree.phase("/hybrid/eval_prf/malicious_protocol")
+        );
+        assert_eq!("prss", tree.phase("protocol/prss"));
+        assert_eq!("prss", tree.phase("/prss"));
+        assert_eq!("other", tree.phase("protocol/hybrid/not_a_step"));
+    }
+
+    #[test]
+    fn bytes() {
+        assert_eq!("1023 B", fmt_bytes(1023));
+        assert_eq!("1.50 KiB", fmt_bytes(1536));
+        assert_eq!("2.00 GiB", fmt_bytes(2 << 30));
+    }
+
+    /// Calibrates the model with metrics of small multiplication runs and checks that it
+    /// predicts the metrics of a larger one.
+    #[tokio::test]
+    async fn calibrate_with_small_runs() {
+        async fn multiply(size: usize) -> Calibration {
+            let world = TestWorld::new_with(TestWorldConfig::default().enable_metrics());
+            let input = (0..size).map(|i| Fp31::truncate_from(u128::try_from(i).unwrap()));
+            world
+                .semi_honest(input, |ctx, shares| async move {
+                    let ctx = ctx.set_total_records(shares.len());
+                    ctx.try_join(
+                        shares.iter().enumerate().map(|(i, share)| {
+                            share.multiply(share, ctx.clone(), RecordId::from(i))
+                        }),
+                    )
+                    .await
+                    .unwrap()
+                })
+                .await;
+
+            // test world reports metrics of all helpers together
+            let metrics = QueryMetrics::from(&world.metrics_snapshot());
+            Calibration::new(config(size), &[[0, 1, 2].map(|_| metrics.clone())])
+        }
+
+        let calibrations = [multiply(10).await, multiply(20).await];
+        let actual = multiply(100).await;
+
+        let estimate = CostModel::fit(&calibrations)
+            .unwrap()
+            .estimate(&actual.config)
+            .unwrap();
+        let actual = &actual.helpers[0];
+        let estimate = &estimate.helpers[0];
+        assert!(estimate.bytes_sent > 0);
+        assert_eq!(actual.get_counter(BYTES_SENT), estimate.bytes_sent);
+        assert_eq!(actual.get_counter(RECORDS_SENT), estimate.records_sent);
+        assert_eq!(
+            actual.get_counter(INDEXED_PRSS_GENERATED),
+            estimate.prss_draws
+        );
+    }
+}
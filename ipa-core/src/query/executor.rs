@@ -3,6 +3,7 @@ use std::{
     fmt::Debug,
     future::{ready, Future},
     pin::Pin,
+    time::Instant,
 };
 
 use ::tokio::{
@@ -12,9 +13,11 @@ use ::tokio::{
 };
 use futures::FutureExt;
 use generic_array::GenericArray;
+use ipa_metrics::counter;
 use ipa_step::StepNarrow;
 use rand::rngs::StdRng;
 use rand_core::SeedableRng;
+use tracing::Instrument;
 use typenum::Unsigned;
 
 #[cfg(any(
@@ -39,10 +42,15 @@ use crate::{
         Gate,
     },
     query::{
-        runner::{execute_hybrid_protocol, OprfIpaQuery, QueryResult},
+        runner::{
+            execute_conversion_lift_protocol, execute_hybrid_protocol,
+            execute_reach_frequency_protocol, execute_sharded_oprf_ipa, OprfIpaQuery, QueryResult,
+        },
         state::RunningQuery,
     },
+    sharding::{ShardConfiguration, ShardIndex},
     sync::Arc,
+    telemetry::metrics::QUERY_ELAPSED_MS,
 };
 #[cfg(any(test, feature = "cli", feature = "test-fixture"))]
 use crate::{
@@ -143,6 +151,16 @@ pub fn execute<R: PrivateKeyRegistry>(
             gateway,
             input,
             move |prss, gateway, config, input| {
+                if gateway.shard_count() > ShardIndex::from(1) {
+                    return Box::pin(execute_sharded_oprf_ipa(
+                        prss,
+                        gateway,
+                        input,
+                        ipa_config,
+                        config,
+                        key_registry,
+                    ));
+                }
                 let ctx = SemiHonestContext::new(prss, gateway);
                 Box::pin(
                     OprfIpaQuery::<_, BA32, R>::new(ipa_config, key_registry)
@@ -157,6 +175,16 @@ pub fn execute<R: PrivateKeyRegistry>(
             gateway,
             input,
             move |prss, gateway, config, input| {
+                if gateway.shard_count() > ShardIndex::from(1) {
+                    return Box::pin(execute_sharded_oprf_ipa(
+                        prss,
+                        gateway,
+                        input,
+                        ipa_config,
+                        config,
+                        key_registry,
+                    ));
+                }
                 let ctx = MaliciousContext::new(prss, gateway);
                 Box::pin(
                     OprfIpaQuery::<_, BA32, R>::new(ipa_config, key_registry)
@@ -165,17 +193,51 @@ pub fn execute<R: PrivateKeyRegistry>(
                 )
             },
         ),
-        (QueryType::MaliciousHybrid(ipa_config), _) => do_query(
+        (QueryType::SemiHonestHybrid(ipa_config) | QueryType::MaliciousHybrid(ipa_config), _) => {
+            do_query(
+                runtime,
+                config,
+                gateway,
+                input,
+                move |prss, gateway, config, input| {
+                    Box::pin(execute_hybrid_protocol(
+                        prss,
+                        gateway,
+                        input,
+                        ipa_config,
+                        config,
+                        key_registry,
+                    ))
+                },
+            )
+        }
+        (QueryType::MaliciousReachFrequency(query_params), _) => do_query(
             runtime,
             config,
             gateway,
             input,
             move |prss, gateway, config, input| {
-                Box::pin(execute_hybrid_protocol(
+                Box::pin(execute_reach_frequency_protocol(
                     prss,
                     gateway,
                     input,
-                    ipa_config,
+                    query_params,
+                    config,
+                    key_registry,
+                ))
+            },
+        ),
+        (QueryType::MaliciousConversionLift(query_params), _) => do_query(
+            runtime,
+            config,
+            gateway,
+            input,
+            move |prss, gateway, config, input| {
+                Box::pin(execute_conversion_lift_protocol(
+                    prss,
+                    gateway,
+                    input,
+                    query_params,
                     config,
                     key_registry,
                 ))
@@ -204,31 +266,40 @@ where
 {
     let (tx, rx) = oneshot::channel();
 
-    let join_handle = executor_handle.spawn(async move {
-        let gateway = gateway.borrow();
-        // TODO: make it a generic argument for this function
-        let mut rng = StdRng::from_entropy();
-        // Negotiate PRSS using the initial gate for the protocol (no narrowing).
-        let prss = negotiate_prss(gateway, &prss_gate(), &mut rng)
-            .await
-            .unwrap();
-
-        // see private-attribution/ipa#1120
-        let v = if !cfg!(feature = "shuttle")
-            && Handle::current().runtime_flavor() == RuntimeFlavor::MultiThread
-        {
-            block_in_place(|| {
-                // block_on runs on the current thread, so if it is also responsible for IO
-                // it's been handed off already by block_in_place.
-                Handle::current()
-                    .block_on(async { query_impl(&prss, gateway, &config, input_stream).await })
-            })
-        } else {
-            query_impl(&prss, gateway, &config, input_stream).await
-        };
-
-        tx.send(v).unwrap();
-    });
+    let join_handle = executor_handle.spawn(
+        async move {
+            let start = Instant::now();
+            let gateway = gateway.borrow();
+            // TODO: make it a generic argument for this function
+            let mut rng = StdRng::from_entropy();
+            // Negotiate PRSS using the initial gate for the protocol (no narrowing).
+            let prss = negotiate_prss(gateway, &prss_gate(), &mut rng)
+                .await
+                .unwrap();
+
+            // see private-attribution/ipa#1120
+            let v = if !cfg!(feature = "shuttle")
+                && Handle::current().runtime_flavor() == RuntimeFlavor::MultiThread
+            {
+                block_in_place(|| {
+                    // block_on runs on the current thread, so if it is also responsible for IO
+                    // it's been handed off already by block_in_place.
+                    Handle::current()
+                        .block_on(async { query_impl(&prss, gateway, &config, input_stream).await })
+                })
+            } else {
+                query_impl(&prss, gateway, &config, input_stream).await
+            };
+            counter!(
+                QUERY_ELAPSED_MS,
+                u64::try_from(start.elapsed().as_millis()).unwrap_or(u64::MAX)
+            );
+
+            tx.send(v).unwrap();
+        }
+        // query processor sets up the span for this query, including the metric partition
+        .instrument(tracing::Span::current()),
+    );
 
     RunningQuery {
         result: rx,
@@ -1,4 +1,5 @@
 mod completion;
+pub mod estimate;
 mod executor;
 mod processor;
 mod runner;
@@ -8,7 +9,10 @@ use completion::Handle as CompletionHandle;
 pub use executor::Result as ProtocolResult;
 pub use processor::{
     NewQueryError, PrepareQueryError, Processor as QueryProcessor, QueryCompletionError,
-    QueryInputError, QueryKillStatus, QueryKilled, QueryStatusError,
+    QueryInputError, QueryKillStatus, QueryKilled, QueryMetricsError, QueryStatusError,
+};
+pub use runner::{
+    credit_cap_ss_bits, hybrid_dp_params, OprfIpaQuery, HYBRID_BREAKDOWNS, HYBRID_SS_BITS,
+    IPA_BREAKDOWNS, REACH_FREQUENCY_SS_BITS,
 };
-pub use runner::OprfIpaQuery;
 pub use state::{min_status, QueryStatus};
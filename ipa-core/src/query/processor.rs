@@ -1,9 +1,14 @@
 use std::{
-    collections::hash_map::Entry,
+    collections::{hash_map::Entry, HashMap},
     fmt::{Debug, Formatter},
+    time::{Duration, Instant},
 };
 
-use futures::{future::try_join, stream};
+use futures::{
+    future::{ready, try_join, Either},
+    stream,
+};
+use ipa_metrics::MetricPartition;
 use serde::Serialize;
 
 use super::min_status;
@@ -11,10 +16,15 @@ use crate::{
     error::Error as ProtocolError,
     executor::IpaRuntime,
     helpers::{
-        query::{CompareStatusRequest, PrepareQuery, QueryConfig},
+        min_progress,
+        query::{
+            CompareStatusRequest, CompareStatusResponse, KillQuery, PrepareQuery, QueryConfig,
+            WaitQueryRequest,
+        },
         routing::RouteId,
         BodyStream, BroadcastError, Gateway, GatewayConfig, MpcTransportError, MpcTransportImpl,
-        Role, RoleAssignment, ShardTransportError, ShardTransportImpl, Transport,
+        Progress, QueryProgress, Role, RoleAssignment, ShardTransportError, ShardTransportImpl,
+        Transport,
     },
     hpke::{KeyRegistry, PrivateKeyOnly},
     protocol::QueryId,
@@ -23,8 +33,10 @@ use crate::{
         state::{QueryState, QueryStatus, RemoveQuery, RunningQueries, StateError},
         CompletionHandle, ProtocolResult,
     },
+    rand::{thread_rng, Rng},
     sharding::ShardIndex,
-    sync::Arc,
+    sync::{Arc, Mutex},
+    telemetry::trace::{TraceContext, TRACEPARENT_FIELD},
     utils::NonZeroU32PowerOfTwo,
 };
 
@@ -54,16 +66,52 @@ pub struct Processor {
     queries: RunningQueries,
     key_registry: Arc<KeyRegistry<PrivateKeyOnly>>,
     active_work: Option<NonZeroU32PowerOfTwo>,
+    /// If set, helpers measure backpressure and agree on the active work while the query runs.
+    adaptive_active_work: bool,
     runtime: IpaRuntime,
+    /// Trace context captured when query is created or prepared. Query inputs arrive
+    /// in separate requests, so this is used to attach query execution to the same
+    /// distributed trace.
+    trace_contexts: Mutex<HashMap<QueryId, TraceContext>>,
+    /// Metric partitions assigned to queries that started running on this helper. Metrics
+    /// emitted while running a query are recorded inside its partition, so they can be
+    /// retrieved after the query is completed.
+    metric_partitions: Mutex<HashMap<QueryId, MetricPartition>>,
+    /// Progress of queries that started running on this helper, updated by their gateways.
+    query_progress: Mutex<HashMap<QueryId, Arc<Progress>>>,
+    /// Time queries that started running on this helper were completed. Their metric partitions
+    /// and progress are released once the retention for aborted queries passes.
+    completed_at: Mutex<HashMap<QueryId, Instant>>,
+    /// How long queries that failed or were killed remain visible through the status API.
+    /// Metrics and progress of completed queries are kept for the same time.
+    aborted_query_retention: Duration,
 }
 
+/// Default time queries that failed or were killed are kept around, so operators
+/// can find out why they ended.
+pub const DEFAULT_ABORTED_QUERY_RETENTION: Duration = Duration::from_secs(60 * 60);
+
+/// Longest time [`Processor::wait_for_completion`] holds on to a request, regardless of the
+/// timeout asked for. Keeps idle connections from piling up if clients go away.
+pub const MAX_QUERY_WAIT: Duration = Duration::from_secs(60);
+
+/// How often [`Processor::wait_for_completion`] checks the status of other shards while the
+/// query does not change on this shard.
+pub const SHARD_STATUS_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
 impl Default for Processor {
     fn default() -> Self {
         Self {
             queries: RunningQueries::default(),
             key_registry: Arc::new(KeyRegistry::<PrivateKeyOnly>::empty()),
             active_work: None,
+            adaptive_active_work: false,
             runtime: IpaRuntime::current(),
+            trace_contexts: Mutex::default(),
+            metric_partitions: Mutex::default(),
+            query_progress: Mutex::default(),
+            completed_at: Mutex::default(),
+            aborted_query_retention: DEFAULT_ABORTED_QUERY_RETENTION,
         }
     }
 }
@@ -118,6 +166,8 @@ pub enum QueryStatusError {
     NotLeader(ShardIndex),
     #[error("This is the leader shard")]
     Leader,
+    #[error("Shard {0:?} responded with a malformed status: {1}")]
+    ShardResponse(ShardIndex, #[source] serde_json::Error),
     #[error("My status {my_status:?} for query {query_id:?} differs from {other_status:?}")]
     DifferentStatus {
         query_id: QueryId,
@@ -126,6 +176,12 @@ pub enum QueryStatusError {
     },
 }
 
+#[derive(thiserror::Error, Debug)]
+pub enum QueryMetricsError {
+    #[error("The query with id {0:?} does not exist or has not started running yet")]
+    NoSuchQuery(QueryId),
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum QueryCompletionError {
     #[error("The query with id {0:?} does not exist")]
@@ -158,10 +214,33 @@ impl Processor {
             queries: RunningQueries::default(),
             key_registry: Arc::new(key_registry),
             active_work,
+            adaptive_active_work: false,
             runtime,
+            trace_contexts: Mutex::default(),
+            metric_partitions: Mutex::default(),
+            query_progress: Mutex::default(),
+            completed_at: Mutex::default(),
+            aborted_query_retention: DEFAULT_ABORTED_QUERY_RETENTION,
         }
     }
 
+    /// Enables adaptive active work for all queries started by this processor.
+    /// See [`GatewayConfig::adaptive_active_work`].
+    #[must_use]
+    pub fn with_adaptive_active_work(mut self, enabled: bool) -> Self {
+        self.adaptive_active_work = enabled;
+        self
+    }
+
+    /// Sets how long queries that failed or were killed are reported by the status API
+    /// before they are forgotten. Metrics and progress of completed queries are released
+    /// after the same time.
+    #[must_use]
+    pub fn with_aborted_query_retention(mut self, retention: Duration) -> Self {
+        self.aborted_query_retention = retention;
+        self
+    }
+
     /// Upon receiving a new query request:
     /// * processor generates new query id
     /// * assigns roles to helpers in the ring.
@@ -214,6 +293,7 @@ impl Processor {
         shard_transport.broadcast(prepare_request.clone()).await?;
 
         handle.set_state(QueryState::AwaitingInputs(req, roles))?;
+        self.save_trace_context(query_id);
 
         guard.restore();
         Ok(prepare_request)
@@ -242,7 +322,7 @@ impl Processor {
             return Err(PrepareQueryError::NotLeader(shard_index));
         }
         let handle = self.queries.handle(req.query_id);
-        if handle.status().is_some() {
+        if handle.status().is_some_and(|status| !status.is_aborted()) {
             return Err(PrepareQueryError::AlreadyRunning);
         }
 
@@ -250,6 +330,7 @@ impl Processor {
         shard_transport.broadcast(req.clone()).await?;
 
         handle.set_state(QueryState::AwaitingInputs(req.config, req.roles))?;
+        self.save_trace_context(req.query_id);
 
         Ok(())
     }
@@ -272,15 +353,25 @@ impl Processor {
         }
 
         let handle = self.queries.handle(req.query_id);
-        if handle.status().is_some() {
+        if handle.status().is_some_and(|status| !status.is_aborted()) {
             return Err(PrepareQueryError::AlreadyRunning);
         }
 
         handle.set_state(QueryState::AwaitingInputs(req.config, req.roles))?;
+        self.save_trace_context(req.query_id);
 
         Ok(())
     }
 
+    fn save_trace_context(&self, query_id: QueryId) {
+        if let Some(trace_context) = TraceContext::current() {
+            self.trace_contexts
+                .lock()
+                .unwrap()
+                .insert(query_id, trace_context);
+        }
+    }
+
     /// Receive inputs for the specified query and creates gateway and network
     ///
     /// ## Errors
@@ -295,6 +386,16 @@ impl Processor {
         query_id: QueryId,
         input_stream: BodyStream,
     ) -> Result<(), QueryInputError> {
+        let trace_context = self.trace_contexts.lock().unwrap().remove(&query_id);
+        let metric_partition: MetricPartition = thread_rng().gen();
+        // Query execution is instrumented with this span, so all metrics emitted while
+        // running this query end up in its own partition.
+        let _span = tracing::info_span!(
+            "query",
+            { ipa_metrics_tracing::PARTITION_FIELD } = metric_partition,
+            { TRACEPARENT_FIELD } = trace_context.as_ref().map(tracing::field::display),
+        )
+        .entered();
         let mut queries = self.queries.inner.lock().unwrap();
         match queries.entry(query_id) {
             Entry::Occupied(entry) => {
@@ -306,6 +407,7 @@ impl Processor {
                     } else {
                         gateway_config.set_active_work_from_query_config(&config);
                     }
+                    gateway_config.adaptive_active_work = self.adaptive_active_work;
                     let gateway = Gateway::new(
                         query_id,
                         gateway_config,
@@ -313,16 +415,29 @@ impl Processor {
                         mpc_transport,
                         shard_transport,
                     );
+                    self.completed_at.lock().unwrap().remove(&query_id);
+                    self.query_progress
+                        .lock()
+                        .unwrap()
+                        .insert(query_id, Arc::clone(gateway.progress()));
+                    let running = executor::execute(
+                        &self.runtime,
+                        config,
+                        Arc::clone(&self.key_registry),
+                        gateway,
+                        input_stream,
+                    );
                     queries.insert(
                         query_id,
-                        QueryState::Running(executor::execute(
-                            &self.runtime,
-                            config,
-                            Arc::clone(&self.key_registry),
-                            gateway,
-                            input_stream,
-                        )),
+                        QueryState::Running(
+                            self.queries.notify_on_completion(&self.runtime, running),
+                        ),
                     );
+                    self.queries.notify();
+                    self.metric_partitions
+                        .lock()
+                        .unwrap()
+                        .insert(query_id, metric_partition);
                     Ok(())
                 } else {
                     let error = StateError::InvalidState {
@@ -337,11 +452,38 @@ impl Processor {
         }
     }
 
+    /// Returns the metric partition assigned to the given query. All metrics emitted while
+    /// running this query are recorded inside this partition.
+    ///
+    /// ## Errors
+    /// If query is not registered on this helper or has not received its inputs yet.
+    ///
+    /// ## Panics
+    /// If failed to obtain exclusive access to the partitions collection.
+    pub fn metric_partition(
+        &self,
+        query_id: QueryId,
+    ) -> Result<MetricPartition, QueryMetricsError> {
+        self.release_expired();
+        self.metric_partitions
+            .lock()
+            .unwrap()
+            .get(&query_id)
+            .copied()
+            .ok_or(QueryMetricsError::NoSuchQuery(query_id))
+    }
+
     /// Returns the status of the running query or [`None`].
-    /// If the query was completed it updates the state to reflect that.
+    /// If the query was completed it updates the state to reflect that. Queries that failed or
+    /// were killed longer than the retention period ago are forgotten.
     fn get_status(&self, query_id: QueryId) -> Option<QueryStatus> {
+        self.release_expired();
         let mut queries = self.queries.inner.lock().unwrap();
         let mut state = queries.remove(&query_id)?;
+        if state.is_expired(self.aborted_query_retention) {
+            self.release(query_id);
+            return None;
+        }
 
         if let QueryState::Running(ref mut running) = state {
             if let Some(result) = running.try_complete() {
@@ -354,6 +496,17 @@ impl Processor {
         Some(status)
     }
 
+    /// Returns the progress of the query on this shard, or [`None`] if it has not started
+    /// running yet.
+    fn get_progress(&self, query_id: QueryId) -> Option<QueryProgress> {
+        self.release_expired();
+        self.query_progress
+            .lock()
+            .unwrap()
+            .get(&query_id)
+            .and_then(|progress| progress.snapshot())
+    }
+
     /// This helper function is used to transform a [`BoxError`] into a
     /// [`QueryStatusError::DifferentStatus`] and retrieve it's internal state. Returns [`None`]
     /// if not possible.
@@ -365,7 +518,7 @@ impl Processor {
             my_status, ..
         })) = api_error
         {
-            return Some(*my_status);
+            return Some(my_status.clone());
         }
         None
     }
@@ -391,7 +544,7 @@ impl Processor {
     #[cfg(feature = "real-world-infra")]
     fn get_state_from_error(shard_error: &crate::net::ShardError) -> Option<QueryStatus> {
         if let crate::net::Error::ShardQueryStatusMismatch { error, .. } = &shard_error.source {
-            return Some(error.actual);
+            return Some(error.actual.clone());
         }
         None
     }
@@ -408,6 +561,25 @@ impl Processor {
         shard_transport: ShardTransportImpl,
         query_id: QueryId,
     ) -> Result<QueryStatus, QueryStatusError> {
+        self.query_status_with_progress(shard_transport, query_id)
+            .await
+            .map(|(status, _)| status)
+    }
+
+    /// Returns the query status in this helper along with its progress, by querying all shards.
+    /// Progress is [`None`] unless the query is running on every shard. See [`min_progress`]
+    /// for how progress of different shards is merged.
+    ///
+    /// ## Errors
+    /// If query is not registered on this helper.
+    ///
+    /// ## Panics
+    /// If the query collection mutex is poisoned.
+    pub async fn query_status_with_progress(
+        &self,
+        shard_transport: ShardTransportImpl,
+        query_id: QueryId,
+    ) -> Result<(QueryStatus, Option<QueryProgress>), QueryStatusError> {
         let shard_index = shard_transport.identity();
         if shard_index != ShardIndex::FIRST {
             return Err(QueryStatusError::NotLeader(shard_index));
@@ -416,25 +588,108 @@ impl Processor {
         let mut status = self
             .get_status(query_id)
             .ok_or(QueryStatusError::NoSuchQuery(query_id))?;
+        let mut progress = self.get_progress(query_id);
+        if status.is_aborted() {
+            // Nothing can override it and the failure reason is not sent to other shards.
+            return Ok((status, progress));
+        }
 
-        let shard_query_status_req = CompareStatusRequest { query_id, status };
+        let shard_query_status_req = CompareStatusRequest {
+            query_id,
+            status: status.clone(),
+        };
 
-        let shard_responses = shard_transport.broadcast(shard_query_status_req).await;
-        if let Err(e) = shard_responses {
-            for (shard, failure) in &e.failures {
-                if let Some(other) = Self::get_state_from_error(failure) {
-                    status = min_status(status, other);
-                } else {
-                    tracing::error!("failed to get status from shard {shard}: {failure:?}");
-                    return Err(e.into());
+        // Shards that agree with our status respond with their progress, the ones that don't
+        // reject the request and report their status instead.
+        match shard_transport.broadcast(shard_query_status_req).await {
+            Ok(responses) => {
+                for (shard, response) in responses {
+                    let CompareStatusResponse {
+                        progress: other_progress,
+                        ..
+                    } = response.try_into_owned().map_err(|e| {
+                        tracing::error!("failed to parse status of shard {shard}: {e}");
+                        QueryStatusError::ShardResponse(shard, e)
+                    })?;
+                    if other_progress.is_none() {
+                        tracing::debug!("shard {shard} has no progress for {query_id:?} yet");
+                    }
+                    progress = min_progress(progress, other_progress);
+                }
+            }
+            Err(e) => {
+                for (shard, failure) in &e.failures {
+                    if let Some(other) = Self::get_state_from_error(failure) {
+                        tracing::debug!(
+                            "shard {shard} is {other:?}, no progress is reported for {query_id:?}"
+                        );
+                        status = min_status(status, other);
+                        progress = None;
+                    } else {
+                        tracing::error!("failed to get status from shard {shard}: {failure:?}");
+                        return Err(e.into());
+                    }
                 }
             }
         }
 
-        Ok(status)
+        Ok((status, progress))
     }
 
-    /// Compares this shard status against the given type. Returns an error if different.
+    /// Waits until the query completes, fails or is killed on every shard and returns its
+    /// status and progress, like [`Self::query_status_with_progress`] does. If that does not
+    /// happen within the requested timeout, capped at [`MAX_QUERY_WAIT`], returns the latest
+    /// status instead, so callers can report progress and wait again.
+    ///
+    /// Status of all shards is checked every time the state of the query changes on this shard,
+    /// and at least every [`SHARD_STATUS_POLL_INTERVAL`]. Other shards do not report their
+    /// transitions, so once the query ended here, the status is returned right away, even if
+    /// some shards have not caught up yet. If another shard fails while this one keeps running,
+    /// it is noticed on the next poll.
+    ///
+    /// ## Errors
+    /// If query is not registered on this helper or shards fail to report their status.
+    ///
+    /// ## Panics
+    /// If the query collection mutex is poisoned.
+    pub async fn wait_for_completion(
+        &self,
+        shard_transport: ShardTransportImpl,
+        req: WaitQueryRequest,
+    ) -> Result<(QueryStatus, Option<QueryProgress>), QueryStatusError> {
+        let deadline = Instant::now() + req.timeout.min(MAX_QUERY_WAIT);
+        // subscribe before checking the status, so transitions in between are not missed
+        let mut changes = self.queries.subscribe();
+        let mut timed_out = false;
+        loop {
+            let (status, progress) = self
+                .query_status_with_progress(shard_transport.clone_ref(), req.query_id)
+                .await?;
+            let local_status = self.get_status(req.query_id);
+            let ended_here = local_status.as_ref().is_none_or(QueryStatus::is_terminal);
+            if status.is_terminal() || ended_here || timed_out {
+                return Ok((status, progress));
+            }
+
+            // Changes are signalled for every query on this helper, so shards are asked again
+            // only when this query moved on or the poll interval elapsed.
+            let next_poll = deadline.min(Instant::now() + SHARD_STATUS_POLL_INTERVAL);
+            loop {
+                let remaining = next_poll.saturating_duration_since(Instant::now());
+                if ::tokio::time::timeout(remaining, changes.changed())
+                    .await
+                    .is_err()
+                    || self.get_status(req.query_id) != local_status
+                {
+                    break;
+                }
+            }
+            timed_out = Instant::now() >= deadline;
+        }
+    }
+
+    /// Compares this shard status against the given type. Returns an error if different,
+    /// otherwise the status along with the progress of the query on this shard.
     ///
     /// ## Errors
     /// If query is not registered on this helper or
@@ -445,7 +700,7 @@ impl Processor {
         &self,
         shard_transport: &ShardTransportImpl,
         req: &CompareStatusRequest,
-    ) -> Result<QueryStatus, QueryStatusError> {
+    ) -> Result<CompareStatusResponse, QueryStatusError> {
         let shard_index = shard_transport.identity();
         if shard_index == ShardIndex::FIRST {
             return Err(QueryStatusError::Leader);
@@ -457,16 +712,20 @@ impl Processor {
             return Err(QueryStatusError::DifferentStatus {
                 query_id: req.query_id,
                 my_status: status,
-                other_status: req.status,
+                other_status: req.status.clone(),
             });
         }
-        Ok(status)
+        Ok(CompareStatusResponse {
+            status,
+            progress: self.get_progress(req.query_id),
+        })
     }
 
-    /// Awaits the query completion
+    /// Awaits the query completion. If the query fails, it remains in the
+    /// [`QueryStatus::Failed`] state, so the status API can report the reason.
     ///
     /// ## Errors
-    /// if query is not registered on this helper.
+    /// if query is not registered on this helper or if it failed.
     ///
     /// ## Panics
     /// If failed to obtain exclusive access to the query collection.
@@ -475,14 +734,27 @@ impl Processor {
         query_id: QueryId,
         shard_transport: ShardTransportImpl,
     ) -> Result<Box<dyn ProtocolResult>, QueryCompletionError> {
-        let handle = {
+        let completion = {
             let mut queries = self.queries.inner.lock().unwrap();
 
             match queries.remove(&query_id) {
-                Some(QueryState::Completed(result)) => return result.map_err(Into::into),
+                Some(QueryState::Completed(Ok(result))) => {
+                    self.queries.notify();
+                    Either::Left(ready(Ok(result)))
+                }
+                Some(QueryState::Completed(Err(e))) => {
+                    queries.insert(query_id, QueryState::failed(&e));
+                    self.queries.notify();
+                    self.mark_completed(query_id);
+                    return Err(e.into());
+                }
                 Some(QueryState::Running(handle)) => {
                     queries.insert(query_id, QueryState::AwaitingCompletion);
-                    CompletionHandle::new(RemoveQuery::new(query_id, &self.queries), handle)
+                    self.queries.notify();
+                    Either::Right(CompletionHandle::new(
+                        RemoveQuery::new(query_id, &self.queries),
+                        handle,
+                    ))
                 }
                 Some(state) => {
                     let state_error = StateError::InvalidState {
@@ -501,7 +773,8 @@ impl Processor {
         // Inform other shards about our intent to complete the query.
         // If any of them rejects it, report the error back. We expect all shards
         // to be in the same state. In normal cycle, this API is called only after
-        // query status reports completion.
+        // query status reports completion, so the leader may be done already, but
+        // other shards still need to release the query.
         if shard_transport.identity() == ShardIndex::FIRST {
             // See shard finalizer protocol to see how shards merge their results together.
             // At the end, only leader holds the value
@@ -510,11 +783,81 @@ impl Processor {
                 .await?;
         }
 
-        Ok(handle.await?)
+        // The handle unregisters the query when it is dropped, so the failure
+        // can only be recorded after it completes.
+        let result = completion.await;
+        self.mark_completed(query_id);
+        result.map_err(|e| {
+            self.queries
+                .inner
+                .lock()
+                .unwrap()
+                .insert(query_id, QueryState::failed(&e));
+            self.queries.notify();
+            e.into()
+        })
+    }
+
+    /// Terminates a query with the given id on this shard and makes sure every other helper
+    /// and shard does the same. The leader shard forwards the request to all of its shards and,
+    /// unless the request came from another helper, to its peer helpers.
+    ///
+    /// Forwarding is best effort: peers that do not know about the query or cannot be reached
+    /// are logged and do not fail the request, because there is nothing left to stop there.
+    ///
+    /// ## Errors
+    /// if query is not registered on this helper.
+    ///
+    /// ## Panics
+    /// If failed to obtain exclusive access to the query collection.
+    pub async fn kill_query(
+        &self,
+        mpc_transport: MpcTransportImpl,
+        shard_transport: ShardTransportImpl,
+        req: KillQuery,
+    ) -> Result<QueryKilled, QueryKillStatus> {
+        let killed = self.kill(req.query_id);
+        if shard_transport.identity() != ShardIndex::FIRST {
+            return killed;
+        }
+
+        let forward = KillQuery {
+            query_id: req.query_id,
+            forwarded: true,
+        };
+        let to_helpers = async {
+            if req.forwarded {
+                return;
+            }
+            if let Err(e) = mpc_transport.broadcast(forward.clone()).await {
+                for (helper, failure) in &e.failures {
+                    tracing::warn!(
+                        "failed to kill query {:?} on {helper:?}: {failure:?}",
+                        req.query_id
+                    );
+                }
+            }
+        };
+        let to_shards = async {
+            if let Err(e) = shard_transport.broadcast(forward.clone()).await {
+                for (shard, failure) in &e.failures {
+                    tracing::warn!(
+                        "failed to kill query {:?} on {shard}: {failure:?}",
+                        req.query_id
+                    );
+                }
+            }
+        };
+        futures::join!(to_helpers, to_shards);
+
+        killed
     }
 
-    /// Terminates a query with the given id. If query is running, then it
-    /// is unregistered and its task is terminated.
+    /// Terminates a query with the given id on this shard only. If query is running, then its
+    /// task is terminated, which drops its gateway and closes all of its send channels, so peers
+    /// waiting for records from this shard fail instead of blocking. The query remains in the
+    /// [`QueryStatus::Killed`] state until the retention period for aborted queries expires or
+    /// it is started again.
     ///
     /// ## Errors
     /// if query is not registered on this helper.
@@ -522,6 +865,7 @@ impl Processor {
     /// ## Panics
     /// If failed to obtain exclusive access to the query collection.
     pub fn kill(&self, query_id: QueryId) -> Result<QueryKilled, QueryKillStatus> {
+        self.release(query_id);
         let mut queries = self.queries.inner.lock().unwrap();
         let Some(state) = queries.remove(&query_id) else {
             return Err(QueryKillStatus::NoSuchQuery(query_id));
@@ -530,9 +874,47 @@ impl Processor {
         if let QueryState::Running(handle) = state {
             handle.join_handle.abort();
         }
+        queries.insert(query_id, QueryState::killed());
+        self.queries.notify();
 
         Ok(QueryKilled(query_id))
     }
+
+    /// Starts the retention period for metrics and progress of a query that ended on this
+    /// helper.
+    fn mark_completed(&self, query_id: QueryId) {
+        self.completed_at
+            .lock()
+            .unwrap()
+            .insert(query_id, Instant::now());
+    }
+
+    /// Releases metrics and progress of queries completed longer than the retention
+    /// period ago.
+    fn release_expired(&self) {
+        let mut expired = Vec::new();
+        self.completed_at
+            .lock()
+            .unwrap()
+            .retain(|query_id, ended_at| {
+                let keep = ended_at.elapsed() < self.aborted_query_retention;
+                if !keep {
+                    expired.push(*query_id);
+                }
+                keep
+            });
+        for query_id in expired {
+            self.release(query_id);
+        }
+    }
+
+    /// Drops everything this helper keeps about the query, except its state.
+    fn release(&self, query_id: QueryId) {
+        self.trace_contexts.lock().unwrap().remove(&query_id);
+        self.metric_partitions.lock().unwrap().remove(&query_id);
+        self.query_progress.lock().unwrap().remove(&query_id);
+        self.completed_at.lock().unwrap().remove(&query_id);
+    }
 }
 
 #[derive(Clone, Serialize)]
@@ -557,8 +939,11 @@ mod tests {
         ff::{boolean_array::BA64, FieldType},
         helpers::{
             make_owned_handler,
-            query::{PrepareQuery, QueryConfig, QueryType::TestMultiply},
-            routing::Addr,
+            query::{
+                CompareStatusRequest, CompareStatusResponse, PrepareQuery, QueryConfig,
+                QueryType::TestMultiply,
+            },
+            routing::{Addr, RouteId},
             ApiError, HandlerBox, HelperIdentity, HelperResponse, InMemoryMpcNetwork,
             InMemoryShardNetwork, InMemoryTransport, RequestHandler, RoleAssignment, Transport,
             TransportIdentity,
@@ -592,8 +977,20 @@ mod tests {
         create_handler(|_| async { Ok(HelperResponse::ok()) })
     }
 
+    /// Accepts every request and agrees with any status the leader asks about.
     fn shard_respond_ok(_si: ShardIndex) -> Arc<dyn RequestHandler<ShardIndex>> {
-        create_handler(|_| async { Ok(HelperResponse::ok()) })
+        create_handler(|addr: Addr<ShardIndex>| async move {
+            Ok(match addr.route {
+                RouteId::QueryStatus => {
+                    let req = addr.into::<CompareStatusRequest>().unwrap();
+                    HelperResponse::from(CompareStatusResponse {
+                        status: req.status,
+                        progress: None,
+                    })
+                }
+                _ => HelperResponse::ok(),
+            })
+        })
     }
 
     fn test_multiply_config() -> QueryConfig {
@@ -925,15 +1322,22 @@ mod tests {
     }
 
     mod complete {
+        use std::{
+            sync::{
+                atomic::{AtomicUsize, Ordering},
+                Arc,
+            },
+            time::Duration,
+        };
 
         use crate::{
             helpers::{make_owned_handler, routing::RouteId, Transport},
             query::{
                 processor::{
                     tests::{HelperResponse, TestComponents, TestComponentsArgs},
-                    QueryId,
+                    Processor, QueryId,
                 },
-                ProtocolResult, QueryCompletionError,
+                ProtocolResult, QueryCompletionError, QueryMetricsError, QueryStatus,
             },
             sharding::ShardIndex,
         };
@@ -1000,6 +1404,67 @@ mod tests {
                 .await
                 .unwrap();
         }
+
+        #[tokio::test]
+        async fn completed_query_is_released_on_all_shards() {
+            let completed = Arc::new(AtomicUsize::new(0));
+            let mut args = TestComponentsArgs::default();
+            args.set_shard_handler(|_| {
+                let completed = Arc::clone(&completed);
+                make_owned_handler(move |req, _| {
+                    if req.route == RouteId::CompleteQuery {
+                        completed.fetch_add(1, Ordering::Relaxed);
+                    }
+                    futures::future::ok(HelperResponse::ok())
+                })
+            });
+
+            let t = TestComponents::new(args);
+            let query_id = t.new_running_query().await;
+            // status requests move the leader to completed state before results are requested
+            assert_eq!(
+                Some(QueryStatus::Completed),
+                t.processor.get_status(query_id)
+            );
+
+            t.processor
+                .complete(query_id, t.shard_transport.clone_ref())
+                .await
+                .unwrap();
+            // the only other shard must be told to release the query
+            assert_eq!(1, completed.load(Ordering::Relaxed));
+        }
+
+        #[tokio::test]
+        async fn releases_metrics_after_retention() {
+            let t = TestComponents {
+                processor: Processor::default().with_aborted_query_retention(Duration::ZERO),
+                ..Default::default()
+            };
+            let query_id = t.new_running_query().await;
+            t.processor
+                .metric_partitions
+                .lock()
+                .unwrap()
+                .insert(query_id, 1);
+            t.processor
+                .query_progress
+                .lock()
+                .unwrap()
+                .insert(query_id, Arc::default());
+            assert!(t.processor.metric_partition(query_id).is_ok());
+
+            t.processor
+                .complete(query_id, t.shard_transport.clone_ref())
+                .await
+                .unwrap();
+            assert!(matches!(
+                t.processor.metric_partition(query_id),
+                Err(QueryMetricsError::NoSuchQuery(_))
+            ));
+            assert!(t.processor.query_progress.lock().unwrap().is_empty());
+            assert!(t.processor.completed_at.lock().unwrap().is_empty());
+        }
     }
 
     mod prepare {
@@ -1125,9 +1590,18 @@ mod tests {
     }
 
     mod query_status {
+        use std::{
+            sync::atomic::{AtomicUsize, Ordering},
+            time::{Duration, Instant},
+        };
 
         use super::*;
-        use crate::{helpers::query::CompareStatusRequest, protocol::QueryId};
+        use crate::{
+            error::Error as ProtocolError,
+            helpers::query::{CompareStatusRequest, CompareStatusResponse, WaitQueryRequest},
+            protocol::QueryId,
+            query::{processor::MAX_QUERY_WAIT, QueryCompletionError},
+        };
 
         /// * From the standpoint of leader shard in Helper 1
         /// * On query_status
@@ -1156,7 +1630,10 @@ mod tests {
                                 other_status: QueryStatus::Preparing,
                             }))
                         }
-                        _ => Ok(HelperResponse::ok()),
+                        _ => Ok(HelperResponse::from(CompareStatusResponse {
+                            status: QueryStatus::AwaitingInputs,
+                            progress: None,
+                        })),
                     }
                 })
             }
@@ -1189,6 +1666,206 @@ mod tests {
             }
         }
 
+        /// A query whose protocol returned an error reports [`QueryStatus::Failed`] and
+        /// complete returns that error.
+        #[tokio::test]
+        async fn failed_query() {
+            let t = TestComponents::default();
+            t.processor
+                .new_query(
+                    t.first_transport.clone_ref(),
+                    t.shard_transport.clone_ref(),
+                    t.query_config,
+                )
+                .await
+                .unwrap();
+            let (tx, rx) = tokio::sync::oneshot::channel();
+            t.processor
+                .queries
+                .handle(QueryId)
+                .set_state(QueryState::Running(RunningQuery {
+                    result: rx,
+                    join_handle: IpaRuntime::current().spawn(async {}),
+                }))
+                .unwrap();
+            tx.send(Err(ProtocolError::MaliciousRevealFailed)).unwrap();
+
+            let failed = QueryStatus::Failed {
+                reason: ProtocolError::MaliciousRevealFailed.to_string(),
+            };
+            assert_eq!(
+                failed,
+                t.processor
+                    .query_status(t.shard_transport.clone_ref(), QueryId)
+                    .await
+                    .unwrap()
+            );
+            assert!(matches!(
+                t.processor
+                    .complete(QueryId, t.shard_transport.clone_ref())
+                    .await,
+                Err(QueryCompletionError::ExecutionError(
+                    ProtocolError::MaliciousRevealFailed
+                ))
+            ));
+            // the reason is still reported after the error was returned by complete
+            assert_eq!(
+                failed,
+                t.processor
+                    .query_status(t.shard_transport.clone_ref(), QueryId)
+                    .await
+                    .unwrap()
+            );
+        }
+
+        #[tokio::test]
+        async fn wait_returns_status_after_timeout() {
+            let t = TestComponents::default();
+            t.processor
+                .new_query(
+                    t.first_transport.clone_ref(),
+                    t.shard_transport.clone_ref(),
+                    t.query_config,
+                )
+                .await
+                .unwrap();
+
+            let (status, progress) = t
+                .processor
+                .wait_for_completion(
+                    t.shard_transport.clone_ref(),
+                    WaitQueryRequest {
+                        query_id: QueryId,
+                        timeout: Duration::from_millis(10),
+                    },
+                )
+                .await
+                .unwrap();
+            assert_eq!(QueryStatus::AwaitingInputs, status);
+            assert_eq!(None, progress);
+        }
+
+        #[tokio::test]
+        async fn wait_ignores_changes_of_other_queries() {
+            let status_requests = Arc::new(AtomicUsize::new(0));
+            let mut args = TestComponentsArgs::default();
+            args.set_shard_handler(|_| {
+                let status_requests = Arc::clone(&status_requests);
+                make_owned_handler(move |req, _| {
+                    if req.route == RouteId::QueryStatus {
+                        status_requests.fetch_add(1, Ordering::Relaxed);
+                    }
+                    futures::future::ok(HelperResponse::from(CompareStatusResponse {
+                        status: QueryStatus::AwaitingInputs,
+                        progress: None,
+                    }))
+                })
+            });
+            let t = TestComponents::new(args);
+            t.processor
+                .new_query(
+                    t.first_transport.clone_ref(),
+                    t.shard_transport.clone_ref(),
+                    t.query_config,
+                )
+                .await
+                .unwrap();
+
+            let (wait, ()) = tokio::join!(
+                t.processor.wait_for_completion(
+                    t.shard_transport.clone_ref(),
+                    WaitQueryRequest {
+                        query_id: QueryId,
+                        timeout: Duration::from_millis(100),
+                    },
+                ),
+                async {
+                    for _ in 0..10 {
+                        tokio::time::sleep(Duration::from_millis(5)).await;
+                        t.processor.queries.notify();
+                    }
+                }
+            );
+            assert_eq!(QueryStatus::AwaitingInputs, wait.unwrap().0);
+            // one request when the wait starts and one when it times out
+            assert_eq!(2, status_requests.load(Ordering::Relaxed));
+        }
+
+        #[tokio::test]
+        async fn wait_returns_once_query_ends() {
+            let t = TestComponents::default();
+            t.processor
+                .new_query(
+                    t.first_transport.clone_ref(),
+                    t.shard_transport.clone_ref(),
+                    t.query_config,
+                )
+                .await
+                .unwrap();
+            let (tx, rx) = tokio::sync::oneshot::channel();
+            let running = t.processor.queries.notify_on_completion(
+                &IpaRuntime::current(),
+                RunningQuery {
+                    result: rx,
+                    join_handle: IpaRuntime::current().spawn(async {}),
+                },
+            );
+            t.processor
+                .queries
+                .handle(QueryId)
+                .set_state(QueryState::Running(running))
+                .unwrap();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                tx.send(Err(ProtocolError::MaliciousRevealFailed)).unwrap();
+            });
+
+            let start = Instant::now();
+            let (status, _) = t
+                .processor
+                .wait_for_completion(
+                    t.shard_transport.clone_ref(),
+                    WaitQueryRequest {
+                        query_id: QueryId,
+                        timeout: MAX_QUERY_WAIT,
+                    },
+                )
+                .await
+                .unwrap();
+            assert!(matches!(status, QueryStatus::Failed { .. }));
+            assert!(start.elapsed() < MAX_QUERY_WAIT);
+        }
+
+        #[tokio::test]
+        async fn wait_returns_once_query_killed() {
+            let t = TestComponents::default();
+            t.processor
+                .new_query(
+                    t.first_transport.clone_ref(),
+                    t.shard_transport.clone_ref(),
+                    t.query_config,
+                )
+                .await
+                .unwrap();
+
+            let start = Instant::now();
+            let (wait, ()) = tokio::join!(
+                t.processor.wait_for_completion(
+                    t.shard_transport.clone_ref(),
+                    WaitQueryRequest {
+                        query_id: QueryId,
+                        timeout: MAX_QUERY_WAIT,
+                    },
+                ),
+                async {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                    t.processor.kill(QueryId).unwrap();
+                }
+            );
+            assert_eq!(QueryStatus::Killed, wait.unwrap().0);
+            assert!(start.elapsed() < MAX_QUERY_WAIT);
+        }
+
         /// * From the standpoint of leader shard in Helper 1
         /// * On query_status
         ///
@@ -1212,7 +1889,10 @@ mod tests {
                             other_status: QueryStatus::Preparing,
                         }))
                     } else {
-                        Ok(HelperResponse::ok())
+                        Ok(HelperResponse::from(CompareStatusResponse {
+                            status: QueryStatus::AwaitingInputs,
+                            progress: None,
+                        }))
                     }
                 })
             }
@@ -1285,21 +1965,92 @@ mod tests {
     }
 
     mod kill {
-        use std::sync::Arc;
+        use std::{
+            sync::{
+                atomic::{AtomicU32, Ordering},
+                Arc,
+            },
+            time::Duration,
+        };
 
-        use super::{TestComponents, TestComponentsArgs};
+        use super::{create_handler, TestComponents, TestComponentsArgs};
         use crate::{
             executor::IpaRuntime,
-            helpers::Transport,
+            helpers::{
+                query::KillQuery,
+                routing::{Addr, RouteId},
+                HelperResponse, RequestHandler, Transport, TransportIdentity,
+            },
             protocol::QueryId,
             query::{
                 processor::Processor,
                 state::{QueryState, RunningQuery},
-                QueryKillStatus,
+                QueryKillStatus, QueryStatus,
             },
             test_executor::run,
         };
 
+        /// Counts kill requests received by peers, all of them must be marked as forwarded.
+        fn count_kills<I: TransportIdentity>(
+            counter: &Arc<AtomicU32>,
+        ) -> Arc<dyn RequestHandler<I>> {
+            let counter = Arc::clone(counter);
+            create_handler(move |addr: Addr<I>| {
+                let counter = Arc::clone(&counter);
+                async move {
+                    assert_eq!(RouteId::KillQuery, addr.route);
+                    assert!(addr.into::<KillQuery>().unwrap().forwarded);
+                    counter.fetch_add(1, Ordering::Relaxed);
+                    Ok(HelperResponse::ok())
+                }
+            })
+        }
+
+        async fn kill_and_count(forwarded: bool) -> (u32, u32) {
+            let helper_kills = Arc::new(AtomicU32::new(0));
+            let shard_kills = Arc::new(AtomicU32::new(0));
+            let mut args = TestComponentsArgs {
+                shard_count: 3,
+                ..TestComponentsArgs::new(&count_kills(&helper_kills))
+            };
+            args.set_shard_handler(|_| count_kills(&shard_kills));
+            let t = TestComponents::new(args);
+
+            // the query is unknown to this helper, but it may still be running elsewhere
+            assert!(matches!(
+                t.processor
+                    .kill_query(
+                        t.first_transport.clone_ref(),
+                        t.shard_transport.clone_ref(),
+                        KillQuery {
+                            query_id: QueryId,
+                            forwarded,
+                        },
+                    )
+                    .await,
+                Err(QueryKillStatus::NoSuchQuery(QueryId))
+            ));
+
+            (
+                helper_kills.load(Ordering::Relaxed),
+                shard_kills.load(Ordering::Relaxed),
+            )
+        }
+
+        #[test]
+        fn forwards_to_helpers_and_shards() {
+            run(|| async {
+                assert_eq!((2, 2), kill_and_count(false).await);
+            });
+        }
+
+        #[test]
+        fn forwarded_request_only_goes_to_shards() {
+            run(|| async {
+                assert_eq!((0, 2), kill_and_count(true).await);
+            });
+        }
+
         #[test]
         fn non_existent_query() {
             run(|| async {
@@ -1327,6 +2078,7 @@ mod tests {
                     .unwrap();
 
                 t.processor.kill(QueryId).unwrap();
+                assert_eq!(Some(QueryStatus::Killed), t.processor.get_status(QueryId));
 
                 // start query again - it should work because the query was killed
                 t.processor
@@ -1366,6 +2118,23 @@ mod tests {
                 }
             });
         }
+
+        #[test]
+        fn forgets_killed_query_after_retention() {
+            run(|| async move {
+                let processor = Processor::default().with_aborted_query_retention(Duration::ZERO);
+                processor
+                    .queries
+                    .inner
+                    .lock()
+                    .unwrap()
+                    .insert(QueryId, QueryState::AwaitingCompletion);
+
+                processor.kill(QueryId).unwrap();
+                assert_eq!(None, processor.get_status(QueryId));
+                assert!(processor.queries.inner.lock().unwrap().is_empty());
+            });
+        }
     }
 
     mod e2e {
@@ -1382,7 +2151,9 @@ mod tests {
             },
             helpers::query::{IpaQueryConfig, QueryType},
             protocol::ipa_prf::OPRFIPAInputRow,
+            query::QueryMetricsError,
             secret_sharing::replicated::semi_honest,
+            telemetry::metrics::{BYTES_RECEIVED, BYTES_SENT, RECORDS_RECEIVED, RECORDS_SENT},
             test_fixture::{ipa::TestRawDataRecord, Reconstruct, TestApp},
         };
 
@@ -1432,6 +2203,69 @@ mod tests {
             Ok(())
         }
 
+        #[tokio::test]
+        async fn query_progress() -> Result<(), BoxError> {
+            let app = TestApp::default();
+            let a = Fp31::truncate_from(4u128);
+            let b = Fp31::truncate_from(5u128);
+            let query_id = app
+                .start_query(vec![a, b].into_iter(), test_multiply_config())
+                .await?;
+
+            while !(app.query_status(query_id).await? == QueryStatus::Completed) {
+                sleep(Duration::from_millis(1)).await;
+            }
+
+            for progress in app.query_progress(query_id).await? {
+                let progress = progress.unwrap();
+                assert!(!progress.phases.is_empty());
+                assert_eq!(progress.step, progress.phases.last().unwrap().step);
+                assert!(progress.records_expected > 0);
+                assert_eq!(progress.records_expected, progress.records_processed);
+            }
+            app.complete_query(query_id).await?;
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn query_metrics() -> Result<(), BoxError> {
+            let app = TestApp::default();
+            assert!(matches!(
+                app.query_metrics(QueryId),
+                Err(ApiError::QueryMetrics(QueryMetricsError::NoSuchQuery(
+                    QueryId
+                )))
+            ));
+
+            let a = Fp31::truncate_from(4u128);
+            let b = Fp31::truncate_from(5u128);
+            let query_id = app
+                .start_query(vec![a, b].into_iter(), test_multiply_config())
+                .await?;
+            app.complete_query(query_id).await?;
+
+            // metrics remain available after query is completed
+            let [metrics, _, _] = app.query_metrics(query_id)?;
+            // PRSS setup exchanges two public keys, multiplication sends one Fp31 value
+            for (counter, expected) in [
+                (RECORDS_SENT, 3),
+                (RECORDS_RECEIVED, 3),
+                (BYTES_SENT, 65),
+                (BYTES_RECEIVED, 65),
+            ] {
+                assert_eq!(expected, metrics.get_counter(counter), "{counter}");
+            }
+            assert_eq!(
+                Some(&1),
+                metrics
+                    .get_dimension(RECORDS_RECEIVED, "step")
+                    .get("protocol/multiply")
+            );
+
+            Ok(())
+        }
+
         #[tokio::test]
         async fn complete_query_ipa() -> Result<(), BoxError> {
             let app = TestApp::default();
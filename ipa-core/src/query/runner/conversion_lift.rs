@@ -0,0 +1,383 @@
+use std::{convert::Infallible, fmt::Debug, marker::PhantomData, ops::Add, sync::Arc};
+
+use generic_array::ArrayLength;
+
+use super::QueryResult;
+use crate::{
+    error::{Error, LengthError},
+    ff::{
+        boolean::Boolean,
+        boolean_array::{BooleanArray, BA3, BA32, BA8},
+        curve_points::RP25519,
+        ec_prime_field::Fp25519,
+        Serializable, U128Conversions,
+    },
+    helpers::{
+        query::{HybridQueryParams, QueryConfig, QuerySize},
+        BodyStream, Gateway,
+    },
+    hpke::PrivateKeyRegistry,
+    protocol::{
+        basics::{shard_fin::FinalizerContext, BooleanArrayMul, BooleanProtocols, Reveal},
+        context::{
+            DZKPUpgraded, MacUpgraded, ShardedContext, ShardedMaliciousContext, UpgradableContext,
+        },
+        hybrid::{
+            hybrid_protocol,
+            oprf::{CONV_CHUNK, PRF_CHUNK},
+            LiftHistogram,
+        },
+        ipa_prf::{prf_eval::PrfSharing, shuffle::ShardedShuffle},
+        prss::{Endpoint, FromPrss},
+        step::ProtocolStep::Hybrid,
+        Gate,
+    },
+    query::{
+        runner::{
+            hybrid::{
+                decrypt_and_reshard, dp_and_padding_params, HYBRID_BREAKDOWNS, HYBRID_SS_BITS,
+            },
+            setup_sharding,
+        },
+        ProtocolResult,
+    },
+    report::hybrid::IndistinguishableHybridReport,
+    secret_sharing::{
+        replicated::semi_honest::AdditiveShare as Replicated, BitDecomposed, TransposeFrom,
+        Vectorizable,
+    },
+};
+
+/// The number of breakdown keys available to each group of a lift experiment. The group is
+/// stored in the most significant bit of an 8 bit breakdown key.
+const MAX_LIFT_BREAKDOWN_KEY: u32 = 128;
+
+/// Conversion lift query. It takes encrypted hybrid reports, where impressions in the lift
+/// experiment are lift impressions, and computes the conversion totals for the control and
+/// the test groups in one pass. Regular impressions are in the control group.
+///
+/// The result is a [`LiftHistogram`] with 128 totals for each group. Breakdown keys of
+/// impressions must be below 128.
+pub struct Query<C, HV, R: PrivateKeyRegistry> {
+    config: HybridQueryParams,
+    key_registry: Arc<R>,
+    phantom_data: PhantomData<(C, HV)>,
+}
+
+impl<C, HV, R: PrivateKeyRegistry> Query<C, HV, R> {
+    pub fn new(query_params: HybridQueryParams, key_registry: Arc<R>) -> Self {
+        Self {
+            config: query_params,
+            key_registry,
+            phantom_data: PhantomData,
+        }
+    }
+}
+
+impl<C, HV, R> Query<C, HV, R>
+where
+    C: UpgradableContext
+        + ShardedShuffle
+        + ShardedContext
+        + FinalizerContext<FinalizingContext = DZKPUpgraded<C>>,
+    HV: BooleanArray + U128Conversions,
+    <HV as Serializable>::Size: Add<<HV as Serializable>::Size, Output: ArrayLength>,
+    R: PrivateKeyRegistry,
+    Replicated<Boolean, CONV_CHUNK>: BooleanProtocols<DZKPUpgraded<C>, CONV_CHUNK>,
+    Replicated<Fp25519, PRF_CHUNK>:
+        PrfSharing<MacUpgraded<C, Fp25519>, PRF_CHUNK, Field = Fp25519> + FromPrss,
+    Replicated<RP25519, PRF_CHUNK>:
+        Reveal<MacUpgraded<C, Fp25519>, Output = <RP25519 as Vectorizable<PRF_CHUNK>>::Array>,
+    Replicated<Boolean>: BooleanProtocols<DZKPUpgraded<C>>,
+    Replicated<HV>: Serializable,
+    Replicated<BA8>: BooleanArrayMul<DZKPUpgraded<C>>
+        + Reveal<DZKPUpgraded<C>, Output = <BA8 as Vectorizable<1>>::Array>,
+    BitDecomposed<Replicated<Boolean, 256>>:
+        for<'bt> TransposeFrom<&'bt Vec<Replicated<HV>>, Error = LengthError>,
+    BitDecomposed<Replicated<Boolean, 256>>:
+        for<'bt> TransposeFrom<&'bt [Replicated<HV>; 256], Error = Infallible>,
+    Vec<Replicated<HV>>:
+        for<'bt> TransposeFrom<&'bt BitDecomposed<Replicated<Boolean, 256>>, Error = LengthError>,
+    DZKPUpgraded<C>: ShardedContext,
+{
+    #[tracing::instrument("conversion_lift_query", skip_all, fields(sz=%query_size))]
+    pub async fn execute(
+        self,
+        ctx: C,
+        query_size: QuerySize,
+        input_stream: BodyStream,
+    ) -> Result<LiftHistogram<Replicated<HV>>, Error> {
+        let Self {
+            config,
+            key_registry,
+            phantom_data: _,
+        } = &self;
+
+        tracing::info!("New conversion lift query: {config:?}");
+        let ctx = ctx.narrow(&Hybrid);
+
+        if config.plaintext_match_keys {
+            return Err(Error::Unsupported(
+                "Conversion lift queries do not support plaintext match keys".to_string(),
+            ));
+        }
+        if config.max_breakdown_key > MAX_LIFT_BREAKDOWN_KEY {
+            return Err(Error::InvalidQueryParameter(
+                format!(
+                    "Conversion lift queries support up to {MAX_LIFT_BREAKDOWN_KEY} breakdown keys, got {}",
+                    config.max_breakdown_key
+                )
+                .into(),
+            ));
+        }
+
+        let lift_reports =
+            decrypt_and_reshard(&ctx, key_registry.as_ref(), query_size, input_stream)
+                .await?
+                .into_iter()
+                .map(IndistinguishableHybridReport::from_lift_report)
+                .collect();
+        let (dp_params, padding_params) = dp_and_padding_params(config);
+
+        let histogram = hybrid_protocol::<_, BA8, BA3, HV, HYBRID_SS_BITS, HYBRID_BREAKDOWNS>(
+            ctx,
+            lift_reports,
+            dp_params,
+            padding_params,
+        )
+        .await?;
+
+        Ok(LiftHistogram::from(histogram))
+    }
+}
+
+/// The result is sent as the totals for the control group, followed by the totals for the
+/// test group. [`LiftHistogram::from`] splits them up again.
+impl<T> ProtocolResult for LiftHistogram<T>
+where
+    T: Send + Debug,
+    Vec<T>: ProtocolResult,
+{
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.control.to_bytes();
+        bytes.extend(self.test.to_bytes());
+        bytes
+    }
+}
+
+pub async fn execute_conversion_lift_protocol<'a, R: PrivateKeyRegistry>(
+    prss: &'a Endpoint,
+    gateway: &'a Gateway,
+    input: BodyStream,
+    query_params: HybridQueryParams,
+    config: &QueryConfig,
+    key_registry: Arc<R>,
+) -> QueryResult {
+    let gate = Gate::default();
+    let sharded = setup_sharding(prss, gateway, &gate).await?;
+
+    let ctx = ShardedMaliciousContext::new_with_gate(prss, gateway, gate, sharded);
+    Ok(Box::new(
+        Query::<_, BA32, R>::new(query_params, key_registry)
+            .execute(ctx, config.size, input)
+            .await?,
+    ))
+}
+
+#[cfg(all(test, unit_test, feature = "in-memory-infra"))]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        error::Error,
+        ff::{boolean_array::BA32, U128Conversions},
+        helpers::{query::HybridQueryParams, BodyStream},
+        hpke::{KeyPair, KeyRegistry},
+        protocol::hybrid::LiftHistogram,
+        query::runner::{
+            conversion_lift::Query as ConversionLiftQuery,
+            hybrid::tests::{build_buffers_from_records, BufferAndKeyRegistry},
+        },
+        secret_sharing::replicated::semi_honest::AdditiveShare as Replicated,
+        test_executor::run,
+        test_fixture::{
+            flatten3v,
+            hybrid::{conversion_lift_in_the_clear, TestHybridRecord},
+            Reconstruct, TestWorld, TestWorldConfig, WithShards,
+        },
+    };
+
+    const MAX_BREAKDOWN_KEY: usize = 128;
+
+    fn impression(match_key: u64, breakdown_key: u32) -> TestHybridRecord {
+        TestHybridRecord::TestImpression {
+            match_key,
+            breakdown_key,
+            key_id: 0,
+        }
+    }
+
+    fn lift_impression(match_key: u64, breakdown_key: u32, test_group: bool) -> TestHybridRecord {
+        TestHybridRecord::TestLiftImpression {
+            match_key,
+            breakdown_key,
+            key_id: 0,
+            test_group,
+        }
+    }
+
+    fn conversion(match_key: u64, value: u32) -> TestHybridRecord {
+        TestHybridRecord::TestConversion {
+            match_key,
+            value,
+            key_id: 0,
+            conversion_site_domain: "meta.com".to_string(),
+            timestamp: 100,
+            epsilon: 0.0,
+            sensitivity: 0.0,
+        }
+    }
+
+    fn reconstruct_u32(shares: [&Vec<Replicated<BA32>>; 3]) -> Vec<u32> {
+        shares
+            .map(Clone::clone)
+            .reconstruct()
+            .iter()
+            .map(U128Conversions::as_u128)
+            .map(|x| u32::try_from(x).expect("test values constructed to fit in u32"))
+            .collect()
+    }
+
+    #[test]
+    fn conversion_lift() {
+        run(|| async {
+            const SHARDS: usize = 2;
+            let records = vec![
+                lift_impression(12345, 1, false),
+                conversion(12345, 2),
+                lift_impression(23456, 1, true),
+                conversion(23456, 3),
+                lift_impression(34567, 2, true),
+                conversion(34567, 5),
+                lift_impression(45678, 2, true), // no conversion
+                impression(56789, 127),          // regular impressions are in the control group
+                conversion(56789, 7),
+                conversion(67890, 1), // no impression
+            ];
+
+            let expected =
+                LiftHistogram::from(conversion_lift_in_the_clear(&records, MAX_BREAKDOWN_KEY));
+            let mut expected_control = vec![0; MAX_BREAKDOWN_KEY];
+            expected_control[1] = 2;
+            expected_control[127] = 7;
+            let mut expected_test = vec![0; MAX_BREAKDOWN_KEY];
+            expected_test[1] = 3;
+            expected_test[2] = 5;
+            assert_eq!(
+                LiftHistogram {
+                    control: expected_control,
+                    test: expected_test,
+                },
+                expected,
+            );
+
+            let BufferAndKeyRegistry {
+                buffers,
+                key_registry,
+                query_sizes,
+            } = build_buffers_from_records(&records, SHARDS);
+
+            let world = TestWorld::<WithShards<SHARDS>>::with_shards(TestWorldConfig::default());
+            let contexts = world.malicious_contexts();
+
+            #[allow(clippy::large_futures)]
+            let results = flatten3v(buffers.into_iter().zip(contexts).map(
+                |(helper_buffers, helper_ctxs)| {
+                    helper_buffers
+                        .into_iter()
+                        .zip(helper_ctxs)
+                        .zip(query_sizes.clone())
+                        .map(|((buffer, ctx), query_size)| {
+                            let query_params = HybridQueryParams {
+                                with_dp: 0,
+                                ..Default::default()
+                            };
+                            let input = BodyStream::from(buffer);
+
+                            ConversionLiftQuery::<_, BA32, KeyRegistry<KeyPair>>::new(
+                                query_params,
+                                Arc::clone(&key_registry),
+                            )
+                            .execute(ctx, query_size, input)
+                        })
+                },
+            ))
+            .await
+            .into_iter()
+            .map(Result::unwrap)
+            .collect::<Vec<_>>();
+
+            let leader_results = LiftHistogram {
+                control: reconstruct_u32([
+                    &results[0].control,
+                    &results[1].control,
+                    &results[2].control,
+                ]),
+                test: reconstruct_u32([&results[0].test, &results[1].test, &results[2].test]),
+            };
+            assert_eq!(expected, leader_results);
+
+            // only the leader shard returns the histogram
+            for follower_result in &results[3..] {
+                assert!(follower_result.control.is_empty());
+                assert!(follower_result.test.is_empty());
+            }
+        });
+    }
+
+    #[test]
+    fn rejects_large_breakdown_keys() {
+        run(|| async {
+            let records = vec![lift_impression(12345, 1, true), conversion(12345, 2)];
+            let BufferAndKeyRegistry {
+                buffers,
+                key_registry,
+                query_sizes,
+            } = build_buffers_from_records(&records, 1);
+
+            let world = TestWorld::<WithShards<1>>::with_shards(TestWorldConfig::default());
+            let contexts = world.malicious_contexts();
+
+            let results = flatten3v(buffers.into_iter().zip(contexts).map(
+                |(helper_buffers, helper_ctxs)| {
+                    helper_buffers
+                        .into_iter()
+                        .zip(helper_ctxs)
+                        .zip(query_sizes.clone())
+                        .map(|((buffer, ctx), query_size)| {
+                            let query_params = HybridQueryParams {
+                                max_breakdown_key: 129,
+                                with_dp: 0,
+                                ..Default::default()
+                            };
+
+                            ConversionLiftQuery::<_, BA32, KeyRegistry<KeyPair>>::new(
+                                query_params,
+                                Arc::clone(&key_registry),
+                            )
+                            .execute(
+                                ctx,
+                                query_size,
+                                BodyStream::from(buffer),
+                            )
+                        })
+                },
+            ))
+            .await;
+
+            for result in results {
+                assert!(matches!(result, Err(Error::InvalidQueryParameter(_))));
+            }
+        });
+    }
+}
@@ -19,8 +19,7 @@ use crate::{
         Serializable, U128Conversions,
     },
     helpers::{
-        query::{DpMechanism, HybridQueryParams, QueryConfig, QuerySize},
-        setup_cross_shard_prss,
+        query::{DpMechanism, HybridQueryParams, QueryConfig, QuerySize, QueryType},
         stream::TryFlattenItersExt,
         BodyStream, Gateway, LengthDelimitedStream,
     },
@@ -28,7 +27,8 @@ use crate::{
     protocol::{
         basics::{shard_fin::FinalizerContext, BooleanArrayMul, BooleanProtocols, Reveal},
         context::{
-            DZKPUpgraded, MacUpgraded, ShardedContext, ShardedMaliciousContext, UpgradableContext,
+            DZKPUpgraded, MacUpgraded, ShardedContext, ShardedMaliciousContext,
+            ShardedSemiHonestContext, UpgradableContext,
         },
         hybrid::{
             hybrid_protocol,
@@ -40,18 +40,22 @@ use crate::{
         step::ProtocolStep::Hybrid,
         Gate,
     },
-    query::runner::reshard_tag::reshard_aad,
-    report::hybrid::{
-        EncryptedHybridReport, IndistinguishableHybridReport, UniqueTag, UniqueTagValidator,
-    },
+    query::runner::{reshard_tag::reshard_aad, setup_sharding},
+    report::hybrid::{EncryptedHybridReport, HybridReport, UniqueTag, UniqueTagValidator},
     secret_sharing::{
         replicated::semi_honest::AdditiveShare as Replicated, BitDecomposed, TransposeFrom,
         Vectorizable,
     },
     seq_join::seq_join,
-    sharding::{ShardConfiguration, Sharded},
 };
 
+/// Number of bits user contributions to hybrid query histograms are capped to (`SS_BITS`).
+/// DP noise is calibrated to `2^HYBRID_SS_BITS`.
+pub const HYBRID_SS_BITS: usize = 3;
+
+/// Number of breakdown keys hybrid query histograms are computed for.
+pub const HYBRID_BREAKDOWNS: usize = 256;
+
 #[allow(dead_code)]
 pub struct Query<C, HV, R: PrivateKeyRegistry> {
     config: HybridQueryParams,
@@ -111,7 +115,6 @@ where
 
         tracing::info!("New hybrid query: {config:?}");
         let ctx = ctx.narrow(&Hybrid);
-        let sz = usize::from(query_size);
 
         if config.plaintext_match_keys {
             return Err(Error::Unsupported(
@@ -119,46 +122,15 @@ where
             ));
         }
 
-        let stream = LengthDelimitedStream::<EncryptedHybridReport<BA8, BA3>, _>::new(input_stream)
-            .map_err(Into::into)
-            .try_flatten_iters()
-            .map(|enc_report_res| async move {
-                enc_report_res.and_then(|enc_report| {
-                    let dec_report = enc_report
-                        .decrypt(key_registry.as_ref())
-                        .map_err(Into::<Error>::into);
-                    let unique_tag = UniqueTag::from_unique_bytes(&enc_report);
-                    dec_report.map(|dec_report1| (dec_report1, unique_tag))
-                })
-            })
-            .take(sz);
-
-        let (decrypted_reports, resharded_tags) = reshard_aad(
-            ctx.narrow(&HybridStep::ReshardByTag),
-            seq_join(ctx.active_work(), stream),
-            |ctx, _, tag| tag.shard_picker(ctx.shard_count()),
-        )
-        .await?;
-
-        let mut unique_encrypted_hybrid_reports = UniqueTagValidator::new(resharded_tags.len());
-        unique_encrypted_hybrid_reports.check_duplicates(&resharded_tags)?;
-
-        let indistinguishable_reports: Vec<IndistinguishableHybridReport<BA8, BA3>> =
-            decrypted_reports.into_iter().map(Into::into).collect();
-
-        let dp_params: DpMechanism = match config.with_dp {
-            0 => DpMechanism::NoDp,
-            _ => DpMechanism::DiscreteLaplace {
-                epsilon: config.epsilon,
-            },
-        };
-
-        #[cfg(feature = "relaxed-dp")]
-        let padding_params = PaddingParameters::relaxed();
-        #[cfg(not(feature = "relaxed-dp"))]
-        let padding_params = PaddingParameters::default();
+        let indistinguishable_reports =
+            decrypt_and_reshard(&ctx, key_registry.as_ref(), query_size, input_stream)
+                .await?
+                .into_iter()
+                .map(Into::into)
+                .collect();
+        let (dp_params, padding_params) = dp_and_padding_params(config);
 
-        hybrid_protocol::<_, BA8, BA3, HV, 3, 256>(
+        hybrid_protocol::<_, BA8, BA3, HV, HYBRID_SS_BITS, HYBRID_BREAKDOWNS>(
             ctx,
             indistinguishable_reports,
             dp_params,
@@ -168,6 +140,70 @@ where
     }
 }
 
+/// Decrypts the hybrid reports in `input_stream`, reshards them by their unique tag
+/// and checks that no report was submitted twice.
+pub(super) async fn decrypt_and_reshard<C, R>(
+    ctx: &C,
+    key_registry: &R,
+    query_size: QuerySize,
+    input_stream: BodyStream,
+) -> Result<Vec<HybridReport<BA8, BA3>>, Error>
+where
+    C: ShardedContext,
+    R: PrivateKeyRegistry,
+{
+    let sz = usize::from(query_size);
+    let stream = LengthDelimitedStream::<EncryptedHybridReport<BA8, BA3>, _>::new(input_stream)
+        .map_err(Into::into)
+        .try_flatten_iters()
+        .map(|enc_report_res| async move {
+            enc_report_res.and_then(|enc_report| {
+                let dec_report = enc_report
+                    .decrypt(key_registry)
+                    .map_err(Into::<Error>::into);
+                let unique_tag = UniqueTag::from_unique_bytes(&enc_report);
+                dec_report.map(|dec_report1| (dec_report1, unique_tag))
+            })
+        })
+        .take(sz);
+
+    let (decrypted_reports, resharded_tags) = reshard_aad(
+        ctx.narrow(&HybridStep::ReshardByTag),
+        seq_join(ctx.active_work(), stream),
+        |ctx, _, tag| tag.shard_picker(ctx.shard_count()),
+    )
+    .await?;
+
+    let mut unique_encrypted_hybrid_reports = UniqueTagValidator::new(resharded_tags.len());
+    unique_encrypted_hybrid_reports.check_duplicates(&resharded_tags)?;
+
+    Ok(decrypted_reports)
+}
+
+/// Returns the DP mechanism hybrid queries apply to their histograms.
+#[must_use]
+pub fn hybrid_dp_params(config: &HybridQueryParams) -> DpMechanism {
+    match config.with_dp {
+        0 => DpMechanism::NoDp,
+        _ => DpMechanism::DiscreteLaplace {
+            epsilon: config.epsilon,
+        },
+    }
+}
+
+pub(super) fn dp_and_padding_params(
+    config: &HybridQueryParams,
+) -> (DpMechanism, PaddingParameters) {
+    let dp_params = hybrid_dp_params(config);
+
+    #[cfg(feature = "relaxed-dp")]
+    let padding_params = PaddingParameters::relaxed();
+    #[cfg(not(feature = "relaxed-dp"))]
+    let padding_params = PaddingParameters::default();
+
+    (dp_params, padding_params)
+}
+
 pub async fn execute_hybrid_protocol<'a, R: PrivateKeyRegistry>(
     prss: &'a Endpoint,
     gateway: &'a Gateway,
@@ -177,25 +213,27 @@ pub async fn execute_hybrid_protocol<'a, R: PrivateKeyRegistry>(
     key_registry: Arc<R>,
 ) -> QueryResult {
     let gate = Gate::default();
-    let cross_shard_prss =
-        setup_cross_shard_prss(gateway, &gate, prss.indexed(&gate), gateway).await?;
-    let sharded = Sharded {
-        shard_id: gateway.shard_id(),
-        shard_count: gateway.shard_count(),
-        prss: Arc::new(cross_shard_prss),
-    };
-
-    let ctx = ShardedMaliciousContext::new_with_gate(prss, gateway, gate, sharded);
-
-    Ok(Box::new(
-        Query::<_, BA32, R>::new(ipa_config, key_registry)
-            .execute(ctx, config.size, input)
-            .await?,
-    ))
+    let sharded = setup_sharding(prss, gateway, &gate).await?;
+
+    Ok(if let QueryType::SemiHonestHybrid(_) = config.query_type {
+        let ctx = ShardedSemiHonestContext::new_with_gate(prss, gateway, sharded, gate);
+        Box::new(
+            Query::<_, BA32, R>::new(ipa_config, key_registry)
+                .execute(ctx, config.size, input)
+                .await?,
+        )
+    } else {
+        let ctx = ShardedMaliciousContext::new_with_gate(prss, gateway, gate, sharded);
+        Box::new(
+            Query::<_, BA32, R>::new(ipa_config, key_registry)
+                .execute(ctx, config.size, input)
+                .await?,
+        )
+    })
 }
 
 #[cfg(all(test, unit_test, feature = "in-memory-infra"))]
-mod tests {
+pub(super) mod tests {
     use std::{
         iter::{repeat, zip},
         sync::Arc,
@@ -225,13 +263,16 @@ mod tests {
         },
     };
 
-    struct BufferAndKeyRegistry {
-        buffers: [Vec<Vec<u8>>; 3],
-        key_registry: Arc<KeyRegistry<KeyPair>>,
-        query_sizes: Vec<QuerySize>,
+    pub(crate) struct BufferAndKeyRegistry {
+        pub buffers: [Vec<Vec<u8>>; 3],
+        pub key_registry: Arc<KeyRegistry<KeyPair>>,
+        pub query_sizes: Vec<QuerySize>,
     }
 
-    fn build_buffers_from_records(records: &[TestHybridRecord], s: usize) -> BufferAndKeyRegistry {
+    pub(crate) fn build_buffers_from_records(
+        records: &[TestHybridRecord],
+        s: usize,
+    ) -> BufferAndKeyRegistry {
         let mut rng = StdRng::seed_from_u64(42);
         let key_id = DEFAULT_KEY_ID;
         let key_registry = Arc::new(KeyRegistry::<KeyPair>::random(1, &mut rng));
@@ -341,6 +382,61 @@ mod tests {
         });
     }
 
+    #[test]
+    fn encrypted_hybrid_reports_semi_honest() {
+        run(|| async {
+            const SHARDS: usize = 2;
+            let (test_hybrid_records, mut expected) = build_hybrid_records_and_expectation();
+            expected.resize(256, 0);
+
+            let BufferAndKeyRegistry {
+                buffers,
+                key_registry,
+                query_sizes,
+            } = build_buffers_from_records(&test_hybrid_records, SHARDS);
+
+            let world = TestWorld::<WithShards<SHARDS>>::with_shards(TestWorldConfig::default());
+            let contexts = world.contexts();
+
+            #[allow(clippy::large_futures)]
+            let results = flatten3v(buffers.into_iter().zip(contexts).map(
+                |(helper_buffers, helper_ctxs)| {
+                    helper_buffers
+                        .into_iter()
+                        .zip(helper_ctxs)
+                        .zip(query_sizes.clone())
+                        .map(|((buffer, ctx), query_size)| {
+                            let query_params = HybridQueryParams {
+                                with_dp: 0,
+                                ..Default::default()
+                            };
+                            let input = BodyStream::from(buffer);
+
+                            HybridQuery::<_, BA32, KeyRegistry<KeyPair>>::new(
+                                query_params,
+                                Arc::clone(&key_registry),
+                            )
+                            .execute(ctx, query_size, input)
+                        })
+                },
+            ))
+            .await;
+
+            let leader_results: Vec<u32> = [
+                results[0].as_ref().unwrap().clone(),
+                results[1].as_ref().unwrap().clone(),
+                results[2].as_ref().unwrap().clone(),
+            ]
+            .reconstruct()
+            .iter()
+            .map(U128Conversions::as_u128)
+            .map(|x| u32::try_from(x).expect("test values constructed to fit in u32"))
+            .collect::<Vec<u32>>();
+
+            assert_eq!(expected, leader_results);
+        });
+    }
+
     // cannot test for Err directly because join3v calls unwrap. This should be sufficient.
     #[tokio::test(flavor = "multi_thread", worker_threads = 3)]
     #[should_panic(expected = "DuplicateBytes")]
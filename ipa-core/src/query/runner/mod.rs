@@ -1,7 +1,9 @@
 #[cfg(any(test, feature = "cli", feature = "test-fixture"))]
 mod add_in_prime_field;
+mod conversion_lift;
 mod hybrid;
 mod oprf_ipa;
+mod reach_frequency;
 mod reshard_tag;
 #[cfg(any(test, feature = "cli", feature = "test-fixture"))]
 mod sharded_shuffle;
@@ -15,7 +17,36 @@ pub(super) use sharded_shuffle::execute_sharded_shuffle;
 #[cfg(any(test, feature = "cli", feature = "test-fixture"))]
 pub(super) use test_multiply::execute_test_multiply;
 
-pub use self::{hybrid::execute_hybrid_protocol, oprf_ipa::OprfIpaQuery};
-use crate::{error::Error, query::ProtocolResult};
+pub use self::{
+    conversion_lift::execute_conversion_lift_protocol,
+    hybrid::{execute_hybrid_protocol, hybrid_dp_params, HYBRID_BREAKDOWNS, HYBRID_SS_BITS},
+    oprf_ipa::{credit_cap_ss_bits, execute_sharded_oprf_ipa, OprfIpaQuery, IPA_BREAKDOWNS},
+    reach_frequency::{execute_reach_frequency_protocol, REACH_FREQUENCY_SS_BITS},
+};
+use crate::{
+    error::Error,
+    helpers::{setup_cross_shard_prss, Gateway},
+    protocol::{prss::Endpoint as PrssEndpoint, Gate},
+    query::ProtocolResult,
+    sharding::{ShardConfiguration, Sharded},
+    sync::Arc,
+};
 
 pub(super) type QueryResult = Result<Box<dyn ProtocolResult>, Error>;
+
+/// Agrees on PRSS with other shards of this helper and returns everything sharded contexts
+/// need to communicate with them. `gate` is the gate the query context starts at.
+async fn setup_sharding(
+    prss: &PrssEndpoint,
+    gateway: &Gateway,
+    gate: &Gate,
+) -> Result<Sharded, Error> {
+    let cross_shard_prss =
+        setup_cross_shard_prss(gateway, gate, prss.indexed(gate), gateway).await?;
+
+    Ok(Sharded {
+        shard_id: gateway.shard_id(),
+        shard_count: gateway.shard_count(),
+        prss: Arc::new(cross_shard_prss),
+    })
+}
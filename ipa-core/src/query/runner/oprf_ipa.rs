@@ -1,33 +1,39 @@
-use std::{convert::Infallible, marker::PhantomData};
+use std::{convert::Infallible, marker::PhantomData, ops::Add};
 
 use futures::{stream::iter, StreamExt, TryStreamExt};
 use futures_util::stream::repeat;
+use generic_array::ArrayLength;
 
 use crate::{
     error::{Error, LengthError},
     ff::{
         boolean::Boolean,
-        boolean_array::{BooleanArray, BA20, BA3, BA8},
+        boolean_array::{BooleanArray, BA20, BA3, BA32, BA8},
         curve_points::RP25519,
         ec_prime_field::Fp25519,
         Field, Serializable, U128Conversions,
     },
     helpers::{
-        query::{DpMechanism, IpaQueryConfig, QuerySize},
-        BodyStream, LengthDelimitedStream, RecordsStream,
+        query::{DpMechanism, IpaQueryConfig, QueryConfig, QuerySize, QueryType},
+        BodyStream, Gateway, LengthDelimitedStream, RecordsStream,
     },
     hpke::PrivateKeyRegistry,
     protocol::{
-        basics::{BooleanArrayMul, Reveal, ShareKnownValue},
-        context::{DZKPUpgraded, MacUpgraded, UpgradableContext},
+        basics::{shard_fin::FinalizerContext, BooleanArrayMul, Reveal, ShareKnownValue},
+        context::{
+            AdaptiveActiveWork, DZKPUpgraded, MacUpgraded, ShardedContext, ShardedMaliciousContext,
+            ShardedSemiHonestContext, UpgradableContext,
+        },
         ipa_prf::{
-            oprf_ipa, oprf_padding::PaddingParameters, prf_eval::PrfSharing, OPRFIPAInputRow,
-            Shuffle, AGG_CHUNK, CONV_CHUNK, PRF_CHUNK, SORT_CHUNK,
+            oprf_ipa, oprf_padding::PaddingParameters, prf_eval::PrfSharing, sharded_oprf_ipa,
+            shuffle::ShardedShuffle, OPRFIPAInputRow, Shuffle, AGG_CHUNK, CONV_CHUNK, PRF_CHUNK,
+            SORT_CHUNK,
         },
-        prss::FromPrss,
+        prss::{Endpoint, FromPrss},
         step::ProtocolStep::IpaPrf,
-        BooleanProtocols,
+        BooleanProtocols, Gate,
     },
+    query::runner::{setup_sharding, QueryResult},
     report::{EncryptedOprfReport, EventType},
     secret_sharing::{
         replicated::semi_honest::{AdditiveShare as Replicated, AdditiveShare},
@@ -36,6 +42,26 @@ use crate::{
     sync::Arc,
 };
 
+/// Number of breakdown keys OPRF IPA histograms are computed for.
+pub const IPA_BREAKDOWNS: usize = 256;
+
+/// Returns the number of bits user contributions are capped to (`SS_BITS`) for the given
+/// per-user credit cap, or `None` if the cap is not supported. Caps of 2 and 4 both run with
+/// 2 bits. DP noise is calibrated to `2^SS_BITS` rather than to the cap itself.
+#[must_use]
+pub fn credit_cap_ss_bits(per_user_credit_cap: u32) -> Option<usize> {
+    match per_user_credit_cap {
+        1 => Some(1),
+        2 | 4 => Some(2),
+        8 => Some(3),
+        16 => Some(4),
+        32 => Some(5),
+        64 => Some(6),
+        128 => Some(7),
+        _ => None,
+    }
+}
+
 pub struct OprfIpaQuery<C, HV, R: PrivateKeyRegistry> {
     config: IpaQueryConfig,
     key_registry: Arc<R>,
@@ -52,53 +78,32 @@ impl<C, HV, R: PrivateKeyRegistry> OprfIpaQuery<C, HV, R> {
     }
 }
 
-#[allow(clippy::too_many_lines)]
 impl<C, HV, R> OprfIpaQuery<C, HV, R>
 where
-    C: UpgradableContext + Shuffle,
-    HV: BooleanArray + U128Conversions,
+    C: UpgradableContext,
     R: PrivateKeyRegistry,
-    Replicated<Boolean>: Serializable + ShareKnownValue<C, Boolean>,
-    Replicated<Boolean>: BooleanProtocols<DZKPUpgraded<C>>,
-    Replicated<Boolean, 256>: BooleanProtocols<DZKPUpgraded<C>, 256>,
-    Replicated<Boolean, AGG_CHUNK>: BooleanProtocols<DZKPUpgraded<C>, AGG_CHUNK>,
-    Replicated<Boolean, CONV_CHUNK>: BooleanProtocols<DZKPUpgraded<C>, CONV_CHUNK>,
-    Replicated<Boolean, SORT_CHUNK>: BooleanProtocols<DZKPUpgraded<C>, SORT_CHUNK>,
-    Replicated<Fp25519, PRF_CHUNK>:
-        PrfSharing<MacUpgraded<C, Fp25519>, PRF_CHUNK, Field = Fp25519> + FromPrss,
-    Replicated<RP25519, PRF_CHUNK>:
-        Reveal<MacUpgraded<C, Fp25519>, Output = <RP25519 as Vectorizable<PRF_CHUNK>>::Array>,
-    Replicated<BA8>: BooleanArrayMul<DZKPUpgraded<C>>
-        + Reveal<DZKPUpgraded<C>, Output = <BA8 as Vectorizable<1>>::Array>,
-    Replicated<BA20>: BooleanArrayMul<DZKPUpgraded<C>>,
-    Replicated<BA3>: BooleanArrayMul<DZKPUpgraded<C>>,
-    Vec<Replicated<HV>>:
-        for<'a> TransposeFrom<&'a BitDecomposed<Replicated<Boolean, 256>>, Error = LengthError>,
-    BitDecomposed<AdditiveShare<Boolean, 256>>:
-        for<'a> TransposeFrom<&'a [AdditiveShare<HV>; 256], Error = Infallible>,
+    Replicated<Boolean>: ShareKnownValue<C, Boolean>,
 {
-    #[tracing::instrument("oprf_ipa_query", skip_all, fields(sz=%query_size))]
-    pub async fn execute(
-        self,
-        ctx: C,
-        query_size: QuerySize,
+    /// Reads up to `sz` reports from `input_stream`, decrypting them unless the query uses
+    /// plaintext match keys.
+    async fn read_input(
+        &self,
+        ctx: &C,
+        sz: usize,
         input_stream: BodyStream,
-    ) -> Result<Vec<Replicated<HV>>, Error> {
+    ) -> Result<Vec<OPRFIPAInputRow<BA8, BA3, BA20>>, Error> {
         let Self {
             config,
             key_registry,
             phantom_data: _,
         } = self;
-        tracing::info!("New query: {config:?}");
-        let ctx = ctx.narrow(&IpaPrf);
-        let sz = usize::from(query_size);
 
-        let input = if config.plaintext_match_keys {
+        if config.plaintext_match_keys {
             let mut v = RecordsStream::<OPRFIPAInputRow<BA8, BA3, BA20>, _>::new(input_stream)
                 .try_concat()
                 .await?;
             v.truncate(sz);
-            v
+            Ok(v)
         } else {
             LengthDelimitedStream::<EncryptedOprfReport<BA8, BA3, BA20, _>, _>::new(input_stream)
                 .map_err(Into::<Error>::into)
@@ -132,14 +137,15 @@ where
                     })
                 })
                 .try_collect::<Vec<_>>()
-                .await?
-        };
+                .await
+        }
+    }
 
-        let aws = config.attribution_window_seconds;
-        let dp_params: DpMechanism = match config.with_dp {
+    fn dp_params(&self) -> (DpMechanism, PaddingParameters) {
+        let dp_params = match self.config.with_dp {
             0 => DpMechanism::NoDp,
             _ => DpMechanism::DiscreteLaplace {
-                epsilon: config.epsilon,
+                epsilon: self.config.epsilon,
             },
         };
 
@@ -147,22 +153,180 @@ where
         let padding_params = PaddingParameters::relaxed();
         #[cfg(not(feature = "relaxed-dp"))]
         let padding_params = PaddingParameters::default();
-        match config.per_user_credit_cap {
-            1 => oprf_ipa::<_, BA8, BA3, HV, BA20, 1, 256>(ctx, input, aws, dp_params, padding_params).await,
-            2 | 4 => oprf_ipa::<_, BA8, BA3, HV, BA20, 2, 256>(ctx, input, aws, dp_params, padding_params).await,
-            8 => oprf_ipa::<_, BA8, BA3, HV, BA20, 3, 256>(ctx, input, aws, dp_params, padding_params).await,
-            16 => oprf_ipa::<_, BA8, BA3, HV, BA20, 4, 256>(ctx, input, aws, dp_params, padding_params).await,
-            32 => oprf_ipa::<_, BA8, BA3, HV, BA20, 5, 256>(ctx, input, aws, dp_params, padding_params).await,
-            64 => oprf_ipa::<_, BA8, BA3, HV, BA20, 6, 256>(ctx, input, aws, dp_params, padding_params).await,
-            128 => oprf_ipa::<_, BA8, BA3, HV, BA20, 7, 256>(ctx, input, aws, dp_params, padding_params).await,
+
+        (dp_params, padding_params)
+    }
+}
+
+impl<C, HV, R> OprfIpaQuery<C, HV, R>
+where
+    C: UpgradableContext + AdaptiveActiveWork + Shuffle,
+    HV: BooleanArray + U128Conversions,
+    R: PrivateKeyRegistry,
+    Replicated<Boolean>: Serializable + ShareKnownValue<C, Boolean>,
+    Replicated<Boolean>: BooleanProtocols<DZKPUpgraded<C>>,
+    Replicated<Boolean, 256>: BooleanProtocols<DZKPUpgraded<C>, 256>,
+    Replicated<Boolean, AGG_CHUNK>: BooleanProtocols<DZKPUpgraded<C>, AGG_CHUNK>,
+    Replicated<Boolean, CONV_CHUNK>: BooleanProtocols<DZKPUpgraded<C>, CONV_CHUNK>,
+    Replicated<Boolean, SORT_CHUNK>: BooleanProtocols<DZKPUpgraded<C>, SORT_CHUNK>,
+    Replicated<Fp25519, PRF_CHUNK>:
+        PrfSharing<MacUpgraded<C, Fp25519>, PRF_CHUNK, Field = Fp25519> + FromPrss,
+    Replicated<RP25519, PRF_CHUNK>:
+        Reveal<MacUpgraded<C, Fp25519>, Output = <RP25519 as Vectorizable<PRF_CHUNK>>::Array>,
+    Replicated<BA8>: BooleanArrayMul<DZKPUpgraded<C>>
+        + Reveal<DZKPUpgraded<C>, Output = <BA8 as Vectorizable<1>>::Array>,
+    Replicated<BA20>: BooleanArrayMul<DZKPUpgraded<C>>,
+    Replicated<BA3>: BooleanArrayMul<DZKPUpgraded<C>>,
+    Vec<Replicated<HV>>:
+        for<'a> TransposeFrom<&'a BitDecomposed<Replicated<Boolean, 256>>, Error = LengthError>,
+    BitDecomposed<AdditiveShare<Boolean, 256>>:
+        for<'a> TransposeFrom<&'a [AdditiveShare<HV>; 256], Error = Infallible>,
+{
+    /// Runs OPRF IPA on a single shard of a helper.
+    ///
+    /// ## Errors
+    /// If input can't be read or the protocol fails.
+    ///
+    /// ## Panics
+    /// If `per_user_credit_cap` is not one of the supported values.
+    #[tracing::instrument("oprf_ipa_query", skip_all, fields(sz=%query_size))]
+    pub async fn execute(
+        self,
+        ctx: C,
+        query_size: QuerySize,
+        input_stream: BodyStream,
+    ) -> Result<Vec<Replicated<HV>>, Error> {
+        tracing::info!("New query: {:?}", self.config);
+        let ctx = ctx.narrow(&IpaPrf);
+        let input = self
+            .read_input(&ctx, usize::from(query_size), input_stream)
+            .await?;
+
+        let aws = self.config.attribution_window_seconds;
+        let (dp_params, padding_params) = self.dp_params();
+        match credit_cap_ss_bits(self.config.per_user_credit_cap) {
+            Some(1) => oprf_ipa::<_, BA8, BA3, HV, BA20, 1, IPA_BREAKDOWNS>(ctx, input, aws, dp_params, padding_params).await,
+            Some(2) => oprf_ipa::<_, BA8, BA3, HV, BA20, 2, IPA_BREAKDOWNS>(ctx, input, aws, dp_params, padding_params).await,
+            Some(3) => oprf_ipa::<_, BA8, BA3, HV, BA20, 3, IPA_BREAKDOWNS>(ctx, input, aws, dp_params, padding_params).await,
+            Some(4) => oprf_ipa::<_, BA8, BA3, HV, BA20, 4, IPA_BREAKDOWNS>(ctx, input, aws, dp_params, padding_params).await,
+            Some(5) => oprf_ipa::<_, BA8, BA3, HV, BA20, 5, IPA_BREAKDOWNS>(ctx, input, aws, dp_params, padding_params).await,
+            Some(6) => oprf_ipa::<_, BA8, BA3, HV, BA20, 6, IPA_BREAKDOWNS>(ctx, input, aws, dp_params, padding_params).await,
+            Some(7) => oprf_ipa::<_, BA8, BA3, HV, BA20, 7, IPA_BREAKDOWNS>(ctx, input, aws, dp_params, padding_params).await,
             _ => panic!(
                 "Invalid value specified for per-user cap: {:?}. Must be one of 1, 2, 4, 8, 16, 32, 64, or 128.",
-                config.per_user_credit_cap
+                self.config.per_user_credit_cap
             ),
         }
     }
 }
 
+impl<C, HV, R> OprfIpaQuery<C, HV, R>
+where
+    C: UpgradableContext
+        + AdaptiveActiveWork
+        + ShardedShuffle
+        + ShardedContext
+        + FinalizerContext<FinalizingContext = DZKPUpgraded<C>>,
+    HV: BooleanArray + U128Conversions,
+    <HV as Serializable>::Size: Add<<HV as Serializable>::Size, Output: ArrayLength>,
+    R: PrivateKeyRegistry,
+    Replicated<Boolean>: Serializable + ShareKnownValue<C, Boolean>,
+    Replicated<Boolean>: BooleanProtocols<DZKPUpgraded<C>>,
+    Replicated<Boolean, 256>: BooleanProtocols<DZKPUpgraded<C>, 256>,
+    Replicated<Boolean, CONV_CHUNK>: BooleanProtocols<DZKPUpgraded<C>, CONV_CHUNK>,
+    Replicated<Boolean, SORT_CHUNK>: BooleanProtocols<DZKPUpgraded<C>, SORT_CHUNK>,
+    Replicated<Fp25519, PRF_CHUNK>:
+        PrfSharing<MacUpgraded<C, Fp25519>, PRF_CHUNK, Field = Fp25519> + FromPrss,
+    Replicated<RP25519, PRF_CHUNK>:
+        Reveal<MacUpgraded<C, Fp25519>, Output = <RP25519 as Vectorizable<PRF_CHUNK>>::Array>,
+    Replicated<BA8>: BooleanArrayMul<DZKPUpgraded<C>>
+        + Reveal<DZKPUpgraded<C>, Output = <BA8 as Vectorizable<1>>::Array>,
+    Replicated<BA20>: BooleanArrayMul<DZKPUpgraded<C>>,
+    Replicated<BA3>: BooleanArrayMul<DZKPUpgraded<C>>,
+    BitDecomposed<Replicated<Boolean, 256>>:
+        for<'a> TransposeFrom<&'a [Replicated<BA3>; 256], Error = Infallible>,
+    BitDecomposed<Replicated<Boolean, 256>>:
+        for<'a> TransposeFrom<&'a [Replicated<HV>; 256], Error = Infallible>,
+    BitDecomposed<Replicated<Boolean, 256>>:
+        for<'a> TransposeFrom<&'a Vec<Replicated<HV>>, Error = LengthError>,
+    Vec<Replicated<HV>>:
+        for<'a> TransposeFrom<&'a BitDecomposed<Replicated<Boolean, 256>>, Error = LengthError>,
+    DZKPUpgraded<C>: ShardedContext,
+{
+    /// Runs OPRF IPA on one shard of a helper. Every shard reads its own part of the input,
+    /// and the leader shard returns the result for the whole query.
+    /// See [`sharded_oprf_ipa`] for details.
+    ///
+    /// ## Errors
+    /// If input can't be read or the protocol fails.
+    ///
+    /// ## Panics
+    /// If `per_user_credit_cap` is not one of the supported values.
+    #[tracing::instrument("sharded_oprf_ipa_query", skip_all, fields(sz=%query_size))]
+    pub async fn execute_sharded(
+        self,
+        ctx: C,
+        query_size: QuerySize,
+        input_stream: BodyStream,
+    ) -> Result<Vec<Replicated<HV>>, Error> {
+        tracing::info!(
+            "New sharded query on shard {} out of {}: {:?}",
+            ctx.shard_id(),
+            ctx.shard_count(),
+            self.config
+        );
+        let ctx = ctx.narrow(&IpaPrf);
+        let input = self
+            .read_input(&ctx, usize::from(query_size), input_stream)
+            .await?;
+
+        let aws = self.config.attribution_window_seconds;
+        let (dp_params, padding_params) = self.dp_params();
+        match credit_cap_ss_bits(self.config.per_user_credit_cap) {
+            Some(1) => sharded_oprf_ipa::<_, BA8, BA3, HV, BA20, 1, IPA_BREAKDOWNS>(ctx, input, aws, dp_params, padding_params).await,
+            Some(2) => sharded_oprf_ipa::<_, BA8, BA3, HV, BA20, 2, IPA_BREAKDOWNS>(ctx, input, aws, dp_params, padding_params).await,
+            Some(3) => sharded_oprf_ipa::<_, BA8, BA3, HV, BA20, 3, IPA_BREAKDOWNS>(ctx, input, aws, dp_params, padding_params).await,
+            Some(4) => sharded_oprf_ipa::<_, BA8, BA3, HV, BA20, 4, IPA_BREAKDOWNS>(ctx, input, aws, dp_params, padding_params).await,
+            Some(5) => sharded_oprf_ipa::<_, BA8, BA3, HV, BA20, 5, IPA_BREAKDOWNS>(ctx, input, aws, dp_params, padding_params).await,
+            Some(6) => sharded_oprf_ipa::<_, BA8, BA3, HV, BA20, 6, IPA_BREAKDOWNS>(ctx, input, aws, dp_params, padding_params).await,
+            Some(7) => sharded_oprf_ipa::<_, BA8, BA3, HV, BA20, 7, IPA_BREAKDOWNS>(ctx, input, aws, dp_params, padding_params).await,
+            _ => panic!(
+                "Invalid value specified for per-user cap: {:?}. Must be one of 1, 2, 4, 8, 16, 32, 64, or 128.",
+                self.config.per_user_credit_cap
+            ),
+        }
+    }
+}
+
+/// Runs OPRF IPA query on a helper that has more than one shard.
+pub async fn execute_sharded_oprf_ipa<'a, R: PrivateKeyRegistry>(
+    prss: &'a Endpoint,
+    gateway: &'a Gateway,
+    input: BodyStream,
+    ipa_config: IpaQueryConfig,
+    config: &QueryConfig,
+    key_registry: Arc<R>,
+) -> QueryResult {
+    let gate = Gate::default();
+    let sharded = setup_sharding(prss, gateway, &gate).await?;
+
+    Ok(if let QueryType::MaliciousOprfIpa(_) = config.query_type {
+        let ctx = ShardedMaliciousContext::new_with_gate(prss, gateway, gate, sharded);
+        Box::new(
+            OprfIpaQuery::<_, BA32, R>::new(ipa_config, key_registry)
+                .execute_sharded(ctx, config.size, input)
+                .await?,
+        )
+    } else {
+        let ctx = ShardedSemiHonestContext::new_with_gate(prss, gateway, sharded, gate);
+        Box::new(
+            OprfIpaQuery::<_, BA32, R>::new(ipa_config, key_registry)
+                .execute_sharded(ctx, config.size, input)
+                .await?,
+        )
+    })
+}
+
 #[cfg(all(test, unit_test))]
 mod tests {
     use std::{iter::zip, sync::Arc};
@@ -180,7 +344,7 @@ mod tests {
             BodyStream,
         },
         hpke::{KeyPair, KeyRegistry},
-        query::runner::OprfIpaQuery,
+        query::runner::{credit_cap_ss_bits, OprfIpaQuery},
         report::{OprfReport, DEFAULT_KEY_ID},
         secret_sharing::IntoShares,
         test_fixture::{ipa::TestRawDataRecord, join3v, Reconstruct, TestWorld},
@@ -282,4 +446,14 @@ mod tests {
             EXPECTED
         );
     }
+
+    #[test]
+    fn credit_cap_bits() {
+        assert_eq!(Some(1), credit_cap_ss_bits(1));
+        assert_eq!(Some(2), credit_cap_ss_bits(2));
+        assert_eq!(Some(2), credit_cap_ss_bits(4));
+        assert_eq!(Some(7), credit_cap_ss_bits(128));
+        assert_eq!(None, credit_cap_ss_bits(3));
+        assert_eq!(None, credit_cap_ss_bits(256));
+    }
 }
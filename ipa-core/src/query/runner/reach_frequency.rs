@@ -0,0 +1,284 @@
+use std::{convert::Infallible, marker::PhantomData, ops::Add, sync::Arc};
+
+use generic_array::ArrayLength;
+
+use super::QueryResult;
+use crate::{
+    error::{Error, LengthError},
+    ff::{
+        boolean::Boolean,
+        boolean_array::{BooleanArray, BA3, BA32, BA8},
+        curve_points::RP25519,
+        ec_prime_field::Fp25519,
+        Serializable, U128Conversions,
+    },
+    helpers::{
+        query::{HybridQueryParams, QueryConfig, QuerySize},
+        BodyStream, Gateway,
+    },
+    hpke::PrivateKeyRegistry,
+    protocol::{
+        basics::{shard_fin::FinalizerContext, BooleanArrayMul, BooleanProtocols, Reveal},
+        context::{
+            DZKPUpgraded, MacUpgraded, ShardedContext, ShardedMaliciousContext, UpgradableContext,
+        },
+        hybrid::{
+            oprf::{CONV_CHUNK, PRF_CHUNK},
+            reach_frequency::reach_frequency_protocol,
+        },
+        ipa_prf::{prf_eval::PrfSharing, shuffle::ShardedShuffle},
+        prss::{Endpoint, FromPrss},
+        step::ProtocolStep::Hybrid,
+        Gate,
+    },
+    query::runner::{
+        hybrid::{decrypt_and_reshard, dp_and_padding_params, HYBRID_BREAKDOWNS},
+        setup_sharding,
+    },
+    secret_sharing::{
+        replicated::semi_honest::AdditiveShare as Replicated, BitDecomposed, TransposeFrom,
+        Vectorizable,
+    },
+};
+
+/// Reach and frequency query. It takes the same encrypted hybrid reports as the hybrid
+/// query, but only impressions should be submitted.
+/// A user contributes to at most 8 breakdown keys of a frequency column, hence `SS_BITS` is 3.
+/// DP noise is calibrated to `2^REACH_FREQUENCY_SS_BITS`.
+pub const REACH_FREQUENCY_SS_BITS: usize = 3;
+
+pub struct Query<C, HV, R: PrivateKeyRegistry> {
+    config: HybridQueryParams,
+    key_registry: Arc<R>,
+    phantom_data: PhantomData<(C, HV)>,
+}
+
+impl<C, HV, R: PrivateKeyRegistry> Query<C, HV, R> {
+    pub fn new(query_params: HybridQueryParams, key_registry: Arc<R>) -> Self {
+        Self {
+            config: query_params,
+            key_registry,
+            phantom_data: PhantomData,
+        }
+    }
+}
+
+impl<C, HV, R> Query<C, HV, R>
+where
+    C: UpgradableContext
+        + ShardedShuffle
+        + ShardedContext
+        + FinalizerContext<FinalizingContext = DZKPUpgraded<C>>,
+    HV: BooleanArray + U128Conversions,
+    <HV as Serializable>::Size: Add<<HV as Serializable>::Size, Output: ArrayLength>,
+    R: PrivateKeyRegistry,
+    Replicated<Boolean, CONV_CHUNK>: BooleanProtocols<DZKPUpgraded<C>, CONV_CHUNK>,
+    Replicated<Fp25519, PRF_CHUNK>:
+        PrfSharing<MacUpgraded<C, Fp25519>, PRF_CHUNK, Field = Fp25519> + FromPrss,
+    Replicated<RP25519, PRF_CHUNK>:
+        Reveal<MacUpgraded<C, Fp25519>, Output = <RP25519 as Vectorizable<PRF_CHUNK>>::Array>,
+    Replicated<Boolean>: BooleanProtocols<DZKPUpgraded<C>>,
+    Replicated<Boolean, 256>: BooleanProtocols<DZKPUpgraded<C>, 256>,
+    Replicated<HV>: Serializable,
+    Replicated<BA8>: BooleanArrayMul<DZKPUpgraded<C>>
+        + Reveal<DZKPUpgraded<C>, Output = <BA8 as Vectorizable<1>>::Array>,
+    BitDecomposed<Replicated<Boolean, 256>>:
+        for<'bt> TransposeFrom<&'bt Vec<Replicated<HV>>, Error = LengthError>,
+    BitDecomposed<Replicated<Boolean, 256>>:
+        for<'bt> TransposeFrom<&'bt [Replicated<HV>; 256], Error = Infallible>,
+    Vec<Replicated<HV>>:
+        for<'bt> TransposeFrom<&'bt BitDecomposed<Replicated<Boolean, 256>>, Error = LengthError>,
+    DZKPUpgraded<C>: ShardedContext,
+{
+    #[tracing::instrument("reach_frequency_query", skip_all, fields(sz=%query_size))]
+    pub async fn execute(
+        self,
+        ctx: C,
+        query_size: QuerySize,
+        input_stream: BodyStream,
+    ) -> Result<Vec<Replicated<HV>>, Error> {
+        let Self {
+            config,
+            key_registry,
+            phantom_data: _,
+        } = &self;
+
+        tracing::info!("New reach and frequency query: {config:?}");
+        let ctx = ctx.narrow(&Hybrid);
+
+        if config.plaintext_match_keys {
+            return Err(Error::Unsupported(
+                "Reach and frequency queries do not support plaintext match keys".to_string(),
+            ));
+        }
+
+        let impressions =
+            decrypt_and_reshard(&ctx, key_registry.as_ref(), query_size, input_stream)
+                .await?
+                .into_iter()
+                .map(Into::into)
+                .collect();
+        let (dp_params, padding_params) = dp_and_padding_params(config);
+
+        // The DP budget is split across the columns.
+        reach_frequency_protocol::<_, BA8, BA3, HV, REACH_FREQUENCY_SS_BITS, HYBRID_BREAKDOWNS>(
+            ctx,
+            impressions,
+            dp_params,
+            padding_params,
+        )
+        .await
+    }
+}
+
+pub async fn execute_reach_frequency_protocol<'a, R: PrivateKeyRegistry>(
+    prss: &'a Endpoint,
+    gateway: &'a Gateway,
+    input: BodyStream,
+    query_params: HybridQueryParams,
+    config: &QueryConfig,
+    key_registry: Arc<R>,
+) -> QueryResult {
+    let gate = Gate::default();
+    let sharded = setup_sharding(prss, gateway, &gate).await?;
+
+    let ctx = ShardedMaliciousContext::new_with_gate(prss, gateway, gate, sharded);
+    Ok(Box::new(
+        Query::<_, BA32, R>::new(query_params, key_registry)
+            .execute(ctx, config.size, input)
+            .await?,
+    ))
+}
+
+#[cfg(all(test, unit_test, feature = "in-memory-infra"))]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        ff::{boolean_array::BA32, U128Conversions},
+        helpers::{query::HybridQueryParams, BodyStream},
+        hpke::{KeyPair, KeyRegistry},
+        protocol::hybrid::reach_frequency::FREQUENCY_BUCKETS,
+        query::runner::{
+            hybrid::tests::{build_buffers_from_records, BufferAndKeyRegistry},
+            reach_frequency::Query as ReachFrequencyQuery,
+        },
+        test_executor::run,
+        test_fixture::{
+            flatten3v, hybrid::TestHybridRecord, Reconstruct, TestWorld, TestWorldConfig,
+            WithShards,
+        },
+    };
+
+    fn impressions(match_key: u64, breakdown_key: u32, count: usize) -> Vec<TestHybridRecord> {
+        vec![
+            TestHybridRecord::TestImpression {
+                match_key,
+                breakdown_key,
+                key_id: 0,
+            };
+            count
+        ]
+    }
+
+    /// Runs the query on two shards and returns the reconstructed result of the leader shard.
+    async fn run_query(records: &[TestHybridRecord]) -> Vec<u32> {
+        const SHARDS: usize = 2;
+        let BufferAndKeyRegistry {
+            buffers,
+            key_registry,
+            query_sizes,
+        } = build_buffers_from_records(records, SHARDS);
+
+        let world = TestWorld::<WithShards<SHARDS>>::with_shards(TestWorldConfig::default());
+        let contexts = world.malicious_contexts();
+
+        #[allow(clippy::large_futures)]
+        let results = flatten3v(buffers.into_iter().zip(contexts).map(
+            |(helper_buffers, helper_ctxs)| {
+                helper_buffers
+                    .into_iter()
+                    .zip(helper_ctxs)
+                    .zip(query_sizes.clone())
+                    .map(|((buffer, ctx), query_size)| {
+                        let query_params = HybridQueryParams {
+                            with_dp: 0,
+                            ..Default::default()
+                        };
+                        let input = BodyStream::from(buffer);
+
+                        ReachFrequencyQuery::<_, BA32, KeyRegistry<KeyPair>>::new(
+                            query_params,
+                            Arc::clone(&key_registry),
+                        )
+                        .execute(ctx, query_size, input)
+                    })
+            },
+        ))
+        .await;
+
+        let follower_results = [
+            results[3].as_ref().unwrap().clone(),
+            results[4].as_ref().unwrap().clone(),
+            results[5].as_ref().unwrap().clone(),
+        ]
+        .reconstruct();
+        assert_eq!(0, follower_results.len());
+
+        [
+            results[0].as_ref().unwrap().clone(),
+            results[1].as_ref().unwrap().clone(),
+            results[2].as_ref().unwrap().clone(),
+        ]
+        .reconstruct()
+        .iter()
+        .map(U128Conversions::as_u128)
+        .map(|x| u32::try_from(x).expect("test values constructed to fit in u32"))
+        .collect::<Vec<u32>>()
+    }
+
+    #[test]
+    fn reach_frequency() {
+        run(|| async {
+            let records = [
+                impressions(12345, 1, 3),
+                impressions(12345, 2, 1),
+                impressions(23456, 1, 1),
+                // only the first 8 reports of a user are counted, so this is in the 8+ bucket
+                impressions(34567, 3, 9),
+                impressions(45678, 0, 2),
+                impressions(56789, 2, 1),
+                impressions(56789, 3, 1),
+            ]
+            .concat();
+
+            let mut expected = vec![0_u32; 256 * FREQUENCY_BUCKETS];
+            for (breakdown_key, frequency) in
+                [(1, 3), (2, 1), (1, 1), (3, 8), (0, 2), (2, 1), (3, 1)]
+            {
+                expected[breakdown_key * FREQUENCY_BUCKETS + frequency - 1] += 1;
+            }
+
+            assert_eq!(expected, run_query(&records).await);
+        });
+    }
+
+    #[test]
+    fn reports_beyond_max_are_dropped() {
+        run(|| async {
+            // A single user saw 10 impressions, each with a different breakdown key.
+            let records = (0..10)
+                .flat_map(|breakdown_key| impressions(12345, breakdown_key, 1))
+                .collect::<Vec<_>>();
+
+            let results = run_query(&records).await;
+
+            // Only 8 of them are counted, each in the first bucket of its breakdown key.
+            assert_eq!(results.iter().sum::<u32>(), 8);
+            for (breakdown_key, buckets) in results.chunks(FREQUENCY_BUCKETS).enumerate() {
+                assert!(buckets[1..].iter().all(|&count| count == 0));
+                assert!(breakdown_key < 10 || buckets[0] == 0);
+            }
+        });
+    }
+}
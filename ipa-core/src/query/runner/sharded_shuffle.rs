@@ -7,7 +7,7 @@ use ipa_step::StepNarrow;
 use crate::{
     error::Error,
     ff::boolean_array::BA64,
-    helpers::{setup_cross_shard_prss, BodyStream, Gateway, SingleRecordStream},
+    helpers::{BodyStream, Gateway, SingleRecordStream},
     protocol::{
         basics::{FinalizerContext, ShardAssembledResult},
         context::{Context, MaliciousProtocolSteps, ShardedContext, ShardedSemiHonestContext},
@@ -16,10 +16,8 @@ use crate::{
         step::{ProtocolStep, TestShardedShuffleStep},
         Gate, RecordId,
     },
-    query::runner::QueryResult,
+    query::runner::{setup_sharding, QueryResult},
     secret_sharing::replicated::semi_honest::AdditiveShare,
-    sharding::{ShardConfiguration, Sharded},
-    sync::Arc,
 };
 
 /// This holds the result of executing the test version of
@@ -69,18 +67,9 @@ pub async fn execute_sharded_shuffle<'a>(
     input: BodyStream,
 ) -> QueryResult {
     let gate = Gate::default().narrow(&ProtocolStep::CrossShardPrss);
-    let cross_shard_prss =
-        setup_cross_shard_prss(gateway, &gate, prss.indexed(&gate), gateway).await?;
-    let ctx = ShardedSemiHonestContext::new_sharded(
-        prss,
-        gateway,
-        Sharded {
-            shard_id: gateway.shard_id(),
-            shard_count: gateway.shard_count(),
-            prss: Arc::new(cross_shard_prss),
-        },
-    )
-    .narrow(&ProtocolStep::ShardedShuffle);
+    let sharded = setup_sharding(prss, gateway, &gate).await?;
+    let ctx = ShardedSemiHonestContext::new_sharded(prss, gateway, sharded)
+        .narrow(&ProtocolStep::ShardedShuffle);
 
     Ok(Box::new(execute(ctx, input).await?))
 }
@@ -3,14 +3,18 @@ use std::{
     fmt::{Debug, Display, Formatter},
     future::Future,
     task::Poll,
+    time::{Duration, Instant},
 };
 
-use ::tokio::sync::oneshot::{error::TryRecvError, Receiver};
+use ::tokio::sync::{
+    oneshot::{self, error::TryRecvError, Receiver},
+    watch,
+};
 use futures::{ready, FutureExt};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    executor::IpaJoinHandle,
+    executor::{IpaJoinHandle, IpaRuntime},
     helpers::{query::QueryConfig, RoleAssignment},
     protocol::QueryId,
     query::runner::QueryResult,
@@ -18,7 +22,7 @@ use crate::{
 };
 
 /// The status of query processing
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub enum QueryStatus {
     /// Only query running on the coordinator helper can be in this state. Means that coordinator
     /// sent out requests to other helpers and asked them to assume a given role for this query.
@@ -33,6 +37,26 @@ pub enum QueryStatus {
     AwaitingCompletion,
     /// Query has finished and results are available.
     Completed,
+    /// Query has finished with an error, for example because a malicious security check
+    /// failed. The error is returned by the complete API.
+    Failed { reason: String },
+    /// Query was terminated by the kill API before it finished.
+    Killed,
+}
+
+impl QueryStatus {
+    /// Returns `true` if the query ended without producing results.
+    #[must_use]
+    pub fn is_aborted(&self) -> bool {
+        matches!(self, QueryStatus::Failed { .. } | QueryStatus::Killed)
+    }
+
+    /// Returns `true` if the query will not make any more progress: it either completed
+    /// or was aborted.
+    #[must_use]
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, QueryStatus::Completed) || self.is_aborted()
+    }
 }
 
 impl Display for QueryStatus {
@@ -49,7 +73,14 @@ impl From<&QueryState> for QueryStatus {
             QueryState::AwaitingInputs(_, _) => QueryStatus::AwaitingInputs,
             QueryState::Running(_) => QueryStatus::Running,
             QueryState::AwaitingCompletion => QueryStatus::AwaitingCompletion,
-            QueryState::Completed(_) => QueryStatus::Completed,
+            QueryState::Completed(Ok(_)) => QueryStatus::Completed,
+            QueryState::Completed(Err(e)) => QueryStatus::Failed {
+                reason: e.to_string(),
+            },
+            QueryState::Failed { reason, .. } => QueryStatus::Failed {
+                reason: reason.clone(),
+            },
+            QueryState::Killed { .. } => QueryStatus::Killed,
         }
     }
 }
@@ -58,10 +89,15 @@ impl From<&QueryState> for QueryStatus {
 /// queried about the state of a sharded helper. In such scenarios, there will be many different
 /// [`QueryStatus`] and the [`Processor`] needs to return a single one that describes the entire
 /// helper. With this function we're saying that the minimum state across all shards is the one
-/// that describes the helper.
+/// that describes the helper. The only exceptions are [`QueryStatus::Failed`] and
+/// [`QueryStatus::Killed`]: if any shard failed or was killed, the whole helper did.
 #[must_use]
 pub fn min_status(a: QueryStatus, b: QueryStatus) -> QueryStatus {
     match (a, b) {
+        (failed @ QueryStatus::Failed { .. }, _) | (_, failed @ QueryStatus::Failed { .. }) => {
+            failed
+        }
+        (QueryStatus::Killed, _) | (_, QueryStatus::Killed) => QueryStatus::Killed,
         (QueryStatus::Preparing, _) | (_, QueryStatus::Preparing) => QueryStatus::Preparing,
         (QueryStatus::AwaitingInputs, _) | (_, QueryStatus::AwaitingInputs) => {
             QueryStatus::AwaitingInputs
@@ -82,16 +118,50 @@ pub enum QueryState {
     Running(RunningQuery),
     AwaitingCompletion,
     Completed(QueryResult),
+    /// The error has already been returned by the complete API. This state is only kept
+    /// to report why the query ended.
+    Failed {
+        reason: String,
+        ended_at: Instant,
+    },
+    Killed {
+        ended_at: Instant,
+    },
 }
 
 impl QueryState {
+    pub fn failed<E: Display>(error: &E) -> Self {
+        Self::Failed {
+            reason: error.to_string(),
+            ended_at: Instant::now(),
+        }
+    }
+
+    pub fn killed() -> Self {
+        Self::Killed {
+            ended_at: Instant::now(),
+        }
+    }
+
+    /// Returns `true` if this query failed or was killed at least `retention` ago and
+    /// does not need to be reported anymore.
+    pub fn is_expired(&self, retention: Duration) -> bool {
+        match self {
+            Self::Failed { ended_at, .. } | Self::Killed { ended_at } => {
+                ended_at.elapsed() >= retention
+            }
+            _ => false,
+        }
+    }
+
     pub fn transition(cur_state: &Self, new_state: Self) -> Result<Self, StateError> {
-        use QueryState::{AwaitingInputs, Empty, Preparing, Running};
+        use QueryState::{AwaitingInputs, Empty, Failed, Killed, Preparing, Running};
 
         match (cur_state, &new_state) {
             // If query is not running, coordinator initial state is preparing
-            // and followers initial state is awaiting inputs
-            (Empty, Preparing(_) | AwaitingInputs(_, _))
+            // and followers initial state is awaiting inputs. Queries that failed
+            // or were killed can be started again.
+            (Empty | Failed { .. } | Killed { .. }, Preparing(_) | AwaitingInputs(_, _))
             | (Preparing(_), AwaitingInputs(_, _))
             | (AwaitingInputs(_, _), Running(_)) => Ok(new_state),
             (_, Preparing(_)) => Err(StateError::AlreadyRunning),
@@ -156,12 +226,16 @@ pub enum StateError {
 /// Keeps track of queries running on this helper.
 pub struct RunningQueries {
     pub inner: Mutex<HashMap<QueryId, QueryState>>,
+    /// Marked as changed every time the state of a query changes. Code that modifies
+    /// `inner` directly must call [`Self::notify`].
+    changes: watch::Sender<()>,
 }
 
 impl Default for RunningQueries {
     fn default() -> Self {
         Self {
             inner: Mutex::new(HashMap::default()),
+            changes: watch::Sender::new(()),
         }
     }
 }
@@ -189,6 +263,8 @@ impl QueryHandle<'_> {
                 entry.insert(QueryState::transition(&QueryState::Empty, new_state)?);
             }
         }
+        drop(inner);
+        self.queries.notify();
 
         Ok(())
     }
@@ -210,6 +286,43 @@ impl RunningQueries {
             queries: self,
         }
     }
+
+    /// Returns a receiver that is notified every time the state of a query changes after
+    /// this call.
+    pub fn subscribe(&self) -> watch::Receiver<()> {
+        self.changes.subscribe()
+    }
+
+    /// Wakes up everyone waiting for the state of a query to change.
+    pub fn notify(&self) {
+        self.changes.send_replace(());
+    }
+
+    /// Makes the given query notify the subscribers once it produces its result. A query
+    /// becomes completed as soon as its result is available, without anyone updating its
+    /// state, so this must be done for every query that starts running.
+    pub fn notify_on_completion(&self, runtime: &IpaRuntime, query: RunningQuery) -> RunningQuery {
+        let RunningQuery {
+            result,
+            join_handle,
+        } = query;
+        let (tx, rx) = oneshot::channel();
+        let changes = self.changes.clone();
+        // The result can't be observed without consuming it, so it is forwarded instead.
+        // If the query is aborted, the result is never sent and nobody is notified.
+        drop(runtime.spawn(async move {
+            if let Ok(result) = result.await {
+                if tx.send(result).is_ok() {
+                    changes.send_replace(());
+                }
+            }
+        }));
+
+        RunningQuery {
+            result: rx,
+            join_handle,
+        }
+    }
 }
 
 /// RAII guard to clean up query state when dropped.
@@ -250,6 +363,7 @@ impl Drop for RemoveQuery<'_> {
                     q = inner.query_id
                 );
             }
+            inner.queries.notify();
         }
     }
 }
@@ -271,11 +385,48 @@ mod tests {
         ];
 
         for i in 0..all.len() {
-            let this = all[i];
-            for other in all.into_iter().skip(i) {
-                assert_eq!(this, min_status(this, other));
-                assert_eq!(this, min_status(other, this));
+            let this = &all[i];
+            for other in all.iter().skip(i) {
+                assert_eq!(*this, min_status(this.clone(), other.clone()));
+                assert_eq!(*this, min_status(other.clone(), this.clone()));
             }
         }
     }
+
+    #[test]
+    fn failed_wins() {
+        let failed = QueryStatus::Failed {
+            reason: "malicious reveal failed".to_string(),
+        };
+        for other in [
+            QueryStatus::Preparing,
+            QueryStatus::AwaitingInputs,
+            QueryStatus::Running,
+            QueryStatus::AwaitingCompletion,
+            QueryStatus::Completed,
+            QueryStatus::Killed,
+            failed.clone(),
+        ] {
+            assert_eq!(failed, min_status(failed.clone(), other.clone()));
+            assert_eq!(failed, min_status(other, failed.clone()));
+        }
+    }
+
+    #[test]
+    fn killed_wins_over_progress() {
+        for other in [
+            QueryStatus::Preparing,
+            QueryStatus::AwaitingInputs,
+            QueryStatus::Running,
+            QueryStatus::AwaitingCompletion,
+            QueryStatus::Completed,
+            QueryStatus::Killed,
+        ] {
+            assert_eq!(
+                QueryStatus::Killed,
+                min_status(QueryStatus::Killed, other.clone())
+            );
+            assert_eq!(QueryStatus::Killed, min_status(other, QueryStatus::Killed));
+        }
+    }
 }
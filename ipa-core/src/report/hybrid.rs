@@ -33,22 +33,27 @@ use bytes::{Buf, BufMut, Bytes};
 use generic_array::{ArrayLength, GenericArray};
 use hpke::Serializable as _;
 use rand_core::{CryptoRng, RngCore};
-use typenum::{Sum, Unsigned, U12, U16};
+use typenum::{Sum, Unsigned, U12, U16, U44};
 
 use crate::{
     const_assert_eq,
     error::{BoxError, Error},
     ff::{
+        boolean::Boolean,
         boolean_array::{
-            BooleanArray, BooleanArrayReader, BooleanArrayWriter, BA112, BA3, BA32, BA64, BA8,
+            BooleanArray, BooleanArrayReader, BooleanArrayWriter, BA112, BA144, BA3, BA32, BA64,
+            BA8,
         },
-        Serializable,
+        Serializable, U128Conversions,
     },
     hpke::{
         open_in_place, seal_in_place, CryptError, EncapsulationSize, PrivateKeyRegistry,
         PublicKeyRegistry, TagSize,
     },
-    protocol::ipa_prf::{boolean_ops::expand_shared_array_in_place, shuffle::Shuffleable},
+    protocol::{
+        group_by::GroupByRecord,
+        ipa_prf::{boolean_ops::expand_shared_array_in_place, shuffle::Shuffleable},
+    },
     report::hybrid_info::{HybridConversionInfo, HybridImpressionInfo},
     secret_sharing::{
         replicated::{semi_honest::AdditiveShare as Replicated, ReplicatedSecretSharing},
@@ -100,6 +105,8 @@ pub enum InvalidHybridReportError {
 pub enum HybridEventType {
     Impression,
     Conversion,
+    /// An impression in a lift experiment, see [`HybridLiftImpressionReport`].
+    LiftImpression,
 }
 
 impl TryFrom<u8> for HybridEventType {
@@ -108,6 +115,7 @@ impl TryFrom<u8> for HybridEventType {
         match value {
             0 => Ok(Self::Impression),
             1 => Ok(Self::Conversion),
+            2 => Ok(Self::LiftImpression),
             _ => Err(InvalidHybridReportError::UnknownEventType(value)),
         }
     }
@@ -262,6 +270,87 @@ where
     }
 }
 
+/// Reports for impression events of a lift experiment are represented here. These carry the
+/// group of the impression in addition to what [`HybridImpressionReport`] has, and are only
+/// meant for conversion lift queries. Other queries treat them as regular impressions.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HybridLiftImpressionReport<BK>
+where
+    BK: SharedValue,
+{
+    pub match_key: Replicated<BA64>,
+    pub breakdown_key: Replicated<BK>,
+    /// Set for impressions in the test group, unset for impressions in the control group.
+    pub test_group: Replicated<Boolean>,
+    pub info: HybridImpressionInfo,
+}
+
+impl<BK> HybridLiftImpressionReport<BK>
+where
+    BK: SharedValue,
+    Replicated<BK>: Serializable,
+{
+    const BK_END: usize = <Replicated<BK> as Serializable>::Size::USIZE;
+    const BTT_END: usize = Self::BK_END + <Replicated<Boolean> as Serializable>::Size::USIZE;
+
+    /// # Panics
+    /// If report length does not fit in `u16`.
+    pub fn ciphertext_len(&self) -> u16 {
+        let len = EncryptedHybridLiftImpressionReport::<BK>::INFO_OFFSET;
+        len.try_into().unwrap()
+    }
+
+    /// # Panics
+    /// If report length does not fit in `u16`.
+    pub fn encrypted_len(&self) -> u16 {
+        self.ciphertext_len() + u16::try_from(self.info.byte_len()).unwrap()
+    }
+
+    /// # Errors
+    /// If there is a problem encrypting the report.
+    pub fn encrypt_to<R: CryptoRng + RngCore, B: BufMut>(
+        &self,
+        key_id: KeyIdentifier,
+        key_registry: &impl PublicKeyRegistry,
+        rng: &mut R,
+        out: &mut B,
+    ) -> Result<(), InvalidHybridReportError> {
+        let mut plaintext_mk = GenericArray::default();
+        self.match_key.serialize(&mut plaintext_mk);
+
+        let mut plaintext_btt = vec![0u8; Self::BTT_END];
+        self.breakdown_key.serialize(GenericArray::from_mut_slice(
+            &mut plaintext_btt[..Self::BK_END],
+        ));
+        self.test_group.serialize(GenericArray::from_mut_slice(
+            &mut plaintext_btt[Self::BK_END..],
+        ));
+
+        let pk = key_registry
+            .public_key(key_id)
+            .ok_or(CryptError::NoSuchKey(key_id))?;
+        let info_enc_bytes = self.info.to_enc_bytes();
+        let info_bytes = self.info.to_bytes();
+
+        let (encap_key_mk, ciphertext_mk, tag_mk) =
+            seal_in_place(pk, plaintext_mk.as_mut(), &info_enc_bytes, rng)?;
+
+        let (encap_key_btt, ciphertext_btt, tag_btt) =
+            seal_in_place(pk, plaintext_btt.as_mut(), &info_enc_bytes, rng)?;
+
+        out.put_slice(&encap_key_mk.to_bytes());
+        out.put_slice(ciphertext_mk);
+        out.put_slice(&tag_mk.to_bytes());
+        out.put_slice(&encap_key_btt.to_bytes());
+        out.put_slice(ciphertext_btt);
+        out.put_slice(&tag_btt.to_bytes());
+        out.put_slice(&[key_id]);
+        out.put_slice(&info_bytes);
+
+        Ok(())
+    }
+}
+
 /// Reports for conversion events are represented here.
 #[derive(Clone, Debug, PartialEq)]
 pub struct HybridConversionReport<V>
@@ -411,7 +500,7 @@ where
     }
 }
 
-/// This enum contains both report types, impression and conversion.
+/// This enum contains all the report types: impression, conversion and lift impression.
 #[derive(Clone, Debug, PartialEq)]
 pub enum HybridReport<BK, V>
 where
@@ -420,6 +509,7 @@ where
 {
     Impression(HybridImpressionReport<BK>),
     Conversion(HybridConversionReport<V>),
+    LiftImpression(HybridLiftImpressionReport<BK>),
 }
 
 impl<BK, V> HybridReport<BK, V>
@@ -443,6 +533,9 @@ where
             HybridReport::Conversion(conversion_report) => {
                 conversion_report.encrypted_len() +1
             }
+            HybridReport::LiftImpression(lift_impression_report) => {
+                lift_impression_report.encrypted_len() +1
+            }
         }
     }
 
@@ -466,6 +559,11 @@ where
                 out.put_u8(HybridEventType::Conversion as u8);
                 conversion_report.encrypt_to(key_id, key_registry, rng, out)
             },
+            HybridReport::LiftImpression(lift_impression_report) => {
+                out.put_u16_le(self.encrypted_len());
+                out.put_u8(HybridEventType::LiftImpression as u8);
+                lift_impression_report.encrypt_to(key_id, key_registry, rng, out)
+            },
         }
     }
 
@@ -500,6 +598,10 @@ where
                     out.put_u8(HybridEventType::Conversion as u8);
                     conversion_report.encrypt_to(key_id, key_registry, rng, out)
             },
+            HybridReport::LiftImpression(lift_impression_report) => {
+                    out.put_u8(HybridEventType::LiftImpression as u8);
+                    lift_impression_report.encrypt_to(key_id, key_registry, rng, out)
+            },
         }
     }
 }
@@ -553,6 +655,16 @@ where
         self.data[Self::KEY_IDENTIFIER_OFFSET]
     }
 
+    /// Parses the unencrypted info that is appended to the report ciphertext.
+    ///
+    /// ## Errors
+    /// If the info bytes are malformed.
+    pub fn info(&self) -> Result<HybridImpressionInfo, InvalidHybridReportError> {
+        HybridImpressionInfo::from_bytes(&self.data[Self::INFO_OFFSET..]).map_err(|e| {
+            InvalidHybridReportError::DeserializationError("HybridImpressionInfo", e.into())
+        })
+    }
+
     /// ## Errors
     /// If the report contents are invalid.
     pub fn from_bytes(bytes: Bytes) -> Result<Self, InvalidHybridReportError> {
@@ -586,10 +698,7 @@ where
         let sk = key_registry
             .private_key(self.key_id())
             .ok_or(CryptError::NoSuchKey(self.key_id()))?;
-        let info =
-            HybridImpressionInfo::from_bytes(&self.data[Self::INFO_OFFSET..]).map_err(|e| {
-                InvalidHybridReportError::DeserializationError("HybridImpressionInfo", e.into())
-            })?;
+        let info = self.info()?;
         let info_enc_bytes = info.to_enc_bytes();
 
         let plaintext_mk = open_in_place(sk, self.encap_key_mk(), &mut ct_mk, &info_enc_bytes)?;
@@ -611,6 +720,123 @@ where
     }
 }
 
+/// `HybridLiftImpressionReport`s are encrypted when they arrive to the helpers,
+/// which is represented here. A `EncryptedHybridLiftImpressionReport` decrypts
+/// into a `HybridLiftImpressionReport`.
+#[derive(Clone, Eq, PartialEq)]
+pub struct EncryptedHybridLiftImpressionReport<BK>
+where
+    BK: SharedValue,
+{
+    data: Bytes,
+    phantom_data: PhantomData<BK>,
+}
+
+impl<BK> EncryptedHybridLiftImpressionReport<BK>
+where
+    BK: SharedValue,
+    Replicated<BK>: Serializable,
+{
+    const ENCAP_KEY_MK_OFFSET: usize = 0;
+    const CIPHERTEXT_MK_OFFSET: usize = Self::ENCAP_KEY_MK_OFFSET + EncapsulationSize::USIZE;
+    const ENCAP_KEY_BTT_OFFSET: usize =
+        (Self::CIPHERTEXT_MK_OFFSET + TagSize::USIZE + Replicated::<BA64>::size());
+    const CIPHERTEXT_BTT_OFFSET: usize = Self::ENCAP_KEY_BTT_OFFSET + EncapsulationSize::USIZE;
+
+    const KEY_IDENTIFIER_OFFSET: usize = (Self::CIPHERTEXT_BTT_OFFSET
+        + TagSize::USIZE
+        + Replicated::<BK>::size()
+        + Replicated::<Boolean>::size());
+    const INFO_OFFSET: usize = Self::KEY_IDENTIFIER_OFFSET + 1;
+
+    pub fn encap_key_mk(&self) -> &[u8] {
+        &self.data[Self::ENCAP_KEY_MK_OFFSET..Self::CIPHERTEXT_MK_OFFSET]
+    }
+
+    pub fn mk_ciphertext(&self) -> &[u8] {
+        &self.data[Self::CIPHERTEXT_MK_OFFSET..Self::ENCAP_KEY_BTT_OFFSET]
+    }
+
+    pub fn encap_key_btt(&self) -> &[u8] {
+        &self.data[Self::ENCAP_KEY_BTT_OFFSET..Self::CIPHERTEXT_BTT_OFFSET]
+    }
+
+    pub fn btt_ciphertext(&self) -> &[u8] {
+        &self.data[Self::CIPHERTEXT_BTT_OFFSET..Self::KEY_IDENTIFIER_OFFSET]
+    }
+
+    pub fn key_id(&self) -> KeyIdentifier {
+        self.data[Self::KEY_IDENTIFIER_OFFSET]
+    }
+
+    /// Parses the unencrypted info that is appended to the report ciphertext.
+    ///
+    /// ## Errors
+    /// If the info bytes are malformed.
+    pub fn info(&self) -> Result<HybridImpressionInfo, InvalidHybridReportError> {
+        HybridImpressionInfo::from_bytes(&self.data[Self::INFO_OFFSET..]).map_err(|e| {
+            InvalidHybridReportError::DeserializationError("HybridImpressionInfo", e.into())
+        })
+    }
+
+    /// ## Errors
+    /// If the report contents are invalid.
+    pub fn from_bytes(bytes: Bytes) -> Result<Self, InvalidHybridReportError> {
+        if bytes.len() < Self::INFO_OFFSET {
+            return Err(InvalidHybridReportError::Length(
+                bytes.len(),
+                Self::INFO_OFFSET,
+            ));
+        }
+        Ok(Self {
+            data: bytes,
+            phantom_data: PhantomData,
+        })
+    }
+
+    /// ## Errors
+    /// If the match key shares in the report cannot be decrypted (e.g. due to a
+    /// failure of the authenticated encryption).
+    /// ## Panics
+    /// Should not panic. Only panics if a `Report` constructor failed to validate the
+    /// contents properly, which would be a bug.
+    pub fn decrypt<P: PrivateKeyRegistry>(
+        &self,
+        key_registry: &P,
+    ) -> Result<HybridLiftImpressionReport<BK>, InvalidHybridReportError> {
+        type CTMKLength = Sum<<Replicated<BA64> as Serializable>::Size, TagSize>;
+
+        let mut ct_mk: GenericArray<u8, CTMKLength> =
+            *GenericArray::from_slice(self.mk_ciphertext());
+        let sk = key_registry
+            .private_key(self.key_id())
+            .ok_or(CryptError::NoSuchKey(self.key_id()))?;
+        let info = self.info()?;
+        let info_enc_bytes = info.to_enc_bytes();
+
+        let plaintext_mk = open_in_place(sk, self.encap_key_mk(), &mut ct_mk, &info_enc_bytes)?;
+        let mut ct_btt = self.btt_ciphertext().to_vec();
+
+        let plaintext_btt = open_in_place(sk, self.encap_key_btt(), &mut ct_btt, &info_enc_bytes)?;
+        let (plaintext_bk, plaintext_tg) = plaintext_btt.split_at(Replicated::<BK>::size());
+
+        Ok(HybridLiftImpressionReport::<BK> {
+            match_key: Replicated::<BA64>::deserialize_infallible(GenericArray::from_slice(
+                plaintext_mk,
+            )),
+            breakdown_key: Replicated::<BK>::deserialize(GenericArray::from_slice(plaintext_bk))
+                .map_err(|e| {
+                    InvalidHybridReportError::DeserializationError("breakdown_key", e.into())
+                })?,
+            test_group: Replicated::<Boolean>::deserialize(GenericArray::from_slice(plaintext_tg))
+                .map_err(|e| {
+                    InvalidHybridReportError::DeserializationError("test_group", e.into())
+                })?,
+            info,
+        })
+    }
+}
+
 #[derive(Clone, Eq, PartialEq)]
 pub struct EncryptedHybridConversionReport<V>
 where
@@ -658,6 +884,16 @@ where
         self.data[Self::KEY_IDENTIFIER_OFFSET]
     }
 
+    /// Parses the unencrypted info that is appended to the report ciphertext.
+    ///
+    /// ## Errors
+    /// If the info bytes are malformed.
+    pub fn info(&self) -> Result<HybridConversionInfo, InvalidHybridReportError> {
+        HybridConversionInfo::from_bytes(&self.data[Self::INFO_OFFSET..]).map_err(|e| {
+            InvalidHybridReportError::DeserializationError("HybridConversionInfo", e.into())
+        })
+    }
+
     /// ## Errors
     /// If the report contents are invalid.
     pub fn from_bytes(bytes: Bytes) -> Result<Self, InvalidHybridReportError> {
@@ -691,10 +927,7 @@ where
         let sk = key_registry
             .private_key(self.key_id())
             .ok_or(CryptError::NoSuchKey(self.key_id()))?;
-        let info =
-            HybridConversionInfo::from_bytes(&self.data[Self::INFO_OFFSET..]).map_err(|e| {
-                InvalidHybridReportError::DeserializationError("HybridConversionInfo", e.into())
-            })?;
+        let info = self.info()?;
         let info_enc_bytes = info.to_enc_bytes();
 
         let plaintext_mk = open_in_place(sk, self.encap_key_mk(), &mut ct_mk, &info_enc_bytes)?;
@@ -721,6 +954,18 @@ pub type PrfHybridReport<BK, V> = IndistinguishableHybridReport<BK, V, u64>;
 /// that OPRF value is no longer required.
 pub type AggregateableHybridReport<BK, V> = IndistinguishableHybridReport<BK, V, ()>;
 
+/// Report carrying up to `N` match key candidates (e.g. hashed email and hashed phone number)
+/// for cross-device attribution. Reports are joined if any of their match keys are equal.
+/// A match key that is a sharing of zero is absent and is never used for joining.
+pub type MultiKeyHybridReport<BK, V, const N: usize> =
+    IndistinguishableHybridReport<BK, V, [Replicated<BA64>; N]>;
+
+/// Multi key report where the first match key candidate is replaced with its OPRF value. The
+/// match key candidates are kept, so that reports that are not joined on the OPRF value can
+/// be joined on one of their other candidates later.
+pub type PrfMultiKeyHybridReport<BK, V, const N: usize> =
+    IndistinguishableHybridReport<BK, V, (u64, [Replicated<BA64>; N])>;
+
 impl<BK, V> IndistinguishableHybridReport<BK, V, ()>
 where
     BK: BooleanArray,
@@ -837,6 +1082,7 @@ where
         match report {
             HybridReport::Impression(r) => r.into(),
             HybridReport::Conversion(r) => r.into(),
+            HybridReport::LiftImpression(r) => r.into(),
         }
     }
 }
@@ -855,6 +1101,21 @@ where
     }
 }
 
+/// Outside of conversion lift queries, the group of a lift impression is ignored.
+impl<BK, V> From<HybridLiftImpressionReport<BK>> for IndistinguishableHybridReport<BK, V>
+where
+    BK: BooleanArray,
+    V: BooleanArray,
+{
+    fn from(lift_impression_report: HybridLiftImpressionReport<BK>) -> Self {
+        Self {
+            match_key: lift_impression_report.match_key,
+            value: Replicated::ZERO,
+            breakdown_key: lift_impression_report.breakdown_key,
+        }
+    }
+}
+
 impl<BK, V> From<HybridConversionReport<V>> for IndistinguishableHybridReport<BK, V>
 where
     BK: BooleanArray,
@@ -869,6 +1130,43 @@ where
     }
 }
 
+impl<BK, V> IndistinguishableHybridReport<BK, V>
+where
+    BK: BooleanArray + U128Conversions,
+    V: BooleanArray,
+{
+    /// Converts a report for a conversion lift query. The test group of a lift impression is
+    /// moved into the most significant bit of its breakdown key, so that aggregating by
+    /// breakdown key yields separate totals for the control and the test groups. Regular
+    /// impressions are in the control group.
+    ///
+    /// This is a local operation on the shares. The most significant bit of every impression
+    /// breakdown key is cleared before the group is set, so a breakdown key that uses it is
+    /// counted in the group it belongs to, under the breakdown key without that bit.
+    #[must_use]
+    pub fn from_lift_report(report: HybridReport<BK, V>) -> Self {
+        let group_bit = BK::BITS - 1;
+        let with_group = |breakdown_key: BK, test_group: Boolean| {
+            BK::truncate_from(
+                breakdown_key.as_u128() & !(1 << group_bit) | test_group.as_u128() << group_bit,
+            )
+        };
+        let (match_key, breakdown_key, test_group) = match report {
+            HybridReport::LiftImpression(r) => (r.match_key, r.breakdown_key, r.test_group),
+            HybridReport::Impression(r) => (r.match_key, r.breakdown_key, Replicated::ZERO),
+            r @ HybridReport::Conversion(_) => return r.into(),
+        };
+        Self {
+            match_key,
+            value: Replicated::ZERO,
+            breakdown_key: ReplicatedSecretSharing::new(
+                with_group(breakdown_key.left(), test_group.left()),
+                with_group(breakdown_key.right(), test_group.right()),
+            ),
+        }
+    }
+}
+
 impl<BK, V> Shuffleable for IndistinguishableHybridReport<BK, V>
 where
     BK: BooleanArray,
@@ -952,6 +1250,194 @@ where
     }
 }
 
+impl<BK, V, const N: usize> MultiKeyHybridReport<BK, V, N>
+where
+    BK: BooleanArray,
+    V: BooleanArray,
+{
+    pub const ZERO: Self = Self {
+        match_key: [Replicated::<BA64>::ZERO; N],
+        value: Replicated::<V>::ZERO,
+        breakdown_key: Replicated::<BK>::ZERO,
+    };
+
+    fn join_fields(match_keys: &[BA64; N], value: V, breakdown_key: BK) -> BA144 {
+        let mut share = BA144::ZERO;
+
+        let writer = match_keys.iter().fold(
+            BooleanArrayWriter::new(&mut share),
+            BooleanArrayWriter::write,
+        );
+        writer.write(&value).write(&breakdown_key);
+
+        share
+    }
+
+    fn split_fields(share: &BA144) -> ([BA64; N], V, BK) {
+        let mut bits = Some(BooleanArrayReader::new(share));
+        let match_keys = std::array::from_fn(|_| {
+            let (match_key, rest) = bits.take().unwrap().read();
+            bits = Some(rest);
+            match_key
+        });
+        let (value, bits) = bits.unwrap().read();
+        let (breakdown_key, _) = bits.read();
+        (match_keys, value, breakdown_key)
+    }
+}
+
+impl<BK, V, const N: usize> From<HybridReport<BK, V>> for MultiKeyHybridReport<BK, V, N>
+where
+    BK: BooleanArray,
+    V: BooleanArray,
+{
+    /// Uses the match key of a single key report as the first candidate, all other
+    /// candidates are absent.
+    fn from(report: HybridReport<BK, V>) -> Self {
+        let IndistinguishableHybridReport {
+            match_key,
+            value,
+            breakdown_key,
+        } = IndistinguishableHybridReport::from(report);
+        let mut match_keys = [Replicated::<BA64>::ZERO; N];
+        match_keys[0] = match_key;
+        Self {
+            match_key: match_keys,
+            value,
+            breakdown_key,
+        }
+    }
+}
+
+impl<BK, V, const N: usize> Shuffleable for MultiKeyHybridReport<BK, V, N>
+where
+    BK: BooleanArray,
+    V: BooleanArray,
+{
+    // this requires N * 64 + BK:BAXX + V:BAYY <= 144, so at most two match keys
+    // can be used with the breakdown key and value types of the hybrid query.
+    type Share = BA144;
+
+    fn left(&self) -> Self::Share {
+        Self::join_fields(
+            &self.match_key.each_ref().map(ReplicatedSecretSharing::left),
+            self.value.left(),
+            self.breakdown_key.left(),
+        )
+    }
+
+    fn right(&self) -> Self::Share {
+        Self::join_fields(
+            &self
+                .match_key
+                .each_ref()
+                .map(ReplicatedSecretSharing::right),
+            self.value.right(),
+            self.breakdown_key.right(),
+        )
+    }
+
+    fn new(l: Self::Share, r: Self::Share) -> Self {
+        debug_assert!(
+            u32::try_from(N).unwrap() * BA64::BITS + BK::BITS + V::BITS <= Self::Share::BITS,
+            "share type {} is too small",
+            std::any::type_name::<Self::Share>(),
+        );
+
+        let (match_keys_l, value_l, breakdown_key_l) = Self::split_fields(&l);
+        let (match_keys_r, value_r, breakdown_key_r) = Self::split_fields(&r);
+        let mut match_keys_r = match_keys_r.into_iter();
+
+        Self {
+            match_key: match_keys_l.map(|match_key_l| {
+                ReplicatedSecretSharing::new(match_key_l, match_keys_r.next().unwrap())
+            }),
+            value: ReplicatedSecretSharing::new(value_l, value_r),
+            breakdown_key: ReplicatedSecretSharing::new(breakdown_key_l, breakdown_key_r),
+        }
+    }
+}
+
+impl<BK, V> GroupByRecord for AggregateableHybridReport<BK, V>
+where
+    BK: BooleanArray + U128Conversions,
+    V: BooleanArray + U128Conversions,
+{
+    type Key = BK;
+    type Value = V;
+    const VALUE_COUNT: usize = 1;
+
+    fn group_key(&self) -> &Replicated<BK> {
+        &self.breakdown_key
+    }
+
+    fn into_values(self) -> impl Iterator<Item = Replicated<V>> {
+        std::iter::once(self.value)
+    }
+}
+
+impl PrfMultiKeyHybridReport<BA8, BA3, 2> {
+    const PRF_MK_SZ: usize = 8;
+    const MK_SZ: usize = <Replicated<BA64> as Serializable>::Size::USIZE;
+    const V_SZ: usize = <Replicated<BA3> as Serializable>::Size::USIZE;
+    const BK_SZ: usize = <Replicated<BA8> as Serializable>::Size::USIZE;
+}
+
+impl Serializable for PrfMultiKeyHybridReport<BA8, BA3, 2> {
+    type Size = U44;
+    type DeserializationError = InvalidHybridReportError;
+
+    fn serialize(&self, buf: &mut GenericArray<u8, Self::Size>) {
+        let (prf_of_match_key, match_keys) = &self.match_key;
+        buf[..Self::PRF_MK_SZ].copy_from_slice(&prf_of_match_key.to_le_bytes());
+
+        let mut offset = Self::PRF_MK_SZ;
+        for match_key in match_keys {
+            match_key.serialize(GenericArray::from_mut_slice(
+                &mut buf[offset..offset + Self::MK_SZ],
+            ));
+            offset += Self::MK_SZ;
+        }
+
+        self.value.serialize(GenericArray::from_mut_slice(
+            &mut buf[offset..offset + Self::V_SZ],
+        ));
+        offset += Self::V_SZ;
+
+        self.breakdown_key.serialize(GenericArray::from_mut_slice(
+            &mut buf[offset..offset + Self::BK_SZ],
+        ));
+    }
+
+    fn deserialize(buf: &GenericArray<u8, Self::Size>) -> Result<Self, Self::DeserializationError> {
+        let prf_of_match_key = u64::from_le_bytes(buf[..Self::PRF_MK_SZ].try_into().unwrap());
+
+        let match_keys = std::array::from_fn(|i| {
+            let offset = Self::PRF_MK_SZ + i * Self::MK_SZ;
+            Replicated::<BA64>::deserialize_infallible(GenericArray::from_slice(
+                &buf[offset..offset + Self::MK_SZ],
+            ))
+        });
+
+        let offset = Self::PRF_MK_SZ + 2 * Self::MK_SZ;
+        let value = Replicated::<BA3>::deserialize(GenericArray::from_slice(
+            &buf[offset..offset + Self::V_SZ],
+        ))
+        .map_err(|e| InvalidHybridReportError::DeserializationError("value", e.into()))?;
+
+        let offset = offset + Self::V_SZ;
+        let breakdown_key = Replicated::<BA8>::deserialize_infallible(GenericArray::from_slice(
+            &buf[offset..offset + Self::BK_SZ],
+        ));
+
+        Ok(Self {
+            match_key: (prf_of_match_key, match_keys),
+            value,
+            breakdown_key,
+        })
+    }
+}
+
 impl PrfHybridReport<BA8, BA3> {
     const PRF_MK_SZ: usize = 8;
     const V_SZ: usize = <Replicated<BA3> as Serializable>::Size::USIZE;
@@ -1002,6 +1488,7 @@ where
 {
     Impression(EncryptedHybridImpressionReport<BK>),
     Conversion(EncryptedHybridConversionReport<V>),
+    LiftImpression(EncryptedHybridLiftImpressionReport<BK>),
 }
 impl<BK, V> EncryptedHybridReport<BK, V>
 where
@@ -1022,6 +1509,9 @@ where
             EncryptedHybridReport::Conversion(conversion_report) => {
                 conversion_report.encap_key_mk()
             }
+            EncryptedHybridReport::LiftImpression(lift_impression_report) => {
+                lift_impression_report.encap_key_mk()
+            }
         }
     }
     pub fn mk_ciphertext(&self) -> &[u8] {
@@ -1032,6 +1522,9 @@ where
             EncryptedHybridReport::Conversion(conversion_report) => {
                 conversion_report.mk_ciphertext()
             }
+            EncryptedHybridReport::LiftImpression(lift_impression_report) => {
+                lift_impression_report.mk_ciphertext()
+            }
         }
     }
     pub fn encap_key_btt(&self) -> &[u8] {
@@ -1042,6 +1535,9 @@ where
             EncryptedHybridReport::Conversion(conversion_report) => {
                 conversion_report.encap_key_btt()
             }
+            EncryptedHybridReport::LiftImpression(lift_impression_report) => {
+                lift_impression_report.encap_key_btt()
+            }
         }
     }
     pub fn btt_ciphertext(&self) -> &[u8] {
@@ -1052,18 +1548,45 @@ where
             EncryptedHybridReport::Conversion(conversion_report) => {
                 conversion_report.btt_ciphertext()
             }
+            EncryptedHybridReport::LiftImpression(lift_impression_report) => {
+                lift_impression_report.btt_ciphertext()
+            }
         }
     }
     pub fn key_id(&self) -> u8 {
         match self {
             EncryptedHybridReport::Impression(impression_report) => impression_report.key_id(),
             EncryptedHybridReport::Conversion(conversion_report) => conversion_report.key_id(),
+            EncryptedHybridReport::LiftImpression(lift_impression_report) => {
+                lift_impression_report.key_id()
+            }
+        }
+    }
+    /// Checks that the unencrypted info carried by this report can be parsed, without
+    /// decrypting the report.
+    ///
+    /// ## Errors
+    /// If the info bytes are malformed, e.g. the conversion site domain is not ASCII.
+    pub fn validate_info(&self) -> Result<(), InvalidHybridReportError> {
+        match self {
+            EncryptedHybridReport::Impression(impression_report) => {
+                impression_report.info().map(|_| ())
+            }
+            EncryptedHybridReport::Conversion(conversion_report) => {
+                conversion_report.info().map(|_| ())
+            }
+            EncryptedHybridReport::LiftImpression(lift_impression_report) => {
+                lift_impression_report.info().map(|_| ())
+            }
         }
     }
     /// ## Errors
     /// If the report contents are invalid.
     pub fn from_bytes(mut bytes: Bytes) -> Result<Self, InvalidHybridReportError> {
-        match HybridEventType::try_from(bytes[0])? {
+        let event_type = *bytes
+            .first()
+            .ok_or(InvalidHybridReportError::Length(0, 1))?;
+        match HybridEventType::try_from(event_type)? {
             HybridEventType::Impression => {
                 bytes.advance(1);
                 let impression_report = EncryptedHybridImpressionReport::<BK>::from_bytes(bytes)?;
@@ -1074,6 +1597,14 @@ where
                 let conversion_report = EncryptedHybridConversionReport::<V>::from_bytes(bytes)?;
                 Ok(EncryptedHybridReport::Conversion(conversion_report))
             }
+            HybridEventType::LiftImpression => {
+                bytes.advance(1);
+                let lift_impression_report =
+                    EncryptedHybridLiftImpressionReport::<BK>::from_bytes(bytes)?;
+                Ok(EncryptedHybridReport::LiftImpression(
+                    lift_impression_report,
+                ))
+            }
         }
     }
     /// ## Errors
@@ -1093,6 +1624,9 @@ where
             EncryptedHybridReport::Conversion(conversion_report) => Ok(HybridReport::Conversion(
                 conversion_report.decrypt(key_registry)?,
             )),
+            EncryptedHybridReport::LiftImpression(lift_impression_report) => Ok(
+                HybridReport::LiftImpression(lift_impression_report.decrypt(key_registry)?),
+            ),
         }
     }
 }
@@ -1232,30 +1766,38 @@ impl UniqueTagValidator {
 
 #[cfg(all(test, unit_test))]
 mod test {
+    use std::array;
+
     use bytes::Bytes;
     use rand::Rng;
 
     use super::{
         EncryptedHybridImpressionReport, EncryptedHybridReport, GenericArray,
-        HybridConversionReport, HybridImpressionReport, HybridReport,
-        IndistinguishableHybridReport, PrfHybridReport, UniqueTag, UniqueTagValidator,
+        HybridConversionReport, HybridImpressionReport, HybridLiftImpressionReport, HybridReport,
+        IndistinguishableHybridReport, PrfHybridReport, PrfMultiKeyHybridReport, UniqueTag,
+        UniqueTagValidator,
     };
     use crate::{
         error::Error,
         ff::{
-            boolean_array::{BA3, BA8},
-            Serializable,
+            boolean::Boolean,
+            boolean_array::{BA3, BA64, BA8},
+            Serializable, U128Conversions,
         },
         hpke::{KeyPair, KeyRegistry},
         report::{
             hybrid::{EncryptedHybridConversionReport, HybridEventType},
             hybrid_info::{HybridConversionInfo, HybridImpressionInfo},
         },
-        secret_sharing::replicated::{
-            semi_honest::{AdditiveShare as Replicated, AdditiveShare},
-            ReplicatedSecretSharing,
+        secret_sharing::{
+            replicated::{
+                semi_honest::{AdditiveShare as Replicated, AdditiveShare},
+                ReplicatedSecretSharing,
+            },
+            IntoShares, SharedValue,
         },
         test_executor::run_random,
+        test_fixture::Reconstruct,
     };
 
     fn build_hybrid_report<R>(event_type: HybridEventType, rng: &mut R) -> HybridReport<BA8, BA3>
@@ -1284,6 +1826,14 @@ mod test {
                     .unwrap(),
                 })
             }
+            HybridEventType::LiftImpression => {
+                HybridReport::LiftImpression(HybridLiftImpressionReport::<BA8> {
+                    match_key: AdditiveShare::new(rng.gen(), rng.gen()),
+                    breakdown_key: AdditiveShare::new(rng.gen(), rng.gen()),
+                    test_group: AdditiveShare::new(rng.gen(), rng.gen()),
+                    info: HybridImpressionInfo::new(0),
+                })
+            }
         }
     }
 
@@ -1360,6 +1910,82 @@ mod test {
         });
     }
 
+    /// The test group of a lift impression ends up in the most significant bit of the
+    /// breakdown key. Other reports are converted as usual, so regular impressions are in
+    /// the control group.
+    #[test]
+    fn convert_lift_report_to_indistinguishable_report() {
+        run_random(|mut rng| async move {
+            let HybridReport::LiftImpression(lift_impression_report) =
+                build_hybrid_report(HybridEventType::LiftImpression, &mut rng)
+            else {
+                unreachable!()
+            };
+            let lift_report = IndistinguishableHybridReport::<BA8, BA3>::from_lift_report(
+                HybridReport::LiftImpression(lift_impression_report.clone()),
+            );
+            assert_eq!(lift_impression_report.match_key, lift_report.match_key);
+            assert_eq!(AdditiveShare::ZERO, lift_report.value);
+            for (breakdown_key, test_group, lift_breakdown_key) in [
+                (
+                    lift_impression_report.breakdown_key.left(),
+                    lift_impression_report.test_group.left(),
+                    lift_report.breakdown_key.left(),
+                ),
+                (
+                    lift_impression_report.breakdown_key.right(),
+                    lift_impression_report.test_group.right(),
+                    lift_report.breakdown_key.right(),
+                ),
+            ] {
+                assert_eq!(
+                    breakdown_key.as_u128() & 0x7f | test_group.as_u128() << 7,
+                    lift_breakdown_key.as_u128()
+                );
+            }
+
+            let report = build_hybrid_report(HybridEventType::Conversion, &mut rng);
+            assert_eq!(
+                IndistinguishableHybridReport::<BA8, BA3>::from(report.clone()),
+                IndistinguishableHybridReport::from_lift_report(report),
+            );
+        });
+    }
+
+    /// Breakdown keys that already use the most significant bit must not move impressions
+    /// into the test group.
+    #[test]
+    fn lift_report_clears_breakdown_key_msb() {
+        run_random(|mut rng| async move {
+            let breakdown_key = BA8::truncate_from(200_u128).share_with(&mut rng);
+            let match_key = BA64::ZERO.share_with(&mut rng);
+            for test_group in [None, Some(false), Some(true)] {
+                let test_group_shares = test_group.map(|g| Boolean::from(g).share_with(&mut rng));
+                let converted = array::from_fn::<_, 3, _>(|i| {
+                    let report = match &test_group_shares {
+                        None => HybridReport::Impression(HybridImpressionReport::<BA8> {
+                            match_key: match_key[i].clone(),
+                            breakdown_key: breakdown_key[i].clone(),
+                            info: HybridImpressionInfo::new(0),
+                        }),
+                        Some(shares) => {
+                            HybridReport::LiftImpression(HybridLiftImpressionReport::<BA8> {
+                                match_key: match_key[i].clone(),
+                                breakdown_key: breakdown_key[i].clone(),
+                                test_group: shares[i].clone(),
+                                info: HybridImpressionInfo::new(0),
+                            })
+                        }
+                    };
+                    IndistinguishableHybridReport::<BA8, BA3>::from_lift_report(report)
+                        .breakdown_key
+                });
+                let expected = if test_group == Some(true) { 200 } else { 72 };
+                assert_eq!(expected, converted.reconstruct().as_u128());
+            }
+        });
+    }
+
     #[test]
     fn unique_encrypted_hybrid_reports() {
         run_random(|mut rng| async move {
@@ -1495,6 +2121,34 @@ mod test {
         });
     }
 
+    #[test]
+    fn enc_dec_roundtrip_hybrid_lift_impression() {
+        run_random(|mut rng| async move {
+            let hybrid_report = build_hybrid_report(HybridEventType::LiftImpression, &mut rng);
+
+            let key_registry = KeyRegistry::<KeyPair>::random(1, &mut rng);
+            let key_id = 0;
+
+            let enc_report_bytes = hybrid_report
+                .encrypt(key_id, &key_registry, &mut rng)
+                .unwrap();
+            assert_eq!(
+                enc_report_bytes.len(),
+                usize::from(hybrid_report.encrypted_len())
+            );
+
+            let enc_report =
+                EncryptedHybridReport::<BA8, BA3>::from_bytes(enc_report_bytes.into()).unwrap();
+            assert!(matches!(
+                enc_report,
+                EncryptedHybridReport::LiftImpression(_)
+            ));
+            let dec_report: HybridReport<BA8, BA3> = enc_report.decrypt(&key_registry).unwrap();
+
+            assert_eq!(dec_report, hybrid_report);
+        });
+    }
+
     #[test]
     fn enc_report_serialization() {
         run_random(|mut rng| async move {
@@ -1529,7 +2183,9 @@ mod test {
 
             // Case 1: Match first, then decrypt
             match enc_report2 {
-                EncryptedHybridReport::Impression(_) => panic!("Expected conversion report"),
+                EncryptedHybridReport::Impression(_) | EncryptedHybridReport::LiftImpression(_) => {
+                    panic!("Expected conversion report")
+                }
                 EncryptedHybridReport::Conversion(enc_report_conv) => {
                     let dec_report2: HybridConversionReport<BA3> =
                         enc_report_conv.decrypt(&key_registry).unwrap();
@@ -1559,4 +2215,25 @@ mod test {
             assert_eq!(report, deserialized_report.unwrap());
         });
     }
+
+    #[test]
+    fn serde_multi_key() {
+        run_random(|mut rng| async move {
+            let report = PrfMultiKeyHybridReport::<BA8, BA3, 2> {
+                match_key: (
+                    rng.gen(),
+                    [
+                        Replicated::new(rng.gen(), rng.gen()),
+                        Replicated::new(rng.gen(), rng.gen()),
+                    ],
+                ),
+                breakdown_key: Replicated::new(rng.gen(), rng.gen()),
+                value: Replicated::new(rng.gen(), rng.gen()),
+            };
+            let mut buf = GenericArray::default();
+            report.serialize(&mut buf);
+            let deserialized_report = PrfMultiKeyHybridReport::<BA8, BA3, 2>::deserialize(&buf);
+            assert_eq!(report, deserialized_report.unwrap());
+        });
+    }
 }
@@ -55,11 +55,11 @@ impl HybridImpressionInfo {
     }
 
     /// ## Errors
-    /// If deserialization fails.
-    /// ## Panics
-    /// If not enough delimiters are found in the input bytes.
+    /// If the input bytes are empty.
     pub fn from_bytes(bytes: &[u8]) -> Result<Self, InvalidHybridReportError> {
-        let key_id = bytes[0];
+        let key_id = *bytes
+            .first()
+            .ok_or(InvalidHybridReportError::Length(bytes.len(), 1))?;
         Ok(Self { key_id })
     }
 }
@@ -163,15 +163,18 @@ impl HybridConversionInfo {
     }
 
     /// ## Errors
-    /// If deserialization fails.
+    /// If deserialization fails, the input is truncated or `conversion_site_domain`
+    /// is not a valid ASCII string.
     /// ## Panics
-    /// If not enough delimiters are found in the input bytes.
+    /// Should not panic. The input length is checked before fixed-size fields are read.
     pub fn from_bytes(bytes: &[u8]) -> Result<Self, InvalidHybridReportError> {
         let mut pos = 0;
-        let delimiter_pos = bytes[pos..]
-            .iter()
-            .position(|&b| b == 0)
-            .unwrap_or_else(|| panic!("not enough delimiters for HybridConversionInfo"));
+        let delimiter_pos = bytes[pos..].iter().position(|&b| b == 0).ok_or_else(|| {
+            InvalidHybridReportError::DeserializationError(
+                "HybridConversionInfo: conversion_site_domain",
+                "not enough delimiters for HybridConversionInfo".into(),
+            )
+        })?;
         let conversion_site_domain = String::from_utf8(bytes[pos..pos + delimiter_pos].to_vec())
             .map_err(|e| {
                 InvalidHybridReportError::DeserializationError(
@@ -179,7 +182,16 @@ impl HybridConversionInfo {
                     e.into(),
                 )
             })?;
+        if !conversion_site_domain.is_ascii() {
+            return Err(NonAsciiStringError::from(conversion_site_domain.as_str()).into());
+        }
         pos += delimiter_pos + 1;
+        if bytes.len() < pos + 3 * 8 + 1 {
+            return Err(InvalidHybridReportError::Length(
+                bytes.len(),
+                pos + 3 * 8 + 1,
+            ));
+        }
         debug_assert!(pos + 3*8 + 1 == bytes.len(), "{}", format!("bytes for HybridConversionInfo::from_bytes has incorrect length. Expected: {}, Actual: {}", pos + 3*8 + 1, bytes.len()).to_string());
 
         let key_id = bytes[pos];
@@ -277,6 +289,33 @@ mod test {
         assert_eq!(info.to_bytes(), info2.to_bytes());
     }
 
+    #[test]
+    fn test_hybrid_conversion_deserialization_errors() {
+        let info = HybridConversionInfo::new(0, "https://www.example2.com", 1_234_567, 1.151, 0.95)
+            .unwrap();
+        let bytes = info.to_bytes();
+        assert!(matches!(
+            HybridConversionInfo::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(InvalidHybridReportError::Length(_, _))
+        ));
+        assert!(matches!(
+            HybridConversionInfo::from_bytes(b"https://www.example2.com"),
+            Err(InvalidHybridReportError::DeserializationError(_, _))
+        ));
+
+        let mut non_ascii = info.clone();
+        non_ascii.conversion_site_domain = "https://www.exämple2.com".to_string();
+        assert!(matches!(
+            HybridConversionInfo::from_bytes(&non_ascii.to_bytes()),
+            Err(InvalidHybridReportError::NonAsciiString(_))
+        ));
+
+        assert!(matches!(
+            HybridImpressionInfo::from_bytes(&[]),
+            Err(InvalidHybridReportError::Length(0, 1))
+        ));
+    }
+
     #[test]
     fn test_hybrid_info_serialization() {
         let info = HybridInfo::new(0, "https://www.example2.com", 1_234_567, 1.151, 0.95).unwrap();
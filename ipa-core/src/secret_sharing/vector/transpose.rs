@@ -243,6 +243,22 @@ macro_rules! read_bool_right_8 {
     };
 }
 
+macro_rules! read_bool_left_8_pad {
+    ($m:ident, $src:ident, $i:ident, $j:ident, $k:ident, $pad_value:expr) => {
+        $m[$k] = $src
+            .get(8 * $i + $k)
+            .map_or($pad_value, |row| row.left_arr().as_raw_slice()[$j])
+    };
+}
+
+macro_rules! read_bool_right_8_pad {
+    ($m:ident, $src:ident, $i:ident, $j:ident, $k:ident, $pad_value:expr) => {
+        $m[$k] = $src
+            .get(8 * $i + $k)
+            .map_or($pad_value, |row| row.right_arr().as_raw_slice()[$j])
+    };
+}
+
 macro_rules! write_bool_left_8 {
     ($dst:ident, $m:ident, $i:ident, $j:ident, $k:ident) => {
         $dst[8 * $i + $k].left_arr_mut().as_raw_mut_slice()[$j] = $m[$k]
@@ -602,6 +618,40 @@ macro_rules! impl_transpose_shares_bool_to_ba_small {
     };
 }
 
+/// Variant of `impl_transpose_shares_bool_to_ba_small` supporting a number of source rows that
+/// is not a multiple of 8. The number of source columns must still be a multiple of 8.
+macro_rules! impl_transpose_shares_bool_to_ba_small_pad {
+    ($dst_row:ty, $src_rows:expr, $src_cols:expr, $test_fn:ident) => {
+        impl TransposeFrom<&[AdditiveShare<Boolean, $src_cols>; $src_rows]>
+            for [AdditiveShare<$dst_row>; $src_cols]
+        {
+            type Error = Infallible;
+
+            fn transpose_from(
+                &mut self,
+                src: &[AdditiveShare<Boolean, $src_cols>; $src_rows],
+            ) -> Result<(), Infallible> {
+                impl_transpose_8_pad!(self, src, $src_rows, $src_cols, read_bool_left_8_pad, 0, write_ba_left_8);
+                impl_transpose_8_pad!(self, src, $src_rows, $src_cols, read_bool_right_8_pad, 0, write_ba_right_8);
+                Ok(())
+            }
+        }
+
+        #[cfg(all(test, unit_test))]
+        #[test]
+        fn $test_fn() {
+            tests::test_transpose_shares_bool_to_ba::<$dst_row, $src_rows, $src_cols>();
+        }
+
+        impl_transpose_shim!(
+            &BitDecomposed<AdditiveShare<Boolean, $src_cols>>, AdditiveShare<Boolean, $src_cols>,
+            Vec<AdditiveShare<$dst_row>>, AdditiveShare<$dst_row>,
+            $src_rows, $src_cols,
+            LengthError,
+        );
+    };
+}
+
 // Input: MxN as `[AdditiveShare<Boolean, N>; {M}]` or similar
 // Output: NxM as `[AdditiveShare<BA{M}>; N]` or similar
 // Arguments: BA{M}, M, N
@@ -624,6 +674,9 @@ impl_transpose_shares_bool_to_ba_small!(BA32, 32, 32, test_transpose_shares_bool
 // Usage: Aggregation output tests
 impl_transpose_shares_bool_to_ba_small!(BA8, 8, 8, test_transpose_shares_bool_to_ba_8x8);
 
+// Usage: Hybrid aggregation of report pairs. M = BK or V bits, N = AGG_CHUNK.
+impl_transpose_shares_bool_to_ba_small_pad!(BA3, 3, 256, test_transpose_shares_bool_to_ba_3x256);
+
 // Usage: Binomial Noise Gen
 impl_transpose_shares_bool_to_ba!(BA16, 16, 16, test_transpose_shares_bool_to_ba_16x16);
 
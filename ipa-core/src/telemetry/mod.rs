@@ -1,6 +1,7 @@
 pub mod memory;
 pub mod stats;
 mod step_stats;
+pub mod trace;
 
 pub use step_stats::CsvExporter as StepStatsCsvExporter;
 
@@ -14,10 +15,14 @@ pub mod metrics {
     pub const REQUESTS_RECEIVED: &str = "requests.received";
     pub const RECORDS_SENT: &str = "records.sent";
     pub const BYTES_SENT: &str = "bytes.sent";
+    pub const RECORDS_RECEIVED: &str = "records.received";
+    pub const BYTES_RECEIVED: &str = "bytes.received";
     pub const INDEXED_PRSS_GENERATED: &str = "i.prss.gen";
     pub const SEQUENTIAL_PRSS_GENERATED: &str = "s.prss.gen";
     pub use ::ipa_step::descriptive::labels::STEP_NARROWED;
     pub const DZKP_BATCH_INCREMENTS: &str = "batch.realloc.front";
+    pub const DZKP_PROOFS_VERIFIED: &str = "dzkp.proofs.verified";
+    pub const QUERY_ELAPSED_MS: &str = "query.elapsed.ms";
 
     #[cfg(feature = "web-app")]
     pub mod web {
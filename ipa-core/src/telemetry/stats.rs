@@ -1,12 +1,13 @@
 use std::{
     collections::{
         hash_map::{Entry, Iter},
-        HashMap,
+        BTreeMap, HashMap,
     },
     fmt::Display,
 };
 
 use ipa_metrics::{MetricPartition, MetricsStore};
+use serde::{Deserialize, Serialize};
 
 use crate::{helpers::Role, protocol::Gate, telemetry::labels};
 
@@ -36,6 +37,83 @@ pub struct Metrics {
     pub counters: HashMap<&'static str, CounterDetails>,
 }
 
+/// Serializable version of [`Metrics`] that can be sent over the wire. It is used to report
+/// metrics collected while running a single query.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueryMetrics {
+    pub counters: BTreeMap<String, CounterSnapshot>,
+}
+
+/// Serializable version of [`CounterDetails`].
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CounterSnapshot {
+    pub total: u64,
+    pub dimensions: BTreeMap<String, BTreeMap<String, u64>>,
+}
+
+impl From<&Metrics> for QueryMetrics {
+    fn from(value: &Metrics) -> Self {
+        let counters = value
+            .counters
+            .iter()
+            .map(|(&name, details)| {
+                let dimensions = details
+                    .iter()
+                    .map(|(&dim, values)| {
+                        (
+                            dim.to_string(),
+                            values.iter().map(|(k, &v)| (k.clone(), v)).collect(),
+                        )
+                    })
+                    .collect();
+                (
+                    name.to_string(),
+                    CounterSnapshot {
+                        total: details.total_value,
+                        dimensions,
+                    },
+                )
+            })
+            .collect();
+
+        Self { counters }
+    }
+}
+
+impl QueryMetrics {
+    /// Returns the total value of the given counter or 0 if it wasn't recorded.
+    #[must_use]
+    pub fn get_counter(&self, name: &str) -> u64 {
+        self.counters.get(name).map_or(0, |c| c.total)
+    }
+
+    /// Returns the value of the given counter recorded for each value of the dimension.
+    /// The returned collection is empty if either the counter or the dimension wasn't recorded.
+    #[must_use]
+    pub fn get_dimension(&self, name: &str, dimension: &str) -> BTreeMap<String, u64> {
+        self.counters
+            .get(name)
+            .and_then(|c| c.dimensions.get(dimension))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Adds all counter values from `other` to this snapshot. This is used to combine the
+    /// metrics reported by several shards of the same helper.
+    pub fn merge(&mut self, other: &Self) {
+        for (name, counter) in &other.counters {
+            let this = self.counters.entry(name.clone()).or_default();
+            this.total += counter.total;
+            for (dimension, values) in &counter.dimensions {
+                let this_values = this.dimensions.entry(dimension.clone()).or_default();
+                for (value, &count) in values {
+                    *this_values.entry(value.clone()).or_default() += count;
+                }
+            }
+        }
+    }
+}
+
 pub struct CompositeKey {
     pub key: &'static str,
     pub labels: Vec<Label>,
@@ -110,7 +188,18 @@ impl Metrics {
     /// If partition does not exist in the metrics store.
     #[must_use]
     pub fn from_partition(metrics_store: &MetricsStore, partition: MetricPartition) -> Self {
-        let v = metrics_store.with_partition(partition, |store| {
+        Self::try_from_partition(metrics_store, partition)
+            .unwrap_or_else(|| panic!("Partition {partition} does not exist"))
+    }
+
+    /// Builds a new metric snapshot for the specified partition or returns `None` if
+    /// no metrics were recorded inside it.
+    #[must_use]
+    pub fn try_from_partition(
+        metrics_store: &MetricsStore,
+        partition: MetricPartition,
+    ) -> Option<Self> {
+        metrics_store.with_partition(partition, |store| {
             let mut this = Self::default();
             for (counter, value) in store.counters() {
                 let composite_key = CompositeKey {
@@ -134,9 +223,7 @@ impl Metrics {
             }
 
             this
-        });
-
-        v.unwrap_or_else(|| panic!("Partition {partition} does not exist"))
+        })
     }
 
     #[must_use]
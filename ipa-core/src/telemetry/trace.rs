@@ -0,0 +1,506 @@
+//! Distributed tracing support.
+//!
+//! Every helper emits tracing spans independently. To make it possible to follow a single query
+//! across the helper network, a [`TraceContext`] is propagated on every helper-to-helper and
+//! shard-to-shard request using the W3C [`traceparent`] header. Spans created on the receiving
+//! side become children of the sender span, so the whole query forms a single trace.
+//!
+//! Spans are exported by [`OtlpFileExporter`], which writes them in the OTLP JSON format, one
+//! `ExportTraceServiceRequest` per line. This is the format consumed by the `otlpjsonfile`
+//! receiver of the OpenTelemetry collector, so the files produced by all helpers can be shipped
+//! to any OTLP-compatible backend.
+//!
+//! [`traceparent`]: https://www.w3.org/TR/trace-context/#traceparent-header
+use std::{
+    fmt::{Debug, Display, Formatter},
+    io::Write,
+    str::FromStr,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use rand::{thread_rng, Rng};
+use serde_json::{json, Value};
+use tracing::{
+    field::{Field, Visit},
+    span::{Attributes, Id, Record},
+    Subscriber,
+};
+use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer, Registry};
+
+/// Name of the HTTP header used to propagate trace context between helpers.
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+
+/// Target for spans that only make sense as part of a distributed trace. These are too noisy
+/// to be written to the log, so log writers are expected to filter this target out.
+pub const TRACE_TARGET: &str = "ipa_trace";
+
+/// Span field that carries the remote parent in the [`traceparent`] format. If a span has this
+/// field set, it becomes a child of the remote span rather than the local one.
+///
+/// [`traceparent`]: https://www.w3.org/TR/trace-context/#traceparent-header
+pub const TRACEPARENT_FIELD: &str = "traceparent";
+
+/// Creates a span for one top-level phase of a protocol. Phases are recorded under
+/// [`TRACE_TARGET`], so every helper contributes them to the distributed trace of the query.
+#[must_use]
+pub fn phase_span(phase: &'static str) -> tracing::Span {
+    tracing::info_span!(target: TRACE_TARGET, "phase", phase)
+}
+
+const VERSION: u8 = 0;
+const SAMPLED_FLAG: u8 = 1;
+
+#[derive(Debug, thiserror::Error)]
+#[error("malformed traceparent value: {0}")]
+pub struct ParseError(String);
+
+/// Identifies a span inside a distributed trace.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct TraceContext {
+    trace_id: u128,
+    span_id: u64,
+    sampled: bool,
+}
+
+impl TraceContext {
+    /// Starts a new trace.
+    #[must_use]
+    pub fn new_root() -> Self {
+        Self {
+            trace_id: non_zero(|| thread_rng().gen()),
+            span_id: new_span_id(),
+            sampled: true,
+        }
+    }
+
+    /// Creates a context for a new span inside the same trace.
+    #[must_use]
+    pub fn child(&self) -> Self {
+        Self {
+            trace_id: self.trace_id,
+            span_id: new_span_id(),
+            sampled: self.sampled,
+        }
+    }
+
+    #[must_use]
+    pub fn trace_id(&self) -> u128 {
+        self.trace_id
+    }
+
+    #[must_use]
+    pub fn span_id(&self) -> u64 {
+        self.span_id
+    }
+
+    /// Returns the trace context of the current span, if [`OtlpFileExporter`] is installed
+    /// and the current span is recorded by it.
+    #[must_use]
+    pub fn current() -> Option<Self> {
+        tracing::Span::current()
+            .with_subscriber(|(id, dispatch)| {
+                dispatch
+                    .downcast_ref::<Registry>()?
+                    .span(id)?
+                    .extensions()
+                    .get::<SpanData>()
+                    .map(|data| data.context)
+            })
+            .flatten()
+    }
+}
+
+impl Debug for TraceContext {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "TraceContext[{self}]")
+    }
+}
+
+impl Display for TraceContext {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{VERSION:02x}-{:032x}-{:016x}-{:02x}",
+            self.trace_id,
+            self.span_id,
+            if self.sampled { SAMPLED_FLAG } else { 0 }
+        )
+    }
+}
+
+impl FromStr for TraceContext {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseError(s.to_string());
+        let parts = s.trim().split('-').collect::<Vec<_>>();
+        let [version, trace_id, span_id, flags] = parts.as_slice() else {
+            return Err(err());
+        };
+        if version.len() != 2 || trace_id.len() != 32 || span_id.len() != 16 || flags.len() != 2 {
+            return Err(err());
+        }
+        // Version `ff` is forbidden by the spec, future versions are parsed as version 0.
+        let version = u8::from_str_radix(version, 16).map_err(|_| err())?;
+        let trace_id = u128::from_str_radix(trace_id, 16).map_err(|_| err())?;
+        let span_id = u64::from_str_radix(span_id, 16).map_err(|_| err())?;
+        let flags = u8::from_str_radix(flags, 16).map_err(|_| err())?;
+        if version == u8::MAX || trace_id == 0 || span_id == 0 {
+            return Err(err());
+        }
+
+        Ok(Self {
+            trace_id,
+            span_id,
+            sampled: flags & SAMPLED_FLAG != 0,
+        })
+    }
+}
+
+fn non_zero<T: Default + PartialEq, F: Fn() -> T>(f: F) -> T {
+    loop {
+        let v = f();
+        if v != T::default() {
+            return v;
+        }
+    }
+}
+
+fn new_span_id() -> u64 {
+    non_zero(|| thread_rng().gen())
+}
+
+/// Per-span state kept by [`OtlpFileExporter`] inside span extensions.
+struct SpanData {
+    context: TraceContext,
+    parent_span_id: Option<u64>,
+    start: SystemTime,
+    attributes: Vec<(&'static str, String)>,
+}
+
+#[derive(Default)]
+struct FieldVisitor {
+    traceparent: Option<TraceContext>,
+    attributes: Vec<(&'static str, String)>,
+}
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == TRACEPARENT_FIELD {
+            match value.parse() {
+                Ok(ctx) => self.traceparent = Some(ctx),
+                Err(e) => tracing::debug!("ignoring remote parent: {e}"),
+            }
+        } else {
+            self.attributes.push((field.name(), value.to_string()));
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.record_str(field, &format!("{value:?}"));
+    }
+}
+
+/// [`Layer`] that exports spans to a file in the OTLP JSON format.
+///
+/// Spans are written when they are closed. Span context is inherited from the parent span,
+/// unless [`TRACEPARENT_FIELD`] is set, in which case the span is attached to the remote parent.
+/// Spans without a parent start a new trace.
+pub struct OtlpFileExporter<W> {
+    writer: Mutex<W>,
+    service_name: String,
+}
+
+impl<W: Write> OtlpFileExporter<W> {
+    pub fn new<S: Into<String>>(writer: W, service_name: S) -> Self {
+        Self {
+            writer: Mutex::new(writer),
+            service_name: service_name.into(),
+        }
+    }
+
+    fn to_otlp_json(&self, name: &str, data: &SpanData, end: SystemTime) -> Value {
+        let attributes = data
+            .attributes
+            .iter()
+            .map(|(k, v)| json!({ "key": k, "value": { "stringValue": v } }))
+            .collect::<Vec<_>>();
+        let mut span = json!({
+            "traceId": format!("{:032x}", data.context.trace_id),
+            "spanId": format!("{:016x}", data.context.span_id),
+            "name": name,
+            // SPAN_KIND_INTERNAL
+            "kind": 1,
+            "startTimeUnixNano": unix_nanos(data.start).to_string(),
+            "endTimeUnixNano": unix_nanos(end).to_string(),
+            "attributes": attributes,
+        });
+        if let Some(parent) = data.parent_span_id {
+            span["parentSpanId"] = Value::String(format!("{parent:016x}"));
+        }
+
+        json!({
+            "resourceSpans": [{
+                "resource": {
+                    "attributes": [{
+                        "key": "service.name",
+                        "value": { "stringValue": self.service_name },
+                    }],
+                },
+                "scopeSpans": [{
+                    "scope": { "name": env!("CARGO_PKG_NAME") },
+                    "spans": [span],
+                }],
+            }],
+        })
+    }
+}
+
+fn unix_nanos(t: SystemTime) -> u128 {
+    t.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos()
+}
+
+impl<S, W> Layer<S> for OtlpFileExporter<W>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: Write + Send + 'static,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut visitor = FieldVisitor::default();
+        attrs.record(&mut visitor);
+
+        let parent = visitor.traceparent.or_else(|| {
+            span.parent()
+                .and_then(|p| p.extensions().get::<SpanData>().map(|d| d.context))
+        });
+        let context = parent.map_or_else(TraceContext::new_root, |p| p.child());
+
+        span.extensions_mut().insert(SpanData {
+            context,
+            parent_span_id: parent.map(|p| p.span_id),
+            start: SystemTime::now(),
+            attributes: visitor.attributes,
+        });
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut visitor = FieldVisitor::default();
+        values.record(&mut visitor);
+        let mut extensions = span.extensions_mut();
+        if let Some(data) = extensions.get_mut::<SpanData>() {
+            data.attributes.extend(visitor.attributes);
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let extensions = span.extensions();
+        let Some(data) = extensions.get::<SpanData>() else {
+            return;
+        };
+        let mut line = serde_json::to_vec(&self.to_otlp_json(span.name(), data, SystemTime::now()))
+            .expect("span must be serializable to JSON");
+        line.push(b'\n');
+
+        // Spans are written one by one, so they are not lost if helper terminates abruptly.
+        if let Err(e) = self.writer.lock().unwrap().write_all(&line) {
+            // Not using tracing here to avoid re-entering this layer.
+            eprintln!("failed to export span {}: {e}", span.name());
+        }
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use std::{
+        collections::HashSet,
+        io::{self, Write},
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use serde_json::Value;
+    use tracing::Instrument;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::{OtlpFileExporter, TraceContext, TRACEPARENT_FIELD};
+    use crate::{
+        ff::{FieldType, Fp31, U128Conversions},
+        helpers::{
+            query::{QueryConfig, QueryType},
+            HelperIdentity,
+        },
+        test_fixture::TestApp,
+    };
+
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuf {
+        fn spans(&self) -> Vec<Value> {
+            let buf = self.0.lock().unwrap();
+            std::str::from_utf8(&buf)
+                .unwrap()
+                .lines()
+                .map(|line| {
+                    let v = serde_json::from_str::<Value>(line).unwrap();
+                    v["resourceSpans"][0]["scopeSpans"][0]["spans"][0].clone()
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn traceparent_round_trip() {
+        let ctx = TraceContext::new_root();
+        let child = ctx.child();
+        assert_eq!(ctx.trace_id(), child.trace_id());
+        assert_ne!(ctx.span_id(), child.span_id());
+        assert_eq!(ctx, ctx.to_string().parse().unwrap());
+    }
+
+    #[test]
+    fn traceparent_parse() {
+        let ctx = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"
+            .parse::<TraceContext>()
+            .unwrap();
+        assert_eq!(0x4bf9_2f35_77b3_4da6_a3ce_929d_0e0e_4736, ctx.trace_id());
+        assert_eq!(0x00f0_67aa_0ba9_02b7, ctx.span_id());
+        assert!(ctx.sampled);
+    }
+
+    #[test]
+    fn traceparent_rejects_invalid() {
+        for s in [
+            "",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e473-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e473z-00f067aa0ba902b7-01",
+        ] {
+            assert!(s.parse::<TraceContext>().is_err(), "{s} must be rejected");
+        }
+    }
+
+    #[test]
+    fn exports_spans() {
+        let buf = SharedBuf::default();
+        let subscriber =
+            tracing_subscriber::registry().with(OtlpFileExporter::new(buf.clone(), "test"));
+        let remote = TraceContext::new_root();
+
+        tracing::subscriber::with_default(subscriber, || {
+            assert_eq!(None, TraceContext::current());
+            let outer = tracing::info_span!("outer", { TRACEPARENT_FIELD } = %remote, foo = 1);
+            let _guard = outer.enter();
+            let current = TraceContext::current().unwrap();
+            assert_eq!(remote.trace_id(), current.trace_id());
+            tracing::info_span!("inner").in_scope(|| {
+                assert_eq!(
+                    remote.trace_id(),
+                    TraceContext::current().unwrap().trace_id()
+                );
+            });
+        });
+
+        let spans = buf.spans();
+        assert_eq!(2, spans.len());
+        let (inner, outer) = (&spans[0], &spans[1]);
+        assert_eq!("inner", inner["name"]);
+        assert_eq!("outer", outer["name"]);
+        let trace_id = format!("{:032x}", remote.trace_id());
+        assert_eq!(trace_id, inner["traceId"]);
+        assert_eq!(trace_id, outer["traceId"]);
+        assert_eq!(format!("{:016x}", remote.span_id()), outer["parentSpanId"]);
+        assert_eq!(outer["spanId"], inner["parentSpanId"]);
+        assert_eq!("foo", outer["attributes"][0]["key"]);
+        assert_eq!("1", outer["attributes"][0]["value"]["stringValue"]);
+    }
+
+    #[test]
+    fn root_spans_start_new_trace() {
+        let buf = SharedBuf::default();
+        let subscriber =
+            tracing_subscriber::registry().with(OtlpFileExporter::new(buf.clone(), "test"));
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("a").in_scope(|| {});
+            tracing::info_span!("b").in_scope(|| {});
+        });
+
+        let spans = buf.spans();
+        assert_eq!(2, spans.len());
+        assert_ne!(spans[0]["traceId"], spans[1]["traceId"]);
+        assert!(spans.iter().all(|s| s.get("parentSpanId").is_none()));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn links_spans_of_all_helpers() {
+        let buf = SharedBuf::default();
+        let subscriber =
+            tracing_subscriber::registry().with(OtlpFileExporter::new(buf.clone(), "test"));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let app = TestApp::default();
+        let input = [4_u128, 5].map(Fp31::truncate_from);
+        let config = QueryConfig::new(QueryType::TestMultiply, FieldType::Fp31, 2).unwrap();
+        app.execute_query(input.into_iter(), config)
+            .instrument(tracing::info_span!("client"))
+            .await
+            .unwrap();
+        // Spans are exported when closed. Stall detectors hold on to query spans until their next
+        // check, which does not take long with the clock paused.
+        drop(app);
+        tokio::time::sleep(Duration::from_secs(60)).await;
+
+        let spans = buf.spans();
+        let client = spans.iter().find(|s| s["name"] == "client").unwrap();
+        let span_ids = spans.iter().map(|s| &s["spanId"]).collect::<Vec<_>>();
+        let linked = |span: &&Value| {
+            span["traceId"] == client["traceId"] && span_ids.contains(&&span["parentSpanId"])
+        };
+
+        // Every helper runs its part of the query in the trace started by the client.
+        let queries = spans.iter().filter(|s| s["name"] == "query");
+        assert_eq!(3, queries.clone().filter(linked).count());
+        assert_eq!(3, queries.count());
+
+        // Requests sent by the leader join the same trace on the receiving helpers.
+        let receivers = spans
+            .iter()
+            .filter(|s| s["name"] == "request")
+            .inspect(|s| assert!(linked(s), "{s} is not linked to the client trace"))
+            .flat_map(|s| s["attributes"].as_array().unwrap())
+            .filter(|a| a["key"] == "id")
+            .map(|a| a["value"]["stringValue"].clone())
+            .collect::<HashSet<_>>();
+        assert_eq!(
+            [HelperIdentity::TWO, HelperIdentity::THREE]
+                .map(|h| Value::String(format!("{h:?}")))
+                .into_iter()
+                .collect::<HashSet<_>>(),
+            receivers
+        );
+    }
+}
@@ -2,20 +2,22 @@ use std::{array, iter::zip};
 
 use futures::future::join_all;
 use generic_array::GenericArray;
+use ipa_metrics::MetricsCurrentThreadContext;
 use typenum::Unsigned;
 
 use crate::{
     app::AppConfig,
-    cli::{install_collector, LoggingHandle},
+    cli::{install_test_collector, LoggingHandle},
     ff::Serializable,
     helpers::{
         query::{QueryConfig, QueryInput},
-        ApiError, InMemoryMpcNetwork, InMemoryShardNetwork, Transport,
+        ApiError, InMemoryMpcNetwork, InMemoryShardNetwork, QueryProgress, Transport,
     },
     protocol::QueryId,
     query::{min_status, QueryStatus},
     secret_sharing::IntoShares,
-    test_fixture::try_join3_array,
+    telemetry::stats::QueryMetrics,
+    test_fixture::{logging, try_join3_array},
     utils::array::zip3,
     AppSetup, HelperApp,
 };
@@ -67,10 +69,12 @@ impl Default for TestApp {
         let (setup, handlers, _shard_handlers) =
             unzip_tuple_array(array::from_fn(|_| AppSetup::new(AppConfig::default())));
 
+        // metric values are partitioned by query using tracing spans
+        logging::setup();
         let mpc_network = InMemoryMpcNetwork::new(handlers.map(Some));
         let shard_network = InMemoryShardNetwork::with_shards(1);
         let drivers = zip3(mpc_network.transports().each_ref(), setup).map(|(t, s)| {
-            let metrics_handle = install_collector().unwrap();
+            let metrics_handle = install_test_collector().unwrap();
             let logging_handle = LoggingHandle { metrics_handle };
             s.connect(
                 Clone::clone(t),
@@ -135,6 +139,34 @@ impl TestApp {
             .unwrap()
     }
 
+    /// Returns the progress of the given query on each helper.
+    ///
+    /// ## Errors
+    /// Propagates errors retrieving the query status.
+    #[allow(clippy::disallowed_methods)]
+    pub async fn query_progress(
+        &self,
+        query_id: QueryId,
+    ) -> Result<Vec<Option<QueryProgress>>, ApiError> {
+        join_all((0..3).map(|i| self.drivers[i].query_status_with_progress(query_id)))
+            .await
+            .into_iter()
+            .map(|r| r.map(|(_, progress)| progress))
+            .collect()
+    }
+
+    /// Returns metrics collected by each helper while running the given query. All helpers
+    /// run on the current thread and it reports to the collector of the first helper that was
+    /// created on it, so only that helper sees the values.
+    ///
+    /// ## Errors
+    /// If query does not exist or has not started running on one or more helpers.
+    pub fn query_metrics(&self, query_id: QueryId) -> Result<[QueryMetrics; 3], ApiError> {
+        MetricsCurrentThreadContext::flush();
+        let [m0, m1, m2] = self.drivers.each_ref().map(|d| d.query_metrics(query_id));
+        Ok([m0?, m1?, m2?])
+    }
+
     /// ## Errors
     /// Returns an error if one or more helpers can't finish the processing.
     /// ## Panics
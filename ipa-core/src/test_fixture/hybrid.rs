@@ -2,6 +2,7 @@ use std::{borrow::Borrow, collections::HashMap, iter::zip};
 
 use crate::{
     ff::{
+        boolean::Boolean,
         boolean_array::{BooleanArray, BA64},
         U128Conversions,
     },
@@ -9,7 +10,8 @@ use crate::{
     report::{
         hybrid::{
             AggregateableHybridReport, HybridConversionReport, HybridImpressionReport,
-            HybridReport, IndistinguishableHybridReport, KeyIdentifier,
+            HybridLiftImpressionReport, HybridReport, IndistinguishableHybridReport, KeyIdentifier,
+            MultiKeyHybridReport,
         },
         hybrid_info::{HybridConversionInfo, HybridImpressionInfo},
     },
@@ -33,6 +35,13 @@ pub enum TestHybridRecord {
         epsilon: f64,
         sensitivity: f64,
     },
+    /// An impression in a lift experiment, only used by conversion lift queries.
+    TestLiftImpression {
+        match_key: u64,
+        breakdown_key: u32,
+        key_id: KeyIdentifier,
+        test_group: bool,
+    },
 }
 
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -44,6 +53,9 @@ pub struct TestIndistinguishableHybridReport<MK = u64> {
 
 pub type TestAggregateableHybridReport = TestIndistinguishableHybridReport<()>;
 
+/// Report with up to `N` match keys, a match key of zero is absent.
+pub type TestMultiKeyHybridReport<const N: usize> = TestIndistinguishableHybridReport<[u64; N]>;
+
 impl<BK, V> Reconstruct<TestIndistinguishableHybridReport>
     for [&IndistinguishableHybridReport<BK, V>; 3]
 where
@@ -123,6 +135,30 @@ where
     }
 }
 
+impl<BK, V, const N: usize> IntoShares<MultiKeyHybridReport<BK, V, N>>
+    for TestMultiKeyHybridReport<N>
+where
+    BK: BooleanArray + U128Conversions + IntoShares<Replicated<BK>>,
+    V: BooleanArray + U128Conversions + IntoShares<Replicated<V>>,
+{
+    fn share_with<R: Rng>(self, rng: &mut R) -> [MultiKeyHybridReport<BK, V, N>; 3] {
+        let ba_match_keys = self.match_key.map(|match_key| {
+            BA64::try_from(u128::from(match_key))
+                .unwrap()
+                .share_with(rng)
+        });
+        let ba_breakdown_key = BK::try_from(u128::from(self.breakdown_key))
+            .unwrap()
+            .share_with(rng);
+        let ba_value = V::try_from(u128::from(self.value)).unwrap().share_with(rng);
+        std::array::from_fn(|i| MultiKeyHybridReport {
+            match_key: ba_match_keys.each_ref().map(|shares| shares[i].clone()),
+            breakdown_key: ba_breakdown_key[i].clone(),
+            value: ba_value[i].clone(),
+        })
+    }
+}
+
 impl<BK, V> IntoShares<HybridReport<BK, V>> for TestHybridRecord
 where
     BK: BooleanArray + U128Conversions + IntoShares<Replicated<BK>>,
@@ -153,6 +189,34 @@ where
                     .try_into()
                     .unwrap()
             }
+            TestHybridRecord::TestLiftImpression {
+                match_key,
+                breakdown_key,
+                key_id,
+                test_group,
+            } => {
+                let ba_match_key = BA64::try_from(u128::from(match_key))
+                    .unwrap()
+                    .share_with(rng);
+                let ba_breakdown_key = BK::try_from(u128::from(breakdown_key))
+                    .unwrap()
+                    .share_with(rng);
+                let test_group = Boolean::from(test_group).share_with(rng);
+                zip(ba_match_key, zip(ba_breakdown_key, test_group))
+                    .map(
+                        |(match_key_share, (breakdown_key_share, test_group_share))| {
+                            HybridReport::LiftImpression::<BK, V>(HybridLiftImpressionReport {
+                                match_key: match_key_share,
+                                breakdown_key: breakdown_key_share,
+                                test_group: test_group_share,
+                                info: HybridImpressionInfo::new(key_id),
+                            })
+                        },
+                    )
+                    .collect::<Vec<_>>()
+                    .try_into()
+                    .unwrap()
+            }
             TestHybridRecord::TestConversion {
                 match_key,
                 value,
@@ -199,9 +263,12 @@ enum MatchEntry {
 impl MatchEntry {
     pub fn from_record(record: &TestHybridRecord) -> Self {
         match record {
-            TestHybridRecord::TestImpression { breakdown_key, .. } => Self::SingleImpression {
-                breakdown_key: *breakdown_key,
-            },
+            TestHybridRecord::TestImpression { breakdown_key, .. }
+            | TestHybridRecord::TestLiftImpression { breakdown_key, .. } => {
+                Self::SingleImpression {
+                    breakdown_key: *breakdown_key,
+                }
+            }
             TestHybridRecord::TestConversion { value, .. } => {
                 Self::SingleConversion { value: *value }
             }
@@ -222,7 +289,8 @@ impl MatchEntry {
 
     fn attribute_impression(breakdown_key: u32, new_record: &TestHybridRecord) -> Self {
         match new_record {
-            TestHybridRecord::TestImpression { .. } => Self::Attributed(None),
+            TestHybridRecord::TestImpression { .. }
+            | TestHybridRecord::TestLiftImpression { .. } => Self::Attributed(None),
             TestHybridRecord::TestConversion { value, .. } => {
                 Self::Attributed(Some((breakdown_key, *value)))
             }
@@ -231,7 +299,8 @@ impl MatchEntry {
 
     fn attribute_conversion(value: u32, new_record: &TestHybridRecord) -> Self {
         match new_record {
-            TestHybridRecord::TestImpression { breakdown_key, .. } => {
+            TestHybridRecord::TestImpression { breakdown_key, .. }
+            | TestHybridRecord::TestLiftImpression { breakdown_key, .. } => {
                 Self::Attributed(Some((*breakdown_key, value)))
             }
             TestHybridRecord::TestConversion {
@@ -259,7 +328,8 @@ pub fn hybrid_in_the_clear<I: IntoIterator<Item: Borrow<TestHybridRecord>>>(
     for input in input_rows {
         match input.borrow() {
             r @ (TestHybridRecord::TestConversion { match_key, .. }
-            | TestHybridRecord::TestImpression { match_key, .. }) => {
+            | TestHybridRecord::TestImpression { match_key, .. }
+            | TestHybridRecord::TestLiftImpression { match_key, .. }) => {
                 attributed_conversions
                     .entry(*match_key)
                     .and_modify(|e| e.add_record(r))
@@ -278,6 +348,40 @@ pub fn hybrid_in_the_clear<I: IntoIterator<Item: Borrow<TestHybridRecord>>>(
     output
 }
 
+/// Computes the expected output of a conversion lift query: the totals for the control group
+/// in the first `max_breakdown` values, followed by the totals for the test group. Regular
+/// impressions are in the control group.
+///
+/// # Panics
+/// It won't, so long as you can convert a usize to a u32
+#[must_use]
+pub fn conversion_lift_in_the_clear<I: IntoIterator<Item: Borrow<TestHybridRecord>>>(
+    input_rows: I,
+    max_breakdown: usize,
+) -> Vec<u32> {
+    let test_group_offset = u32::try_from(max_breakdown).unwrap();
+    hybrid_in_the_clear(
+        input_rows.into_iter().map(|input| match input.borrow() {
+            TestHybridRecord::TestLiftImpression {
+                match_key,
+                breakdown_key,
+                key_id,
+                test_group,
+            } => TestHybridRecord::TestImpression {
+                match_key: *match_key,
+                breakdown_key: if *test_group {
+                    breakdown_key + test_group_offset
+                } else {
+                    *breakdown_key
+                },
+                key_id: *key_id,
+            },
+            r => r.clone(),
+        }),
+        2 * max_breakdown,
+    )
+}
+
 #[must_use]
 #[allow(clippy::too_many_lines)]
 pub fn build_hybrid_records_and_expectation() -> (Vec<TestHybridRecord>, Vec<u32>) {
@@ -182,7 +182,8 @@ mod tests {
         let mut match_key_to_event_count = HashMap::new();
         for event in gen.take(TEST_COUNT.try_into().unwrap()) {
             match event {
-                TestHybridRecord::TestImpression { match_key, .. } => {
+                TestHybridRecord::TestImpression { match_key, .. }
+                | TestHybridRecord::TestLiftImpression { match_key, .. } => {
                     match_key_to_event_count
                         .entry(match_key)
                         .and_modify(|count| *count += 1)
@@ -242,8 +243,9 @@ mod tests {
                     assert!(breakdown_key <= MAX_BREAKDOWN_KEY);
                     match_keys.insert(match_key);
                 }
-                TestHybridRecord::TestConversion { .. } => {
-                    panic!("No conversions should be generated");
+                TestHybridRecord::TestConversion { .. }
+                | TestHybridRecord::TestLiftImpression { .. } => {
+                    panic!("No conversions or lift impressions should be generated");
                 }
             }
         }
@@ -267,7 +269,8 @@ mod tests {
                     assert!(value <= MAX_VALUE);
                     match_keys.insert(match_key);
                 }
-                TestHybridRecord::TestImpression { .. } => {
+                TestHybridRecord::TestImpression { .. }
+                | TestHybridRecord::TestLiftImpression { .. } => {
                     panic!("No impressions should be generated");
                 }
             }
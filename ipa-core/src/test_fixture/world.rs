@@ -23,7 +23,7 @@ use tracing::{Instrument, Level, Span};
 
 use crate::{
     helpers::{
-        in_memory_config::{passthrough, DynStreamInterceptor},
+        in_memory_config::{no_emulation, passthrough, DynNetworkEmulator, DynStreamInterceptor},
         Gateway, GatewayConfig, HelperIdentity, InMemoryMpcNetwork, InMemoryShardNetwork,
         InMemoryTransport, Role, RoleAssignment, TotalRecords, Transport,
     },
@@ -159,6 +159,15 @@ pub struct TestWorldConfig {
     /// [`passthrough`]: crate::helpers::in_memory_config::passthrough
     pub stream_interceptor: DynStreamInterceptor,
 
+    /// Conditions of the links between helpers and shards. By default, all data is
+    /// delivered instantly. Setting it makes it possible to observe how protocols behave
+    /// when helpers are far apart, for example over a WAN.
+    ///
+    /// [`wan`] emulator may be used to slow down links between helpers only.
+    ///
+    /// [`wan`]: crate::helpers::in_memory_config::wan
+    pub network_emulator: DynNetworkEmulator,
+
     /// Timeout for tests run by this `TestWorld`.
     ///
     /// If `None`, there is no timeout.
@@ -336,8 +345,11 @@ impl<S: ShardingScheme> TestWorld<S> {
         let global_prss_rng_seed = rng.next_u64();
 
         let shard_count = ShardIndex::try_from(S::SHARDS).unwrap();
-        let shard_network =
-            InMemoryShardNetwork::with_stream_interceptor(shard_count, &config.stream_interceptor);
+        let shard_network = InMemoryShardNetwork::with_network_emulator(
+            shard_count,
+            &config.stream_interceptor,
+            &config.network_emulator,
+        );
 
         let shards = shard_count
             .iter()
@@ -421,6 +433,7 @@ impl Default for TestWorldConfig {
             seed: thread_rng().next_u64(),
             initial_gate: None,
             stream_interceptor: passthrough(),
+            network_emulator: no_emulation(),
             timeout: Some(Duration::from_secs(10)),
         }
     }
@@ -848,9 +861,10 @@ impl<S: ShardingScheme> ShardWorld<S> {
     ) -> Self {
         let participants = make_participants(rng);
 
-        let network = InMemoryMpcNetwork::with_stream_interceptor(
+        let network = InMemoryMpcNetwork::with_network_emulator(
             InMemoryMpcNetwork::noop_handlers(),
             &config.stream_interceptor,
+            &config.network_emulator,
             shard_constructor.shard_id(),
         );
 
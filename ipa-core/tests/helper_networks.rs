@@ -1,10 +1,15 @@
 mod common;
 
-use std::{array, path::Path, process::Command};
+use std::{
+    array,
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    process::{Command, Stdio},
+};
 
 use common::{
     spawn_helpers, tempdir::TempDir, test_ipa, test_multiply, test_network, CommandExt,
-    UnwrapStatusExt, HELPER_BIN,
+    UnwrapStatusExt, HELPER_BIN, TEST_MPC_BIN,
 };
 use ipa_core::{cli::CliPaths, helpers::HelperIdentity, test_fixture::ipa::IpaSecurityModel};
 
@@ -134,6 +139,82 @@ fn keygen_confgen() {
     drop(helpers);
 }
 
+/// Launches helpers with `helper test-network` and runs test multiply against them.
+#[test]
+#[cfg(all(test, web_test))]
+fn test_network_launcher() {
+    let dir = TempDir::new_delete_on_drop();
+    let network_dir = dir.path().join("network");
+
+    let mut command = Command::new(HELPER_BIN);
+    command
+        .silent()
+        .arg("test-network")
+        .args(["--output-dir".as_ref(), network_dir.as_os_str()])
+        .arg("--")
+        .arg(TEST_MPC_BIN)
+        .arg("--network")
+        .arg(network_dir.join("network.toml"))
+        .arg("multiply")
+        .stdin(Stdio::piped());
+    let mut launcher = command.spawn().unwrap();
+    launcher.stdin.take().unwrap().write_all(b"3,6\n").unwrap();
+    launcher.wait().unwrap_status();
+    assert!(network_dir.join("helper3_shard0.log").exists());
+}
+
+/// Sharded networks come up as well, and a failing command fails the launcher.
+#[test]
+#[cfg(all(test, web_test))]
+fn test_network_launcher_sharded() {
+    let dir = TempDir::new_delete_on_drop();
+
+    let launch = |name: &str, command: &str| {
+        Command::new(HELPER_BIN)
+            .silent()
+            .arg("test-network")
+            .args(["--shards", "2"])
+            .arg("--disable-https")
+            .args(["--output-dir".as_ref(), dir.path().join(name).as_os_str()])
+            .args(["--", command])
+            .status()
+    };
+
+    launch("ok", "true").unwrap_status();
+    assert!(dir.path().join("ok/helper3_shard1.log").exists());
+    assert!(!launch("fail", "false").unwrap().success());
+}
+
+/// Without a command, the launcher keeps the network running until it is terminated.
+#[test]
+#[cfg(all(test, web_test, unix))]
+fn test_network_launcher_terminated() {
+    let dir = TempDir::new_delete_on_drop();
+
+    let mut launcher = Command::new(HELPER_BIN)
+        .arg("test-network")
+        .arg("--disable-https")
+        .args([
+            "--output-dir".as_ref(),
+            dir.path().join("network").as_os_str(),
+        ])
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let logs = BufReader::new(launcher.stderr.take().unwrap());
+    assert!(logs
+        .lines()
+        .map(Result::unwrap)
+        .any(|line| line.contains("helpers are ready")));
+
+    Command::new("kill")
+        .args(["-TERM", &launcher.id().to_string()])
+        .status()
+        .unwrap_status();
+    launcher.wait().unwrap_status();
+}
+
 fn exec_keygen_cmd(helper_identity: HelperIdentity, dest_dir: &Path) {
     let mut command = Command::new(HELPER_BIN);
     command
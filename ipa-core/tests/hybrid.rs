@@ -20,7 +20,7 @@ use common::{
 };
 use futures_util::{StreamExt, TryStreamExt};
 use ipa_core::{
-    cli::playbook::HybridQueryResult,
+    cli::playbook::{BatchQueryStatus, BatchSummary, HybridQueryResult},
     error::BoxError,
     helpers::{query::HybridQueryParams, LengthDelimitedStream},
 };
@@ -147,6 +147,121 @@ fn test_hybrid() {
         .all(|(a, b)| a == b));
 }
 
+#[test]
+fn test_hybrid_split_inputs() {
+    const INPUT_SIZE: usize = 100;
+    const SHARDS: usize = 2;
+    const MAX_CONVERSION_VALUE: usize = 5;
+    const MAX_BREAKDOWN_KEY: u32 = 5;
+
+    let dir = TempDir::new_delete_on_drop();
+
+    // Gen inputs
+    let input_file = dir.path().join("ipa_inputs.txt");
+    let in_the_clear_output_file = dir.path().join("ipa_output_in_the_clear.json");
+    let output_file = dir.path().join("ipa_output.json");
+
+    let mut command = Command::new(TEST_RC_BIN);
+    command
+        .args(["--output-file".as_ref(), input_file.as_os_str()])
+        .arg("gen-hybrid-inputs")
+        .args(["--count", &INPUT_SIZE.to_string()])
+        .args(["--max-conversion-value", &MAX_CONVERSION_VALUE.to_string()])
+        .args(["--max-breakdown-key", &MAX_BREAKDOWN_KEY.to_string()])
+        .args(["--seed", &thread_rng().next_u64().to_string()])
+        .silent()
+        .stdin(Stdio::piped());
+    command.status().unwrap_status();
+
+    let mut command = Command::new(IN_THE_CLEAR_BIN);
+    command
+        .args(["--input-file".as_ref(), input_file.as_os_str()])
+        .args([
+            "--output-file".as_ref(),
+            in_the_clear_output_file.as_os_str(),
+        ])
+        .silent()
+        .stdin(Stdio::piped());
+    command.status().unwrap_status();
+
+    let config_path = dir.path().join("config");
+    let sockets = test_sharded_setup::<SHARDS>(&config_path);
+    let _helpers = spawn_shards(&config_path, &sockets, true);
+
+    // encrypt input
+    let mut command = Command::new(CRYPTO_UTIL_BIN);
+    command
+        .arg("hybrid-encrypt")
+        .args(["--input-file".as_ref(), input_file.as_os_str()])
+        .args(["--output-dir".as_ref(), dir.path().as_os_str()])
+        .args(["--network".into(), config_path.join("network.toml")])
+        .stdin(Stdio::piped());
+    command.status().unwrap_status();
+    let enc1 = dir.path().join("helper1.enc");
+    let enc2 = dir.path().join("helper2.enc");
+    let enc3 = dir.path().join("helper3.enc");
+
+    // validate and split it between shards
+    let mut command = Command::new(CRYPTO_UTIL_BIN);
+    command
+        .arg("validate")
+        .args(["--input-file1".as_ref(), enc1.as_os_str()])
+        .args(["--input-file2".as_ref(), enc2.as_os_str()])
+        .args(["--input-file3".as_ref(), enc3.as_os_str()])
+        .args(["--network".into(), config_path.join("network.toml")])
+        .silent()
+        .stdin(Stdio::piped());
+    command.status().unwrap_status();
+
+    let shard_dir = dir.path().join("shards");
+    std::fs::create_dir(&shard_dir).unwrap();
+    let mut command = Command::new(CRYPTO_UTIL_BIN);
+    command
+        .arg("split")
+        .args(["--input-file1".as_ref(), enc1.as_os_str()])
+        .args(["--input-file2".as_ref(), enc2.as_os_str()])
+        .args(["--input-file3".as_ref(), enc3.as_os_str()])
+        .args(["--output-dir".as_ref(), shard_dir.as_os_str()])
+        .args(["--shards", &SHARDS.to_string()])
+        .args(["--strategy", "by-size"])
+        .stdin(Stdio::piped());
+    command.status().unwrap_status();
+
+    // Run Hybrid
+    let mut command = Command::new(TEST_RC_BIN);
+    command
+        .args(["--network".into(), config_path.join("network.toml")])
+        .args(["--output-file".as_ref(), output_file.as_os_str()])
+        .args(["--shard-count", SHARDS.to_string().as_str()])
+        .args(["--wait", "2"])
+        .arg("malicious-hybrid")
+        .silent()
+        .args(["--count", INPUT_SIZE.to_string().as_str()])
+        .args(["--enc-input-dir".as_ref(), shard_dir.as_os_str()])
+        .args(["--max-breakdown-key", &MAX_BREAKDOWN_KEY.to_string()])
+        .args(["--with-dp", "0"])
+        .stdin(Stdio::piped());
+
+    let test_mpc = command.spawn().unwrap().terminate_on_drop();
+    test_mpc.wait().unwrap_status();
+
+    let output = serde_json::from_str::<HybridQueryResult>(
+        &std::fs::read_to_string(&output_file).expect("IPA results file should exist"),
+    )
+    .expect("IPA results file is valid JSON");
+    assert_eq!(INPUT_SIZE, usize::from(output.input_size));
+
+    let expected_result: Vec<u32> = from_reader(
+        File::open(in_the_clear_output_file)
+            .expect("file should exist as it's created above in the test"),
+    )
+    .expect("should match hard coded format from in_the_clear");
+    assert_eq!(
+        expected_result[..output.breakdowns.len()],
+        output.breakdowns[..]
+    );
+}
+
 #[test]
 fn test_hybrid_poll() {
     const INPUT_SIZE: usize = 100;
@@ -289,6 +404,126 @@ fn test_hybrid_poll() {
         .all(|(a, b)| a == b));
 }
 
+#[test]
+fn test_hybrid_batch() {
+    const INPUT_SIZE: usize = 100;
+    const SHARDS: usize = 2;
+    const MAX_CONVERSION_VALUE: usize = 5;
+    const MAX_BREAKDOWN_KEY: u32 = 5;
+
+    let dir = TempDir::new_delete_on_drop();
+
+    // Gen inputs
+    let input_file = dir.path().join("ipa_inputs.txt");
+    let in_the_clear_output_file = dir.path().join("ipa_output_in_the_clear.json");
+    let summary_file = dir.path().join("summary.json");
+
+    let mut command = Command::new(TEST_RC_BIN);
+    command
+        .args(["--output-file".as_ref(), input_file.as_os_str()])
+        .arg("gen-hybrid-inputs")
+        .args(["--count", &INPUT_SIZE.to_string()])
+        .args(["--max-conversion-value", &MAX_CONVERSION_VALUE.to_string()])
+        .args(["--max-breakdown-key", &MAX_BREAKDOWN_KEY.to_string()])
+        .args(["--seed", &thread_rng().next_u64().to_string()])
+        .silent()
+        .stdin(Stdio::piped());
+    command.status().unwrap_status();
+
+    let mut command = Command::new(IN_THE_CLEAR_BIN);
+    command
+        .args(["--input-file".as_ref(), input_file.as_os_str()])
+        .args([
+            "--output-file".as_ref(),
+            in_the_clear_output_file.as_os_str(),
+        ])
+        .silent()
+        .stdin(Stdio::piped());
+    command.status().unwrap_status();
+
+    let config_path = dir.path().join("config");
+    let sockets = test_sharded_setup::<SHARDS>(&config_path);
+    let _helpers = spawn_shards(&config_path, &sockets, true);
+
+    // encrypt input
+    let mut command = Command::new(CRYPTO_UTIL_BIN);
+    command
+        .arg("hybrid-encrypt")
+        .args(["--input-file".as_ref(), input_file.as_os_str()])
+        .args(["--output-dir".as_ref(), dir.path().as_os_str()])
+        .args(["--network".into(), config_path.join("network.toml")])
+        .stdin(Stdio::piped());
+    command.status().unwrap_status();
+
+    // the same reports are aggregated twice, in both security models
+    let manifest = dir.path().join("batch.toml");
+    std::fs::write(
+        &manifest,
+        format!(
+            r#"
+[[queries]]
+name = "malicious"
+count = {INPUT_SIZE}
+output = "malicious.json"
+inputs = {{ encrypted = ["helper1.enc", "helper2.enc", "helper3.enc"] }}
+params = {{ max_breakdown_key = {MAX_BREAKDOWN_KEY}, with_dp = 0, epsilon = 0.0 }}
+
+[[queries]]
+name = "semi-honest"
+kind = "semi-honest-hybrid"
+count = {INPUT_SIZE}
+output = "semi-honest.json"
+inputs = {{ encrypted = ["helper1.enc", "helper2.enc", "helper3.enc"] }}
+params = {{ max_breakdown_key = {MAX_BREAKDOWN_KEY}, with_dp = 0, epsilon = 0.0 }}
+"#
+        ),
+    )
+    .unwrap();
+
+    let mut command = Command::new(TEST_RC_BIN);
+    command
+        .args(["--network".into(), config_path.join("network.toml")])
+        .args(["--output-file".as_ref(), summary_file.as_os_str()])
+        .args(["--shard-count", SHARDS.to_string().as_str()])
+        .args(["--wait", "2"])
+        .arg("batch")
+        .args(["--manifest".as_ref(), manifest.as_os_str()])
+        .silent()
+        .stdin(Stdio::piped());
+
+    let test_mpc = command.spawn().unwrap().terminate_on_drop();
+    test_mpc.wait().unwrap_status();
+
+    let summary = serde_json::from_str::<BatchSummary>(
+        &std::fs::read_to_string(&summary_file).expect("batch summary file should exist"),
+    )
+    .expect("batch summary file is valid JSON");
+    assert_eq!(2, summary.succeeded);
+    assert!(summary
+        .queries
+        .iter()
+        .all(|query| query.status == BatchQueryStatus::Succeeded && query.attempts == 1));
+
+    let expected_result: Vec<u32> = from_reader(
+        File::open(in_the_clear_output_file)
+            .expect("file should exist as it's created above in the test"),
+    )
+    .expect("should match hard coded format from in_the_clear");
+    for name in ["malicious", "semi-honest"] {
+        let output = serde_json::from_str::<HybridQueryResult>(
+            &std::fs::read_to_string(dir.path().join(format!("{name}.json")))
+                .expect("query results file should exist"),
+        )
+        .expect("query results file is valid JSON");
+        assert_eq!(INPUT_SIZE, usize::from(output.input_size));
+        assert_eq!(
+            &expected_result[..output.breakdowns.len()],
+            output.breakdowns,
+            "{name} query results"
+        );
+    }
+}
+
 fn create_upload_files<const SHARDS: usize>(
     enc_file1: &Path,
     enc_file2: &Path,
@@ -99,6 +99,22 @@ impl PartitionedStore {
         self.default_store.merge(other.default_store);
     }
 
+    /// Merges all partitions into the default store and returns it as a new,
+    /// non-partitioned store. Useful for exporting process-wide metrics, regardless of
+    /// which partition they were emitted in.
+    #[must_use]
+    pub fn flatten(self) -> Self {
+        let mut default_store = self.default_store;
+        for store in self.inner.into_values() {
+            default_store.merge(store);
+        }
+
+        Self {
+            inner: hashbrown::HashMap::with_hasher(FxBuildHasher),
+            default_store,
+        }
+    }
+
     pub fn counter_val<'a, const LABELS: usize, B: Borrow<MetricName<'a, LABELS>>>(
         &'a self,
         key: B,
@@ -261,4 +277,17 @@ mod tests {
         );
         assert_eq!(6, store1.counter_val(counter!("foo")));
     }
+
+    #[test]
+    fn flatten() {
+        let mut store = PartitionedStore::new();
+        store.with_partition_mut(1, |store| store.counter(counter!("foo")).inc(1));
+        store.with_partition_mut(2, |store| store.counter(counter!("foo")).inc(2));
+        store.counter(counter!("foo")).inc(3);
+
+        let store = store.flatten();
+        assert_eq!(1, store.len());
+        assert_eq!(None, store.with_partition(1, |_| ()));
+        assert_eq!(6, store.counter_val(counter!("foo")));
+    }
 }